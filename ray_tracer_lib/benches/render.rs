@@ -0,0 +1,31 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use ray_tracer_lib::camera::Camera;
+use ray_tracer_lib::mathf::vector3::Vector3;
+use ray_tracer_lib::transformations;
+use ray_tracer_lib::world;
+use std::f64::consts::PI;
+
+fn default_world_camera() -> Camera {
+    let mut camera = Camera::new(64, 64, PI / 2.);
+    camera.set_transform(transformations::view_transform(
+        Vector3::new(0., 0., -5.),
+        Vector3::new(0., 0., 0.),
+        Vector3::new(0., 1., 0.),
+    )).unwrap();
+    camera
+}
+
+fn render_default_world(c: &mut Criterion) {
+    let world = world::default_world();
+    let camera = default_world_camera();
+
+    let mut group = c.benchmark_group("default_world_64x64");
+    group.bench_function("render", |b| b.iter(|| camera.render(&world)));
+    group.bench_function("render_multithreaded", |b| {
+        b.iter(|| camera.render_multithreaded(&world))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, render_default_world);
+criterion_main!(benches);
@@ -0,0 +1,127 @@
+use crate::color::Color;
+use crate::light::Light;
+use crate::mathf::vector3::Vector3;
+use crate::rng::Rng;
+use crate::sampler::{self, SampleStrategy};
+
+/// A flat rectangular light, a parallelogram from `corner` along `u_vec` and
+/// `v_vec`. Shadow rays go to several points spread over it and are averaged,
+/// so shadows get soft edges where only part of the light is blocked.
+#[derive(Debug)]
+pub struct AreaLight {
+    pub corner: Vector3,
+    /// One full edge of the light
+    pub u_vec: Vector3,
+    /// The other full edge of the light
+    pub v_vec: Vector3,
+    pub intensity: Color,
+    /// When false nothing blocks this light, see `Light::casts_shadows`
+    pub casts_shadows: bool,
+    // Worked out once, every shading point uses the same points on the light
+    positions: Vec<Vector3>,
+}
+
+impl AreaLight {
+    /// `samples` points are spread over the light with the given strategy, see
+    /// `sampler::samples_2d`. Stratified samples give smoother penumbras than
+    /// random ones for the same number of shadow rays. The random strategies use a
+    /// fixed seed, so a render is the same every time.
+    pub fn new(
+        corner: Vector3,
+        u_vec: Vector3,
+        v_vec: Vector3,
+        samples: usize,
+        strategy: SampleStrategy,
+        intensity: Color,
+    ) -> AreaLight {
+        let positions = sampler::samples_2d(strategy, samples, &mut Rng::new(0))
+            .into_iter()
+            .map(|(u, v)| &(&corner + &(&u_vec * u)) + &(&v_vec * v))
+            .collect();
+        AreaLight {
+            corner,
+            u_vec,
+            v_vec,
+            intensity,
+            casts_shadows: true,
+            positions,
+        }
+    }
+
+    /// The middle of the light, which shading treats as its position
+    pub fn center(&self) -> Vector3 {
+        &(&self.corner + &(&self.u_vec * 0.5)) + &(&self.v_vec * 0.5)
+    }
+}
+
+impl Light for AreaLight {
+    fn intensity(&self) -> &Color {
+        &self.intensity
+    }
+
+    fn direction_from(&self, point: &Vector3) -> Vector3 {
+        (&self.center() - point).normalize()
+    }
+
+    fn distance_from(&self, point: &Vector3) -> f64 {
+        (&self.center() - point).magnitude()
+    }
+
+    fn positions_to_sample(&self) -> Vec<Vector3> {
+        self.positions.clone()
+    }
+
+    fn casts_shadows(&self) -> bool {
+        self.casts_shadows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathf::sphere::Sphere;
+    use crate::world;
+    use std::sync::Arc;
+
+    fn square_light(samples: usize, strategy: SampleStrategy) -> AreaLight {
+        AreaLight::new(
+            Vector3::new(-4., 10., -4.),
+            Vector3::new(8., 0., 0.),
+            Vector3::new(0., 0., 8.),
+            samples,
+            strategy,
+            Color::new(1., 1., 1.),
+        )
+    }
+
+    #[test]
+    fn stratified_samples_put_one_point_in_each_cell_of_the_light() {
+        let light = square_light(4, SampleStrategy::Stratified);
+        let mut cells: Vec<(bool, bool)> = light
+            .positions_to_sample()
+            .iter()
+            .map(|position| {
+                assert_eq!(position.y, 10.);
+                assert!(position.x.abs() <= 4. && position.z.abs() <= 4.);
+                (position.x > 0., position.z > 0.)
+            })
+            .collect();
+        cells.sort();
+        assert_eq!(cells, vec![(false, false), (false, true), (true, false), (true, true)]);
+        assert_eq!(light.center(), Vector3::new(0., 10., 0.));
+    }
+
+    #[test]
+    fn a_partly_blocked_area_light_casts_a_soft_shadow() {
+        let mut world = world::new();
+        world.objects.push(Arc::new(Sphere::new(None, None)));
+
+        for strategy in [SampleStrategy::Grid, SampleStrategy::Stratified] {
+            let light = square_light(16, strategy);
+            // The sphere hides the middle of the light but not its edges
+            let penumbra = light.intensity_at(&Vector3::new(0., -5., 0.), &world, None);
+            assert!(penumbra > 0. && penumbra < 1., "{:?} gave {}", strategy, penumbra);
+            assert_eq!(light.intensity_at(&Vector3::new(20., 0., 0.), &world, None), 1.);
+        }
+    }
+}
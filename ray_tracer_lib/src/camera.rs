@@ -2,19 +2,142 @@ use rayon::prelude::*;
 
 use crate::color::Color;
 use crate::canvas::Canvas;
+use crate::mathf;
 use crate::mathf::matrix::Matrix;
 use crate::mathf::ray::Ray;
 use crate::mathf::vector3::Vector3;
-use crate::world::World;
+use crate::transformations;
+use crate::render_stats::{RenderCounters, RenderStats};
+use crate::rng::Rng;
+use crate::sampler::{self, SampleStrategy};
+use crate::world::{DebugMode, RenderQuality, World};
+use std::f64::consts::PI;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How the camera maps pixels to rays.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Projection {
+    /// Rays fan out from a single point, distant objects look smaller.
+    #[default]
+    Perspective,
+    /// Rays are parallel, the image plane is `width` by `height` world units.
+    /// Objects keep the same size regardless of their distance from the camera.
+    Orthographic { width: f64, height: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CameraError {
+    /// The transform has no inverse, so no rays can be cast from the camera.
+    NonInvertibleTransform,
+    /// The scene is empty or goes on forever, so there is nothing to fit in view.
+    UnboundedScene,
+}
+
+impl fmt::Display for CameraError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CameraError::NonInvertibleTransform => {
+                write!(f, "the camera transform is not invertible")
+            }
+            CameraError::UnboundedScene => {
+                write!(f, "the scene has no finite bounds to frame")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CameraError {}
 
 pub struct Camera {
     pub hsize: usize,
     pub vsize: usize,
     pub field_of_view: f64,
-    pub transform: Matrix,
+    transform: Matrix,
+    inverse_transform: Matrix,
     pub pixel_size: f64,
     pub half_width: f64,
     pub half_height: f64,
+    /// Radius of the lens. When 0 the camera is a pin-hole and everything is in focus.
+    pub aperture: f64,
+    /// Distance from the camera to the plane that is in perfect focus.
+    pub focal_distance: f64,
+    /// Number of rays averaged per pixel when the aperture is larger than 0. When
+    /// anti-aliasing, each of the `SampleConfig::per_pixel` rays goes through its
+    /// own point on the lens instead, so that sets the number.
+    pub aperture_samples: usize,
+    pub quality: RenderQuality,
+    pub projection: Projection,
+    /// When set, pixels show the geometry instead of the lit scene, see `World::color_at_debug`
+    pub debug_mode: Option<DebugMode>,
+}
+
+/// How many rays `Camera::render_with` sends through each pixel and where.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleConfig {
+    /// Rays per pixel. With 1 the ray goes through the center of the pixel (or
+    /// several points on the lens when the camera has an aperture). The grid
+    /// strategies round this to a square, so 16 is a 4 by 4 grid.
+    pub per_pixel: usize,
+    /// Where the rays go within the pixel. This replaces the old `jitter` flag,
+    /// `jitter: true` is `SampleStrategy::Random` and `false` is `SampleStrategy::Grid`.
+    pub strategy: SampleStrategy,
+    /// The same seed always gives the same image when the strategy is random
+    pub seed: u64,
+    /// Render each row in parallel
+    pub multithreaded: bool,
+}
+
+impl SampleConfig {
+    /// One ray per pixel, the fastest mode and what `Camera::render` uses
+    pub fn single() -> SampleConfig {
+        SampleConfig {
+            per_pixel: 1,
+            strategy: SampleStrategy::Grid,
+            seed: 0,
+            multithreaded: false,
+        }
+    }
+
+    /// A `grid_size` by `grid_size` grid of evenly spaced rays per pixel
+    pub fn grid(grid_size: usize) -> SampleConfig {
+        let grid_size = grid_size.max(1);
+        SampleConfig {
+            per_pixel: grid_size * grid_size,
+            ..SampleConfig::single()
+        }
+    }
+
+    /// `samples` rays placed at random within each pixel
+    pub fn jittered(samples: usize, seed: u64) -> SampleConfig {
+        SampleConfig {
+            per_pixel: samples.max(1),
+            strategy: SampleStrategy::Random,
+            seed,
+            multithreaded: false,
+        }
+    }
+
+    /// `grid_size` by `grid_size` rays per pixel, each placed at random within
+    /// its cell of the grid. Less noisy than `jittered` for the same number of rays.
+    pub fn stratified(grid_size: usize, seed: u64) -> SampleConfig {
+        let grid_size = grid_size.max(1);
+        SampleConfig {
+            per_pixel: grid_size * grid_size,
+            strategy: SampleStrategy::Stratified,
+            seed,
+            multithreaded: false,
+        }
+    }
+
+    /// The same config with the strategy the old `jitter` flag picked
+    #[deprecated(note = "set `strategy` to `SampleStrategy::Random` or `SampleStrategy::Grid` instead")]
+    pub fn with_jitter(self, jitter: bool) -> SampleConfig {
+        SampleConfig {
+            strategy: if jitter { SampleStrategy::Random } else { SampleStrategy::Grid },
+            ..self
+        }
+    }
 }
 
 impl Camera {
@@ -35,77 +158,390 @@ impl Camera {
             vsize,
             field_of_view,
             transform: Matrix::identity_4x4(),
+            inverse_transform: Matrix::identity_4x4(),
             pixel_size,
             half_width,
             half_height,
+            aperture: 0.,
+            focal_distance: 1.,
+            aperture_samples: 1,
+            quality: RenderQuality::default(),
+            projection: Projection::default(),
+            debug_mode: None,
+        }
+    }
+
+    pub fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    /// Set the camera transform. The inverse is computed once here rather than for
+    /// every ray, so a transform without an inverse is rejected up front.
+    pub fn set_transform(&mut self, transform: Matrix) -> Result<(), CameraError> {
+        let inverse_transform = transform
+            .inverse()
+            .ok_or(CameraError::NonInvertibleTransform)?;
+        self.transform = transform;
+        self.inverse_transform = inverse_transform;
+        Ok(())
+    }
+
+    /// Point the camera from `from` towards `to`, see `transformations::view_transform`.
+    /// Fails if `up` is parallel to the direction the camera looks in.
+    pub fn look_at(&mut self, from: Vector3, to: Vector3, up: Vector3) -> Result<(), CameraError> {
+        self.set_transform(transformations::view_transform(from, to, up))
+    }
+
+    /// Move the camera back until the whole world fits in view, keeping the
+    /// direction it looks in. `margin` is extra room around the scene as a
+    /// fraction of its size, 0.1 leaves 10% to spare. An orthographic camera is
+    /// resized to fit instead. Fails if the world is empty or holds a shape that
+    /// goes on forever, like a plane (add those after framing).
+    pub fn frame_scene(&mut self, world: &World, margin: f64) -> Result<(), CameraError> {
+        let bounds = world.bounds();
+        let corners = [&bounds.min, &bounds.max];
+        if bounds.is_empty() || corners.iter().any(|c| !(c.x.is_finite() && c.y.is_finite() && c.z.is_finite())) {
+            return Err(CameraError::UnboundedScene);
+        }
+
+        // Fit a sphere around the box in view rather than the box itself, so
+        // the result doesn't depend on which way the camera is turned
+        let center = (&bounds.min + &bounds.max) * 0.5;
+        let radius = ((&bounds.max - &bounds.min).magnitude() / 2.).max(mathf::EQUALITY_EPSILON) * (1. + margin);
+
+        let forward = self
+            .inverse_transform
+            .multiply_vector(&Vector3::new(0., 0., -1.))
+            .normalize();
+        let up = self.inverse_transform.multiply_vector(&Vector3::new(0., 1., 0.));
+
+        let distance = match self.projection {
+            Projection::Perspective => radius / self.half_width.min(self.half_height).atan().sin(),
+            Projection::Orthographic { width, height } => {
+                let scale = 2. * radius / width.min(height);
+                self.projection = Projection::Orthographic {
+                    width: width * scale,
+                    height: height * scale,
+                };
+                2. * radius
+            }
+        };
+
+        let from = &center - &(&forward * distance);
+        self.look_at(from, center, up)
+    }
+
+    /// How many of the world's objects are at least partly inside the camera's
+    /// view. Each object's bounding box is tested against the sides of the view,
+    /// so an object near a corner may be counted when it is just out of sight.
+    /// Objects that go on forever, like planes, are always counted.
+    pub fn objects_in_view(&self, world: &World) -> usize {
+        let planes = self.frustum_planes();
+        world
+            .objects
+            .iter()
+            .map(|object| object.parent_space_bounds())
+            .filter(|bounds| !bounds.is_empty())
+            .filter(|bounds| {
+                let bounds = bounds.transform(&self.transform);
+                planes.iter().all(|(normal, distance)| {
+                    // The corner of the box furthest along the normal, if even that
+                    // is outside the plane then the whole box is
+                    let corner = Vector3::new(
+                        if normal.x >= 0. { bounds.max.x } else { bounds.min.x },
+                        if normal.y >= 0. { bounds.max.y } else { bounds.min.y },
+                        if normal.z >= 0. { bounds.max.z } else { bounds.min.z },
+                    );
+                    // An infinite box can give NaN here, which counts as inside
+                    let inside = normal.dot(&corner) + distance;
+                    inside >= 0. || inside.is_nan()
+                })
+            })
+            .count()
+    }
+
+    // The sides of the view in camera space as a normal pointing into the view
+    // and an offset, a point p is inside a side when normal · p + offset >= 0.
+    // The camera looks toward -z, there is no far side.
+    fn frustum_planes(&self) -> Vec<(Vector3, f64)> {
+        let behind = (Vector3::new(0., 0., -1.), 0.);
+        match self.projection {
+            Projection::Perspective => vec![
+                behind,
+                (Vector3::new(1., 0., -self.half_width), 0.),
+                (Vector3::new(-1., 0., -self.half_width), 0.),
+                (Vector3::new(0., 1., -self.half_height), 0.),
+                (Vector3::new(0., -1., -self.half_height), 0.),
+            ],
+            Projection::Orthographic { width, height } => vec![
+                behind,
+                (Vector3::new(1., 0., 0.), width / 2.),
+                (Vector3::new(-1., 0., 0.), width / 2.),
+                (Vector3::new(0., 1., 0.), height / 2.),
+                (Vector3::new(0., -1., 0.), height / 2.),
+            ],
         }
     }
 
     pub fn ray_for_pixel(&self, px: usize, py: usize) -> Ray {
-        // The offset from the edge of the canvas to the pixel's center
-        let xoffset = (px as f64 + 0.5) * self.pixel_size;
-        let yoffset = (py as f64 + 0.5) * self.pixel_size;
+        self.ray_for_pixel_sample(px, py, 0, 1)
+    }
 
-        // The untransformed coordinates of the pixel in world space.
+    /// Returns the ray for one of `samples` points spread across the lens.
+    /// With an aperture of 0 every sample is the same pin-hole ray.
+    /// An orthographic camera has no lens, so every sample is the same parallel ray.
+    pub fn ray_for_pixel_sample(&self, px: usize, py: usize, sample: usize, samples: usize) -> Ray {
+        // Aim for the pixel's center
+        self.ray_for_subpixel_sample(px, py, 0.5, 0.5, sample, samples)
+    }
+
+    /// Returns the ray through a point inside a pixel, `dx` and `dy` are the offset
+    /// from the pixel's top left corner in the range 0.0 to 1.0. Used to take
+    /// several samples per pixel when anti-aliasing.
+    pub fn ray_for_subpixel(&self, px: usize, py: usize, dx: f64, dy: f64) -> Ray {
+        self.ray_through(px as f64 + dx, py as f64 + dy, (0., 0.))
+    }
+
+    /// The ray through a point inside a pixel like `ray_for_subpixel`, starting
+    /// from one of `samples` points spread across the lens like `ray_for_pixel_sample`.
+    pub fn ray_for_subpixel_sample(&self, px: usize, py: usize, dx: f64, dy: f64, sample: usize, samples: usize) -> Ray {
+        let lens = if self.aperture > 0. {
+            lens_sample(px, py, sample, samples)
+        } else {
+            (0., 0.)
+        };
+        self.ray_through(px as f64 + dx, py as f64 + dy, lens)
+    }
+
+    // `x` and `y` are measured in pixels from the top left of the canvas
+    fn ray_through(&self, x: f64, y: f64, (lens_x, lens_y): (f64, f64)) -> Ray {
+        if let Projection::Orthographic { width, height } = self.projection {
+            return self.orthographic_ray_through(x, y, width, height);
+        }
+
+        // The offset from the edge of the canvas to the point
+        let xoffset = x * self.pixel_size;
+        let yoffset = y * self.pixel_size;
+
+        // The untransformed coordinates of the point in world space.
         // Remember the camera looks toward -z, so +x is to the *left*
         let world_x = self.half_width - xoffset;
         let world_y = self.half_height - yoffset;
 
-        // Using the camera matrix, transform the canvas point and the origin,
-        // and then compute the ray's direction vector.
-        // Remember that the canvas is at z=-1
-        let pixel = self
-            .transform
-            .inverse()
-            .unwrap()
-            .multiply_point(&Vector3::new(world_x, world_y, -1.));
-        let origin = self
-            .transform
-            .inverse()
-            .unwrap()
-            .multiply_point(&Vector3::new(0., 0., 0.));
+        // Using the camera matrix, transform the point on the focal plane and the
+        // origin on the lens, and then compute the ray's direction vector.
+        // Remember that the canvas is at z=-1, the focal plane is the canvas pushed
+        // out to z=-focal_distance.
+        let inverse_transform = &self.inverse_transform;
+        let pixel = inverse_transform.multiply_point(&Vector3::new(
+            world_x * self.focal_distance,
+            world_y * self.focal_distance,
+            -self.focal_distance,
+        ));
+        let origin = inverse_transform.multiply_point(&Vector3::new(
+            lens_x * self.aperture,
+            lens_y * self.aperture,
+            0.,
+        ));
 
         let direction = (&pixel - &origin).normalize();
         Ray::new(origin, direction)
     }
 
+    fn orthographic_ray_through(&self, x: f64, y: f64, width: f64, height: f64) -> Ray {
+        // The offset from the edge of the image plane to the point
+        let xoffset = x / self.hsize as f64 * width;
+        let yoffset = y / self.vsize as f64 * height;
+
+        // Remember the camera looks toward -z, so +x is to the *left*
+        let world_x = width / 2. - xoffset;
+        let world_y = height / 2. - yoffset;
+
+        // Every ray starts on the image plane at z=0 and points straight down -z
+        let inverse_transform = &self.inverse_transform;
+        let origin = inverse_transform.multiply_point(&Vector3::new(world_x, world_y, 0.));
+        let direction = inverse_transform
+            .multiply_vector(&Vector3::new(0., 0., -1.))
+            .normalize();
+        Ray::new(origin, direction)
+    }
+
     pub fn color_at_pixel(&self, world: &World, x: usize, y: usize) -> Color {
-        let ray = self.ray_for_pixel(x, y);
-        world.color_at(ray)
+        self.color_at_pixel_counted(world, x, y, None)
+    }
+
+    fn color_at_pixel_counted(&self, world: &World, x: usize, y: usize, counters: Option<&RenderCounters>) -> Color {
+        let trace = |ray: Ray| match (self.debug_mode, counters) {
+            (Some(mode), _) => world.color_at_debug(ray, mode),
+            (None, None) => world.color_at_with_quality(ray, self.quality),
+            (None, Some(counters)) => world.color_at_counted(ray, self.quality, 0, counters),
+        };
+
+        if self.aperture <= 0.
+            || self.aperture_samples <= 1
+            || self.projection != Projection::Perspective
+        {
+            return trace(self.ray_for_pixel(x, y));
+        }
+
+        // Average several rays through different points on the lens, anything
+        // not on the focal plane will be blurred.
+        let samples = self.aperture_samples;
+        Color::average((0..samples).map(|sample| trace(self.ray_for_pixel_sample(x, y, sample, samples))))
     }
 
     pub fn render(&self, world: &World) -> Canvas {
+        self.render_with(world, SampleConfig::single())
+    }
+
+    /// Same as `render`, but overwrites every pixel of an existing canvas instead
+    /// of allocating a new one. Reusing one canvas saves an allocation per frame
+    /// when rendering animations. Panics if the canvas isn't `hsize` by `vsize`.
+    pub fn render_into(&self, world: &World, canvas: &mut Canvas) {
+        self.render_with_into(world, SampleConfig::single(), canvas, None)
+    }
+
+    /// Supersample each pixel with a `grid_size` by `grid_size` grid of evenly spaced rays.
+    pub fn render_antialiased(&self, world: &World, grid_size: usize) -> Canvas {
+        self.render_with(world, SampleConfig::grid(grid_size))
+    }
+
+    /// Supersample each pixel with `samples` rays placed at random within the pixel.
+    /// Regular grids alias on fine repeating detail (like a distant checker pattern),
+    /// random offsets turn that into noise instead. The same `seed` always gives
+    /// the same image.
+    pub fn render_antialiased_jittered(&self, world: &World, samples: usize, seed: u64) -> Canvas {
+        self.render_with(world, SampleConfig::jittered(samples, seed))
+    }
+
+    /// Render with as many rays per pixel as `config` asks for, on one thread or many.
+    // Note - do not use a multithreaded config in WebAssembly as Rayon does not
+    // support wasm at this time.
+    pub fn render_with(&self, world: &World, config: SampleConfig) -> Canvas {
         let mut image = Canvas::new(self.hsize, self.vsize);
+        self.render_with_into(world, config, &mut image, None);
+        image
+    }
+
+    // Records the work done in `counters` when given some, see `render_with_stats`
+    fn render_with_into(&self, world: &World, config: SampleConfig, image: &mut Canvas, counters: Option<&RenderCounters>) {
+        assert!(
+            image.width == self.hsize && image.height == self.vsize,
+            "the canvas is {}x{} but the camera renders {}x{}",
+            image.width,
+            image.height,
+            self.hsize,
+            self.vsize
+        );
+
+        let pixel_color = |x: usize, y: usize| {
+            if config.per_pixel <= 1 {
+                self.color_at_pixel_counted(world, x, y, counters)
+            } else {
+                let mut rng = Rng::for_pixel(config.seed, x, y, 0);
+                let offsets = sampler::samples_2d(config.strategy, config.per_pixel, &mut rng);
+                self.color_at_subpixels(world, x, y, &offsets, &mut rng, counters)
+            }
+        };
+
         for y in 0..self.vsize {
-            for x in 0..self.hsize {
-                let color = self.color_at_pixel(world, x, y);
-                // let ray = self.ray_for_pixel(x, y);
-                // let color = world.color_at(ray);
-                image.write_pixel(x, y, &color);
+            if config.multithreaded {
+                let colors: Vec<Color> = (0..self.hsize)
+                    .into_par_iter()
+                    .map(|x| pixel_color(x, y))
+                    .collect();
+                for (x, color) in colors.iter().enumerate() {
+                    image.write_pixel(x, y, color);
+                }
+            } else {
+                for x in 0..self.hsize {
+                    image.write_pixel(x, y, &pixel_color(x, y));
+                }
             }
         }
-        image
+    }
+
+    // The average of a ray through the pixel at each of the subpixel `offsets`,
+    // each through its own point on the lens and seeded from the pixel's `rng`.
+    // Samples that came out NaN are skipped.
+    fn color_at_subpixels(
+        &self,
+        world: &World,
+        x: usize,
+        y: usize,
+        offsets: &[(f64, f64)],
+        rng: &mut Rng,
+        counters: Option<&RenderCounters>,
+    ) -> Color {
+        Color::average(offsets.iter().enumerate().map(|(sample, &(dx, dy))| {
+            let ray = self.ray_for_subpixel_sample(x, y, dx, dy, sample, offsets.len());
+            let seed = rng.next_u64();
+            match (self.debug_mode, counters) {
+                (Some(mode), _) => world.color_at_debug(ray, mode),
+                (None, None) => world.color_at_sample(ray, self.quality, seed),
+                (None, Some(counters)) => world.color_at_counted(ray, self.quality, seed, counters),
+            }
+        }))
+    }
+
+    /// Renders the world and returns how long it took in wall-clock time.
+    /// The clock is only read before and after the render, never per pixel.
+    // Note - do not use this function in WebAssembly as std::time::Instant is not
+    // available on wasm32-unknown-unknown.
+    pub fn render_timed(&self, world: &World) -> (Canvas, Duration) {
+        let start = Instant::now();
+        let image = self.render(world);
+        (image, start.elapsed())
     }
 
     // Note - do not use this function in WebAssembly as Rayon does not support wasm
     // at this time.
     pub fn render_multithreaded(&self, world: &World) -> Canvas {
-        let mut image = Canvas::new(self.hsize, self.vsize);
-        for y in 0..self.vsize {
-            let colors: Vec<crate::color::Color> = (0..self.hsize)
-                .into_par_iter()
-                .map(|x| {
-                    let ray = self.ray_for_pixel(x, y);
-                    world.color_at(ray)
-                })
-                .collect();
+        let config = SampleConfig {
+            multithreaded: true,
+            ..SampleConfig::single()
+        };
+        self.render_with(world, config)
+    }
 
-            for (x, color) in colors.iter().enumerate() {
-                image.write_pixel(x, y, &color);
-            }
-        }
-        image
+    /// The distance from the camera to the first hit through the center of each
+    /// pixel, indexed `[y][x]` like `Canvas::pixels`. Infinity where nothing is
+    /// hit. Handy for fog or other effects applied after rendering.
+    pub fn render_depth(&self, world: &World) -> Vec<Vec<f64>> {
+        (0..self.vsize)
+            .map(|y| {
+                (0..self.hsize)
+                    .map(|x| {
+                        // Camera rays are normalized, so t is already a distance
+                        let ray = self.ray_for_pixel(x, y);
+                        world.intersect(ray).hit().map_or(f64::INFINITY, |hit| hit.t)
+                    })
+                    .collect()
+            })
+            .collect()
     }
+
+    /// Renders the world like `render_with`, and also counts the rays cast, the
+    /// objects intersected and how deep reflection and refraction went. Counting
+    /// has a small cost, so `render` doesn't do it.
+    // Note - do not use a multithreaded config in WebAssembly as Rayon does not
+    // support wasm at this time.
+    pub fn render_with_stats(&self, world: &World, config: SampleConfig) -> (Canvas, RenderStats) {
+        let counters = RenderCounters::default();
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        self.render_with_into(world, config, &mut image, Some(&counters));
+        (image, counters.stats())
+    }
+}
+
+// Returns a point on the unit disk for the given sample using a Vogel spiral.
+// Each pixel rotates the spiral by a different amount so the blur doesn't band.
+fn lens_sample(px: usize, py: usize, sample: usize, samples: usize) -> (f64, f64) {
+    let rotation = Rng::for_pixel(0, px, py, 0).next_f64() * 2. * PI;
+
+    let radius = ((sample as f64 + 0.5) / samples as f64).sqrt();
+    let theta = sample as f64 * mathf::GOLDEN_ANGLE + rotation;
+    (radius * theta.cos(), radius * theta.sin())
 }
 
 #[cfg(test)]
@@ -115,7 +551,9 @@ mod tests {
     use crate::mathf::approximately;
     use crate::transformations;
     use crate::world;
-    use std::f64::consts::PI;
+    use crate::mathf::plane::Plane;
+    use crate::mathf::sphere::Sphere;
+    use std::sync::Arc;
 
     #[test]
     fn it_creates_a_camera() {
@@ -126,7 +564,7 @@ mod tests {
         assert_eq!(camera.hsize, 160);
         assert_eq!(camera.vsize, 120);
         assert_eq!(camera.field_of_view, PI / 2.);
-        assert_eq!(camera.transform, Matrix::identity_4x4());
+        assert_eq!(camera.transform(), &Matrix::identity_4x4());
     }
 
     #[test]
@@ -160,8 +598,8 @@ mod tests {
     #[test]
     fn constructing_a_ray_when_the_camera_is_transformed() {
         let mut camera = Camera::new(201, 101, PI / 2.);
-        camera.transform = transformations::rotation_y(PI / 4.)
-            .multiply_4x4(&transformations::translation(&Vector3::new(0., -2., 5.)));
+        camera.set_transform(transformations::rotation_y(PI / 4.)
+            .multiply_4x4(&transformations::translation(&Vector3::new(0., -2., 5.)))).unwrap();
 
         let ray = camera.ray_for_pixel(100, 50);
         assert_eq!(ray.origin, Vector3::new(0., 2., -5.));
@@ -171,6 +609,130 @@ mod tests {
         );
     }
 
+    #[test]
+    fn look_at_sets_the_view_transform() {
+        let from = Vector3::new(0., 1.5, -5.);
+        let to = Vector3::new(0., 1., 0.);
+        let up = Vector3::new(0., 1., 0.);
+        let mut camera = Camera::new(201, 101, PI / 2.);
+        camera.look_at(from.clone(), to.clone(), up.clone()).unwrap();
+
+        let expected = transformations::view_transform(from, to, up);
+        assert_eq!(camera.transform(), &expected);
+        assert_eq!(camera.inverse_transform, expected.inverse().unwrap());
+    }
+
+    #[test]
+    fn looking_straight_along_up_is_rejected() {
+        let mut camera = Camera::new(201, 101, PI / 2.);
+        let result = camera.look_at(Vector3::new(0., 0., 0.), Vector3::new(0., 5., 0.), Vector3::new(0., 1., 0.));
+        assert_eq!(result, Err(CameraError::NonInvertibleTransform));
+    }
+
+    #[test]
+    fn looking_at_the_point_the_camera_is_at_is_rejected() {
+        let mut camera = Camera::new(201, 101, PI / 2.);
+        let from = Vector3::new(1., 2., 3.);
+        let result = camera.look_at(from.clone(), from, Vector3::new(0., 1., 0.));
+        assert_eq!(result, Err(CameraError::NonInvertibleTransform));
+    }
+
+    #[test]
+    fn a_non_invertible_transform_is_rejected() {
+        let mut camera = Camera::new(201, 101, PI / 2.);
+        let result = camera.set_transform(transformations::scaling(&Vector3::new(1., 0., 1.)));
+        assert_eq!(result, Err(CameraError::NonInvertibleTransform));
+
+        // The camera keeps its previous transform and can still cast rays
+        assert_eq!(camera.transform(), &Matrix::identity_4x4());
+        let ray = camera.ray_for_pixel(100, 50);
+        assert_eq!(ray.direction, Vector3::new(0., 0., -1.));
+    }
+
+    #[test]
+    fn a_camera_defaults_to_a_pin_hole() {
+        let camera = Camera::new(160, 120, PI / 2.);
+        assert_eq!(camera.aperture, 0.);
+        assert_eq!(camera.focal_distance, 1.);
+        assert_eq!(camera.aperture_samples, 1);
+    }
+
+    #[test]
+    fn a_zero_aperture_gives_the_pin_hole_ray() {
+        let mut camera = Camera::new(201, 101, PI / 2.);
+        camera.set_transform(transformations::rotation_y(PI / 4.)
+            .multiply_4x4(&transformations::translation(&Vector3::new(0., -2., 5.)))).unwrap();
+        let pin_hole = camera.ray_for_pixel(10, 20);
+
+        camera.focal_distance = 5.;
+        for sample in 0..8 {
+            let ray = camera.ray_for_pixel_sample(10, 20, sample, 8);
+            assert_eq!(ray.origin, pin_hole.origin);
+            assert_eq!(ray.direction, pin_hole.direction);
+        }
+    }
+
+    #[test]
+    fn aperture_rays_start_on_the_lens_and_meet_at_the_focal_plane() {
+        let mut camera = Camera::new(201, 101, PI / 2.);
+        camera.aperture = 0.5;
+        camera.focal_distance = 4.;
+
+        for sample in 0..8 {
+            let ray = camera.ray_for_pixel_sample(100, 50, sample, 8);
+            assert!(ray.origin.magnitude() <= 0.5);
+            assert!(approximately(ray.origin.z, 0.));
+
+            // Every ray through the center pixel passes through the center of the focal plane
+            let t = -4. / ray.direction.z;
+            assert_eq!(ray.position(t), Vector3::new(0., 0., -4.));
+        }
+    }
+
+    #[test]
+    fn a_camera_defaults_to_a_perspective_projection() {
+        let camera = Camera::new(160, 120, PI / 2.);
+        assert_eq!(camera.projection, Projection::Perspective);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel_with_different_origins() {
+        let mut camera = Camera::new(20, 10, PI / 2.);
+        camera.projection = Projection::Orthographic {
+            width: 4.,
+            height: 2.,
+        };
+
+        let center = camera.ray_for_pixel(10, 5);
+        let corner = camera.ray_for_pixel(0, 0);
+        assert_eq!(center.direction, Vector3::new(0., 0., -1.));
+        assert_eq!(corner.direction, Vector3::new(0., 0., -1.));
+        assert_eq!(center.origin, Vector3::new(-0.1, -0.1, 0.));
+        assert_eq!(corner.origin, Vector3::new(1.9, 0.9, 0.));
+    }
+
+    #[test]
+    fn orthographic_rays_follow_the_camera_transform() {
+        let mut camera = Camera::new(20, 10, PI / 2.);
+        camera.projection = Projection::Orthographic {
+            width: 4.,
+            height: 2.,
+        };
+        camera.set_transform(transformations::view_transform(
+            Vector3::new(0., 0., -5.),
+            Vector3::new(0., 0., 0.),
+            Vector3::new(0., 1., 0.),
+        )).unwrap();
+
+        let left = camera.ray_for_pixel(0, 5);
+        let right = camera.ray_for_pixel(19, 5);
+        assert_eq!(left.direction, Vector3::new(0., 0., 1.));
+        assert_eq!(right.direction, left.direction);
+        assert!(approximately(left.origin.z, -5.));
+        assert!(approximately(right.origin.z, -5.));
+        assert!(left.origin.x < right.origin.x);
+    }
+
     #[test]
     fn rendering_a_world_with_a_camera() {
         let world = world::default_world();
@@ -178,10 +740,389 @@ mod tests {
         let from = Vector3::new(0., 0., -5.);
         let to = Vector3::new(0., 0., 0.);
         let up = Vector3::new(0., 1., 0.);
-        camera.transform = transformations::view_transform(from, to, up);
+        camera.set_transform(transformations::view_transform(from, to, up)).unwrap();
         let image = camera.render(&world);
 
         let pixel_at = &image.pixels[5][5];
         assert_eq!(pixel_at, &Color::new(0.38066, 0.47583, 0.2855));
     }
+
+    #[test]
+    fn rendering_into_a_used_canvas_overwrites_every_pixel() {
+        let world = world::default_world();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera
+            .look_at(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 0.), Vector3::new(0., 1., 0.))
+            .unwrap();
+
+        let mut canvas = Canvas::new(11, 11);
+        for row in canvas.pixels.iter_mut() {
+            row.fill(Color::new(1., 0., 1.));
+        }
+        camera.render_into(&world, &mut canvas);
+        assert_eq!(canvas.pixels, camera.render(&world).pixels);
+    }
+
+    #[test]
+    #[should_panic(expected = "the canvas is 10x11")]
+    fn rendering_into_a_canvas_of_the_wrong_size_is_rejected() {
+        let camera = Camera::new(11, 11, PI / 2.);
+        camera.render_into(&world::default_world(), &mut Canvas::new(10, 11));
+    }
+
+    #[test]
+    fn framing_a_scene_fits_every_object_in_view() {
+        let mut world = world::new();
+        for x in [-2., 2.] {
+            world.add(Arc::new(Sphere::new(
+                Some(transformations::translation(&Vector3::new(x, 0., 0.))),
+                None,
+            )));
+        }
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera.frame_scene(&world, 0.1).unwrap();
+
+        let hits = |x, y| world.intersect(camera.ray_for_pixel(x, y)).hit().is_some();
+        assert!(hits(3, 5) && hits(7, 5));
+        // Nothing is cut off at the edges of the image
+        for i in 0..11 {
+            assert!(!hits(i, 0) && !hits(i, 10) && !hits(0, i) && !hits(10, i));
+        }
+    }
+
+    #[test]
+    fn a_scene_without_bounds_cant_be_framed() {
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        assert_eq!(camera.frame_scene(&world::new(), 0.), Err(CameraError::UnboundedScene));
+
+        let mut world = world::new();
+        world.add(Arc::new(Plane::new(None, None)));
+        assert_eq!(camera.frame_scene(&world, 0.), Err(CameraError::UnboundedScene));
+    }
+
+    #[test]
+    fn counting_the_objects_in_view() {
+        let mut world = world::new();
+        for position in [
+            Vector3::new(0., 0., 5.),
+            Vector3::new(0., 0., -5.),
+            Vector3::new(20., 0., 5.),
+        ] {
+            world.add(Arc::new(Sphere::new(Some(transformations::translation(&position)), None)));
+        }
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera
+            .look_at(Vector3::new(0., 0., 0.), Vector3::new(0., 0., 1.), Vector3::new(0., 1., 0.))
+            .unwrap();
+
+        // One in front, one behind and one off to the side
+        assert_eq!(camera.objects_in_view(&world), 1);
+
+        // Partly in view still counts
+        world.add(Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(5.5, 0., 5.))),
+            None,
+        )));
+        assert_eq!(camera.objects_in_view(&world), 2);
+
+        world.add(Arc::new(Plane::new(None, None)));
+        assert_eq!(camera.objects_in_view(&world), 3);
+
+        camera.projection = Projection::Orthographic { width: 4., height: 4. };
+        assert_eq!(camera.objects_in_view(&world), 2);
+    }
+
+    #[test]
+    fn rendering_the_depth_of_each_pixel() {
+        let world = world::default_world();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        camera
+            .look_at(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 0.), Vector3::new(0., 1., 0.))
+            .unwrap();
+        let depth = camera.render_depth(&world);
+
+        assert_eq!(depth.len(), 11);
+        assert!(depth.iter().all(|row| row.len() == 11));
+        // Straight at the front of the outer sphere
+        assert!(approximately(depth[5][5], 4.));
+        // The corners look past the spheres
+        assert_eq!(depth[0][0], f64::INFINITY);
+    }
+
+    #[test]
+    fn the_depth_is_a_distance_in_world_space() {
+        let world = world::default_world();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        // Off to the side and scaled, so camera space units aren't world units
+        let view = transformations::view_transform(
+            Vector3::new(7., 0., 0.),
+            Vector3::new(0., 0., 0.),
+            Vector3::new(0., 1., 0.),
+        );
+        camera
+            .set_transform(transformations::scaling(&Vector3::new(0.5, 0.5, 0.5)).multiply_4x4(&view))
+            .unwrap();
+        let depth = camera.render_depth(&world);
+
+        // From x = 7 to the side of the outer sphere at x = 1
+        assert!(approximately(depth[5][5], 6.));
+    }
+
+    #[test]
+    fn a_timed_render_matches_a_regular_render() {
+        let world = world::default_world();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        let from = Vector3::new(0., 0., -5.);
+        let to = Vector3::new(0., 0., 0.);
+        let up = Vector3::new(0., 1., 0.);
+        camera.set_transform(transformations::view_transform(from, to, up)).unwrap();
+        let (image, elapsed) = camera.render_timed(&world);
+
+        assert_eq!(image.pixels, camera.render(&world).pixels);
+        assert!(elapsed > Duration::from_secs(0));
+    }
+
+    #[test]
+    fn a_single_sample_config_matches_a_regular_render() {
+        let (world, camera) = checkered_world_and_camera();
+        let expected = camera.render(&world).pixels;
+        assert_eq!(camera.render_with(&world, SampleConfig::single()).pixels, expected);
+
+        let parallel = SampleConfig {
+            multithreaded: true,
+            ..SampleConfig::single()
+        };
+        assert_eq!(camera.render_with(&world, parallel).pixels, expected);
+    }
+
+    #[test]
+    fn a_sample_config_gives_the_same_image_on_one_thread_or_many() {
+        let (world, camera) = checkered_world_and_camera();
+        let config = SampleConfig::jittered(4, 7);
+        let parallel = SampleConfig {
+            multithreaded: true,
+            ..config
+        };
+        assert_eq!(camera.render_with(&world, parallel).pixels, camera.render_with(&world, config).pixels);
+        assert_eq!(camera.render_with(&world, config).pixels, camera.render_antialiased_jittered(&world, 4, 7).pixels);
+    }
+
+    #[test]
+    fn a_camera_in_normals_mode_renders_the_normals() {
+        let world = world::default_world();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        let from = Vector3::new(0., 0., -5.);
+        let to = Vector3::new(0., 0., 0.);
+        let up = Vector3::new(0., 1., 0.);
+        camera.set_transform(transformations::view_transform(from, to, up)).unwrap();
+        camera.debug_mode = Some(DebugMode::Normals);
+        let image = camera.render(&world);
+
+        assert_eq!(image.pixels[5][5], Color::new(0.5, 0.5, 0.));
+        assert_eq!(image.pixels[0][0], Color::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn rendering_with_stats_counts_one_primary_ray_per_pixel() {
+        let world = world::default_world();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        let from = Vector3::new(0., 0., -5.);
+        let to = Vector3::new(0., 0., 0.);
+        let up = Vector3::new(0., 1., 0.);
+        camera.set_transform(transformations::view_transform(from, to, up)).unwrap();
+        let (image, stats) = camera.render_with_stats(&world, SampleConfig::single());
+
+        assert_eq!(image.pixels, camera.render(&world).pixels);
+        assert_eq!(stats.primary_rays, 11 * 11);
+        // Nothing in the default world is reflective or transparent
+        assert_eq!(stats.secondary_rays, 0);
+        assert_eq!(stats.max_depth, 0);
+        assert_eq!(stats.depth_limit_reached, 0);
+        assert!(stats.shadow_rays > 0);
+        assert!(stats.intersection_tests >= 2 * stats.primary_rays);
+    }
+
+    #[test]
+    fn rendering_with_stats_follows_the_sample_config() {
+        let world = world::default_world();
+        let mut camera = Camera::new(11, 11, PI / 2.);
+        let from = Vector3::new(0., 0., -5.);
+        let to = Vector3::new(0., 0., 0.);
+        let up = Vector3::new(0., 1., 0.);
+        camera.set_transform(transformations::view_transform(from, to, up)).unwrap();
+        let config = SampleConfig {
+            multithreaded: true,
+            ..SampleConfig::jittered(4, 7)
+        };
+        let (image, stats) = camera.render_with_stats(&world, config);
+
+        assert_eq!(image.pixels, camera.render_with(&world, config).pixels);
+        assert_eq!(stats.primary_rays, 4 * 11 * 11);
+    }
+
+    #[test]
+    fn rays_cut_off_by_the_depth_limit_are_black_and_counted() {
+        use crate::material::Material;
+        use crate::point_light::PointLight;
+
+        // Two mirrors facing each other, any ray between them bounces forever
+        let mirrors = |reflective: f64, max_depth: usize| {
+            let mut world = world::new();
+            world.add_light(PointLight::new(Vector3::new(0., 0.5, 0.), Color::new(1., 1., 1.)));
+            for y in [-1., 1.] {
+                world.add(Arc::new(Plane::new(
+                    Some(transformations::translation(&Vector3::new(0., y, 0.))),
+                    Some(Material::builder().reflective(reflective).build()),
+                )));
+            }
+            world.max_depth = max_depth;
+            world
+        };
+        let mut camera = Camera::new(5, 5, PI / 2.);
+        camera
+            .look_at(Vector3::new(0., 0., 0.), Vector3::new(0., -1., 5.), Vector3::new(0., 1., 0.))
+            .unwrap();
+
+        let (image, stats) = camera.render_with_stats(&mirrors(1., 2), SampleConfig::single());
+        assert_eq!(stats.max_depth, 2);
+        // Every pixel sees a mirror, and each one gives up after two bounces
+        assert_eq!(stats.depth_limit_reached, 5 * 5);
+        assert_eq!(image.pixels, camera.render(&mirrors(1., 2)).pixels);
+
+        // With no bounces allowed the mirrors are missing their reflections
+        let (image, stats) = camera.render_with_stats(&mirrors(1., 0), SampleConfig::single());
+        assert_eq!(stats.secondary_rays, 0);
+        assert_eq!(stats.depth_limit_reached, 5 * 5);
+        assert_eq!(image.pixels, camera.render(&mirrors(0., 0)).pixels);
+    }
+
+    #[test]
+    fn a_subpixel_ray_through_the_center_matches_the_pixel_ray() {
+        let mut camera = Camera::new(201, 101, PI / 2.);
+        camera.set_transform(transformations::rotation_y(PI / 4.)).unwrap();
+        let ray = camera.ray_for_subpixel(10, 20, 0.5, 0.5);
+        assert_eq!(ray.direction, camera.ray_for_pixel(10, 20).direction);
+
+        // The far corner of a pixel is the near corner of the next one
+        let corner = camera.ray_for_subpixel(10, 20, 1., 1.);
+        assert_eq!(corner.direction, camera.ray_for_subpixel(11, 21, 0., 0.).direction);
+    }
+
+    #[test]
+    fn antialiasing_keeps_the_depth_of_field() {
+        let (world, mut camera) = checkered_world_and_camera();
+        let pin_hole = camera.render_with(&world, SampleConfig::grid(2));
+
+        camera.aperture = 0.5;
+        camera.focal_distance = 2.;
+        let blurred = camera.render_with(&world, SampleConfig::grid(2));
+        assert_ne!(blurred.pixels, pin_hole.pixels);
+
+        // A subpixel ray on the lens still passes through its point on the focal plane
+        let center = camera.ray_for_subpixel(3, 4, 0.25, 0.75);
+        let forward = camera.inverse_transform.multiply_vector(&Vector3::new(0., 0., -1.));
+        let focal_point = center.position(2. / center.direction.dot(&forward));
+        for sample in 0..4 {
+            let ray = camera.ray_for_subpixel_sample(3, 4, 0.25, 0.75, sample, 4);
+            assert_ne!(ray.origin, center.origin);
+            let t = (&focal_point - &ray.origin).magnitude();
+            assert_eq!(ray.position(t), focal_point);
+        }
+    }
+
+    fn checkered_world_and_camera() -> (World, Camera) {
+        use crate::color;
+        use crate::material::Material;
+        use crate::mathf::plane::Plane;
+        use crate::point_light::PointLight;
+        use crate::patterns::checker::CheckerPattern;
+        use std::sync::Arc;
+
+        // A fine checker pattern stretching into the distance aliases badly
+        let pattern = CheckerPattern::new(None, color::WHITE, color::BLACK);
+        let material = Material::builder()
+            .pattern(Arc::new(pattern))
+            .ambient(1.)
+            .diffuse(0.)
+            .specular(0.)
+            .build();
+        let mut world = world::new();
+        world.add_light(PointLight::new(Vector3::new(0., 10., 0.), color::WHITE));
+        world.objects = vec![Arc::new(Plane::new(None, Some(material)))];
+
+        let mut camera = Camera::new(16, 8, PI / 3.);
+        camera.set_transform(transformations::view_transform(
+            Vector3::new(0., 1., -5.),
+            Vector3::new(0., 0., 10.),
+            Vector3::new(0., 1., 0.),
+        )).unwrap();
+        (world, camera)
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn the_old_jitter_flag_picks_random_or_grid_samples() {
+        assert_eq!(SampleConfig::grid(2).with_jitter(true), SampleConfig::jittered(4, 0));
+        assert_eq!(SampleConfig::jittered(4, 0).with_jitter(false), SampleConfig::grid(2));
+    }
+
+    #[test]
+    fn jittered_antialiasing_is_reproducible_with_the_same_seed() {
+        let (world, camera) = checkered_world_and_camera();
+        let a = camera.render_antialiased_jittered(&world, 4, 42);
+        let b = camera.render_antialiased_jittered(&world, 4, 42);
+        assert_eq!(a.pixels, b.pixels);
+
+        let c = camera.render_antialiased_jittered(&world, 4, 7);
+        assert_ne!(a.pixels, c.pixels);
+    }
+
+    #[test]
+    fn stratified_antialiasing_is_closer_to_the_reference_than_random_jitter() {
+        let (world, camera) = checkered_world_and_camera();
+        let reference = camera.render_antialiased(&world, 16);
+        let error = |config: SampleConfig| {
+            let image = camera.render_with(&world, config);
+            let mut total = 0.;
+            for (row, reference_row) in image.pixels.iter().zip(&reference.pixels) {
+                for (pixel, expected) in row.iter().zip(reference_row) {
+                    total += (pixel.luminance() - expected.luminance()).abs();
+                }
+            }
+            total
+        };
+
+        let stratified = SampleConfig::stratified(2, 42);
+        assert_eq!(stratified.per_pixel, 4);
+        assert!(error(stratified) < error(SampleConfig::jittered(4, 42)));
+    }
+
+    #[test]
+    fn a_jittered_render_is_the_same_on_any_number_of_threads() {
+        let (world, camera) = checkered_world_and_camera();
+        let single = SampleConfig::jittered(4, 42);
+        let multi = SampleConfig {
+            multithreaded: true,
+            ..single
+        };
+        let a = crate::ppm::canvas_to_ppm(&camera.render_with(&world, single));
+        let b = crate::ppm::canvas_to_ppm(&camera.render_with(&world, multi));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn a_one_sample_grid_matches_a_regular_render() {
+        let (world, camera) = checkered_world_and_camera();
+        assert_eq!(camera.render_antialiased(&world, 1).pixels, camera.render(&world).pixels);
+    }
+
+    #[test]
+    fn antialiasing_averages_samples_across_an_edge() {
+        let (world, camera) = checkered_world_and_camera();
+        let image = camera.render_antialiased(&world, 4);
+        // Far away squares are smaller than a pixel, so they blend to grey
+        // rather than being either black or white
+        let pixel = &image.pixels[4][8];
+        assert!(pixel.r > 0.05 && pixel.r < 0.95);
+    }
 }
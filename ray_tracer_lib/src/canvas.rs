@@ -1,5 +1,14 @@
 use crate::color::Color;
 
+/// What `Canvas::sample_uv_with_edges` reads past the side of the image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+    /// The image repeats, so the far side is read
+    Repeat,
+    /// The edge pixels are stretched outward
+    Clamp,
+}
+
 pub struct Canvas {
     pub width: usize,
     pub height: usize,
@@ -16,7 +25,112 @@ impl Canvas {
     }
 
     pub fn write_pixel(&mut self, x: usize, y: usize, color: &Color) {
-        self.pixels[y as usize][x as usize] = color.clone();
+        self.pixels[y][x] = color.clone();
+    }
+
+    /// The pixels row by row as red, green, blue and alpha bytes. Alpha is always 255.
+    /// This is the layout expected by an HTML canvas `ImageData`.
+    pub fn to_rgba_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.width * self.height * 4);
+        for color in self.pixels.iter().flat_map(|row| row.iter()) {
+            bytes.extend_from_slice(&color.to_rgb8());
+            bytes.push(255);
+        }
+        bytes
+    }
+
+    /// The color at (u, v) blended between the four nearest pixels, u runs left to
+    /// right and v bottom to top, both 0.0 to 1.0. The image repeats in both directions.
+    pub fn sample_uv(&self, u: f64, v: f64) -> Color {
+        self.sample_uv_with_edges(u, v, Edge::Repeat, Edge::Repeat)
+    }
+
+    /// Same as `sample_uv`, with the left and right sides handled by `u_edge`
+    /// and the top and bottom by `v_edge`.
+    pub fn sample_uv_with_edges(&self, u: f64, v: f64, u_edge: Edge, v_edge: Edge) -> Color {
+        let wrap = |i: i64, size: usize, edge: Edge| match edge {
+            Edge::Repeat => i.rem_euclid(size as i64) as usize,
+            Edge::Clamp => i.clamp(0, size as i64 - 1) as usize,
+        };
+        let x = u * self.width as f64 - 0.5;
+        let y = (1. - v) * self.height as f64 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let tx = x - x0;
+        let ty = y - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let pixel = |x: i64, y: i64| &self.pixels[wrap(y, self.height, v_edge)][wrap(x, self.width, u_edge)];
+
+        let top = pixel(x0, y0) * (1. - tx) + pixel(x0 + 1, y0) * tx;
+        let bottom = pixel(x0, y0 + 1) * (1. - tx) + pixel(x0 + 1, y0 + 1) * tx;
+        top * (1. - ty) + bottom * ty
+    }
+
+    /// Returns a copy of the canvas with every pixel gamma corrected, see `Color::apply_gamma`
+    pub fn apply_gamma(&self, gamma: f64) -> Canvas {
+        self.map_pixels(|color| color.apply_gamma(gamma))
+    }
+
+    /// Returns a copy of the canvas with every pixel tone mapped, see `Color::tone_map_reinhard`
+    pub fn tone_map_reinhard(&self) -> Canvas {
+        self.map_pixels(Color::tone_map_reinhard)
+    }
+
+    /// Returns a copy of the canvas in shades of gray, each pixel set to its luminance
+    /// (see `Color::luminance`)
+    pub fn to_grayscale(&self) -> Canvas {
+        self.map_pixels(|color| {
+            let luminance = color.luminance();
+            Color::new(luminance, luminance, luminance)
+        })
+    }
+
+    /// The `width` by `height` rectangle with its top left corner at `x`, `y`.
+    /// A rectangle that goes past the edges of the canvas is clamped to fit, so
+    /// the result may be smaller than asked for (or empty).
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Canvas {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        Canvas {
+            width,
+            height,
+            pixels: self.pixels[y..y + height]
+                .iter()
+                .map(|row| row[x..x + width].to_vec())
+                .collect(),
+        }
+    }
+
+    /// Scale to `width` by `height` by copying the nearest pixel, no blending.
+    pub fn resize_nearest(&self, width: usize, height: usize) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        if self.width == 0 || self.height == 0 {
+            return canvas;
+        }
+
+        for y in 0..height {
+            // Sample at the center of each new pixel
+            let source_y = (((y as f64 + 0.5) * self.height as f64 / height as f64) as usize).min(self.height - 1);
+            for x in 0..width {
+                let source_x = (((x as f64 + 0.5) * self.width as f64 / width as f64) as usize).min(self.width - 1);
+                canvas.pixels[y][x] = self.pixels[source_y][source_x].clone();
+            }
+        }
+        canvas
+    }
+
+    fn map_pixels<F: Fn(&Color) -> Color>(&self, f: F) -> Canvas {
+        Canvas {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .pixels
+                .iter()
+                .map(|row| row.iter().map(&f).collect())
+                .collect(),
+        }
     }
 }
 
@@ -50,4 +164,111 @@ mod tests {
         let black = Color::new(0.0, 0.0, 0.0);
         assert!(canvas.pixels[2][1] == black);
     }
+
+    #[test]
+    fn test_canvas_to_rgba_bytes() {
+        let mut canvas = Canvas::new(2, 2);
+        canvas.write_pixel(1, 0, &Color::new(1.0, 0.5, 0.0));
+        canvas.write_pixel(0, 1, &Color::new(2.0, 2.0, 2.0));
+
+        let bytes = canvas.to_rgba_bytes();
+        assert_eq!(bytes.len(), 16);
+        assert!(bytes.iter().skip(3).step_by(4).all(|alpha| *alpha == 255));
+        assert_eq!(bytes[0..4], [0, 0, 0, 255]);
+        assert_eq!(bytes[4..8], [255, 128, 0, 255]);
+        assert_eq!(bytes[8..12], [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_canvas_sample_uv_edges() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(0, 0, &Color::new(1.0, 1.0, 1.0));
+
+        // Half a pixel past the left side, between the last pixel and the first
+        assert_eq!(canvas.sample_uv(0.0, 0.5), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(canvas.sample_uv_with_edges(0.0, 0.5, Edge::Clamp, Edge::Clamp), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(canvas.sample_uv_with_edges(0.25, 0.0, Edge::Repeat, Edge::Clamp), Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_canvas_tone_map_reinhard() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(0, 0, &Color::new(3.0, 1.0, 0.0));
+
+        let mapped = canvas.tone_map_reinhard();
+        assert_eq!(mapped.pixels[0][0], Color::new(0.75, 0.5, 0.0));
+        assert_eq!(mapped.pixels[0][1], Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_canvas_to_grayscale() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(0, 0, &Color::new(0.0, 1.0, 0.0));
+        canvas.write_pixel(1, 0, &Color::new(0.2, 0.4, 0.6));
+
+        let gray = canvas.to_grayscale();
+        for pixel in gray.pixels[0].iter() {
+            assert!(pixel.r == pixel.g && pixel.g == pixel.b);
+        }
+        assert_eq!(gray.pixels[0][0], Color::new(0.7152, 0.7152, 0.7152));
+        assert_eq!(gray.pixels[0][1].r, Color::new(0.2, 0.4, 0.6).luminance());
+    }
+
+    #[test]
+    fn test_canvas_apply_gamma() {
+        let mut canvas = Canvas::new(2, 1);
+        canvas.write_pixel(0, 0, &Color::new(0.5, 0.5, 0.5));
+        canvas.write_pixel(1, 0, &Color::new(1.0, 1.0, 1.0));
+
+        let corrected = canvas.apply_gamma(1.0 / 2.2);
+        assert_eq!(corrected.width, 2);
+        assert_eq!(corrected.height, 1);
+        assert_eq!(corrected.pixels[0][0], Color::new(0.72974, 0.72974, 0.72974));
+        assert_eq!(corrected.pixels[0][1], Color::new(1.0, 1.0, 1.0));
+
+        // A gamma of 1.0 leaves the canvas unchanged
+        assert_eq!(canvas.apply_gamma(1.0).pixels, canvas.pixels);
+    }
+
+    fn numbered_canvas(width: usize, height: usize) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                canvas.write_pixel(x, y, &Color::new(x as f64, y as f64, 0.));
+            }
+        }
+        canvas
+    }
+
+    #[test]
+    fn cropping_keeps_the_pixels_in_the_rectangle() {
+        let canvas = numbered_canvas(5, 4);
+        let cropped = canvas.crop(1, 2, 3, 2);
+        assert_eq!((cropped.width, cropped.height), (3, 2));
+        assert_eq!(cropped.pixels[0][0], Color::new(1., 2., 0.));
+        assert_eq!(cropped.pixels[1][2], Color::new(3., 3., 0.));
+    }
+
+    #[test]
+    fn cropping_past_the_edge_is_clamped() {
+        let canvas = numbered_canvas(5, 4);
+        let cropped = canvas.crop(3, 1, 10, 10);
+        assert_eq!((cropped.width, cropped.height), (2, 3));
+        assert_eq!(cropped.pixels[2][1], Color::new(4., 3., 0.));
+
+        let outside = canvas.crop(7, 7, 2, 2);
+        assert_eq!((outside.width, outside.height), (0, 0));
+    }
+
+    #[test]
+    fn resizing_a_2x2_to_4x4_duplicates_each_pixel() {
+        let canvas = numbered_canvas(2, 2);
+        let resized = canvas.resize_nearest(4, 4);
+        assert_eq!((resized.width, resized.height), (4, 4));
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(resized.pixels[y][x], canvas.pixels[y / 2][x / 2]);
+            }
+        }
+    }
 }
@@ -12,6 +12,154 @@ impl Color {
     pub fn new(r: f64, g: f64, b: f64) -> Color {
         Color { r, g, b }
     }
+
+    /// Create a color from a hex value like 0xFF8000, each byte is mapped into 0.0 to 1.0
+    pub fn from_hex(hex: u32) -> Color {
+        let r = (hex >> 16) & 0xFF;
+        let g = (hex >> 8) & 0xFF;
+        let b = hex & 0xFF;
+        Color::new(r as f64 / 255., g as f64 / 255., b as f64 / 255.)
+    }
+
+    /// Raise each channel to the given power. Use 1.0 / 2.2 to convert linear
+    /// light into something that looks correct on an sRGB display.
+    pub fn apply_gamma(&self, gamma: f64) -> Color {
+        // Negative values would turn into NaN, there is no light to correct anyway
+        Color {
+            r: self.r.max(0.).powf(gamma),
+            g: self.g.max(0.).powf(gamma),
+            b: self.b.max(0.).powf(gamma),
+        }
+    }
+
+    /// How bright the color looks, using the Rec. 709 weights. Green counts for
+    /// the most as our eyes are most sensitive to it.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// Hue (in degrees, 0.0 up to 360.0), saturation and value. Grays have a hue
+    /// and saturation of 0.
+    pub fn to_hsv(&self) -> (f64, f64, f64) {
+        let max = self.r.max(self.g).max(self.b);
+        let min = self.r.min(self.g).min(self.b);
+        let chroma = max - min;
+        if chroma <= 0. {
+            return (0., 0., max);
+        }
+
+        let sector = if max == self.r {
+            ((self.g - self.b) / chroma).rem_euclid(6.)
+        } else if max == self.g {
+            (self.b - self.r) / chroma + 2.
+        } else {
+            (self.r - self.g) / chroma + 4.
+        };
+        (sector * 60., chroma / max, max)
+    }
+
+    /// The inverse of `to_hsv`, the hue wraps around so -120.0 is the same as 240.0
+    pub fn from_hsv(hue: f64, saturation: f64, value: f64) -> Color {
+        let sector = hue.rem_euclid(360.) / 60.;
+        let chroma = value * saturation;
+        let x = chroma * (1. - (sector % 2. - 1.).abs());
+        let (r, g, b) = match sector as u32 {
+            0 => (chroma, x, 0.),
+            1 => (x, chroma, 0.),
+            2 => (0., chroma, x),
+            3 => (0., x, chroma),
+            4 => (x, 0., chroma),
+            _ => (chroma, 0., x),
+        };
+        let min = value - chroma;
+        Color::new(r + min, g + min, b + min)
+    }
+
+    /// Move the color towards (factor below 1) or away from (above 1) the gray
+    /// with the same luminance. 0 gives that gray and 1 leaves the color as is.
+    pub fn adjust_saturation(&self, factor: f64) -> Color {
+        let luminance = self.luminance();
+        let gray = Color::new(luminance, luminance, luminance);
+        &gray + &((self - &gray) * factor)
+    }
+
+    /// True if no channel is NaN or infinite
+    pub fn is_finite(&self) -> bool {
+        self.r.is_finite() && self.g.is_finite() && self.b.is_finite()
+    }
+
+    /// The average of the colors, skipping any with a NaN or infinite channel so
+    /// one bad sample (say from a degenerate refraction) doesn't spoil the rest.
+    /// Black if there are no good colors.
+    pub fn average<I: IntoIterator<Item = Color>>(colors: I) -> Color {
+        let mut sum = BLACK;
+        let mut count = 0;
+        for color in colors.into_iter().filter(Color::is_finite) {
+            sum = sum + color;
+            count += 1;
+        }
+        if count == 0 {
+            return BLACK;
+        }
+        sum * (1. / count as f64)
+    }
+
+    /// Convert to 8 bit channels, anything outside of 0.0 to 1.0 is clamped
+    pub fn to_rgb8(&self) -> [u8; 3] {
+        [
+            channel_to_byte(self.r),
+            channel_to_byte(self.g),
+            channel_to_byte(self.b),
+        ]
+    }
+
+    /// Scale each channel from 0.0 to 1.0 up to 0 to `max_value`, anything outside
+    /// of 0.0 to 1.0 is clamped. `to_rgb8` is the same with a `max_value` of 255.
+    pub fn to_scaled(&self, max_value: u16) -> [u16; 3] {
+        [
+            scale_channel(self.r, max_value),
+            scale_channel(self.g, max_value),
+            scale_channel(self.b, max_value),
+        ]
+    }
+
+    /// Reinhard tone mapping, c / (c + 1) for each channel. Squeezes any amount
+    /// of light into the 0.0 to 1.0 range so bright highlights roll off instead
+    /// of clipping to white.
+    pub fn tone_map_reinhard(&self) -> Color {
+        // Negative values have no light, and would divide by zero at -1
+        let map = |c: f64| {
+            let c = c.max(0.);
+            c / (c + 1.)
+        };
+        Color {
+            r: map(self.r),
+            g: map(self.g),
+            b: map(self.b),
+        }
+    }
+}
+
+fn channel_to_byte(value: f64) -> u8 {
+    scale_channel(value, 255) as u8
+}
+
+fn scale_channel(value: f64, max_value: u16) -> u16 {
+    // Values can be above 1.0, if so clamp them to the correct range for output
+    let clamped = num::clamp(value, 0.0, 1.0);
+    (clamped * max_value as f64).ceil() as u16
+}
+
+impl From<[f64; 3]> for Color {
+    fn from(rgb: [f64; 3]) -> Self {
+        Color::new(rgb[0], rgb[1], rgb[2])
+    }
+}
+
+impl From<(f64, f64, f64)> for Color {
+    fn from((r, g, b): (f64, f64, f64)) -> Self {
+        Color::new(r, g, b)
+    }
 }
 
 impl PartialEq for Color {
@@ -59,6 +207,17 @@ impl ops::Add for Color {
     }
 }
 
+impl ops::Add<&Color> for &Color {
+    type Output = Color;
+    fn add(self, other: &Color) -> Color {
+        Color {
+            r: self.r + other.r,
+            g: self.g + other.g,
+            b: self.b + other.b,
+        }
+    }
+}
+
 impl ops::Sub for Color {
     type Output = Color;
     fn sub(self, other: Color) -> Color {
@@ -70,6 +229,17 @@ impl ops::Sub for Color {
     }
 }
 
+impl ops::Sub<&Color> for &Color {
+    type Output = Color;
+    fn sub(self, other: &Color) -> Color {
+        Color {
+            r: self.r - other.r,
+            g: self.g - other.g,
+            b: self.b - other.b,
+        }
+    }
+}
+
 impl ops::Mul<f64> for Color {
     type Output = Color;
     fn mul(self, other: f64) -> Color {
@@ -167,6 +337,68 @@ mod tests {
         assert!(mathf::approximately(c.b, 0.04));
     }
 
+    #[test]
+    fn operators_on_references_match_operators_on_values() {
+        let a = Color::new(0.9, 0.6, 0.75);
+        let b = Color::new(0.7, 0.1, 0.25);
+        assert_eq!(&a + &b, a.clone() + b.clone());
+        assert_eq!(&a - &b, a.clone() - b.clone());
+        assert_eq!(&a * &b, a.clone() * b.clone());
+        assert_eq!(&a * 2.0, a.clone() * 2.0);
+    }
+
+    #[test]
+    fn averaging_skips_colors_that_are_not_finite() {
+        let valid = Color::new(0.2, 0.4, 0.6);
+        let nan = Color::new(f64::NAN, 0.5, 0.5);
+        let infinite = Color::new(0.5, f64::INFINITY, 0.5);
+        assert_eq!(Color::average(vec![nan.clone(), valid.clone(), infinite]), valid);
+        assert_eq!(Color::average(vec![valid.clone(), Color::new(0.4, 0.6, 0.8)]), Color::new(0.3, 0.5, 0.7));
+        assert_eq!(Color::average(vec![nan]), BLACK);
+        assert_eq!(Color::average(vec![]), BLACK);
+    }
+
+    #[test]
+    fn converting_to_and_from_hsv() {
+        let (h, s, v) = RED.to_hsv();
+        assert_eq!((h, s, v), (0., 1., 1.));
+        assert_eq!(Color::new(0., 0.5, 0.5).to_hsv(), (180., 1., 0.5));
+        assert_eq!(Color::new(0.4, 0.4, 0.4).to_hsv(), (0., 0., 0.4));
+        assert_eq!(Color::from_hsv(-120., 1., 1.), BLUE);
+
+        for color in [
+            Color::new(0.9, 0.6, 0.75),
+            Color::new(0.1, 0.8, 0.3),
+            Color::new(0.2, 0.3, 0.95),
+            Color::new(0.5, 0.5, 0.1),
+            Color::new(0.25, 0.25, 0.25),
+            BLACK,
+        ] {
+            let (h, s, v) = color.to_hsv();
+            assert_eq!(Color::from_hsv(h, s, v), color);
+        }
+    }
+
+    #[test]
+    fn adjusting_the_saturation() {
+        let color = Color::new(0.9, 0.2, 0.4);
+        let luminance = color.luminance();
+        assert_eq!(color.adjust_saturation(0.), Color::new(luminance, luminance, luminance));
+        assert_eq!(color.adjust_saturation(1.), color);
+
+        let (_, muted, _) = color.adjust_saturation(0.5).to_hsv();
+        let (_, saturated, _) = color.to_hsv();
+        assert!(muted < saturated);
+    }
+
+    #[test]
+    fn test_color_luminance() {
+        assert!(GREEN.luminance() > RED.luminance());
+        assert!(RED.luminance() > BLUE.luminance());
+        assert!(mathf::approximately(WHITE.luminance(), 1.0));
+        assert!(mathf::approximately(BLACK.luminance(), 0.0));
+    }
+
     #[test]
     fn test_color_equals() {
         let color = Color::new(0.5, 0.4, 1.7);
@@ -175,4 +407,47 @@ mod tests {
         assert!(color == same_color);
         assert!(color != different_color);
     }
+
+    #[test]
+    fn test_color_apply_gamma() {
+        let color = Color::new(0.5, 1.0, -0.5).apply_gamma(1.0 / 2.2);
+        assert!(mathf::approximately(color.r, 0.72974));
+        assert!(mathf::approximately(color.g, 1.0));
+        assert!(mathf::approximately(color.b, 0.0));
+    }
+
+    #[test]
+    fn test_color_to_rgb8() {
+        assert_eq!(Color::new(1.0, 0.5, 0.0).to_rgb8(), [255, 128, 0]);
+        assert_eq!(Color::new(1.5, -0.5, 0.2).to_rgb8(), [255, 0, 51]);
+    }
+
+    #[test]
+    fn test_color_to_scaled() {
+        assert_eq!(Color::new(1.0, 0.5, 0.0).to_scaled(255), [255, 128, 0]);
+        assert_eq!(Color::new(1.5, -0.5, 0.5).to_scaled(65535), [65535, 0, 32768]);
+    }
+
+    #[test]
+    fn test_color_tone_map_reinhard() {
+        let color = Color::new(3.0, 0.0, 1.0).tone_map_reinhard();
+        assert_eq!(color, Color::new(0.75, 0.0, 0.5));
+    }
+
+    #[test]
+    fn test_color_from_array_and_tuple() {
+        assert_eq!(Color::from([0.5, 0.4, 1.7]), Color::new(0.5, 0.4, 1.7));
+        let color: Color = (0.5, 0.4, 1.7).into();
+        assert_eq!(color, Color::new(0.5, 0.4, 1.7));
+    }
+
+    #[test]
+    fn test_color_from_hex() {
+        assert_eq!(Color::from_hex(0xFF0000), RED);
+        assert_eq!(Color::from_hex(0x00FF00), GREEN);
+        assert_eq!(Color::from_hex(0x0000FF), BLUE);
+        assert_eq!(Color::from_hex(0xFFFFFF), WHITE);
+        assert_eq!(Color::from_hex(0x000000), BLACK);
+        assert_eq!(Color::from_hex(0x336699), Color::new(0.2, 0.4, 0.6));
+    }
 }
@@ -0,0 +1,76 @@
+use crate::color::Color;
+use crate::light::Light;
+use crate::mathf::vector3::Vector3;
+
+/// A light infinitely far away, like the sun. All of its rays are parallel
+/// and it is equally bright everywhere.
+#[derive(Debug)]
+pub struct DirectionalLight {
+    /// The direction the light travels in
+    pub direction: Vector3,
+    pub intensity: Color,
+    /// When false nothing blocks this light, see `Light::casts_shadows`
+    pub casts_shadows: bool,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vector3, intensity: Color) -> DirectionalLight {
+        DirectionalLight {
+            direction: direction.normalize(),
+            intensity,
+            casts_shadows: true,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn intensity(&self) -> &Color {
+        &self.intensity
+    }
+
+    fn direction_from(&self, _point: &Vector3) -> Vector3 {
+        -self.direction.clone()
+    }
+
+    fn distance_from(&self, _point: &Vector3) -> f64 {
+        f64::INFINITY
+    }
+
+    fn positions_to_sample(&self) -> Vec<Vector3> {
+        vec![]
+    }
+
+    fn casts_shadows(&self) -> bool {
+        self.casts_shadows
+    }
+}
+
+impl PartialEq for DirectionalLight {
+    fn eq(&self, other: &Self) -> bool {
+        self.direction == other.direction
+            && self.intensity == other.intensity
+            && self.casts_shadows == other.casts_shadows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_directional_light_has_a_direction_and_intensity() {
+        let light = DirectionalLight::new(Vector3::new(0., -2., 0.), Color::new(1., 1., 1.));
+        assert_eq!(light.direction, Vector3::new(0., -1., 0.));
+        assert_eq!(light.intensity, Color::new(1., 1., 1.));
+    }
+
+    #[test]
+    fn the_light_vector_is_the_same_everywhere() {
+        let light = DirectionalLight::new(Vector3::new(1., -1., 0.), Color::new(1., 1., 1.));
+        let expected = Vector3::new(-(2f64.sqrt()) / 2., 2f64.sqrt() / 2., 0.);
+
+        assert_eq!(light.direction_from(&Vector3::new(0., 0., 0.)), expected);
+        assert_eq!(light.direction_from(&Vector3::new(100., -50., 3.)), expected);
+        assert_eq!(light.distance_from(&Vector3::new(100., -50., 3.)), f64::INFINITY);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::canvas::{Canvas, Edge};
+use crate::color::Color;
+use crate::mathf::vector3::Vector3;
+use crate::patterns::cube_map::cube_face_uv;
+use crate::png_encoder;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Supplies the color seen by rays that escape the scene without hitting anything.
+pub trait Environment: fmt::Debug + Send + Sync {
+    fn sample(&self, direction: &Vector3) -> Color;
+}
+
+/// A vertical gradient, the horizon color at the bottom blending into the
+/// zenith color straight up.
+#[derive(Debug)]
+pub struct GradientSky {
+    pub zenith: Color,
+    pub horizon: Color,
+}
+
+impl GradientSky {
+    pub fn new(zenith: Color, horizon: Color) -> GradientSky {
+        GradientSky { zenith, horizon }
+    }
+}
+
+impl Environment for GradientSky {
+    fn sample(&self, direction: &Vector3) -> Color {
+        let t = (direction.normalize().y + 1.) / 2.;
+        &self.horizon * (1. - t) + &self.zenith * t
+    }
+}
+
+/// Wraps an equirectangular image (like a panorama photo) around the scene.
+/// The center of the image is straight down -z, the top row is straight up.
+pub struct ImageEnvironment {
+    pub image: Canvas,
+}
+
+impl fmt::Debug for ImageEnvironment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ImageEnvironment")
+            .field("width", &self.image.width)
+            .field("height", &self.image.height)
+            .finish()
+    }
+}
+
+impl ImageEnvironment {
+    pub fn new(image: Canvas) -> ImageEnvironment {
+        ImageEnvironment { image }
+    }
+
+    pub fn from_png(file_path: &str) -> Result<ImageEnvironment, png::DecodingError> {
+        Ok(ImageEnvironment::new(png_encoder::load_canvas_from_png(file_path)?))
+    }
+}
+
+impl Environment for ImageEnvironment {
+    fn sample(&self, direction: &Vector3) -> Color {
+        let direction = direction.normalize();
+
+        // Longitude around the y axis and latitude down from straight up, both 0 to 1
+        let u = 0.5 + direction.x.atan2(-direction.z) / (2. * PI);
+        let v = direction.y.clamp(-1., 1.).acos() / PI;
+
+        // Wrap around horizontally and stop at the top and bottom rows
+        self.image.sample_uv_with_edges(u, 1. - v, Edge::Repeat, Edge::Clamp)
+    }
+}
+
+/// Six square images on the faces of a cube around the scene, the classic skybox.
+/// Each face is drawn as seen from inside the cube with +y up, except the top and
+/// bottom faces which have -z and +z at the top of the image.
+pub struct CubeMapEnvironment {
+    /// The faces in the order +x, -x, +y, -y, +z, -z
+    pub faces: [Canvas; 6],
+}
+
+impl fmt::Debug for CubeMapEnvironment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sizes: Vec<(usize, usize)> = self.faces.iter().map(|face| (face.width, face.height)).collect();
+        f.debug_struct("CubeMapEnvironment").field("face_sizes", &sizes).finish()
+    }
+}
+
+impl CubeMapEnvironment {
+    pub fn new(faces: [Canvas; 6]) -> CubeMapEnvironment {
+        CubeMapEnvironment { faces }
+    }
+
+    /// Load the faces from PNG files given in the order +x, -x, +y, -y, +z, -z
+    pub fn from_png(file_paths: [&str; 6]) -> Result<CubeMapEnvironment, png::DecodingError> {
+        let [px, nx, py, ny, pz, nz] = file_paths;
+        Ok(CubeMapEnvironment::new([
+            png_encoder::load_canvas_from_png(px)?,
+            png_encoder::load_canvas_from_png(nx)?,
+            png_encoder::load_canvas_from_png(py)?,
+            png_encoder::load_canvas_from_png(ny)?,
+            png_encoder::load_canvas_from_png(pz)?,
+            png_encoder::load_canvas_from_png(nz)?,
+        ]))
+    }
+}
+
+impl Environment for CubeMapEnvironment {
+    fn sample(&self, direction: &Vector3) -> Color {
+        let (face, u, v) = cube_face_uv(direction);
+        // Stop at the edges of the face, the neighboring face isn't next to it in the image
+        self.faces[face].sample_uv_with_edges(u, v, Edge::Clamp, Edge::Clamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looking_straight_up_returns_the_zenith_color() {
+        let sky = GradientSky::new(Color::new(0.2, 0.4, 1.0), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(sky.sample(&Vector3::new(0., 1., 0.)), Color::new(0.2, 0.4, 1.0));
+        assert_eq!(sky.sample(&Vector3::new(0., 5., 0.)), Color::new(0.2, 0.4, 1.0));
+    }
+
+    #[test]
+    fn looking_at_the_horizon_returns_the_midpoint() {
+        let sky = GradientSky::new(Color::new(0.2, 0.4, 1.0), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(sky.sample(&Vector3::new(1., 0., 0.)), Color::new(0.6, 0.7, 1.0));
+        assert_eq!(sky.sample(&Vector3::new(0., 0., -1.)), Color::new(0.6, 0.7, 1.0));
+    }
+
+    #[test]
+    fn looking_straight_down_returns_the_horizon_color() {
+        let sky = GradientSky::new(Color::new(0.2, 0.4, 1.0), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(sky.sample(&Vector3::new(0., -1., 0.)), Color::new(1.0, 1.0, 1.0));
+    }
+
+    fn directions() -> Vec<Vector3> {
+        vec![
+            Vector3::new(0., 1., 0.),
+            Vector3::new(0., -1., 0.),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(-1., 0., 0.),
+            Vector3::new(0., 0., 1.),
+            Vector3::new(0., 0., -1.),
+            Vector3::new(0.3, -0.7, 0.2),
+        ]
+    }
+
+    #[test]
+    fn a_solid_color_image_samples_the_same_in_every_direction() {
+        let mut image = Canvas::new(2, 1);
+        image.write_pixel(0, 0, &Color::new(1.0, 0.6, 0.0));
+        image.write_pixel(1, 0, &Color::new(1.0, 0.6, 0.0));
+        let path = std::env::temp_dir().join("ray_tracer_solid_color_environment.png");
+        let path = path.to_str().unwrap().to_string();
+        png_encoder::save_canvas_to_png(&image, path.clone());
+
+        let environment = ImageEnvironment::from_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for direction in directions() {
+            assert_eq!(environment.sample(&direction), Color::new(1.0, 0.6, 0.0));
+        }
+    }
+
+    #[test]
+    fn the_center_of_the_image_is_straight_ahead() {
+        // Four columns, each a different color. Looking down -z is the seam between
+        // the middle two, looking down +z is the seam between the first and last.
+        let mut image = Canvas::new(4, 1);
+        image.write_pixel(0, 0, &Color::new(1., 0., 0.));
+        image.write_pixel(1, 0, &Color::new(0., 1., 0.));
+        image.write_pixel(2, 0, &Color::new(0., 0., 1.));
+        image.write_pixel(3, 0, &Color::new(1., 1., 1.));
+        let environment = ImageEnvironment::new(image);
+
+        assert_eq!(environment.sample(&Vector3::new(0., 0., -1.)), Color::new(0., 0.5, 0.5));
+        assert_eq!(environment.sample(&Vector3::new(0., 0., 1.)), Color::new(1., 0.5, 0.5));
+        assert_eq!(environment.sample(&Vector3::new(1., 0., 0.)), Color::new(0.5, 0.5, 1.));
+    }
+
+    fn solid_face(color: &Color) -> Canvas {
+        let mut face = Canvas::new(4, 4);
+        for y in 0..4 {
+            for x in 0..4 {
+                face.write_pixel(x, y, color);
+            }
+        }
+        face
+    }
+
+    // Each pixel holds where it is on the face, u in red and v in green, and blue
+    // tells the faces apart. Blending pixels keeps that, so a sample shows where
+    // on which face it came from.
+    fn position_face(blue: f64) -> Canvas {
+        let size = 100;
+        let mut face = Canvas::new(size, size);
+        for y in 0..size {
+            for x in 0..size {
+                let u = (x as f64 + 0.5) / size as f64;
+                let v = 1. - (y as f64 + 0.5) / size as f64;
+                face.write_pixel(x, y, &Color::new(u, v, blue));
+            }
+        }
+        face
+    }
+
+    #[test]
+    fn each_axis_samples_its_own_face() {
+        let right = Color::new(1., 0., 0.);
+        let left = Color::new(0., 1., 0.);
+        let up = Color::new(0., 0., 1.);
+        let down = Color::new(1., 1., 0.);
+        let front = Color::new(0., 1., 1.);
+        let back = Color::new(1., 0., 1.);
+        let environment = CubeMapEnvironment::new([
+            solid_face(&right),
+            solid_face(&left),
+            solid_face(&up),
+            solid_face(&down),
+            solid_face(&front),
+            solid_face(&back),
+        ]);
+
+        assert_eq!(environment.sample(&Vector3::new(1., 0., 0.)), right);
+        assert_eq!(environment.sample(&Vector3::new(-2., 0., 0.)), left);
+        assert_eq!(environment.sample(&Vector3::new(0., 1., 0.)), up);
+        assert_eq!(environment.sample(&Vector3::new(0.3, -0.7, 0.2)), down);
+        assert_eq!(environment.sample(&Vector3::new(0., 0., 1.)), front);
+        assert_eq!(environment.sample(&Vector3::new(0., 0., -1.)), back);
+    }
+
+    #[test]
+    fn the_edges_of_a_face_meet_the_right_neighbors() {
+        let (right, up, down, front) = (0.1, 0.3, 0.4, 0.5);
+        let environment = CubeMapEnvironment::new([
+            position_face(right),
+            position_face(0.2),
+            position_face(up),
+            position_face(down),
+            position_face(front),
+            position_face(0.6),
+        ]);
+        let sample = |x: f64, y: f64, z: f64| environment.sample(&Vector3::new(x, y, z));
+
+        // Straight along +x is the middle of its face
+        assert_eq!(sample(1., 0., 0.), Color::new(0.5, 0.5, right));
+
+        // Either side of the edge between +x and +z
+        assert_eq!(sample(1., 0., 0.99), Color::new(0.005, 0.5, right));
+        assert_eq!(sample(0.99, 0., 1.), Color::new(0.995, 0.5, front));
+
+        // Either side of the edge between +x and +y
+        assert_eq!(sample(1., 0.99, 0.), Color::new(0.5, 0.995, right));
+        assert_eq!(sample(0.99, 1., 0.), Color::new(0.995, 0.5, up));
+
+        // Either side of the edge between -y and +z
+        assert_eq!(sample(0., -1., 0.99), Color::new(0.5, 0.995, down));
+        assert_eq!(sample(0., -0.99, 1.), Color::new(0.5, 0.005, front));
+    }
+}
@@ -0,0 +1,82 @@
+//! Helpers for comparing rendered images, mostly for catching visual
+//! regressions in tests.
+
+use crate::canvas::Canvas;
+
+/// The largest difference in any one color channel of any pixel, 0.0 if the
+/// canvases are identical. Panics if the canvases aren't the same size.
+pub fn canvas_max_channel_diff(a: &Canvas, b: &Canvas) -> f64 {
+    worst_pixel(a, b).map_or(0., |(_, _, diff)| diff)
+}
+
+/// Panics if any color channel of any pixel differs by more than `tolerance`.
+/// The message names the pixel that differs the most.
+pub fn assert_canvases_close(a: &Canvas, b: &Canvas, tolerance: f64) {
+    if let Some((x, y, diff)) = worst_pixel(a, b) {
+        if diff > tolerance {
+            panic!(
+                "canvases differ by {} at pixel ({}, {}), expected at most {}: {:?} != {:?}",
+                diff, x, y, tolerance, a.pixels[y][x], b.pixels[y][x]
+            );
+        }
+    }
+}
+
+// The x, y and channel difference of the pixel that differs the most
+fn worst_pixel(a: &Canvas, b: &Canvas) -> Option<(usize, usize, f64)> {
+    assert!(
+        a.width == b.width && a.height == b.height,
+        "canvases are different sizes: {}x{} and {}x{}",
+        a.width,
+        a.height,
+        b.width,
+        b.height
+    );
+
+    let mut worst = None;
+    for (y, (row_a, row_b)) in a.pixels.iter().zip(b.pixels.iter()).enumerate() {
+        for (x, (pa, pb)) in row_a.iter().zip(row_b.iter()).enumerate() {
+            let diff = (pa.r - pb.r).abs().max((pa.g - pb.g).abs()).max((pa.b - pb.b).abs());
+            match worst {
+                Some((_, _, max)) if max >= diff => {}
+                _ => worst = Some((x, y, diff)),
+            }
+        }
+    }
+    worst
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn identical_canvases_have_no_difference() {
+        let mut a = Canvas::new(3, 2);
+        let mut b = Canvas::new(3, 2);
+        a.write_pixel(1, 1, &Color::new(0.2, 0.4, 0.6));
+        b.write_pixel(1, 1, &Color::new(0.2, 0.4, 0.6));
+        assert_eq!(canvas_max_channel_diff(&a, &b), 0.);
+        assert_canvases_close(&a, &b, 0.);
+    }
+
+    #[test]
+    fn the_difference_is_the_largest_channel_change() {
+        let a = Canvas::new(3, 2);
+        let mut b = Canvas::new(3, 2);
+        b.write_pixel(2, 1, &Color::new(0.1, 0.5, 0.25));
+        assert_eq!(canvas_max_channel_diff(&a, &b), 0.5);
+        assert_eq!(worst_pixel(&a, &b), Some((2, 1, 0.5)));
+    }
+
+    #[test]
+    #[should_panic(expected = "at pixel (2, 1)")]
+    fn canvases_that_differ_too_much_panic_with_the_worst_pixel() {
+        let a = Canvas::new(3, 2);
+        let mut b = Canvas::new(3, 2);
+        b.write_pixel(0, 0, &Color::new(0.05, 0., 0.));
+        b.write_pixel(2, 1, &Color::new(0.1, 0.5, 0.25));
+        assert_canvases_close(&a, &b, 0.1);
+    }
+}
@@ -0,0 +1,87 @@
+//! Saving a canvas without having to pick the encoder by hand
+
+use crate::canvas::Canvas;
+use crate::png_encoder;
+use crate::ppm;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum SaveError {
+    /// The file extension isn't one of the formats that can be written
+    UnknownFormat(String),
+    Io(std::io::Error),
+    Png(png::EncodingError),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::UnknownFormat(path) => {
+                write!(
+                    f,
+                    "can't tell the image format of {}, use .png or .ppm",
+                    path
+                )
+            }
+            SaveError::Io(error) => write!(f, "failed to write image: {}", error),
+            SaveError::Png(error) => write!(f, "failed to write png: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+/// Save the canvas as a PNG or PPM, picked by the extension of the path
+/// (`.png` or `.ppm`, in any case).
+pub fn save_image(canvas: &Canvas, file_path: &str) -> Result<(), SaveError> {
+    let extension = Path::new(file_path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("png") => png_encoder::write_canvas_to_png(canvas, file_path).map_err(SaveError::Png),
+        Some("ppm") => std::fs::write(file_path, ppm::canvas_to_ppm(canvas)).map_err(SaveError::Io),
+        _ => Err(SaveError::UnknownFormat(file_path.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved_bytes(file_name: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(file_name);
+        let path = path.to_str().unwrap();
+        save_image(&Canvas::new(3, 2), path).unwrap();
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn the_extension_picks_the_format() {
+        assert_eq!(
+            &saved_bytes("ray_tracer_save_image.png")[..8],
+            b"\x89PNG\r\n\x1a\n"
+        );
+        assert_eq!(&saved_bytes("ray_tracer_save_image.ppm")[..3], b"P3\n");
+        assert_eq!(
+            &saved_bytes("ray_tracer_save_image_upper.PNG")[..4],
+            b"\x89PNG"
+        );
+    }
+
+    #[test]
+    fn an_unknown_extension_is_an_error() {
+        let canvas = Canvas::new(3, 2);
+        for path in ["image.jpg", "image", "png"] {
+            assert!(matches!(
+                save_image(&canvas, path),
+                Err(SaveError::UnknownFormat(_))
+            ));
+            assert!(!Path::new(path).exists());
+        }
+    }
+}
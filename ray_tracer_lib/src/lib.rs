@@ -1,12 +1,24 @@
+pub mod area_light;
 pub mod camera;
 pub mod canvas;
 pub mod color;
+pub mod directional_light;
+pub mod environment;
+pub mod image_diff;
+pub mod io;
+pub mod light;
 pub mod material;
 pub mod mathf;
+pub mod obj_file;
+pub mod patterns;
 pub mod phong_lighting;
 pub mod png_encoder;
 pub mod point_light;
 pub mod ppm;
+pub mod render_stats;
+pub mod rng;
+pub mod sampler;
+pub mod scene;
 pub mod transformations;
 pub mod world;
 
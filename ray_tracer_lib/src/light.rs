@@ -0,0 +1,34 @@
+use crate::color::Color;
+use crate::mathf::vector3::Vector3;
+use crate::render_stats::RenderCounters;
+use crate::world::World;
+use std::fmt;
+
+/// Anything that can illuminate a surface.
+pub trait Light: fmt::Debug + Send + Sync {
+    fn intensity(&self) -> &Color;
+
+    /// Unit vector pointing from the point towards the light
+    fn direction_from(&self, point: &Vector3) -> Vector3;
+
+    /// Distance from the point to the light, infinite if the light has no position
+    fn distance_from(&self, point: &Vector3) -> f64;
+
+    /// The points shadow rays are cast towards. A light with a single position
+    /// returns just that, a light with no position (like the sun) returns nothing.
+    fn positions_to_sample(&self) -> Vec<Vector3>;
+
+    /// The fraction of the light that reaches the point, 0 when it is fully in
+    /// shadow and 1 when nothing is in the way. By default a shadow ray is cast to
+    /// each of `positions_to_sample`, see `World::light_visibility`. Shading goes
+    /// through this, so a light can override it to be blocked some other way.
+    fn intensity_at(&self, point: &Vector3, world: &World, counters: Option<&RenderCounters>) -> f64 {
+        world.light_visibility(self, point, counters)
+    }
+
+    /// False for a light that shines through everything, like a fill light that
+    /// should brighten the scene without adding shadows of its own.
+    fn casts_shadows(&self) -> bool {
+        true
+    }
+}
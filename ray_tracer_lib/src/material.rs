@@ -1,5 +1,48 @@
+use crate::color;
 use crate::color::Color;
 use crate::mathf;
+use crate::patterns::normal_map::NormalMap;
+use crate::patterns::perturb::Perturbation;
+use crate::patterns::{self, Pattern};
+use std::sync::{Arc, RwLock};
+
+/// How the specular highlight is worked out.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LightingModel {
+    /// Compares the reflected light direction with the eye direction
+    #[default]
+    Phong,
+    /// Compares the vector halfway between the light and the eye with the normal.
+    /// Highlights are a little wider and don't cut off at grazing angles.
+    BlinnPhong,
+}
+
+/// Refractive indices of some common materials, so they don't need to be looked
+/// up. `Material::builder().refractive_index(IndexOfRefraction::Water.value())`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndexOfRefraction {
+    Vacuum,
+    Air,
+    Water,
+    Glass,
+    Diamond,
+}
+
+impl IndexOfRefraction {
+    pub fn value(self) -> f64 {
+        match self {
+            IndexOfRefraction::Vacuum => 1.0,
+            IndexOfRefraction::Air => 1.00029,
+            IndexOfRefraction::Water => 1.333,
+            IndexOfRefraction::Glass => 1.52,
+            IndexOfRefraction::Diamond => 2.417,
+        }
+    }
+}
+
+/// A material that can be used by many shapes at once.
+/// Changes made through the lock are seen by every shape sharing it.
+pub type SharedMaterial = Arc<RwLock<Material>>;
 
 #[derive(Debug, Clone)]
 pub struct Material {
@@ -8,6 +51,29 @@ pub struct Material {
     pub diffuse: f64,
     pub specular: f64,
     pub shininess: f64,
+    pub reflective: f64,
+    pub transparency: f64,
+    pub refractive_index: f64,
+    /// Light given off by the surface itself, added once whatever the lights and
+    /// shadows. Black (the default) for surfaces that don't glow.
+    pub emission: Color,
+    /// When set the pattern is used for the color instead of `color`
+    pub pattern: Option<Arc<dyn Pattern>>,
+    /// When set the surface normal is jiggled to fake a bumpy surface. With a
+    /// normal map as well the bumps are added on top of the mapped normal.
+    pub bump: Option<Perturbation>,
+    /// When set the surface normal is taken from an image, see `NormalMap`
+    pub normal_map: Option<Arc<NormalMap>>,
+    pub lighting_model: LightingModel,
+    /// How far past the terminator (where the surface turns away from the light)
+    /// diffuse and specular light fade in, as the cosine of the angle to the light.
+    /// 0 (the default) switches them on sharply, a small value like 0.1 softens
+    /// highlights that would otherwise pop at the edge of the lit side.
+    pub terminator_ramp: f64,
+    /// How far reflection rays are scattered from the mirror direction, 0 (the
+    /// default) for a perfect mirror. A small value like 0.05 looks like brushed
+    /// metal, the scattering is noisy so use it with multi-sampling.
+    pub reflective_fuzz: f64,
 }
 
 impl Default for Material {
@@ -24,8 +90,147 @@ impl Material {
             diffuse: 0.9,
             specular: 0.9,
             shininess: 200.0,
+            reflective: 0.0,
+            transparency: 0.0,
+            refractive_index: 1.0,
+            emission: color::BLACK,
+            pattern: None,
+            bump: None,
+            normal_map: None,
+            lighting_model: LightingModel::default(),
+            terminator_ramp: 0.0,
+            reflective_fuzz: 0.0,
         }
     }
+
+    /// Clear glass, water and the like, with the given refractive index (see
+    /// `IndexOfRefraction`). Fully reflective and fully transparent, so the
+    /// Fresnel effect decides how much of each is seen. The surface itself is
+    /// black apart from the specular highlight.
+    pub fn dielectric(refractive_index: f64) -> Material {
+        Material {
+            color: color::BLACK,
+            ambient: 0.0,
+            diffuse: 0.0,
+            specular: 1.0,
+            shininess: 300.0,
+            reflective: 1.0,
+            transparency: 1.0,
+            refractive_index,
+            ..Material::new()
+        }
+    }
+
+    /// Start building a material from the defaults, see `MaterialBuilder`
+    pub fn builder() -> MaterialBuilder {
+        MaterialBuilder {
+            material: Material::new(),
+        }
+    }
+
+    /// Wrap the material so it can be shared between shapes
+    pub fn shared(self) -> SharedMaterial {
+        Arc::new(RwLock::new(self))
+    }
+}
+
+/// Chainable setters for a `Material`, anything not set keeps the value from `Material::new()`
+///
+/// ```
+/// use ray_tracer_lib::color::Color;
+/// use ray_tracer_lib::material::Material;
+///
+/// let material = Material::builder()
+///     .color(Color::new(0.1, 1., 0.5))
+///     .diffuse(0.7)
+///     .specular(0.3)
+///     .build();
+/// assert_eq!(material.diffuse, 0.7);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MaterialBuilder {
+    material: Material,
+}
+
+impl MaterialBuilder {
+    pub fn color(mut self, color: Color) -> Self {
+        self.material.color = color;
+        self
+    }
+
+    pub fn ambient(mut self, ambient: f64) -> Self {
+        self.material.ambient = ambient;
+        self
+    }
+
+    pub fn diffuse(mut self, diffuse: f64) -> Self {
+        self.material.diffuse = diffuse;
+        self
+    }
+
+    pub fn specular(mut self, specular: f64) -> Self {
+        self.material.specular = specular;
+        self
+    }
+
+    pub fn shininess(mut self, shininess: f64) -> Self {
+        self.material.shininess = shininess;
+        self
+    }
+
+    pub fn reflective(mut self, reflective: f64) -> Self {
+        self.material.reflective = reflective;
+        self
+    }
+
+    pub fn transparency(mut self, transparency: f64) -> Self {
+        self.material.transparency = transparency;
+        self
+    }
+
+    pub fn refractive_index(mut self, refractive_index: f64) -> Self {
+        self.material.refractive_index = refractive_index;
+        self
+    }
+
+    pub fn emission(mut self, emission: Color) -> Self {
+        self.material.emission = emission;
+        self
+    }
+
+    pub fn pattern(mut self, pattern: Arc<dyn Pattern>) -> Self {
+        self.material.pattern = Some(pattern);
+        self
+    }
+
+    pub fn bump(mut self, bump: Perturbation) -> Self {
+        self.material.bump = Some(bump);
+        self
+    }
+
+    pub fn normal_map(mut self, normal_map: Arc<NormalMap>) -> Self {
+        self.material.normal_map = Some(normal_map);
+        self
+    }
+
+    pub fn lighting_model(mut self, lighting_model: LightingModel) -> Self {
+        self.material.lighting_model = lighting_model;
+        self
+    }
+
+    pub fn terminator_ramp(mut self, terminator_ramp: f64) -> Self {
+        self.material.terminator_ramp = terminator_ramp;
+        self
+    }
+
+    pub fn reflective_fuzz(mut self, reflective_fuzz: f64) -> Self {
+        self.material.reflective_fuzz = reflective_fuzz;
+        self
+    }
+
+    pub fn build(self) -> Material {
+        self.material
+    }
 }
 
 impl PartialEq for Material {
@@ -35,6 +240,24 @@ impl PartialEq for Material {
             && mathf::approximately(self.diffuse, other.diffuse)
             && mathf::approximately(self.specular, other.specular)
             && mathf::approximately(self.shininess, other.shininess)
+            && mathf::approximately(self.reflective, other.reflective)
+            && mathf::approximately(self.transparency, other.transparency)
+            && mathf::approximately(self.refractive_index, other.refractive_index)
+            && self.emission == other.emission
+            && match (&self.pattern, &other.pattern) {
+                (None, None) => true,
+                (Some(a), Some(b)) => patterns::same_pattern(a.as_ref(), b.as_ref()),
+                _ => false,
+            }
+            && self.bump == other.bump
+            && match (&self.normal_map, &other.normal_map) {
+                (None, None) => true,
+                (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+                _ => false,
+            }
+            && self.lighting_model == other.lighting_model
+            && mathf::approximately(self.terminator_ramp, other.terminator_ramp)
+            && mathf::approximately(self.reflective_fuzz, other.reflective_fuzz)
     }
 }
 
@@ -50,5 +273,64 @@ mod tests {
         assert_eq!(material.diffuse, 0.9);
         assert_eq!(material.specular, 0.9);
         assert_eq!(material.shininess, 200.0);
+        assert_eq!(material.reflective, 0.0);
+        assert_eq!(material.transparency, 0.0);
+        assert_eq!(material.refractive_index, 1.0);
+        assert_eq!(material.emission, color::BLACK);
+        assert!(material.pattern.is_none());
+        assert!(material.bump.is_none());
+        assert!(material.normal_map.is_none());
+        assert_eq!(material.lighting_model, LightingModel::Phong);
+        assert_eq!(material.terminator_ramp, 0.0);
+        assert_eq!(material.reflective_fuzz, 0.0);
+    }
+
+    #[test]
+    fn common_refractive_indices() {
+        assert!(mathf::approximately(IndexOfRefraction::Diamond.value(), 2.417));
+        assert!(mathf::approximately(IndexOfRefraction::Water.value(), 1.333));
+        assert_eq!(IndexOfRefraction::Vacuum.value(), Material::new().refractive_index);
+
+        let material = Material::builder()
+            .refractive_index(IndexOfRefraction::Glass.value())
+            .build();
+        assert_eq!(material.refractive_index, 1.52);
+    }
+
+    #[test]
+    fn a_dielectric_is_clear_and_reflective() {
+        let glass = Material::dielectric(IndexOfRefraction::Glass.value());
+        assert_eq!(glass.reflective, 1.0);
+        assert_eq!(glass.transparency, 1.0);
+        assert_eq!(glass.refractive_index, 1.52);
+        assert_eq!(glass.ambient, 0.0);
+        assert_eq!(glass.diffuse, 0.0);
+        assert!(glass.pattern.is_none());
+    }
+
+    #[test]
+    fn building_a_material() {
+        let material = Material::builder()
+            .color(Color::new(0.1, 1., 0.5))
+            .diffuse(0.7)
+            .specular(0.3)
+            .reflective(0.2)
+            .build();
+
+        assert_eq!(material.color, Color::new(0.1, 1., 0.5));
+        assert_eq!(material.diffuse, 0.7);
+        assert_eq!(material.specular, 0.3);
+        assert_eq!(material.reflective, 0.2);
+
+        // Anything not set keeps its default
+        let default = Material::new();
+        assert_eq!(material.ambient, default.ambient);
+        assert_eq!(material.shininess, default.shininess);
+        assert_eq!(material.transparency, default.transparency);
+        assert_eq!(material.refractive_index, default.refractive_index);
+        assert!(material.pattern.is_none());
+        assert!(material.bump.is_none());
+
+        assert_eq!(Material::builder().build(), default);
     }
 }
@@ -0,0 +1,191 @@
+use crate::mathf::matrix::Matrix;
+use crate::mathf::ray::Ray;
+use crate::mathf::vector3::Vector3;
+
+/// An axis aligned box that contains a shape. Checking a ray against the box is
+/// cheap, so shapes whose box is missed don't need to be intersected at all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundingBox {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl BoundingBox {
+    pub fn new(min: Vector3, max: Vector3) -> BoundingBox {
+        BoundingBox { min, max }
+    }
+
+    /// A box that contains nothing, adding a point to it gives a box around just that point
+    pub fn empty() -> BoundingBox {
+        BoundingBox {
+            min: Vector3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vector3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    /// A box that contains everything, for shapes like planes that go on forever
+    pub fn infinite() -> BoundingBox {
+        BoundingBox {
+            min: Vector3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            max: Vector3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn add_point(&mut self, point: &Vector3) {
+        self.min = Vector3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z));
+        self.max = Vector3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z));
+    }
+
+    pub fn merge(&mut self, other: &BoundingBox) {
+        if !other.is_empty() {
+            self.add_point(&other.min);
+            self.add_point(&other.max);
+        }
+    }
+
+    pub fn contains_point(&self, point: &Vector3) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x)
+            && (self.min.y..=self.max.y).contains(&point.y)
+            && (self.min.z..=self.max.z).contains(&point.z)
+    }
+
+    /// The box around this one after it is transformed, found by transforming all
+    /// eight corners. The result may be larger than needed, for example a rotated box.
+    pub fn transform(&self, matrix: &Matrix) -> BoundingBox {
+        if self.is_empty() {
+            return self.clone();
+        }
+
+        let corners = [
+            (self.min.x, self.min.y, self.min.z),
+            (self.min.x, self.min.y, self.max.z),
+            (self.min.x, self.max.y, self.min.z),
+            (self.min.x, self.max.y, self.max.z),
+            (self.max.x, self.min.y, self.min.z),
+            (self.max.x, self.min.y, self.max.z),
+            (self.max.x, self.max.y, self.min.z),
+            (self.max.x, self.max.y, self.max.z),
+        ];
+
+        let mut result = BoundingBox::empty();
+        for corner in corners.iter() {
+            let point = matrix.multiply_point(&Vector3::from(*corner));
+            // An infinite side multiplied by 0 is NaN, give up and treat it as infinite
+            if !(point.x.is_finite() && point.y.is_finite() && point.z.is_finite()) {
+                return BoundingBox::infinite();
+            }
+            result.add_point(&point);
+        }
+        result
+    }
+
+    /// True if the ray passes through the box somewhere between t=0 and `max_t`
+    pub fn intersects(&self, ray: &Ray, max_t: f64) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        let (xmin, xmax) = check_axis(ray.origin.x, ray.direction.x, self.min.x, self.max.x);
+        let (ymin, ymax) = check_axis(ray.origin.y, ray.direction.y, self.min.y, self.max.y);
+        let (zmin, zmax) = check_axis(ray.origin.z, ray.direction.z, self.min.z, self.max.z);
+
+        let tmin = xmin.max(ymin).max(zmin).max(0.);
+        let tmax = xmax.min(ymax).min(zmax).min(max_t);
+        tmin <= tmax
+    }
+}
+
+// The range of t where the ray is between the two planes of a slab
+fn check_axis(origin: f64, direction: f64, min: f64, max: f64) -> (f64, f64) {
+    if direction == 0. {
+        // Parallel to the slab, either always inside it or never
+        return if origin >= min && origin <= max {
+            (f64::NEG_INFINITY, f64::INFINITY)
+        } else {
+            (f64::INFINITY, f64::NEG_INFINITY)
+        };
+    }
+
+    let t1 = (min - origin) / direction;
+    let t2 = (max - origin) / direction;
+    if t1 > t2 {
+        (t2, t1)
+    } else {
+        (t1, t2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transformations;
+    use std::f64::consts::PI;
+
+    fn unit_box() -> BoundingBox {
+        BoundingBox::new(Vector3::new(-1., -1., -1.), Vector3::new(1., 1., 1.))
+    }
+
+    #[test]
+    fn adding_points_to_an_empty_bounding_box() {
+        let mut bounds = BoundingBox::empty();
+        assert!(bounds.is_empty());
+        bounds.add_point(&Vector3::new(-5., 2., 0.));
+        bounds.add_point(&Vector3::new(7., 0., -3.));
+        assert_eq!(bounds.min, Vector3::new(-5., 0., -3.));
+        assert_eq!(bounds.max, Vector3::new(7., 2., 0.));
+    }
+
+    #[test]
+    fn merging_bounding_boxes() {
+        let mut a = BoundingBox::new(Vector3::new(-5., -2., 0.), Vector3::new(7., 4., 4.));
+        a.merge(&BoundingBox::new(Vector3::new(8., -7., -2.), Vector3::new(14., 2., 8.)));
+        assert_eq!(a.min, Vector3::new(-5., -7., -2.));
+        assert_eq!(a.max, Vector3::new(14., 4., 8.));
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn transforming_a_bounding_box() {
+        let bounds = unit_box();
+        let matrix = transformations::rotation_x(PI / 4.)
+            .multiply_4x4(&transformations::rotation_y(PI / 4.));
+        let result = bounds.transform(&matrix);
+        assert_eq!(result.min, Vector3::new(-1.41421, -1.70711, -1.70711));
+        assert_eq!(result.max, Vector3::new(1.41421, 1.70711, 1.70711));
+    }
+
+    #[test]
+    fn an_infinite_box_stays_infinite_when_transformed() {
+        let matrix = transformations::rotation_x(PI / 4.);
+        let result = BoundingBox::infinite().transform(&matrix);
+        // Vector3 equality subtracts the components, which is NaN for infinities
+        assert_eq!((result.min.x, result.min.y, result.min.z), (f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY));
+        assert_eq!((result.max.x, result.max.y, result.max.z), (f64::INFINITY, f64::INFINITY, f64::INFINITY));
+    }
+
+    #[test]
+    fn intersecting_a_ray_with_a_bounding_box() {
+        let bounds = unit_box();
+        let hit = Ray::new(Vector3::new(5., 0.5, 0.), Vector3::new(-1., 0., 0.));
+        let miss = Ray::new(Vector3::new(-2., 0., 0.), Vector3::new(2., 4., 6.).normalize());
+        let parallel = Ray::new(Vector3::new(0., 2., 0.), Vector3::new(1., 0., 0.));
+        assert!(bounds.intersects(&hit, f64::INFINITY));
+        assert!(!bounds.intersects(&miss, f64::INFINITY));
+        assert!(!bounds.intersects(&parallel, f64::INFINITY));
+    }
+
+    #[test]
+    fn a_box_beyond_max_t_or_behind_the_ray_is_missed() {
+        let bounds = unit_box();
+        let ray = Ray::new(Vector3::new(5., 0., 0.), Vector3::new(-1., 0., 0.));
+        assert!(bounds.intersects(&ray, 4.));
+        assert!(!bounds.intersects(&ray, 3.));
+
+        let away = Ray::new(Vector3::new(5., 0., 0.), Vector3::new(1., 0., 0.));
+        assert!(!bounds.intersects(&away, f64::INFINITY));
+    }
+}
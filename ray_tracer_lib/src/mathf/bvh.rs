@@ -0,0 +1,335 @@
+use crate::material::{Material, SharedMaterial};
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::group::GroupTransform;
+use crate::mathf::intersection::{Intersection, Intersections};
+use crate::mathf::matrix::Matrix;
+use crate::mathf::ray::Ray;
+use crate::mathf::shapes::Shape;
+use crate::mathf::vector3::Vector3;
+use std::sync::Arc;
+
+// Nodes with this many shapes or fewer aren't split any further
+const MAX_LEAF_SIZE: usize = 2;
+
+/// A bounding volume hierarchy, a tree of bounding boxes over a list of shapes.
+/// A ray only visits the parts of the tree whose boxes it passes through, so
+/// finding the hits among n shapes takes roughly log n box tests instead of n
+/// intersections. See `World::build_bvh`.
+///
+/// The shapes stay in world space, the tree itself has no transform and can't
+/// be put in a group. Shapes that go on forever (like planes) can't be sorted
+/// into boxes and are intersected on every ray.
+#[derive(Debug)]
+pub struct Bvh {
+    root: Option<BvhNode>,
+    unbounded: Vec<Arc<dyn Shape>>,
+    bounds: BoundingBox,
+    material: SharedMaterial,
+    transform: Matrix,
+}
+
+#[derive(Debug)]
+enum BvhNode {
+    Leaf {
+        bounds: BoundingBox,
+        shapes: Vec<Arc<dyn Shape>>,
+    },
+    Branch {
+        bounds: BoundingBox,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+impl Bvh {
+    pub fn new(shapes: Vec<Arc<dyn Shape>>) -> Bvh {
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = shapes
+            .into_iter()
+            .partition(|shape| is_finite(&shape.parent_space_bounds()));
+
+        let mut bounds = BoundingBox::empty();
+        for shape in bounded.iter().chain(unbounded.iter()) {
+            bounds.merge(&shape.parent_space_bounds());
+        }
+
+        let root = if bounded.is_empty() {
+            None
+        } else {
+            Some(BvhNode::build(bounded))
+        };
+
+        Bvh {
+            root,
+            unbounded,
+            bounds,
+            material: Material::new().shared(),
+            transform: Matrix::identity_4x4(),
+        }
+    }
+
+    /// How many levels the tree has, 0 when it holds no bounded shapes
+    pub fn depth(&self) -> usize {
+        self.root.as_ref().map_or(0, BvhNode::depth)
+    }
+
+    /// The shapes a ray might hit before `max_t`, those in every leaf whose box
+    /// it passes through by then plus the unbounded ones. Each one still needs
+    /// to be intersected.
+    pub fn candidates<'a>(&'a self, ray: &Ray, max_t: f64, found: &mut Vec<&'a Arc<dyn Shape>>) {
+        found.extend(self.unbounded.iter());
+        if let Some(root) = &self.root {
+            root.candidates(ray, max_t, found);
+        }
+    }
+}
+
+impl BvhNode {
+    fn build(mut shapes: Vec<Arc<dyn Shape>>) -> BvhNode {
+        let mut bounds = BoundingBox::empty();
+        for shape in shapes.iter() {
+            bounds.merge(&shape.parent_space_bounds());
+        }
+
+        if shapes.len() <= MAX_LEAF_SIZE {
+            return BvhNode::Leaf { bounds, shapes };
+        }
+
+        // Split in half along the axis the centers are most spread out on
+        let mut centers = BoundingBox::empty();
+        for shape in shapes.iter() {
+            centers.add_point(&center(&shape.parent_space_bounds()));
+        }
+        let extent = &centers.max - &centers.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let key = |shape: &Arc<dyn Shape>| {
+            let c = center(&shape.parent_space_bounds());
+            [c.x, c.y, c.z][axis]
+        };
+        shapes.sort_by(|a, b| key(a).total_cmp(&key(b)));
+
+        let right = shapes.split_off(shapes.len() / 2);
+        BvhNode::Branch {
+            bounds,
+            left: Box::new(BvhNode::build(shapes)),
+            right: Box::new(BvhNode::build(right)),
+        }
+    }
+
+    fn depth(&self) -> usize {
+        match self {
+            BvhNode::Leaf { .. } => 1,
+            BvhNode::Branch { left, right, .. } => 1 + left.depth().max(right.depth()),
+        }
+    }
+
+    fn candidates<'a>(&'a self, ray: &Ray, max_t: f64, found: &mut Vec<&'a Arc<dyn Shape>>) {
+        match self {
+            BvhNode::Leaf { bounds, shapes } => {
+                if bounds.intersects(ray, max_t) {
+                    found.extend(shapes.iter());
+                }
+            }
+            BvhNode::Branch {
+                bounds,
+                left,
+                right,
+            } => {
+                if bounds.intersects(ray, max_t) {
+                    left.candidates(ray, max_t, found);
+                    right.candidates(ray, max_t, found);
+                }
+            }
+        }
+    }
+
+    fn includes(&self, other: &dyn Shape) -> bool {
+        match self {
+            BvhNode::Leaf { shapes, .. } => shapes.iter().any(|shape| shape.includes(other)),
+            BvhNode::Branch { left, right, .. } => left.includes(other) || right.includes(other),
+        }
+    }
+
+    fn parts(&self, found: &mut Vec<Arc<dyn Shape>>) {
+        match self {
+            BvhNode::Leaf { shapes, .. } => found.extend(shapes.iter().flat_map(|shape| shape.parts(shape))),
+            BvhNode::Branch { left, right, .. } => {
+                left.parts(found);
+                right.parts(found);
+            }
+        }
+    }
+}
+
+fn is_finite(bounds: &BoundingBox) -> bool {
+    [
+        bounds.min.x,
+        bounds.min.y,
+        bounds.min.z,
+        bounds.max.x,
+        bounds.max.y,
+        bounds.max.z,
+    ]
+    .iter()
+    .all(|v| v.is_finite())
+}
+
+fn center(bounds: &BoundingBox) -> Vector3 {
+    (&bounds.min + &bounds.max) * 0.5
+}
+
+impl Shape for Bvh {
+    fn shared_material(&self) -> &SharedMaterial {
+        &self.material
+    }
+
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn has_identity_transform(&self) -> bool {
+        true
+    }
+
+    fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        None
+    }
+
+    fn set_parent(&self, _parent: Arc<GroupTransform>) {
+        panic!("A BVH holds shapes in world space, it can't be added to a group");
+    }
+
+    fn local_intersect(&self, _shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
+        let mut candidates = vec![];
+        self.candidates(&object_ray, f64::INFINITY, &mut candidates);
+        let xs = candidates
+            .into_iter()
+            .flat_map(|shape| shape.intersect(Arc::clone(shape), object_ray.clone()))
+            .collect();
+
+        let mut xs = Intersections::new(xs);
+        xs.sort();
+        xs.intersections
+    }
+
+    fn local_normal_at(&self, _object_point: Vector3) -> Vector3 {
+        unreachable!("The normal of a BVH is computed by the shape that was hit")
+    }
+
+    fn local_eq(&self, other: &dyn Shape) -> bool {
+        std::ptr::eq(
+            self as *const Bvh as *const (),
+            other as *const dyn Shape as *const (),
+        )
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.bounds.clone()
+    }
+
+    fn includes(&self, other: &dyn Shape) -> bool {
+        self.unbounded.iter().any(|shape| shape.includes(other))
+            || self.root.as_ref().is_some_and(|root| root.includes(other))
+    }
+
+    fn parts(&self, _shape: &Arc<dyn Shape>) -> Vec<Arc<dyn Shape>> {
+        let mut parts: Vec<Arc<dyn Shape>> = self.unbounded.iter().flat_map(|shape| shape.parts(shape)).collect();
+        if let Some(root) = &self.root {
+            root.parts(&mut parts);
+        }
+        parts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathf::plane::Plane;
+    use crate::mathf::sphere::Sphere;
+    use crate::transformations;
+
+    fn spheres_along_x(count: usize) -> Vec<Arc<dyn Shape>> {
+        (0..count)
+            .map(|i| {
+                let sphere = Sphere::new(
+                    Some(transformations::translation(&Vector3::new(
+                        i as f64 * 3.,
+                        0.,
+                        0.,
+                    ))),
+                    None,
+                );
+                Arc::new(sphere) as Arc<dyn Shape>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_tree_is_split_until_the_leaves_are_small() {
+        let bvh = Bvh::new(spheres_along_x(16));
+        // 16 shapes in leaves of 2 takes 8 leaves, 4 levels
+        assert_eq!(bvh.depth(), 4);
+        assert_eq!(
+            bvh.bounds(),
+            BoundingBox::new(Vector3::new(-1., -1., -1.), Vector3::new(46., 1., 1.))
+        );
+    }
+
+    #[test]
+    fn a_ray_finds_the_same_hits_as_intersecting_every_shape() {
+        let shapes = spheres_along_x(10);
+        let bvh: Arc<dyn Shape> = Arc::new(Bvh::new(shapes.clone()));
+
+        let ray = Ray::new(Vector3::new(9., 0., -5.), Vector3::new(0., 0., 1.));
+        let xs = bvh.intersect(Arc::clone(&bvh), ray);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.);
+        assert!(Arc::ptr_eq(&xs[0].object, &shapes[3]));
+
+        let miss = Ray::new(Vector3::new(1.5, 0., -5.), Vector3::new(0., 0., 1.));
+        assert!(bvh.intersect(Arc::clone(&bvh), miss).is_empty());
+    }
+
+    #[test]
+    fn only_shapes_reached_before_max_t_are_candidates() {
+        let shapes = spheres_along_x(10);
+        let bvh = Bvh::new(shapes.clone());
+        let ray = Ray::new(Vector3::new(-5., 0., 0.), Vector3::new(1., 0., 0.));
+
+        let mut everything = vec![];
+        bvh.candidates(&ray, f64::INFINITY, &mut everything);
+        assert_eq!(everything.len(), 10);
+
+        // Only the leaf holding the first two spheres starts before t = 5
+        let mut nearby = vec![];
+        bvh.candidates(&ray, 5., &mut nearby);
+        assert_eq!(nearby.len(), 2);
+        assert!(nearby.iter().any(|shape| Arc::ptr_eq(shape, &shapes[0])));
+    }
+
+    #[test]
+    fn unbounded_shapes_are_always_intersected() {
+        let mut shapes = spheres_along_x(4);
+        let floor: Arc<dyn Shape> = Arc::new(Plane::new(
+            Some(transformations::translation(&Vector3::new(0., -1., 0.))),
+            None,
+        ));
+        shapes.push(Arc::clone(&floor));
+        let bvh: Arc<dyn Shape> = Arc::new(Bvh::new(shapes));
+        assert!(bvh.includes(floor.as_ref()));
+
+        let ray = Ray::new(Vector3::new(100., 5., 0.), Vector3::new(0., -1., 0.));
+        let xs = bvh.intersect(Arc::clone(&bvh), ray);
+        assert_eq!(xs.len(), 1);
+        assert!(Arc::ptr_eq(&xs[0].object, &floor));
+    }
+}
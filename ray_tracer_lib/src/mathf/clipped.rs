@@ -0,0 +1,161 @@
+use crate::material::{Material, SharedMaterial};
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::group::GroupTransform;
+use crate::mathf::intersection::Intersection;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::ray::Ray;
+use crate::mathf::shapes::Shape;
+use crate::mathf::vector3::Vector3;
+use std::sync::Arc;
+
+/// A shape cut by a plane, only the part on the side the normal points to is kept.
+///
+/// Cheaper than a CSG difference with a cube, but the cut is left open rather
+/// than capped, so rays through it see the inside of the shape. Like CSG the
+/// clipped shape has no transform of its own and hits refer to the inner shape.
+#[derive(Debug)]
+pub struct ClippedShape {
+    pub shape: Arc<dyn Shape>,
+    /// A point on the clipping plane
+    pub point: Vector3,
+    /// Points towards the part that is kept
+    pub normal: Vector3,
+    material: SharedMaterial,
+    parent_space_bounds: BoundingBox,
+    node: Arc<GroupTransform>,
+}
+
+impl ClippedShape {
+    pub fn new(shape: Arc<dyn Shape>, point: Vector3, normal: Vector3) -> ClippedShape {
+        let node = Arc::new(GroupTransform::new(Matrix::identity_4x4()));
+        shape.set_parent(Arc::clone(&node));
+
+        let mut clipped = ClippedShape {
+            shape,
+            point,
+            normal: normal.normalize(),
+            material: Material::new().shared(),
+            parent_space_bounds: BoundingBox::empty(),
+            node,
+        };
+        clipped.parent_space_bounds = clipped.bounds().transform(clipped.transform());
+        clipped
+    }
+
+    /// True if the point is on the side of the plane that is kept (or on the plane)
+    pub fn keeps(&self, point: &Vector3) -> bool {
+        (point - &self.point).dot(&self.normal) >= 0.
+    }
+}
+
+impl Shape for ClippedShape {
+    fn shared_material(&self) -> &SharedMaterial {
+        &self.material
+    }
+
+    fn transform(&self) -> &Matrix {
+        self.node.transform()
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        self.node.inverse_transform()
+    }
+
+    fn has_identity_transform(&self) -> bool {
+        self.node.is_identity()
+    }
+
+    fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        self.node.parent()
+    }
+
+    fn set_parent(&self, parent: Arc<GroupTransform>) {
+        self.node.set_parent(parent);
+    }
+
+    fn local_intersect(&self, _shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
+        self.shape
+            .intersect(Arc::clone(&self.shape), object_ray.clone())
+            .into_iter()
+            .filter(|i| self.keeps(&object_ray.position(i.t)))
+            .collect()
+    }
+
+    fn local_normal_at(&self, _object_point: Vector3) -> Vector3 {
+        unreachable!("The normal of a clipped shape is computed by the inner shape")
+    }
+
+    fn local_eq(&self, other: &dyn Shape) -> bool {
+        std::ptr::eq(self as *const ClippedShape as *const (), other as *const dyn Shape as *const ())
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.shape.parent_space_bounds()
+    }
+
+    fn parent_space_bounds(&self) -> BoundingBox {
+        self.parent_space_bounds.clone()
+    }
+
+    fn includes(&self, other: &dyn Shape) -> bool {
+        self.shape.includes(other)
+    }
+
+    fn parts(&self, _shape: &Arc<dyn Shape>) -> Vec<Arc<dyn Shape>> {
+        self.shape.parts(&self.shape)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathf::sphere::Sphere;
+    use crate::transformations;
+
+    #[test]
+    fn clipping_a_sphere_removes_the_back_half() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let clipped: Arc<dyn Shape> = Arc::new(ClippedShape::new(
+            Arc::clone(&sphere),
+            Vector3::new(0., 0., 0.),
+            Vector3::new(0., 0., -1.),
+        ));
+
+        let from_the_front = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let xs = clipped.intersect(Arc::clone(&clipped), from_the_front);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 4.);
+        assert!(Arc::ptr_eq(&xs[0].object, &sphere));
+
+        // From behind the front half is still seen, through the open cut
+        let from_behind = Ray::new(Vector3::new(0., 0., 5.), Vector3::new(0., 0., -1.));
+        let xs = clipped.intersect(Arc::clone(&clipped), from_behind);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 6.);
+
+        let through_the_back = Ray::new(Vector3::new(0., -5., 0.5), Vector3::new(0., 1., 0.));
+        assert!(clipped.intersect(Arc::clone(&clipped), through_the_back).is_empty());
+    }
+
+    #[test]
+    fn the_inner_shape_keeps_its_transform() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(0., 0., 5.))),
+            None,
+        ));
+        let clipped: Arc<dyn Shape> = Arc::new(ClippedShape::new(
+            Arc::clone(&sphere),
+            Vector3::new(0., 0., 5.),
+            Vector3::new(0., 1., 0.),
+        ));
+
+        let ray = Ray::new(Vector3::new(0., 0.5, 0.), Vector3::new(0., 0., 1.));
+        let xs = clipped.intersect(Arc::clone(&clipped), ray.clone());
+        assert_eq!(xs.len(), 2);
+        let normal = xs[0].object.normal_at(ray.position(xs[0].t));
+        assert_eq!(normal, Vector3::new(0., 0.5, -(0.75f64.sqrt())));
+
+        let below_the_cut = Ray::new(Vector3::new(0., -0.5, 0.), Vector3::new(0., 0., 1.));
+        assert!(clipped.intersect(Arc::clone(&clipped), below_the_cut).is_empty());
+    }
+}
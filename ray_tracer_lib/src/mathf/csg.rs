@@ -0,0 +1,284 @@
+use crate::material::{Material, SharedMaterial};
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::group::GroupTransform;
+use crate::mathf::intersection::{Intersection, Intersections};
+use crate::mathf::matrix::Matrix;
+use crate::mathf::ray::Ray;
+use crate::mathf::shapes::Shape;
+use crate::mathf::vector3::Vector3;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+/// Constructive solid geometry, combines two shapes with a set operation.
+///
+/// A CSG shape doesn't have a transform of its own, the children keep their own
+/// transforms and are intersected in world space. Hits always refer to one of the
+/// children so the children's materials are used when shading.
+#[derive(Debug)]
+pub struct CSG {
+    pub operation: Operation,
+    pub left: Arc<dyn Shape>,
+    pub right: Arc<dyn Shape>,
+    material: SharedMaterial,
+    parent_space_bounds: BoundingBox,
+    node: Arc<GroupTransform>,
+}
+
+/// Decide if an intersection should be kept.
+/// `left_hit` is true if the left shape was hit, `in_left` is true if the hit
+/// occurs inside the left shape and `in_right` is true if it occurs inside the right.
+pub fn intersection_allowed(operation: Operation, left_hit: bool, in_left: bool, in_right: bool) -> bool {
+    match operation {
+        Operation::Union => (left_hit && !in_right) || (!left_hit && !in_left),
+        Operation::Intersection => (left_hit && in_right) || (!left_hit && in_left),
+        Operation::Difference => (left_hit && !in_right) || (!left_hit && in_left),
+    }
+}
+
+impl CSG {
+    pub fn new(operation: Operation, left: Arc<dyn Shape>, right: Arc<dyn Shape>) -> CSG {
+        // The children are intersected in the space of the CSG, so they need
+        // its transform (and those of any groups it is in) to find their normals
+        let node = Arc::new(GroupTransform::new(Matrix::identity_4x4()));
+        left.set_parent(Arc::clone(&node));
+        right.set_parent(Arc::clone(&node));
+
+        let mut csg = CSG {
+            operation,
+            left,
+            right,
+            material: Material::new().shared(),
+            node,
+            parent_space_bounds: BoundingBox::empty(),
+        };
+        csg.parent_space_bounds = csg.bounds().transform(csg.transform());
+        csg
+    }
+
+    /// Keep only the intersections allowed by the operation.
+    /// The intersections must be sorted by t.
+    pub fn filter_intersections(&self, intersections: Intersections) -> Vec<Intersection> {
+        // Begin outside of both children
+        let mut in_left = false;
+        let mut in_right = false;
+
+        let mut result = vec![];
+        for i in intersections.intersections {
+            let left_hit = self.left.includes(&*i.object);
+
+            if intersection_allowed(self.operation, left_hit, in_left, in_right) {
+                result.push(i);
+            }
+
+            // Every hit crosses the surface of a child, so toggle whichever one was hit
+            if left_hit {
+                in_left = !in_left;
+            } else {
+                in_right = !in_right;
+            }
+        }
+
+        result
+    }
+}
+
+impl Shape for CSG {
+    fn shared_material(&self) -> &SharedMaterial {
+        &self.material
+    }
+
+    fn transform(&self) -> &Matrix {
+        self.node.transform()
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        self.node.inverse_transform()
+    }
+
+    fn has_identity_transform(&self) -> bool {
+        self.node.is_identity()
+    }
+
+    fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        self.node.parent()
+    }
+
+    fn set_parent(&self, parent: Arc<GroupTransform>) {
+        self.node.set_parent(parent);
+    }
+
+    fn local_intersect(&self, _shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
+        let left = Intersections::new(self.left.intersect(Arc::clone(&self.left), object_ray.clone()));
+        let right = Intersections::new(self.right.intersect(Arc::clone(&self.right), object_ray));
+
+        self.filter_intersections(left.merge(right))
+    }
+
+    fn local_normal_at(&self, _object_point: Vector3) -> Vector3 {
+        // Intersections always reference one of the children, so the normal is
+        // computed by the child and never by the CSG itself.
+        unreachable!("The normal of a CSG shape is computed by the child that was hit")
+    }
+
+    fn local_eq(&self, other: &dyn Shape) -> bool {
+        std::ptr::eq(self as *const CSG as *const (), other as *const dyn Shape as *const ())
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let mut bounds = self.left.parent_space_bounds();
+        bounds.merge(&self.right.parent_space_bounds());
+        bounds
+    }
+
+    fn parent_space_bounds(&self) -> BoundingBox {
+        self.parent_space_bounds.clone()
+    }
+
+    fn includes(&self, other: &dyn Shape) -> bool {
+        self.left.includes(other) || self.right.includes(other)
+    }
+
+    fn parts(&self, _shape: &Arc<dyn Shape>) -> Vec<Arc<dyn Shape>> {
+        let mut parts = self.left.parts(&self.left);
+        parts.extend(self.right.parts(&self.right));
+        parts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathf::sphere::Sphere;
+    use crate::transformations;
+
+    fn two_spheres() -> (Arc<dyn Shape>, Arc<dyn Shape>) {
+        let s1: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let s2: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(0., 0., 0.5))),
+            None,
+        ));
+        (s1, s2)
+    }
+
+    #[test]
+    fn csg_is_created_with_an_operation_and_two_shapes() {
+        let (s1, s2) = two_spheres();
+        let c = CSG::new(Operation::Union, Arc::clone(&s1), Arc::clone(&s2));
+        assert_eq!(c.operation, Operation::Union);
+        assert!(c.left.includes(&*s1));
+        assert!(c.right.includes(&*s2));
+        assert!(!c.left.includes(&*s2));
+    }
+
+    #[test]
+    fn evaluating_the_rule_for_a_csg_operation() {
+        use Operation::*;
+        // (operation, left_hit, in_left, in_right, result)
+        let table = [
+            (Union, true, true, true, false),
+            (Union, true, true, false, true),
+            (Union, true, false, true, false),
+            (Union, true, false, false, true),
+            (Union, false, true, true, false),
+            (Union, false, true, false, false),
+            (Union, false, false, true, true),
+            (Union, false, false, false, true),
+            (Intersection, true, true, true, true),
+            (Intersection, true, true, false, false),
+            (Intersection, true, false, true, true),
+            (Intersection, true, false, false, false),
+            (Intersection, false, true, true, true),
+            (Intersection, false, true, false, true),
+            (Intersection, false, false, true, false),
+            (Intersection, false, false, false, false),
+            (Difference, true, true, true, false),
+            (Difference, true, true, false, true),
+            (Difference, true, false, true, false),
+            (Difference, true, false, false, true),
+            (Difference, false, true, true, true),
+            (Difference, false, true, false, true),
+            (Difference, false, false, true, false),
+            (Difference, false, false, false, false),
+        ];
+
+        for (operation, left_hit, in_left, in_right, expected) in table.iter() {
+            assert_eq!(
+                intersection_allowed(*operation, *left_hit, *in_left, *in_right),
+                *expected
+            );
+        }
+    }
+
+    #[test]
+    fn filtering_a_list_of_intersections() {
+        let table = [
+            (Operation::Union, 0, 3),
+            (Operation::Intersection, 1, 2),
+            (Operation::Difference, 0, 1),
+        ];
+
+        for (operation, x0, x1) in table.iter() {
+            let (s1, s2) = two_spheres();
+            let c = CSG::new(*operation, Arc::clone(&s1), Arc::clone(&s2));
+            let xs = Intersections::new(vec![
+                Intersection::new(1., Arc::clone(&s1)),
+                Intersection::new(2., Arc::clone(&s2)),
+                Intersection::new(3., Arc::clone(&s1)),
+                Intersection::new(4., Arc::clone(&s2)),
+            ]);
+            let ts: Vec<f64> = xs.intersections.iter().map(|i| i.t).collect();
+
+            let result = c.filter_intersections(xs);
+            assert_eq!(result.len(), 2);
+            assert_eq!(result[0].t, ts[*x0]);
+            assert_eq!(result[1].t, ts[*x1]);
+        }
+    }
+
+    #[test]
+    fn a_ray_misses_a_csg_object() {
+        let (s1, s2) = two_spheres();
+        let c = CSG::new(Operation::Union, s1, s2);
+        let c: Arc<dyn Shape> = Arc::new(c);
+        let ray = Ray::new(Vector3::new(0., 2., -5.), Vector3::new(0., 0., 1.));
+        let xs = c.intersect(Arc::clone(&c), ray);
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn a_ray_hits_a_csg_object() {
+        let (s1, s2) = two_spheres();
+        let c = CSG::new(Operation::Union, Arc::clone(&s1), Arc::clone(&s2));
+        let c: Arc<dyn Shape> = Arc::new(c);
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let xs = c.intersect(Arc::clone(&c), ray);
+
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.);
+        assert!(s1.includes(&*xs[0].object));
+        assert_eq!(xs[1].t, 6.5);
+        assert!(s2.includes(&*xs[1].object));
+    }
+
+    #[test]
+    fn a_difference_carves_the_right_shape_out_of_the_left() {
+        let (s1, s2) = two_spheres();
+        let c = CSG::new(Operation::Difference, Arc::clone(&s1), Arc::clone(&s2));
+        let c: Arc<dyn Shape> = Arc::new(c);
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let xs = c.intersect(Arc::clone(&c), ray);
+
+        // The front of the left sphere, then the inside of the right sphere
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.);
+        assert!(s1.includes(&*xs[0].object));
+        assert_eq!(xs[1].t, 4.5);
+        assert!(s2.includes(&*xs[1].object));
+    }
+}
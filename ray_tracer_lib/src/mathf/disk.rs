@@ -0,0 +1,229 @@
+use crate::material::{Material, SharedMaterial};
+use crate::mathf;
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::group::{set_parent_once, GroupTransform};
+use crate::mathf::intersection::Intersection;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::ray::Ray;
+use crate::mathf::shapes::{shape_id, Shape};
+use crate::mathf::vector3::Vector3;
+use std::sync::{Arc, OnceLock};
+
+/// A section of the xz plane between an inner and outer radius.
+/// An inner radius of 0 is a solid disk, anything larger is a ring.
+#[derive(Debug)]
+pub struct Disk {
+    id: u32,
+    material: SharedMaterial,
+    parent_space_bounds: BoundingBox,
+    transform: Matrix,
+    inverse_transform: Matrix,
+    identity_transform: bool,
+    parent: OnceLock<Arc<GroupTransform>>,
+    inner_radius: f64,
+    outer_radius: f64,
+}
+
+impl Shape for Disk {
+    fn shared_material(&self) -> &SharedMaterial {
+        &self.material
+    }
+
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn has_identity_transform(&self) -> bool {
+        self.identity_transform
+    }
+
+    fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        self.parent.get()
+    }
+
+    fn set_parent(&self, parent: Arc<GroupTransform>) {
+        set_parent_once(&self.parent, parent);
+    }
+
+    fn local_intersect(&self, shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
+        if object_ray.direction.y.abs() < mathf::EQUALITY_EPSILON {
+            return vec![];
+        }
+
+        // Same as a plane, then throw away hits outside of the ring
+        let t = -object_ray.origin.y / object_ray.direction.y;
+        let point = object_ray.position(t);
+        let distance_squared = point.x * point.x + point.z * point.z;
+
+        if distance_squared < self.inner_radius * self.inner_radius
+            || distance_squared > self.outer_radius * self.outer_radius
+        {
+            return vec![];
+        }
+
+        vec![Intersection::new(t, Arc::clone(&shape))]
+    }
+
+    fn local_normal_at(&self, _object_point: Vector3) -> Vector3 {
+        Vector3::new(0., 1., 0.)
+    }
+
+    fn local_eq(&self, other: &dyn Shape) -> bool {
+        other.id() == Some(self.id)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let r = self.outer_radius;
+        BoundingBox::new(Vector3::new(-r, 0., -r), Vector3::new(r, 0., r))
+    }
+
+    fn parent_space_bounds(&self) -> BoundingBox {
+        self.parent_space_bounds.clone()
+    }
+
+    fn id(&self) -> Option<u32> {
+        Some(self.id)
+    }
+}
+
+impl Disk {
+    /// Use a material that may be shared with other shapes
+    pub fn set_material(&mut self, material: SharedMaterial) {
+        self.material = material;
+    }
+
+    pub fn new(transform: Option<Matrix>, material: Option<Material>) -> Disk {
+        Disk::new_with_radius(transform, material, 0., 1.)
+    }
+
+    /// A disk with a chosen id, see `Sphere::with_id`
+    pub fn with_id(id: u32, transform: Option<Matrix>, material: Option<Material>) -> Disk {
+        let mut disk = Disk::new(transform, material);
+        disk.id = id;
+        disk
+    }
+
+    /// A ring between the two radii. If the inner radius is the larger one the two
+    /// are swapped, rather than leaving a disk that nothing can hit.
+    pub fn new_with_radius(
+        transform: Option<Matrix>,
+        material: Option<Material>,
+        inner_radius: f64,
+        outer_radius: f64,
+    ) -> Disk {
+        let t = match transform {
+            None => Matrix::identity_4x4(),
+            Some(x) => x,
+        };
+        let inverse_transform = t.inverse().unwrap();
+        let mat = material.unwrap_or_default();
+        let (inner_radius, outer_radius) = if inner_radius > outer_radius {
+            (outer_radius, inner_radius)
+        } else {
+            (inner_radius, outer_radius)
+        };
+        let mut shape = Disk {
+            id: shape_id(),
+            transform: t,
+            material: mat.shared(),
+            identity_transform: inverse_transform.is_identity(),
+            inverse_transform,
+            parent: OnceLock::new(),
+            inner_radius,
+            outer_radius,
+            parent_space_bounds: BoundingBox::empty(),
+        };
+        shape.parent_space_bounds = shape.bounds().transform(shape.transform());
+        shape
+    }
+
+    pub fn inner_radius(&self) -> f64 {
+        self.inner_radius
+    }
+
+    pub fn outer_radius(&self) -> f64 {
+        self.outer_radius
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disks_compare_by_id() {
+        let a: Arc<dyn Shape> = Arc::new(Disk::with_id(7, None, None));
+        let b: Arc<dyn Shape> = Arc::new(Disk::with_id(7, Some(Matrix::identity_4x4().translate(0., 1., 0.)), None));
+        // Same material and transform, but a different disk
+        let c: Arc<dyn Shape> = Arc::new(Disk::new(None, None));
+        assert_eq!(a.id(), Some(7));
+        assert!(a == b);
+        assert!(a != c);
+    }
+
+    #[test]
+    fn the_default_disk() {
+        let disk = Disk::new(None, None);
+        assert_eq!(disk.inner_radius(), 0.);
+        assert_eq!(disk.outer_radius(), 1.);
+    }
+
+    #[test]
+    fn the_normal_of_a_disk_is_constant_everywhere() {
+        let disk = Disk::new(None, None);
+        assert_eq!(disk.local_normal_at(Vector3::new(0., 0., 0.)), Vector3::new(0., 1., 0.));
+        assert_eq!(disk.local_normal_at(Vector3::new(0.5, 0., -0.5)), Vector3::new(0., 1., 0.));
+    }
+
+    #[test]
+    fn a_ray_through_the_center_hits_a_disk() {
+        let disk: Arc<dyn Shape> = Arc::new(Disk::new(None, None));
+        let ray = Ray::new(Vector3::new(0., 1., 0.), Vector3::new(0., -1., 0.));
+        let xs = disk.local_intersect(Arc::clone(&disk), ray);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 1.);
+    }
+
+    #[test]
+    fn a_ray_parallel_to_a_disk_misses() {
+        let disk: Arc<dyn Shape> = Arc::new(Disk::new(None, None));
+        let ray = Ray::new(Vector3::new(0., 1., 0.), Vector3::new(0., 0., 1.));
+        let xs = disk.local_intersect(Arc::clone(&disk), ray);
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn a_ray_through_the_hole_misses_a_disk() {
+        let disk: Arc<dyn Shape> = Arc::new(Disk::new_with_radius(None, None, 0.5, 1.));
+        let ray = Ray::new(Vector3::new(0.25, 1., 0.), Vector3::new(0., -1., 0.));
+        let xs = disk.local_intersect(Arc::clone(&disk), ray);
+        assert!(xs.is_empty());
+
+        let ray = Ray::new(Vector3::new(0.75, 1., 0.), Vector3::new(0., -1., 0.));
+        let xs = disk.local_intersect(Arc::clone(&disk), ray);
+        assert_eq!(xs.len(), 1);
+    }
+
+    #[test]
+    fn radii_given_the_wrong_way_round_are_swapped() {
+        let disk: Arc<dyn Shape> = Arc::new(Disk::new_with_radius(None, None, 2., 1.));
+        let ray = Ray::new(Vector3::new(1.5, 1., 0.), Vector3::new(0., -1., 0.));
+        assert_eq!(disk.local_intersect(Arc::clone(&disk), ray).len(), 1);
+        assert_eq!(
+            disk.bounds(),
+            BoundingBox::new(Vector3::new(-2., 0., -2.), Vector3::new(2., 0., 2.))
+        );
+    }
+
+    #[test]
+    fn a_ray_outside_the_outer_radius_misses_a_disk() {
+        let disk: Arc<dyn Shape> = Arc::new(Disk::new(None, None));
+        let ray = Ray::new(Vector3::new(1., 1., 1.), Vector3::new(0., -1., 0.));
+        let xs = disk.local_intersect(Arc::clone(&disk), ray);
+        assert!(xs.is_empty());
+    }
+}
@@ -0,0 +1,285 @@
+use crate::material::{Material, SharedMaterial};
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::intersection::{Intersection, Intersections};
+use crate::mathf::matrix::Matrix;
+use crate::mathf::ray::Ray;
+use crate::mathf::shapes::Shape;
+use crate::mathf::vector3::Vector3;
+use std::sync::{Arc, OnceLock};
+
+/// The transform of a group, shared with each of its children so they can
+/// convert between world and object space without a reference to the group itself.
+#[derive(Debug)]
+pub struct GroupTransform {
+    transform: Matrix,
+    inverse_transform: Matrix,
+    identity: bool,
+    parent: OnceLock<Arc<GroupTransform>>,
+}
+
+impl GroupTransform {
+    pub fn new(transform: Matrix) -> GroupTransform {
+        let inverse_transform = transform.inverse().unwrap();
+        GroupTransform {
+            identity: inverse_transform.is_identity(),
+            transform,
+            inverse_transform,
+            parent: OnceLock::new(),
+        }
+    }
+
+    pub fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    pub fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    /// True if the transform is exactly the identity, worked out once up front
+    pub fn is_identity(&self) -> bool {
+        self.identity
+    }
+
+    pub fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        self.parent.get()
+    }
+
+    pub fn set_parent(&self, parent: Arc<GroupTransform>) {
+        set_parent_once(&self.parent, parent);
+    }
+
+    /// Convert a point from world space to the space of this group
+    pub fn world_to_object(&self, point: &Vector3) -> Vector3 {
+        let point = match self.parent() {
+            Some(parent) => parent.world_to_object(point),
+            None => point.clone(),
+        };
+        self.inverse_transform.multiply_point(&point)
+    }
+
+    /// Convert a normal in the space of this group to world space
+    pub fn normal_to_world(&self, normal: &Vector3) -> Vector3 {
+        let normal = self
+            .inverse_transform
+            .transpose()
+            .multiply_vector(normal)
+            .normalize();
+        match self.parent() {
+            Some(parent) => parent.normal_to_world(&normal),
+            None => normal,
+        }
+    }
+}
+
+/// Record the group a shape belongs to. A shape's transforms are only meaningful
+/// relative to a single parent, so adding it to a second group is a bug.
+pub(crate) fn set_parent_once(cell: &OnceLock<Arc<GroupTransform>>, parent: Arc<GroupTransform>) {
+    if cell.set(parent).is_err() {
+        panic!("A shape can only be added to one group");
+    }
+}
+
+/// A collection of shapes that are transformed as a single unit.
+///
+/// Like CSG, hits always refer to one of the children so the children's
+/// materials are used when shading.
+#[derive(Debug)]
+pub struct Group {
+    node: Arc<GroupTransform>,
+    children: Vec<Arc<dyn Shape>>,
+    material: SharedMaterial,
+    parent_space_bounds: BoundingBox,
+}
+
+impl Group {
+    pub fn new(transform: Option<Matrix>, children: Vec<Arc<dyn Shape>>) -> Group {
+        let node = Arc::new(GroupTransform::new(transform.unwrap_or_else(Matrix::identity_4x4)));
+        for child in children.iter() {
+            child.set_parent(Arc::clone(&node));
+        }
+
+        let mut group = Group {
+            node,
+            children,
+            material: Material::new().shared(),
+            parent_space_bounds: BoundingBox::empty(),
+        };
+        group.parent_space_bounds = group.bounds().transform(group.transform());
+        group
+    }
+
+    pub fn children(&self) -> &[Arc<dyn Shape>] {
+        &self.children
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+impl Shape for Group {
+    fn shared_material(&self) -> &SharedMaterial {
+        &self.material
+    }
+
+    fn transform(&self) -> &Matrix {
+        self.node.transform()
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        self.node.inverse_transform()
+    }
+
+    fn has_identity_transform(&self) -> bool {
+        self.node.is_identity()
+    }
+
+    fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        self.node.parent()
+    }
+
+    fn set_parent(&self, parent: Arc<GroupTransform>) {
+        self.node.set_parent(parent);
+    }
+
+    fn local_intersect(&self, _shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
+        let mut xs = Intersections::new(vec![]);
+        for child in self.children.iter() {
+            xs.intersections
+                .extend(child.intersect(Arc::clone(child), object_ray.clone()));
+        }
+        xs.sort();
+        xs.intersections
+    }
+
+    fn local_normal_at(&self, _object_point: Vector3) -> Vector3 {
+        // Intersections always reference one of the children, so the normal is
+        // computed by the child and never by the group itself.
+        unreachable!("The normal of a group is computed by the child that was hit")
+    }
+
+    fn local_eq(&self, other: &dyn Shape) -> bool {
+        std::ptr::eq(self as *const Group as *const (), other as *const dyn Shape as *const ())
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let mut bounds = BoundingBox::empty();
+        for child in self.children.iter() {
+            bounds.merge(&child.parent_space_bounds());
+        }
+        bounds
+    }
+
+    fn parent_space_bounds(&self) -> BoundingBox {
+        self.parent_space_bounds.clone()
+    }
+
+    fn includes(&self, other: &dyn Shape) -> bool {
+        self.children.iter().any(|child| child.includes(other))
+    }
+
+    fn parts(&self, _shape: &Arc<dyn Shape>) -> Vec<Arc<dyn Shape>> {
+        self.children.iter().flat_map(|child| child.parts(child)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathf::sphere::Sphere;
+    use crate::transformations;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn intersecting_a_ray_with_an_empty_group() {
+        let g: Arc<dyn Shape> = Arc::new(Group::new(None, vec![]));
+        let ray = Ray::new(Vector3::new(0., 0., 0.), Vector3::new(0., 0., 1.));
+        let xs = g.intersect(Arc::clone(&g), ray);
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn intersecting_a_ray_with_a_nonempty_group() {
+        let s1: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let s2: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(0., 0., -3.))),
+            None,
+        ));
+        let s3: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(5., 0., 0.))),
+            None,
+        ));
+        let g: Arc<dyn Shape> = Arc::new(Group::new(
+            None,
+            vec![Arc::clone(&s1), Arc::clone(&s2), Arc::clone(&s3)],
+        ));
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let xs = g.intersect(Arc::clone(&g), ray);
+
+        assert_eq!(xs.len(), 4);
+        assert!(s2.includes(&*xs[0].object));
+        assert!(s2.includes(&*xs[1].object));
+        assert!(s1.includes(&*xs[2].object));
+        assert!(s1.includes(&*xs[3].object));
+    }
+
+    #[test]
+    fn intersecting_a_transformed_group() {
+        let s: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(5., 0., 0.))),
+            None,
+        ));
+        let g: Arc<dyn Shape> = Arc::new(Group::new(
+            Some(transformations::scaling(&Vector3::new(2., 2., 2.))),
+            vec![s],
+        ));
+        let ray = Ray::new(Vector3::new(10., 0., -10.), Vector3::new(0., 0., 1.));
+        let xs = g.intersect(Arc::clone(&g), ray);
+        assert_eq!(xs.len(), 2);
+    }
+
+    fn sphere_in_nested_groups(g2_scale: Vector3) -> (Arc<dyn Shape>, Group) {
+        let s: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(5., 0., 0.))),
+            None,
+        ));
+        let g2: Arc<dyn Shape> = Arc::new(Group::new(
+            Some(transformations::scaling(&g2_scale)),
+            vec![Arc::clone(&s)],
+        ));
+        let g1 = Group::new(Some(transformations::rotation_y(PI / 2.)), vec![g2]);
+        (s, g1)
+    }
+
+    #[test]
+    fn converting_a_point_from_world_to_object_space() {
+        let (s, _g1) = sphere_in_nested_groups(Vector3::new(2., 2., 2.));
+        let p = s.world_to_object(&Vector3::new(-2., 0., -10.));
+        assert_eq!(p, Vector3::new(0., 0., -1.));
+    }
+
+    #[test]
+    fn converting_a_normal_from_object_to_world_space() {
+        let (s, _g1) = sphere_in_nested_groups(Vector3::new(1., 2., 3.));
+        let v = 3f64.sqrt() / 3.;
+        let n = s.normal_to_world(&Vector3::new(v, v, v));
+        assert_eq!(n, Vector3::new(2. / 7., 3. / 7., -6. / 7.));
+    }
+
+    #[test]
+    fn finding_the_normal_on_a_child_object() {
+        let (s, _g1) = sphere_in_nested_groups(Vector3::new(1., 2., 3.));
+        let n = s.normal_at(Vector3::new(1.7321, 1.1547, -5.5774));
+        // The book only gives the point to 4 decimal places
+        assert!(n.distance(&Vector3::new(0.2857, 0.4286, -0.8571)) < 0.0001);
+    }
+
+    #[test]
+    #[should_panic(expected = "A shape can only be added to one group")]
+    fn a_shape_can_only_be_added_to_one_group() {
+        let s: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let _g1 = Group::new(None, vec![Arc::clone(&s)]);
+        let _g2 = Group::new(None, vec![s]);
+    }
+}
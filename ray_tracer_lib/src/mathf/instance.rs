@@ -0,0 +1,293 @@
+use crate::material::{Material, SharedMaterial};
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::group::GroupTransform;
+use crate::mathf::intersection::Intersection;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::ray::Ray;
+use crate::mathf::shapes::Shape;
+use crate::mathf::vector3::Vector3;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Another copy of a shape somewhere else in the scene. The geometry is shared,
+/// so a mesh can appear many times for little more than the cost of a transform.
+///
+/// The shared shape must not be added to a group (or given to anything else that
+/// sets its parent), its own transform is applied inside the instance's.
+#[derive(Debug)]
+pub struct Instance {
+    node: Arc<GroupTransform>,
+    shape: Arc<dyn Shape>,
+    material: SharedMaterial,
+    parent_space_bounds: BoundingBox,
+    // The shape each hit refers to, one per part of the shared shape, built up
+    // front so intersecting never has to lock or allocate them. Keyed by the
+    // address of that part, reusing them means two hits on the same part of the
+    // same instance are the same object, which refraction relies on.
+    hits: HashMap<usize, Arc<dyn Shape>>,
+}
+
+fn part_key(part: &Arc<dyn Shape>) -> usize {
+    Arc::as_ptr(part) as *const () as usize
+}
+
+impl Instance {
+    pub fn new(transform: Option<Matrix>, shape: Arc<dyn Shape>) -> Instance {
+        let node = Arc::new(GroupTransform::new(transform.unwrap_or_else(Matrix::identity_4x4)));
+        let hits = shape
+            .parts(&shape)
+            .into_iter()
+            .map(|part| {
+                let hit: Arc<dyn Shape> = Arc::new(InstanceHit {
+                    node: Arc::clone(&node),
+                    part: Arc::clone(&part),
+                });
+                (part_key(&part), hit)
+            })
+            .collect();
+        let mut instance = Instance {
+            node,
+            shape,
+            material: Material::new().shared(),
+            parent_space_bounds: BoundingBox::empty(),
+            hits,
+        };
+        instance.parent_space_bounds = instance.bounds().transform(instance.transform());
+        instance
+    }
+
+    /// The shape shared between instances
+    pub fn shape(&self) -> &Arc<dyn Shape> {
+        &self.shape
+    }
+
+    fn hit_for(&self, part: &Arc<dyn Shape>) -> Arc<dyn Shape> {
+        match self.hits.get(&part_key(part)) {
+            Some(hit) => Arc::clone(hit),
+            // Only if the shared shape's `parts` missed one of them
+            None => Arc::new(InstanceHit {
+                node: Arc::clone(&self.node),
+                part: Arc::clone(part),
+            }),
+        }
+    }
+}
+
+impl Shape for Instance {
+    fn shared_material(&self) -> &SharedMaterial {
+        &self.material
+    }
+
+    fn transform(&self) -> &Matrix {
+        self.node.transform()
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        self.node.inverse_transform()
+    }
+
+    fn has_identity_transform(&self) -> bool {
+        self.node.is_identity()
+    }
+
+    fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        self.node.parent()
+    }
+
+    fn set_parent(&self, parent: Arc<GroupTransform>) {
+        self.node.set_parent(parent);
+    }
+
+    fn local_intersect(&self, _shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
+        self.shape
+            .intersect(Arc::clone(&self.shape), object_ray)
+            .into_iter()
+            .map(|i| Intersection::new(i.t, self.hit_for(&i.object)))
+            .collect()
+    }
+
+    fn local_normal_at(&self, _object_point: Vector3) -> Vector3 {
+        unreachable!("The normal of an instance is computed by the part that was hit")
+    }
+
+    fn local_eq(&self, other: &dyn Shape) -> bool {
+        std::ptr::eq(self as *const Instance as *const (), other as *const dyn Shape as *const ())
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.shape.parent_space_bounds()
+    }
+
+    fn parent_space_bounds(&self) -> BoundingBox {
+        self.parent_space_bounds.clone()
+    }
+
+    fn includes(&self, other: &dyn Shape) -> bool {
+        self.hits.values().any(|hit| hit.includes(other))
+    }
+
+    fn parts(&self, _shape: &Arc<dyn Shape>) -> Vec<Arc<dyn Shape>> {
+        self.hits.values().cloned().collect()
+    }
+}
+
+// What an intersection with an instance refers to, one part of the shared shape
+// as seen through the instance's transform.
+#[derive(Debug)]
+struct InstanceHit {
+    node: Arc<GroupTransform>,
+    part: Arc<dyn Shape>,
+}
+
+impl Shape for InstanceHit {
+    fn shared_material(&self) -> &SharedMaterial {
+        self.part.shared_material()
+    }
+
+    fn transform(&self) -> &Matrix {
+        self.part.transform()
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        self.part.inverse_transform()
+    }
+
+    fn has_identity_transform(&self) -> bool {
+        self.part.has_identity_transform()
+    }
+
+    fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        Some(&self.node)
+    }
+
+    fn set_parent(&self, _parent: Arc<GroupTransform>) {
+        unreachable!("An instance hit only appears in intersections")
+    }
+
+    fn local_intersect(&self, _shape: Arc<dyn Shape>, _object_ray: Ray) -> Vec<Intersection> {
+        unreachable!("An instance hit only appears in intersections")
+    }
+
+    fn local_normal_at(&self, object_point: Vector3) -> Vector3 {
+        self.part.local_normal_at(object_point)
+    }
+
+    fn uv_at(&self, object_point: &Vector3) -> Option<(f64, f64)> {
+        self.part.uv_at(object_point)
+    }
+
+    fn local_eq(&self, other: &dyn Shape) -> bool {
+        std::ptr::eq(self as *const InstanceHit as *const (), other as *const dyn Shape as *const ())
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.part.bounds()
+    }
+
+    // The part converts from the instance's space with its own transform and any
+    // groups inside the shared shape, the instance's transforms come before that.
+    fn world_to_object(&self, point: &Vector3) -> Vector3 {
+        self.part.world_to_object(&self.node.world_to_object(point))
+    }
+
+    fn normal_to_world(&self, normal: &Vector3) -> Vector3 {
+        self.node.normal_to_world(&self.part.normal_to_world(normal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathf::group::Group;
+    use crate::mathf::sphere::Sphere;
+    use crate::transformations;
+
+    #[test]
+    fn an_instance_is_intersected_where_it_was_moved_to() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let instance: Arc<dyn Shape> = Arc::new(Instance::new(
+            Some(transformations::translation(&Vector3::new(5., 0., 0.))),
+            Arc::clone(&sphere),
+        ));
+
+        let ray = Ray::new(Vector3::new(5., 0., -5.), Vector3::new(0., 0., 1.));
+        let xs = instance.intersect(Arc::clone(&instance), ray);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.);
+        assert_eq!(xs[1].t, 6.);
+        // Both hits are on the same part of the same instance
+        assert!(Arc::ptr_eq(&xs[0].object, &xs[1].object));
+        assert_eq!(xs[0].object.normal_at(Vector3::new(5., 0., -1.)), Vector3::new(0., 0., -1.));
+
+        let miss = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        assert!(instance.intersect(Arc::clone(&instance), miss).is_empty());
+    }
+
+    #[test]
+    fn instances_share_the_geometry_rather_than_copying_it() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let instances: Vec<Instance> = (0..3)
+            .map(|i| {
+                let offset = Vector3::new(i as f64 * 3., 0., 0.);
+                Instance::new(Some(transformations::translation(&offset)), Arc::clone(&sphere))
+            })
+            .collect();
+
+        // Each instance holds the sphere itself and in the hit object for it
+        assert_eq!(Arc::strong_count(&sphere), 1 + 3 * 2);
+        assert!(instances.iter().all(|instance| Arc::ptr_eq(instance.shape(), &sphere)));
+    }
+
+    #[test]
+    fn the_normal_of_a_group_inside_an_instance_uses_every_transform() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(5., 0., 0.))),
+            None,
+        ));
+        let group: Arc<dyn Shape> = Arc::new(Group::new(
+            Some(transformations::scaling(&Vector3::new(1., 2., 3.))),
+            vec![sphere],
+        ));
+        let instance: Arc<dyn Shape> = Arc::new(Instance::new(
+            Some(transformations::rotation_y(std::f64::consts::PI / 2.)),
+            group,
+        ));
+
+        // The same setup as the book's "finding the normal on a child object"
+        let ray = Ray::new(Vector3::new(1.7321, 1.1547, 10.), Vector3::new(0., 0., -1.));
+        let xs = instance.intersect(Arc::clone(&instance), ray);
+        assert!(!xs.is_empty());
+        let normal = xs[0].object.normal_at(Vector3::new(1.7321, 1.1547, -5.5774));
+        assert!((&normal - &Vector3::new(0.2857, 0.42854, -0.85716)).magnitude() < 0.0001);
+    }
+
+    #[test]
+    fn every_part_of_the_shared_shape_has_one_hit_object() {
+        let left: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let right: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(3., 0., 0.))),
+            None,
+        ));
+        let group: Arc<dyn Shape> = Arc::new(Group::new(None, vec![Arc::clone(&left), Arc::clone(&right)]));
+        let instance: Arc<dyn Shape> = Arc::new(Instance::new(None, group));
+        assert_eq!(instance.parts(&instance).len(), 2);
+
+        // Different rays hitting the same part get the same object
+        let first = Ray::new(Vector3::new(3., 0., -5.), Vector3::new(0., 0., 1.));
+        let second = Ray::new(Vector3::new(3.5, 0., -5.), Vector3::new(0., 0., 1.));
+        let a = instance.intersect(Arc::clone(&instance), first);
+        let b = instance.intersect(Arc::clone(&instance), second);
+        assert!(Arc::ptr_eq(&a[0].object, &b[0].object));
+        assert!(instance.includes(&*a[0].object));
+        assert!(!instance.includes(&*right));
+    }
+
+    #[test]
+    fn an_instance_is_bounded_by_the_shared_shape_moved_into_place() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let instance = Instance::new(Some(transformations::translation(&Vector3::new(5., 0., 0.))), sphere);
+        let bounds = instance.parent_space_bounds();
+        assert_eq!(bounds.min, Vector3::new(4., -1., -1.));
+        assert_eq!(bounds.max, Vector3::new(6., 1., 1.));
+    }
+}
@@ -1,7 +1,10 @@
 use crate::mathf;
 use crate::mathf::ray::Ray;
-use crate::mathf::vector3::Vector3;
 use crate::mathf::shapes::Shape;
+use crate::mathf::sphere;
+use crate::mathf::vector3::Vector3;
+use std::cmp::Ordering;
+use std::ops;
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
@@ -18,6 +21,109 @@ pub struct Computations {
     pub normal_vector: Vector3,
     pub is_inside: bool,
     pub over_point: Vector3,
+    pub under_point: Vector3,
+    pub reflect_vector: Vector3,
+    /// Refractive index of the material the ray is leaving
+    pub n1: f64,
+    /// Refractive index of the material the ray is entering
+    pub n2: f64,
+}
+
+impl Computations {
+    /// Build the computations for a hit from the surface normal at the point. Useful
+    /// for testing shading by hand, `prepare_computations` is what a render uses.
+    /// The normal is flipped if it faces away from the eye, and the offset points
+    /// and the reflection vector are worked out from the other values.
+    pub fn new(
+        t: f64,
+        object: Arc<dyn Shape>,
+        point: Vector3,
+        eye_vector: Vector3,
+        normal_vector: Vector3,
+        n1: f64,
+        n2: f64,
+    ) -> Computations {
+        Computations::new_with_offset(t, object, point, eye_vector, normal_vector, n1, n2, mathf::SHADOW_EPSILON)
+    }
+
+    /// Like `new`, but the over and under points are `offset` from the surface
+    /// rather than `mathf::SHADOW_EPSILON`
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_offset(
+        t: f64,
+        object: Arc<dyn Shape>,
+        point: Vector3,
+        eye_vector: Vector3,
+        normal_vector: Vector3,
+        n1: f64,
+        n2: f64,
+        offset: f64,
+    ) -> Computations {
+        let mut normal_vector = normal_vector;
+        let is_inside;
+        if normal_vector.dot(&eye_vector) < 0. {
+            is_inside = true;
+            normal_vector = -normal_vector;
+        } else {
+            is_inside = false;
+        }
+
+        // Nudge the points off the real surface, a bumpy normal could point them the wrong way
+        let over_point = &point + &(normal_vector.clone() * offset);
+        let under_point = &point - &(normal_vector.clone() * offset);
+
+        let normal_map = object.material().normal_map.clone();
+        if let Some(normal_map) = normal_map {
+            normal_vector = normal_map.normal_at_shape(&*object, &point);
+            if is_inside {
+                normal_vector = -normal_vector;
+            }
+        }
+
+        // Bumps go on top of the normal map, and are sampled in object space so
+        // they move with the shape
+        if let Some(bump) = object.material().bump {
+            let object_point = object.world_to_object(&point);
+            normal_vector = bump.perturb_normal(&object_point, &normal_vector);
+        }
+
+        let reflect_vector = sphere::reflect(&(-eye_vector.clone()), &normal_vector);
+
+        Computations {
+            t,
+            object,
+            point,
+            eye_vector,
+            normal_vector,
+            is_inside,
+            over_point,
+            under_point,
+            reflect_vector,
+            n1,
+            n2,
+        }
+    }
+
+    /// Schlick's approximation of the Fresnel effect. Returns the fraction of
+    /// the light that is reflected, from 0.0 to 1.0.
+    pub fn schlick(&self) -> f64 {
+        let mut cos = self.eye_vector.dot(&self.normal_vector);
+
+        // Total internal reflection can only occur if n1 > n2
+        if self.n1 > self.n2 {
+            let n = self.n1 / self.n2;
+            let sin2_t = n * n * (1. - cos * cos);
+            if sin2_t > 1. {
+                return 1.;
+            }
+
+            // When n1 > n2 use cos(theta_t) instead
+            cos = (1. - sin2_t).sqrt();
+        }
+
+        let r0 = ((self.n1 - self.n2) / (self.n1 + self.n2)).powi(2);
+        r0 + (1. - r0) * (1. - cos).powi(5)
+    }
 }
 
 pub struct Intersections {
@@ -36,6 +142,54 @@ impl Intersections {
         Intersections { intersections }
     }
 
+    pub fn len(&self) -> usize {
+        self.intersections.len()
+    }
+
+    pub fn count(&self) -> usize {
+        self.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intersections.is_empty()
+    }
+
+    /// Sort by t, a NaN t is treated as greater than any other value so it ends up last
+    pub fn sort(&mut self) {
+        self.intersections.sort_by(compare_t);
+    }
+
+    /// The intersections in order of t without reordering them in place, NaN last
+    /// as for `sort`
+    pub fn sorted(&self) -> impl Iterator<Item = &Intersection> {
+        let mut sorted: Vec<&Intersection> = self.intersections.iter().collect();
+        sorted.sort_by(|a, b| compare_t(a, b));
+        sorted.into_iter()
+    }
+
+    /// Combine two sets of intersections, the result is sorted by t
+    pub fn merge(mut self, other: Intersections) -> Intersections {
+        self.intersections.extend(other.intersections);
+        self.sort();
+        self
+    }
+
+    /// The fraction of light that passes through every surface between t=0 and `distance`.
+    /// An opaque surface blocks all of the light, a transparent one lets `transparency`
+    /// of it through. Used for shadow rays so glass doesn't cast a solid shadow.
+    pub fn transmittance(&self, distance: f64) -> f64 {
+        let mut transmitted = 1.;
+        for i in &self.intersections {
+            if i.t >= 0. && i.t < distance {
+                transmitted *= i.object.material().transparency.clamp(0., 1.);
+                if transmitted <= 0. {
+                    return 0.;
+                }
+            }
+        }
+        transmitted
+    }
+
     pub fn hit(&self) -> Option<Intersection> {
         // If there is a hit, it will be the intersection with the lowest nonnegative t value
 
@@ -57,45 +211,122 @@ impl Intersections {
     }
 }
 
+impl ops::Index<usize> for Intersections {
+    type Output = Intersection;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.intersections[index]
+    }
+}
+
+fn compare_t(a: &Intersection, b: &Intersection) -> Ordering {
+    match a.t.partial_cmp(&b.t) {
+        Some(ordering) => ordering,
+        None => match (a.t.is_nan(), b.t.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            _ => Ordering::Less,
+        },
+    }
+}
+
 impl Intersection {
     pub fn new(t: f64, object: Arc<dyn Shape>) -> Intersection {
         Intersection { t, object }
     }
 
+    /// Prepare the computations treating this as the only intersection, so the
+    /// ray is assumed to travel through empty space (refractive index 1.0).
     pub fn prepare_computations(&self, ray: Ray) -> Computations {
+        self.prepare_computations_with(ray, &Intersections::new(vec![self.clone()]))
+    }
+
+    /// Prepare the computations for this hit. All of the intersections along the
+    /// ray are needed to work out which materials the ray is passing between.
+    pub fn prepare_computations_with(&self, ray: Ray, intersections: &Intersections) -> Computations {
+        self.prepare_computations_with_offset(ray, intersections, mathf::SHADOW_EPSILON)
+    }
+
+    /// Like `prepare_computations_with`, with the over and under points `offset`
+    /// from the surface. See `World::surface_offset`.
+    pub fn prepare_computations_with_offset(&self, ray: Ray, intersections: &Intersections, offset: f64) -> Computations {
         let point = ray.position(self.t);
         let eye_vector = -ray.direction;
-        let mut normal_vector = self.object.normal_at(point.clone());
-
-        let is_inside;
-        if normal_vector.dot(&eye_vector) < 0. {
-            is_inside = true;
-            normal_vector = -normal_vector;
-        } else {
-            is_inside = false;
-        }
+        let normal_vector = self.object.normal_at(point.clone());
+        let (n1, n2) = self.refractive_indices(intersections);
 
-        let over_point = &point + &(normal_vector.clone() * mathf::EPSILON);
-        Computations {
-            t: self.t,
-            object: Arc::clone(&self.object),
+        Computations::new_with_offset(
+            self.t,
+            Arc::clone(&self.object),
             point,
             eye_vector,
             normal_vector,
-            is_inside,
-            over_point,
+            n1,
+            n2,
+            offset,
+        )
+    }
+
+    // Walk the intersections keeping track of which objects the ray is inside of,
+    // when we reach this hit the last object entered gives n1 and the next one gives n2.
+    fn refractive_indices(&self, intersections: &Intersections) -> (f64, f64) {
+        let mut n1 = 1.0;
+        let mut n2 = 1.0;
+        let mut containers: Vec<&Arc<dyn Shape>> = vec![];
+
+        for i in intersections.intersections.iter() {
+            let is_hit = i.t == self.t && same_object(&i.object, &self.object);
+
+            if is_hit {
+                if let Some(last) = containers.last() {
+                    n1 = last.material().refractive_index;
+                }
+            }
+
+            match containers.iter().position(|o| same_object(o, &i.object)) {
+                Some(index) => {
+                    containers.remove(index);
+                }
+                None => containers.push(&i.object),
+            }
+
+            if is_hit {
+                if let Some(last) = containers.last() {
+                    n2 = last.material().refractive_index;
+                }
+                break;
+            }
         }
+
+        (n1, n2)
     }
 }
 
+fn same_object(a: &Arc<dyn Shape>, b: &Arc<dyn Shape>) -> bool {
+    std::ptr::eq(Arc::as_ptr(a) as *const (), Arc::as_ptr(b) as *const ())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::material::Material;
     use crate::mathf::approximately;
-    use crate::mathf::vector3::Vector3;
+    use crate::mathf::plane::Plane;
     use crate::mathf::sphere::Sphere;
+    use crate::mathf::vector3::Vector3;
+    use crate::canvas::Canvas;
+    use crate::color::Color;
+    use crate::mathf::uv_mapping::UvMapping;
+    use crate::patterns::normal_map::NormalMap;
+    use crate::patterns::perturb::Perturbation;
     use crate::transformations;
 
+    fn glass_sphere(transform: Option<crate::mathf::matrix::Matrix>, refractive_index: f64) -> Arc<dyn Shape> {
+        let mut material = Material::new();
+        material.transparency = 1.0;
+        material.refractive_index = refractive_index;
+        Arc::new(Sphere::new(transform, Some(material)))
+    }
+
     #[test]
     fn an_intersection_encapsulates_t_and_object() {
         let s: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
@@ -164,7 +395,276 @@ mod tests {
         let i = Intersection::new(5., Arc::clone(&sphere));
 
         let computations = i.prepare_computations(ray);
-        assert!(computations.over_point.z < -crate::mathf::EPSILON / 2.);
+        assert!(computations.over_point.z < -crate::mathf::SHADOW_EPSILON / 2.);
         assert!(computations.point.z > computations.over_point.z);
     }
+
+    #[test]
+    fn test_intersections_len_count_and_index() {
+        let s: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let xs = Intersections::new(vec![]);
+        assert!(xs.is_empty());
+        assert_eq!(xs.len(), 0);
+
+        let xs = Intersections::new(vec![
+            Intersection::new(1.0, Arc::clone(&s)),
+            Intersection::new(2.0, Arc::clone(&s)),
+        ]);
+        assert!(!xs.is_empty());
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs.count(), 2);
+        assert_eq!(xs[0].t, 1.0);
+        assert_eq!(xs[1].t, 2.0);
+    }
+
+    #[test]
+    fn merging_intersections_sorts_by_t() {
+        let s: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let a = Intersections::new(vec![
+            Intersection::new(1.0, Arc::clone(&s)),
+            Intersection::new(5.0, Arc::clone(&s)),
+        ]);
+        let b = Intersections::new(vec![
+            Intersection::new(4.0, Arc::clone(&s)),
+            Intersection::new(-2.0, Arc::clone(&s)),
+        ]);
+
+        let xs = a.merge(b);
+        assert_eq!(xs.count(), 4);
+        assert_eq!(xs[0].t, -2.0);
+        assert_eq!(xs[1].t, 1.0);
+        assert_eq!(xs[2].t, 4.0);
+        assert_eq!(xs[3].t, 5.0);
+    }
+
+    #[test]
+    fn sorting_intersections_with_a_nan_t_does_not_panic() {
+        let s: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let mut xs = Intersections::new(vec![
+            Intersection::new(3.0, Arc::clone(&s)),
+            Intersection::new(f64::NAN, Arc::clone(&s)),
+            Intersection::new(1.0, Arc::clone(&s)),
+        ]);
+
+        xs.sort();
+        assert_eq!(xs[0].t, 1.0);
+        assert_eq!(xs[1].t, 3.0);
+        assert!(xs[2].t.is_nan());
+        assert_eq!(xs.hit().unwrap().t, 1.0);
+    }
+
+    #[test]
+    fn a_sorted_view_of_intersections_leaves_them_in_place() {
+        let s: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let xs = Intersections::new(vec![
+            Intersection::new(5.0, Arc::clone(&s)),
+            Intersection::new(f64::NAN, Arc::clone(&s)),
+            Intersection::new(-2.0, Arc::clone(&s)),
+            Intersection::new(1.0, Arc::clone(&s)),
+        ]);
+
+        let ts: Vec<f64> = xs.sorted().map(|i| i.t).collect();
+        assert_eq!(ts[..3], [-2.0, 1.0, 5.0]);
+        assert!(ts[3].is_nan());
+        assert_eq!(xs[0].t, 5.0);
+        assert_eq!(xs[3].t, 1.0);
+
+        // The second surface in front of the ray
+        assert_eq!(xs.sorted().filter(|i| i.t >= 0.).nth(1).unwrap().t, 5.0);
+    }
+
+    #[test]
+    fn a_bumpy_material_perturbs_the_normal_but_not_the_over_point() {
+        let mut material = Material::new();
+        material.bump = Some(Perturbation::new(0.2, 4.));
+        let bumpy: Arc<dyn Shape> = Arc::new(Plane::new(None, Some(material)));
+        let flat: Arc<dyn Shape> = Arc::new(Plane::new(None, None));
+
+        let ray = Ray::new(Vector3::new(0.3, 1., 0.7), Vector3::new(0., -1., 0.));
+        let bumpy_comps = Intersection::new(1., bumpy).prepare_computations(ray.clone());
+        let flat_comps = Intersection::new(1., flat).prepare_computations(ray);
+
+        assert_ne!(bumpy_comps.normal_vector, flat_comps.normal_vector);
+        assert!(approximately(bumpy_comps.normal_vector.magnitude(), 1.));
+        assert_eq!(bumpy_comps.over_point, flat_comps.over_point);
+        assert_eq!(
+            bumpy_comps.normal_vector,
+            Perturbation::new(0.2, 4.).perturb_normal(&Vector3::new(0.3, 0., 0.7), &flat_comps.normal_vector)
+        );
+    }
+
+    #[test]
+    fn a_zero_amplitude_bump_leaves_the_normal_alone() {
+        let mut material = Material::new();
+        material.bump = Some(Perturbation::new(0., 4.));
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new(None, Some(material)));
+        let ray = Ray::new(Vector3::new(0.3, 1., 0.7), Vector3::new(0., -1., 0.));
+        let comps = Intersection::new(1., plane).prepare_computations(ray);
+        assert_eq!(comps.normal_vector, Vector3::new(0., 1., 0.));
+    }
+
+    #[test]
+    fn a_bump_is_applied_on_top_of_a_normal_map() {
+        let mut image = Canvas::new(2, 2);
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            image.write_pixel(x, y, &Color::new(0.5, 0.5, 1.));
+        }
+        let normal_map = Arc::new(NormalMap::new(None, image, UvMapping::Spherical));
+        let bump = Perturbation::new(0.2, 4.);
+        let mapped = Material::builder().normal_map(normal_map).bump(bump).build();
+        let mapped: Arc<dyn Shape> = Arc::new(Sphere::new(None, Some(mapped)));
+        let bumped: Arc<dyn Shape> = Arc::new(Sphere::new(None, Some(Material::builder().bump(bump).build())));
+
+        // A flat map leaves the geometric normal, so only the bump shows, from
+        // outside and (flipped) from inside
+        let outside = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let inside = Ray::new(Vector3::new(0., 0., 0.), Vector3::new(0., 0., 1.));
+        for (t, ray) in [(4., outside), (1., inside)] {
+            let comps = Intersection::new(t, Arc::clone(&mapped)).prepare_computations(ray.clone());
+            let expected = Intersection::new(t, Arc::clone(&bumped)).prepare_computations(ray);
+            assert_ne!(comps.normal_vector, comps.eye_vector);
+            assert_eq!(comps.normal_vector, expected.normal_vector);
+        }
+    }
+
+    #[test]
+    fn precomputing_the_reflection_vector() {
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new(None, None));
+        let ray = Ray::new(
+            Vector3::new(0., 1., -1.),
+            Vector3::new(0., -(2f64.sqrt()) / 2., 2f64.sqrt() / 2.),
+        );
+        let i = Intersection::new(2f64.sqrt(), Arc::clone(&plane));
+        let computations = i.prepare_computations(ray);
+        assert_eq!(
+            computations.reflect_vector,
+            Vector3::new(0., 2f64.sqrt() / 2., 2f64.sqrt() / 2.)
+        );
+    }
+
+    #[test]
+    fn the_reflection_vector_for_a_45_degree_hit() {
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new(None, None));
+        let ray = Ray::new(
+            Vector3::new(0., 1., -1.),
+            Vector3::new(0., -(2f64.sqrt()) / 2., 2f64.sqrt() / 2.),
+        );
+        let computations = Intersection::new(2f64.sqrt(), plane).prepare_computations(ray.clone());
+        assert_eq!(
+            computations.reflect_vector,
+            sphere::reflect(&ray.direction, &computations.normal_vector)
+        );
+    }
+
+    #[test]
+    fn building_computations_by_hand_matches_prepare_computations() {
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new(None, None));
+        let ray = Ray::new(
+            Vector3::new(0., 1., -1.),
+            Vector3::new(0., -(2f64.sqrt()) / 2., 2f64.sqrt() / 2.),
+        );
+        let prepared = Intersection::new(2f64.sqrt(), Arc::clone(&plane)).prepare_computations(ray.clone());
+
+        let computations = Computations::new(
+            2f64.sqrt(),
+            plane,
+            Vector3::new(0., 0., 0.),
+            -ray.direction,
+            Vector3::new(0., 1., 0.),
+            1.,
+            1.,
+        );
+        assert_eq!(computations.point, prepared.point);
+        assert_eq!(computations.normal_vector, prepared.normal_vector);
+        assert_eq!(computations.over_point, prepared.over_point);
+        assert_eq!(computations.under_point, prepared.under_point);
+        assert_eq!(computations.reflect_vector, prepared.reflect_vector);
+        assert_eq!(computations.is_inside, prepared.is_inside);
+    }
+
+    #[test]
+    fn building_computations_flips_a_normal_facing_away_from_the_eye() {
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new(None, None));
+        let computations = Computations::new(
+            1.,
+            plane,
+            Vector3::new(0., 0., 0.),
+            Vector3::new(0., -1., 0.),
+            Vector3::new(0., 1., 0.),
+            1.,
+            1.,
+        );
+        assert!(computations.is_inside);
+        assert_eq!(computations.normal_vector, Vector3::new(0., -1., 0.));
+        assert_eq!(computations.reflect_vector, Vector3::new(0., -1., 0.));
+    }
+
+    #[test]
+    fn finding_n1_and_n2_at_various_intersections() {
+        let a = glass_sphere(Some(transformations::scaling(&Vector3::new(2., 2., 2.))), 1.5);
+        let b = glass_sphere(Some(transformations::translation(&Vector3::new(0., 0., -0.25))), 2.0);
+        let c = glass_sphere(Some(transformations::translation(&Vector3::new(0., 0., 0.25))), 2.5);
+
+        let ray = Ray::new(Vector3::new(0., 0., -4.), Vector3::new(0., 0., 1.));
+        let xs = Intersections::new(vec![
+            Intersection::new(2., Arc::clone(&a)),
+            Intersection::new(2.75, Arc::clone(&b)),
+            Intersection::new(3.25, Arc::clone(&c)),
+            Intersection::new(4.75, Arc::clone(&b)),
+            Intersection::new(5.25, Arc::clone(&c)),
+            Intersection::new(6., Arc::clone(&a)),
+        ]);
+
+        let expected = [(1.0, 1.5), (1.5, 2.0), (2.0, 2.5), (2.5, 2.5), (2.5, 1.5), (1.5, 1.0)];
+        for (index, (n1, n2)) in expected.iter().enumerate() {
+            let computations = xs[index].prepare_computations_with(ray.clone(), &xs);
+            assert_eq!(computations.n1, *n1);
+            assert_eq!(computations.n2, *n2);
+        }
+    }
+
+    #[test]
+    fn the_under_point_is_offset_below_the_surface() {
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let shape = glass_sphere(Some(transformations::translation(&Vector3::new(0., 0., 1.))), 1.5);
+        let i = Intersection::new(5., Arc::clone(&shape));
+        let xs = Intersections::new(vec![i.clone()]);
+
+        let computations = i.prepare_computations_with(ray, &xs);
+        assert!(computations.under_point.z > crate::mathf::SHADOW_EPSILON / 2.);
+        assert!(computations.point.z < computations.under_point.z);
+    }
+
+    #[test]
+    fn the_schlick_approximation_under_total_internal_reflection() {
+        let shape = glass_sphere(None, 1.5);
+        let ray = Ray::new(Vector3::new(0., 0., 2f64.sqrt() / 2.), Vector3::new(0., 1., 0.));
+        let xs = Intersections::new(vec![
+            Intersection::new(-(2f64.sqrt()) / 2., Arc::clone(&shape)),
+            Intersection::new(2f64.sqrt() / 2., Arc::clone(&shape)),
+        ]);
+        let computations = xs[1].prepare_computations_with(ray, &xs);
+        assert_eq!(computations.schlick(), 1.0);
+    }
+
+    #[test]
+    fn the_schlick_approximation_with_a_perpendicular_viewing_angle() {
+        let shape = glass_sphere(None, 1.5);
+        let ray = Ray::new(Vector3::new(0., 0., 0.), Vector3::new(0., 1., 0.));
+        let xs = Intersections::new(vec![
+            Intersection::new(-1., Arc::clone(&shape)),
+            Intersection::new(1., Arc::clone(&shape)),
+        ]);
+        let computations = xs[1].prepare_computations_with(ray, &xs);
+        assert!(approximately(computations.schlick(), 0.04));
+    }
+
+    #[test]
+    fn the_schlick_approximation_with_small_angle_and_n2_greater_than_n1() {
+        let shape = glass_sphere(None, 1.5);
+        let ray = Ray::new(Vector3::new(0., 0.99, -2.), Vector3::new(0., 0., 1.));
+        let xs = Intersections::new(vec![Intersection::new(1.8589, Arc::clone(&shape))]);
+        let computations = xs[0].prepare_computations_with(ray, &xs);
+        assert!(approximately(computations.schlick(), 0.48873));
+    }
 }
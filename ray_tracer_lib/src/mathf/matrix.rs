@@ -1,5 +1,7 @@
 use crate::mathf;
+use crate::mathf::matrix_n::MatrixN;
 use crate::mathf::vector3::Vector3;
+use crate::transformations;
 
 #[derive(Debug, Clone)]
 pub struct Matrix {
@@ -40,14 +42,19 @@ impl PartialEq for Row {
 // ------------ Matrix implementations ------------
 impl PartialEq for Matrix {
     fn eq(&self, other: &Self) -> bool {
-        for r in 0..self.size {
-            for c in 0..self.size {
-                if !mathf::approximately(self.data[r][c], other.data[r][c]) {
-                    return false;
-                }
+        self.approx_eq(other, mathf::EQUALITY_EPSILON)
+    }
+}
+
+impl From<&Matrix> for MatrixN {
+    fn from(matrix: &Matrix) -> Self {
+        let mut result = MatrixN::new(matrix.size);
+        for row in 0..matrix.size {
+            for col in 0..matrix.size {
+                result[(row, col)] = matrix.data[row][col];
             }
         }
-        true
+        result
     }
 }
 
@@ -112,7 +119,16 @@ impl Matrix {
         // By setting w to be 1 the point * transform = transformed point in space;
         // If w = 0 then point * transform = only rotated point.
         debug_assert!(self.size == 4, "Currently only supports 4x4 matrices");
-        self.multiply_vector4(&rhs, 1.)
+        let (point, w) = self.multiply_vector4(rhs, 1.);
+
+        // Affine transforms (all of the ones in `transformations`) always leave w
+        // at exactly 1. Projective matrices don't, so divide to get back to w = 1.
+        // A w of 0 is a point at infinity, there's nothing to divide by so it is
+        // returned as is rather than turned into infinities and NaN.
+        if w != 1. && w != 0. {
+            return &point / w;
+        }
+        point
     }
 
     pub fn multiply_vector(&self, rhs: &Vector3) -> Vector3 {
@@ -120,10 +136,11 @@ impl Matrix {
         // By setting w to be 1 the point * transform = transformed point in space;
         // If w = 0 then point * transform = only rotated point.
         debug_assert!(self.size == 4, "Currently only supports 4x4 matrices");
-        self.multiply_vector4(&rhs, 0.)
+        self.multiply_vector4(rhs, 0.).0
     }
 
-    fn multiply_vector4(&self, rhs: &Vector3, w: f64) -> Vector3 {
+    // The x, y and z of the product, and the new w
+    fn multiply_vector4(&self, rhs: &Vector3, w: f64) -> (Vector3, f64) {
         debug_assert!(self.size == 4, "Currently only supports 4x4 matrices");
 
         let x = self.data[0][0] * rhs.x
@@ -141,12 +158,12 @@ impl Matrix {
             + self.data[2][2] * rhs.z
             + self.data[2][3] * w;
 
-        // let w = self.data[3][0] * rhs.x
-        //     + self.data[3][1] * rhs.y
-        //     + self.data[3][2] * rhs.z
-        //     + self.data[3][3] * w;
+        let w = self.data[3][0] * rhs.x
+            + self.data[3][1] * rhs.y
+            + self.data[3][2] * rhs.z
+            + self.data[3][3] * w;
 
-        Vector3::new(x, y, z)
+        (Vector3::new(x, y, z), w)
     }
 
     pub fn transpose(&self) -> Matrix {
@@ -163,12 +180,12 @@ impl Matrix {
 
     // Return a copy of a matrix with a given row and column removed
     pub fn submatrix(&self, remove_row: usize, remove_col: usize) -> Matrix {
-        debug_assert!(self.size == 4 || self.size == 3, "Only supports 4x4 and 3x3 matrices");
-        let mut matrix = match self.size {
-            4 => Matrix::new_size(3),
-            3 => Matrix::new_size(2),
-            x => panic!(format!("Unexpected matrix size: {}", x)),
-        };
+        debug_assert!(
+            self.size > 2,
+            "The submatrix of a {0}x{0} matrix would be smaller than 2x2, see MatrixN::submatrix",
+            self.size
+        );
+        let mut matrix = Matrix::new_size(self.size - 1);
 
         for row in 0..matrix.size {
             let mut actual_row = row;
@@ -222,7 +239,9 @@ impl Matrix {
     pub fn inverse(&self) -> Option<Matrix> {
         debug_assert!(self.size == 4, "Currently only supports 4x4 matrices");
 
-        if !self.is_invertible() {
+        // Computed once, it is the same for every element
+        let determinant = self.determinant();
+        if mathf::approximately(determinant, 0.0) {
             return None
         }
 
@@ -232,7 +251,7 @@ impl Matrix {
                 let c = self.cofactor(row, col);
 
                 // note the "[col][row]" here which achieves a transpose
-                matrix.data[col][row] = c / self.determinant();
+                matrix.data[col][row] = c / determinant;
             }
         }
 
@@ -240,10 +259,90 @@ impl Matrix {
     }
 }
 
+impl Matrix {
+    /// True if every element is within `epsilon` of the other matrix's element.
+    /// `==` does the same with `mathf::EQUALITY_EPSILON`, a looser epsilon can be
+    /// handy for matrices built from many transforms where the error adds up.
+    pub fn approx_eq(&self, other: &Matrix, epsilon: f64) -> bool {
+        for r in 0..self.size {
+            for c in 0..self.size {
+                if (self.data[r][c] - other.data[r][c]).abs() >= epsilon {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// True if this is exactly the 4x4 identity matrix, so transforming by it
+    /// would change nothing. Unlike `==` there is no epsilon, a tiny translation
+    /// or scale still moves points.
+    pub fn is_identity(&self) -> bool {
+        self.size == 4
+            && (0..4).all(|r| (0..4).all(|c| self.data[r][c] == if r == c { 1. } else { 0. }))
+    }
+
+    /// Every element multiplied by `factor`. Not to be confused with `scale`,
+    /// which applies a scaling transform.
+    pub fn multiply_scalar(&self, factor: f64) -> Matrix {
+        let mut matrix = Matrix::new_size(self.size);
+        for r in 0..self.size {
+            for c in 0..self.size {
+                matrix.data[r][c] = self.data[r][c] * factor;
+            }
+        }
+        matrix
+    }
+
+    /// Element by element difference, handy for seeing how far apart two
+    /// matrices are rather than only whether they are equal.
+    pub fn subtract(&self, other: &Matrix) -> Matrix {
+        debug_assert!(self.size == other.size, "Both matrices must be the same size");
+        let mut matrix = Matrix::new_size(self.size);
+        for r in 0..self.size {
+            for c in 0..self.size {
+                matrix.data[r][c] = self.data[r][c] - other.data[r][c];
+            }
+        }
+        matrix
+    }
+}
+
+// Fluent versions of the functions in `transformations`. Each one applies its
+// transform after the ones already in the matrix, so a chain reads in the order
+// the transforms happen:
+// Matrix::identity_4x4().rotate_x(r).scale(5., 5., 5.).translate(10., 5., 7.)
+impl Matrix {
+    pub fn translate(self, x: f64, y: f64, z: f64) -> Matrix {
+        transformations::translation(&Vector3::new(x, y, z)).multiply_4x4(&self)
+    }
+
+    pub fn scale(self, x: f64, y: f64, z: f64) -> Matrix {
+        transformations::scaling(&Vector3::new(x, y, z)).multiply_4x4(&self)
+    }
+
+    pub fn rotate_x(self, radians: f64) -> Matrix {
+        transformations::rotation_x(radians).multiply_4x4(&self)
+    }
+
+    pub fn rotate_y(self, radians: f64) -> Matrix {
+        transformations::rotation_y(radians).multiply_4x4(&self)
+    }
+
+    pub fn rotate_z(self, radians: f64) -> Matrix {
+        transformations::rotation_z(radians).multiply_4x4(&self)
+    }
+
+    pub fn shear(self, xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Matrix {
+        transformations::shearing(xy, xz, yx, yz, zx, zy).multiply_4x4(&self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::mathf::approximately;
+    use std::f64::consts::PI;
 
     #[test]
     fn it_creates_a_4x4_matrix() {
@@ -438,12 +537,12 @@ mod tests {
         // let vector = Vector4::new(1.0, 2.0, 3.0, 1.0);
         let vector = Vector3::new(1.0, 2.0, 3.0);
 
-        let result = matrix1.multiply_vector4(&vector, 1.0);
+        let (result, w) = matrix1.multiply_vector4(&vector, 1.0);
 
         assert!(approximately(result.x, 18.0));
         assert!(approximately(result.y, 24.0));
         assert!(approximately(result.z, 33.0));
-        // assert!(approximately(result.w, 1.0));
+        assert!(approximately(w, 1.0));
     }
 
     #[test]
@@ -479,12 +578,12 @@ mod tests {
         // let vector = Vector4::new(1.0, 2.0, 3.0, 4.0);
         // let result = matrix.multiply_vector4(&vector);
         let vector = Vector3::new(1.0, 2.0, 3.0);
-        let result = matrix.multiply_vector4(&vector, 0.);
+        let (result, w) = matrix.multiply_vector4(&vector, 0.);
 
         assert_eq!(vector.x, result.x);
         assert_eq!(vector.y, result.y);
         assert_eq!(vector.z, result.z);
-        // assert_eq!(vector.w, result.w);
+        assert_eq!(w, 0.);
     }
 
     #[test]
@@ -915,6 +1014,21 @@ mod tests {
         assert_eq!(inverted_matrix, expected);
     }
 
+    #[test]
+    fn the_inverse_divides_every_cofactor_by_the_determinant() {
+        let mut a = Matrix::new();
+        a.data[0] = Row::new([-5., 2., 6., -8.]);
+        a.data[1] = Row::new([1., -5., 1., 8.]);
+        a.data[2] = Row::new([7., 7., -6., -7.]);
+        a.data[3] = Row::new([1., -3., 7., 4.]);
+        let inverse = a.inverse().unwrap();
+        for row in 0..4 {
+            for col in 0..4 {
+                assert_eq!(inverse.data[col][row], a.cofactor(row, col) / a.determinant());
+            }
+        }
+    }
+
     #[test]
     fn test_4x4_matrix_inverse_inverse() {
         // Testing that if you multiply a matrix A by another matrix B, producing C,
@@ -966,4 +1080,99 @@ mod tests {
         let result = matrix_c.multiply_4x4(&matrix_b.inverse().unwrap());
         assert_eq!(result, matrix_a);
     }
+
+    #[test]
+    fn fluent_transformations_are_applied_in_order() {
+        let point = Vector3::new(1.0, 0.0, 1.0);
+        let transform = Matrix::identity_4x4()
+            .rotate_x(PI / 2.0)
+            .scale(5.0, 5.0, 5.0)
+            .translate(10.0, 5.0, 7.0);
+
+        let expected = transformations::translation(&Vector3::new(10.0, 5.0, 7.0))
+            .multiply_4x4(&transformations::scaling(&Vector3::new(5.0, 5.0, 5.0)))
+            .multiply_4x4(&transformations::rotation_x(PI / 2.0));
+        assert_eq!(transform, expected);
+        assert_eq!(transform.multiply_point(&point), Vector3::new(15.0, 0.0, 7.0));
+    }
+
+    #[test]
+    fn fluent_rotations_and_shearing_match_the_transformation_functions() {
+        let transform = Matrix::identity_4x4()
+            .rotate_y(PI / 4.0)
+            .rotate_z(PI / 3.0)
+            .shear(1.0, 0.0, 0.0, 0.0, 0.0, 1.0);
+
+        let expected = transformations::shearing(1.0, 0.0, 0.0, 0.0, 0.0, 1.0)
+            .multiply_4x4(&transformations::rotation_z(PI / 3.0))
+            .multiply_4x4(&transformations::rotation_y(PI / 4.0));
+        assert_eq!(transform, expected);
+    }
+
+    #[test]
+    fn comparing_matrices_with_a_chosen_epsilon() {
+        let a = Matrix::identity_4x4();
+        let mut b = Matrix::identity_4x4();
+        b[1][2] = 1e-4;
+        b[3][0] = -1e-4;
+
+        assert!(a != b);
+        assert!(!a.approx_eq(&b, mathf::EQUALITY_EPSILON));
+        assert!(a.approx_eq(&b, 1e-3));
+        assert!(a.approx_eq(&a, mathf::EQUALITY_EPSILON));
+    }
+
+    #[test]
+    fn multiplying_by_a_scalar_and_subtracting_element_by_element() {
+        let a = Matrix::identity_4x4()
+            .rotate_y(PI / 3.)
+            .scale(2., 3., 4.)
+            .translate(1., -2., 5.);
+
+        let doubled = a.multiply_scalar(2.);
+        for r in 0..4 {
+            for c in 0..4 {
+                assert_eq!(doubled[r][c], a[r][c] * 2.);
+            }
+        }
+        assert!(a.subtract(&a).data.iter().all(|row| (0..4).all(|c| row[c] == 0.)));
+
+        // How far a matrix times its inverse is from the identity
+        let error = a
+            .multiply_4x4(&a.inverse().unwrap())
+            .subtract(&Matrix::identity_4x4())
+            .data
+            .iter()
+            .flat_map(|row| (0..4).map(move |c| row[c].abs()))
+            .fold(0., f64::max);
+        assert!(error < 1e-12);
+    }
+
+    #[test]
+    fn checking_for_the_identity_matrix() {
+        assert!(Matrix::identity_4x4().is_identity());
+        assert!(Matrix::identity_4x4().translate(0., 0., 0.).is_identity());
+        assert!(!Matrix::identity_4x4().translate(1e-7, 0., 0.).is_identity());
+        assert!(!Matrix::identity_4x4().scale(1.000005, 1., 1.).is_identity());
+        assert!(!Matrix::identity_4x4().translate(1., 0., 0.).is_identity());
+        assert!(!Matrix::identity_4x4().scale(2., 2., 2.).is_identity());
+    }
+
+    #[test]
+    fn a_projective_matrix_divides_points_by_w() {
+        let mut matrix = Matrix::identity_4x4();
+        matrix.data[3][3] = 2.;
+        assert_eq!(matrix.multiply_point(&Vector3::new(2., 4., 6.)), Vector3::new(1., 2., 3.));
+        // Vectors have a w of 0, so aren't divided
+        assert_eq!(matrix.multiply_vector(&Vector3::new(2., 4., 6.)), Vector3::new(2., 4., 6.));
+
+        // w can also depend on the point, as in a perspective projection
+        let mut perspective = Matrix::identity_4x4();
+        perspective.data[3][2] = 1.;
+        perspective.data[3][3] = 0.;
+        assert_eq!(perspective.multiply_point(&Vector3::new(2., 4., 2.)), Vector3::new(1., 2., 1.));
+
+        // On the plane z = 0 the point goes off to infinity, w is left undivided
+        assert_eq!(perspective.multiply_point(&Vector3::new(2., 4., 0.)), Vector3::new(2., 4., 0.));
+    }
 }
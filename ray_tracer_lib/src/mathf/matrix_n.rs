@@ -0,0 +1,256 @@
+use crate::mathf;
+
+/// A square matrix of any size.
+///
+/// `Matrix` is fixed at 4x4 (or smaller) which is all that transformations need,
+/// and is faster for it. This is for everything else.
+#[derive(Debug, Clone)]
+pub struct MatrixN {
+    dim: usize,
+    data: Vec<f64>,
+}
+
+impl PartialEq for MatrixN {
+    fn eq(&self, other: &Self) -> bool {
+        self.dim == other.dim
+            && self
+                .data
+                .iter()
+                .zip(other.data.iter())
+                .all(|(a, b)| mathf::approximately(*a, *b))
+    }
+}
+
+impl std::ops::Index<(usize, usize)> for MatrixN {
+    type Output = f64;
+    fn index(&self, (row, col): (usize, usize)) -> &Self::Output {
+        &self.data[row * self.dim + col]
+    }
+}
+
+impl std::ops::IndexMut<(usize, usize)> for MatrixN {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut Self::Output {
+        &mut self.data[row * self.dim + col]
+    }
+}
+
+impl MatrixN {
+    /// Create a dim x dim matrix filled with zeros
+    pub fn new(dim: usize) -> MatrixN {
+        MatrixN {
+            dim,
+            data: vec![0.0; dim * dim],
+        }
+    }
+
+    pub fn identity(dim: usize) -> MatrixN {
+        let mut matrix = MatrixN::new(dim);
+        for i in 0..dim {
+            matrix[(i, i)] = 1.;
+        }
+        matrix
+    }
+
+    /// Create a matrix from its rows, every row must be as long as there are rows
+    pub fn from_rows(rows: &[Vec<f64>]) -> MatrixN {
+        let dim = rows.len();
+        let mut matrix = MatrixN::new(dim);
+        for (row, values) in rows.iter().enumerate() {
+            assert_eq!(values.len(), dim, "A MatrixN must be square");
+            for (col, value) in values.iter().enumerate() {
+                matrix[(row, col)] = *value;
+            }
+        }
+        matrix
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn multiply(&self, rhs: &MatrixN) -> MatrixN {
+        debug_assert!(self.dim == rhs.dim, "Matrices must be the same size");
+        let mut matrix = MatrixN::new(self.dim);
+        for row in 0..self.dim {
+            for col in 0..self.dim {
+                matrix[(row, col)] = (0..self.dim).map(|i| self[(row, i)] * rhs[(i, col)]).sum();
+            }
+        }
+        matrix
+    }
+
+    pub fn transpose(&self) -> MatrixN {
+        let mut matrix = MatrixN::new(self.dim);
+        for row in 0..self.dim {
+            for col in 0..self.dim {
+                matrix[(row, col)] = self[(col, row)];
+            }
+        }
+        matrix
+    }
+
+    // Return a copy of a matrix with a given row and column removed
+    pub fn submatrix(&self, remove_row: usize, remove_col: usize) -> MatrixN {
+        debug_assert!(self.dim > 1, "A 1x1 matrix has no submatrix");
+        let mut matrix = MatrixN::new(self.dim - 1);
+        for row in 0..matrix.dim {
+            let actual_row = if row >= remove_row { row + 1 } else { row };
+            for col in 0..matrix.dim {
+                let actual_col = if col >= remove_col { col + 1 } else { col };
+                matrix[(row, col)] = self[(actual_row, actual_col)];
+            }
+        }
+        matrix
+    }
+
+    // The minor of an element at row i and column j is the determinate of the submatrix at (i,j)
+    pub fn minor(&self, row: usize, col: usize) -> f64 {
+        self.submatrix(row, col).determinant()
+    }
+
+    pub fn cofactor(&self, row: usize, col: usize) -> f64 {
+        let minor = self.minor(row, col);
+        if (row + col) % 2 == 1 {
+            -minor
+        } else {
+            minor
+        }
+    }
+
+    pub fn determinant(&self) -> f64 {
+        match self.dim {
+            0 => 1.,
+            1 => self[(0, 0)],
+            2 => self[(0, 0)] * self[(1, 1)] - self[(0, 1)] * self[(1, 0)],
+            _ => (0..self.dim).map(|col| self[(0, col)] * self.cofactor(0, col)).sum(),
+        }
+    }
+
+    pub fn is_invertible(&self) -> bool {
+        !mathf::approximately(self.determinant(), 0.0)
+    }
+
+    pub fn inverse(&self) -> Option<MatrixN> {
+        let determinant = self.determinant();
+        if mathf::approximately(determinant, 0.0) {
+            return None;
+        }
+
+        let mut matrix = MatrixN::new(self.dim);
+        for row in 0..self.dim {
+            for col in 0..self.dim {
+                // note the "(col, row)" here which achieves a transpose
+                matrix[(col, row)] = self.cofactor(row, col) / determinant;
+            }
+        }
+        Some(matrix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathf::matrix::Matrix;
+    use crate::mathf::vector3::Vector3;
+    use crate::transformations;
+
+    #[test]
+    fn test_2x2_determinant() {
+        let matrix = MatrixN::from_rows(&[vec![1., 5.], vec![-3., 2.]]);
+        assert_eq!(matrix.determinant(), 17.);
+    }
+
+    #[test]
+    fn test_3x3_determinant_and_cofactors() {
+        let matrix = MatrixN::from_rows(&[vec![1., 2., 6.], vec![-5., 8., -4.], vec![2., 6., 4.]]);
+        assert_eq!(matrix.cofactor(0, 0), 56.);
+        assert_eq!(matrix.cofactor(0, 1), 12.);
+        assert_eq!(matrix.cofactor(0, 2), -46.);
+        assert_eq!(matrix.determinant(), -196.);
+    }
+
+    #[test]
+    fn test_4x4_determinant_matches_matrix() {
+        let matrix = MatrixN::from_rows(&[
+            vec![-2., -8., 3., 5.],
+            vec![-3., 1., 7., 3.],
+            vec![1., 2., -9., 6.],
+            vec![-6., 7., 7., -9.],
+        ]);
+        assert_eq!(matrix.determinant(), -4071.);
+    }
+
+    #[test]
+    fn test_5x5_determinant() {
+        // The determinant of a triangular matrix is the product of its diagonal
+        let matrix = MatrixN::from_rows(&[
+            vec![1., 7., -3., 2., 9.],
+            vec![0., 2., 4., -1., 3.],
+            vec![0., 0., 3., 5., -2.],
+            vec![0., 0., 0., 4., 8.],
+            vec![0., 0., 0., 0., 5.],
+        ]);
+        assert_eq!(matrix.determinant(), 120.);
+
+        // Swapping two rows flips the sign
+        let swapped = MatrixN::from_rows(&[
+            vec![0., 2., 4., -1., 3.],
+            vec![1., 7., -3., 2., 9.],
+            vec![0., 0., 3., 5., -2.],
+            vec![0., 0., 0., 4., 8.],
+            vec![0., 0., 0., 0., 5.],
+        ]);
+        assert_eq!(swapped.determinant(), -120.);
+    }
+
+    #[test]
+    fn test_submatrix_of_a_5x5() {
+        let matrix = MatrixN::from_rows(&[
+            vec![1., 2., 3., 4., 5.],
+            vec![6., 7., 8., 9., 10.],
+            vec![11., 12., 13., 14., 15.],
+            vec![16., 17., 18., 19., 20.],
+            vec![21., 22., 23., 24., 25.],
+        ]);
+        let expected = MatrixN::from_rows(&[
+            vec![1., 2., 4., 5.],
+            vec![6., 7., 9., 10.],
+            vec![16., 17., 19., 20.],
+            vec![21., 22., 24., 25.],
+        ]);
+        assert_eq!(matrix.submatrix(2, 2), expected);
+    }
+
+    #[test]
+    fn multiplying_a_5x5_by_its_inverse_gives_the_identity() {
+        let matrix = MatrixN::from_rows(&[
+            vec![2., 0., 1., 3., -1.],
+            vec![1., 4., 0., 2., 2.],
+            vec![0., 1., 5., -2., 1.],
+            vec![3., 2., 1., 6., 0.],
+            vec![-1., 0., 2., 1., 3.],
+        ]);
+        let inverse = matrix.inverse().unwrap();
+        assert_eq!(matrix.multiply(&inverse), MatrixN::identity(5));
+        assert_eq!(inverse.multiply(&matrix), MatrixN::identity(5));
+    }
+
+    #[test]
+    fn a_singular_matrix_has_no_inverse() {
+        let matrix = MatrixN::from_rows(&[vec![1., 2., 3.], vec![2., 4., 6.], vec![0., 1., 1.]]);
+        assert!(!matrix.is_invertible());
+        assert!(matrix.inverse().is_none());
+    }
+
+    #[test]
+    fn test_inverse_agrees_with_matrix() {
+        let transform = transformations::translation(&Vector3::new(1., 2., 3.))
+            .multiply_4x4(&transformations::rotation_y(0.5))
+            .multiply_4x4(&transformations::scaling(&Vector3::new(2., 3., 4.)));
+        let expected: MatrixN = (&transform.inverse().unwrap()).into();
+        let inverse = MatrixN::from(&transform).inverse().unwrap();
+        assert_eq!(inverse, expected);
+        assert_eq!(MatrixN::from(&transform.transpose()), MatrixN::from(&transform).transpose());
+        assert_eq!(MatrixN::from(&Matrix::identity_4x4()), MatrixN::identity(4));
+    }
+}
@@ -1,20 +1,43 @@
+pub mod bounding_box;
+pub mod bvh;
+pub mod clipped;
+pub mod csg;
+pub mod disk;
+pub mod group;
+pub mod instance;
 pub mod intersection;
 pub mod matrix;
+pub mod matrix_n;
+pub mod node;
 pub mod plane;
 pub mod ray;
 pub mod shapes;
 pub mod sphere;
+pub mod torus;
+pub mod triangle;
+pub mod uv_mapping;
 pub mod vector3;
 
 use std::f64::consts::PI;
 
-pub const EPSILON: f64 = 0.00001;
+/// Tolerance used when comparing floats for equality, see `approximately`.
+/// Also used to decide if a ray is parallel to a flat surface.
+pub const EQUALITY_EPSILON: f64 = 0.00001;
+
+/// Distance a hit point is pushed off of a surface (the `over_point`) before
+/// casting a shadow ray. Without it floating point error causes a surface to
+/// shadow itself, which shows up as speckles ("acne") in the render.
+pub const SHADOW_EPSILON: f64 = 0.0001;
+
+/// The golden angle in radians, pi * (3 - sqrt(5)). Turning by it between
+/// successive samples spreads them evenly around a circle, whatever the count.
+pub const GOLDEN_ANGLE: f64 = 2.399_963_229_728_653;
 const DEGREE_TO_RADIAN: f64 = (PI * 2.0) / 360.0;
 const RADIAN_TO_DEGREE: f64 = 360.0 / (PI * 2.0);
 
 
 pub fn approximately(a: f64, b: f64) -> bool {
-    (a - b).abs() < EPSILON
+    (a - b).abs() < EQUALITY_EPSILON
 }
 
 pub fn degree_to_radian(degree: f64) -> f64 {
@@ -42,6 +65,12 @@ mod tests {
         assert!(!approximately(1.0 + 0.001, 1.0));
     }
 
+    #[test]
+    fn the_shadow_offset_is_not_treated_as_equal() {
+        assert!(approximately(1.0, 1.0 + EQUALITY_EPSILON / 2.));
+        assert!(!approximately(1.0, 1.0 + SHADOW_EPSILON));
+    }
+
     #[test]
     fn test_degree_to_radian() {
         assert!(approximately(degree_to_radian(1.0), 0.0174533));
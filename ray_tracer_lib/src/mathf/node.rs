@@ -0,0 +1,159 @@
+use crate::mathf::instance::Instance;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::shapes::Shape;
+use std::sync::Arc;
+
+/// Something in a scene graph, either another node or a shape at the bottom of the tree.
+#[derive(Debug)]
+pub enum NodeChild {
+    Node(Node),
+    Shape(Arc<dyn Shape>),
+}
+
+impl From<Node> for NodeChild {
+    fn from(node: Node) -> NodeChild {
+        NodeChild::Node(node)
+    }
+}
+
+impl From<Arc<dyn Shape>> for NodeChild {
+    fn from(shape: Arc<dyn Shape>) -> NodeChild {
+        NodeChild::Shape(shape)
+    }
+}
+
+/// A transform shared by everything below it in a scene graph.
+///
+/// Unlike a `Group` the shapes don't store a parent, so the same shape can sit
+/// under several nodes (or several times under one). Nothing is intersected
+/// through the tree itself, `instances` flattens it into one `Instance` per
+/// shape with the transforms on the way down composed together.
+#[derive(Debug)]
+pub struct Node {
+    transform: Matrix,
+    children: Vec<NodeChild>,
+}
+
+impl Node {
+    pub fn new(transform: Option<Matrix>, children: Vec<NodeChild>) -> Node {
+        Node {
+            transform: transform.unwrap_or_else(Matrix::identity_4x4),
+            children,
+        }
+    }
+
+    pub fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    pub fn children(&self) -> &[NodeChild] {
+        &self.children
+    }
+
+    pub fn add_child(&mut self, child: impl Into<NodeChild>) {
+        self.children.push(child.into());
+    }
+
+    /// Every shape in the tree along with the transform from its parent node's
+    /// space to the space of the root node's parent (usually the world). The
+    /// shape's own transform is not included.
+    pub fn leaves(&self) -> Vec<(Matrix, Arc<dyn Shape>)> {
+        let mut leaves = Vec::new();
+        self.collect_leaves(&Matrix::identity_4x4(), &mut leaves);
+        leaves
+    }
+
+    fn collect_leaves(&self, outer: &Matrix, leaves: &mut Vec<(Matrix, Arc<dyn Shape>)>) {
+        let transform = outer.multiply_4x4(&self.transform);
+        for child in &self.children {
+            match child {
+                NodeChild::Node(node) => node.collect_leaves(&transform, leaves),
+                NodeChild::Shape(shape) => leaves.push((transform.clone(), Arc::clone(shape))),
+            }
+        }
+    }
+
+    /// The tree as shapes that can be added to a `World`, one instance per leaf
+    pub fn instances(&self) -> Vec<Arc<dyn Shape>> {
+        self.leaves()
+            .into_iter()
+            .map(|(transform, shape)| {
+                Arc::new(Instance::new(Some(transform), shape)) as Arc<dyn Shape>
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathf::ray::Ray;
+    use crate::mathf::sphere::Sphere;
+    use crate::mathf::vector3::Vector3;
+    use crate::transformations;
+
+    #[test]
+    fn a_leaf_under_nested_nodes_uses_the_composed_transform() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::scaling(&Vector3::new(2., 2., 2.))),
+            None,
+        ));
+        let inner = Node::new(
+            Some(transformations::translation(&Vector3::new(5., 0., 0.))),
+            vec![Arc::clone(&sphere).into()],
+        );
+        let outer = Node::new(
+            Some(transformations::translation(&Vector3::new(0., 3., 0.))),
+            vec![inner.into()],
+        );
+
+        let leaves = outer.leaves();
+        assert_eq!(leaves.len(), 1);
+        assert_eq!(
+            leaves[0].0,
+            transformations::translation(&Vector3::new(5., 3., 0.))
+        );
+
+        // A sphere of radius 2 centered at (5, 3, 0)
+        let instances = outer.instances();
+        let ray = Ray::new(Vector3::new(5., 3., -10.), Vector3::new(0., 0., 1.));
+        let xs = instances[0].intersect(Arc::clone(&instances[0]), ray);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 8.);
+        assert_eq!(xs[1].t, 12.);
+        assert_eq!(
+            xs[0].object.normal_at(Vector3::new(5., 3., -2.)),
+            Vector3::new(0., 0., -1.)
+        );
+
+        let through_the_untranslated_sphere =
+            Ray::new(Vector3::new(0., 0., -10.), Vector3::new(0., 0., 1.));
+        assert!(instances[0]
+            .intersect(Arc::clone(&instances[0]), through_the_untranslated_sphere)
+            .is_empty());
+    }
+
+    #[test]
+    fn a_shape_can_appear_under_several_nodes() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let mut root = Node::new(None, vec![]);
+        for x in [-3., 3.] {
+            root.add_child(Node::new(
+                Some(transformations::translation(&Vector3::new(x, 0., 0.))),
+                vec![Arc::clone(&sphere).into()],
+            ));
+        }
+
+        let instances = root.instances();
+        assert_eq!(instances.len(), 2);
+        assert_eq!(
+            instances[0].parent_space_bounds().min,
+            Vector3::new(-4., -1., -1.)
+        );
+        assert_eq!(
+            instances[1].parent_space_bounds().max,
+            Vector3::new(4., 1., 1.)
+        );
+        assert!(sphere.parent().is_none());
+    }
+}
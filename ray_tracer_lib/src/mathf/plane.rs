@@ -1,22 +1,58 @@
-use crate::material::Material;
+use crate::material::{Material, SharedMaterial};
 use crate::mathf;
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::group::{set_parent_once, GroupTransform};
 use crate::mathf::intersection::Intersection;
 use crate::mathf::matrix::Matrix;
 use crate::mathf::ray::Ray;
-use crate::mathf::shapes::Shape;
+use crate::mathf::shapes::{shape_id, Shape};
 use crate::mathf::vector3::Vector3;
-use std::sync::Arc;
+use crate::mathf::uv_mapping::UvMapping;
+use std::sync::{Arc, OnceLock};
 
+/// Which axes a plane lies along before it is transformed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PlaneOrientation {
+    /// A floor, the normal is +y. u runs along x and v along z.
+    #[default]
+    XZ,
+    /// A wall facing +z. u runs along x and v along y.
+    XY,
+    /// A wall facing +x. u runs along y and v along z.
+    YZ,
+}
+
+impl PlaneOrientation {
+    // Swaps axes so the plane is the xz plane, with u along x and v along z.
+    // Each swap undoes itself, so this also maps back again.
+    fn to_xz(self, v: &Vector3) -> Vector3 {
+        match self {
+            PlaneOrientation::XZ => v.clone(),
+            PlaneOrientation::XY => Vector3::new(v.x, v.z, v.y),
+            PlaneOrientation::YZ => Vector3::new(v.y, v.x, v.z),
+        }
+    }
+}
+
+/// The xz plane, or the xy or yz plane, see `PlaneOrientation`. By default it
+/// goes on forever, optional bounds in u and v cut it down to a rectangle, for
+/// things like walls.
 #[derive(Debug)]
 pub struct Plane {
-    //    id: u32,
-    material: Material,
+    id: u32,
+    material: SharedMaterial,
+    parent_space_bounds: BoundingBox,
     transform: Matrix,
     inverse_transform: Matrix,
+    identity_transform: bool,
+    parent: OnceLock<Arc<GroupTransform>>,
+    u_bounds: Option<(f64, f64)>,
+    v_bounds: Option<(f64, f64)>,
+    orientation: PlaneOrientation,
 }
 
 impl Shape for Plane {
-    fn material(&self) -> &Material {
+    fn shared_material(&self) -> &SharedMaterial {
         &self.material
     }
 
@@ -28,43 +64,161 @@ impl Shape for Plane {
         &self.inverse_transform
     }
 
+    fn has_identity_transform(&self) -> bool {
+        self.identity_transform
+    }
+
+    fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        self.parent.get()
+    }
+
+    fn set_parent(&self, parent: Arc<GroupTransform>) {
+        set_parent_once(&self.parent, parent);
+    }
+
     fn local_intersect(&self, shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
-        if object_ray.direction.y.abs() < mathf::EPSILON {
-            return vec![];
+        match self.hit_t(&object_ray) {
+            None => vec![],
+            Some(t) => vec![Intersection::new(t, Arc::clone(&shape))],
         }
+    }
 
-        // Note that this formula only works if this is a plane stretching
-        // infinitely far in the x and z dimensions (which is the case here).
-        let t = -object_ray.origin.y / object_ray.direction.y;
+    fn is_opaque(&self) -> bool {
+        self.material().transparency == 0.
+    }
 
-        let i = Intersection::new(t, Arc::clone(&shape));
-        vec![i]
+    fn local_intersects_before(&self, _shape: Arc<dyn Shape>, object_ray: Ray, max_t: f64) -> bool {
+        self.hit_t(&object_ray).is_some_and(|t| t >= 0. && t < max_t)
     }
 
     fn local_normal_at(&self, _object_point: Vector3) -> Vector3 {
-        Vector3::new(0., 1., 0.)
+        self.orientation.to_xz(&Vector3::new(0., 1., 0.))
+    }
+
+    fn uv_at(&self, object_point: &Vector3) -> Option<(f64, f64)> {
+        Some(UvMapping::Planar.uv(&self.orientation.to_xz(object_point)))
     }
 
     fn local_eq(&self, other: &dyn Shape) -> bool {
-        //        self.id == other.id
-        self.material() == other.material() || self.transform() == other.transform()
+        other.id() == Some(self.id)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let (min_x, max_x) = self.u_bounds.unwrap_or((f64::NEG_INFINITY, f64::INFINITY));
+        let (min_z, max_z) = self.v_bounds.unwrap_or((f64::NEG_INFINITY, f64::INFINITY));
+        BoundingBox::new(
+            self.orientation.to_xz(&Vector3::new(min_x, 0., min_z)),
+            self.orientation.to_xz(&Vector3::new(max_x, 0., max_z)),
+        )
+    }
+
+    fn parent_space_bounds(&self) -> BoundingBox {
+        self.parent_space_bounds.clone()
+    }
+
+    fn id(&self) -> Option<u32> {
+        Some(self.id)
     }
 }
 
 impl Plane {
+    /// Use a material that may be shared with other shapes
+    pub fn set_material(&mut self, material: SharedMaterial) {
+        self.material = material;
+    }
+
     pub fn new(transform: Option<Matrix>, material: Option<Material>) -> Plane {
+        Plane::new_bounded(transform, material, None, None)
+    }
+
+    /// A plane with a chosen id, see `Sphere::with_id`
+    pub fn with_id(id: u32, transform: Option<Matrix>, material: Option<Material>) -> Plane {
+        let mut plane = Plane::new(transform, material);
+        plane.id = id;
+        plane
+    }
+
+    /// A plane that only goes from min to max in x (`u_bounds`) and z (`v_bounds`),
+    /// `None` leaves it unbounded in that direction.
+    pub fn new_bounded(
+        transform: Option<Matrix>,
+        material: Option<Material>,
+        u_bounds: Option<(f64, f64)>,
+        v_bounds: Option<(f64, f64)>,
+    ) -> Plane {
+        Plane::new_oriented(transform, material, PlaneOrientation::XZ, u_bounds, v_bounds)
+    }
+
+    /// A plane lying along other axes than x and z, so walls don't need a
+    /// rotation. The bounds work the same as `new_bounded`, in the plane's u and v.
+    pub fn new_oriented(
+        transform: Option<Matrix>,
+        material: Option<Material>,
+        orientation: PlaneOrientation,
+        u_bounds: Option<(f64, f64)>,
+        v_bounds: Option<(f64, f64)>,
+    ) -> Plane {
         let t = match transform {
             None => Matrix::identity_4x4(),
             Some(x) => x,
         };
         let inverse_transform = t.inverse().unwrap();
         let mat = material.unwrap_or_default();
-        Plane {
-            //            id: sphere_id(),
+        let mut shape = Plane {
+            id: shape_id(),
             transform: t,
-            material: mat,
+            material: mat.shared(),
+            identity_transform: inverse_transform.is_identity(),
             inverse_transform,
+            parent: OnceLock::new(),
+            parent_space_bounds: BoundingBox::empty(),
+            u_bounds,
+            v_bounds,
+            orientation,
+        };
+        shape.parent_space_bounds = shape.bounds().transform(shape.transform());
+        shape
+    }
+
+    pub fn u_bounds(&self) -> Option<(f64, f64)> {
+        self.u_bounds
+    }
+
+    pub fn v_bounds(&self) -> Option<(f64, f64)> {
+        self.v_bounds
+    }
+
+    pub fn orientation(&self) -> PlaneOrientation {
+        self.orientation
+    }
+
+    // Where the ray crosses the plane, None if it runs alongside it or passes
+    // outside the bounds
+    fn hit_t(&self, object_ray: &Ray) -> Option<f64> {
+        let object_ray = Ray {
+            origin: self.orientation.to_xz(&object_ray.origin),
+            direction: self.orientation.to_xz(&object_ray.direction),
+        };
+        if object_ray.direction.y.abs() < mathf::EQUALITY_EPSILON {
+            return None;
         }
+
+        // Note that this formula only works if this is a plane stretching
+        // infinitely far in the x and z dimensions (which is the case here).
+        let t = -object_ray.origin.y / object_ray.direction.y;
+
+        let point = object_ray.position(t);
+        if !within(self.u_bounds, point.x) || !within(self.v_bounds, point.z) {
+            return None;
+        }
+        Some(t)
+    }
+}
+
+fn within(bounds: Option<(f64, f64)>, value: f64) -> bool {
+    match bounds {
+        None => true,
+        Some((min, max)) => (min..=max).contains(&value),
     }
 }
 
@@ -72,6 +226,14 @@ impl Plane {
 mod tests {
     use super::*;
     use crate::mathf::vector3::Vector3;
+    use crate::transformations;
+
+    #[test]
+    fn the_uv_of_a_plane_repeats_every_unit() {
+        let plane = Plane::new(None, None);
+        assert_eq!(plane.uv_at(&Vector3::new(0.25, 0., 0.5)), Some((0.25, 0.5)));
+        assert_eq!(plane.uv_at(&Vector3::new(1.25, 0., -0.5)), Some((0.25, 0.5)));
+    }
 
     #[test]
     fn the_normal_of_a_plane_is_constant_everywhere() {
@@ -95,6 +257,20 @@ mod tests {
         assert!(xs.is_empty());
     }
 
+    #[test]
+    fn a_plane_is_hit_before_a_distance_only_if_it_is_crossed_by_then() {
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new(
+            Some(transformations::translation(&Vector3::new(0., -1., 0.))),
+            None,
+        ));
+        let ray = Ray::new(Vector3::new(0., 1., 0.), Vector3::new(0., -1., 0.));
+        assert!(plane.intersects_before(Arc::clone(&plane), ray.clone(), 2.5));
+        assert!(!plane.intersects_before(Arc::clone(&plane), ray.clone(), 2.));
+
+        let away = Ray::new(Vector3::new(0., 1., 0.), Vector3::new(0., 1., 0.));
+        assert!(!plane.intersects_before(Arc::clone(&plane), away, f64::INFINITY));
+    }
+
     #[test]
     fn test_intersect_with_a_coplanar_ray() {
         let plane = Plane::new(None, None);
@@ -129,4 +305,64 @@ mod tests {
         assert_eq!(xs[0].t, 1.);
         assert_eq!(&xs[0].object, &Arc::clone(&plane));
     }
+
+    fn bounded_plane() -> Arc<dyn Shape> {
+        Arc::new(Plane::new_bounded(None, None, Some((-1., 2.)), Some((-3., 4.))))
+    }
+
+    #[test]
+    fn a_ray_through_the_middle_of_a_bounded_plane_hits() {
+        let plane = bounded_plane();
+        let ray = Ray::new(Vector3::new(0.5, 1., 0.5), Vector3::new(0., -1., 0.));
+        let xs = plane.local_intersect(Arc::clone(&plane), ray);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 1.);
+    }
+
+    #[test]
+    fn a_ray_just_past_a_bound_misses() {
+        let plane = bounded_plane();
+        for (x, z) in [(2.01, 0.), (-1.01, 0.), (0., 4.01), (0., -3.01)] {
+            let ray = Ray::new(Vector3::new(x, 1., z), Vector3::new(0., -1., 0.));
+            assert!(plane.local_intersect(Arc::clone(&plane), ray).is_empty());
+        }
+    }
+
+    #[test]
+    fn a_bounded_plane_has_finite_bounds() {
+        let plane = bounded_plane();
+        assert_eq!(plane.bounds(), BoundingBox::new(Vector3::new(-1., 0., -3.), Vector3::new(2., 0., 4.)));
+    }
+
+    #[test]
+    fn a_yz_plane_faces_along_x() {
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new_oriented(None, None, PlaneOrientation::YZ, None, None));
+        let ray = Ray::new(Vector3::new(3., 0.5, -2.), Vector3::new(-1., 0., 0.));
+        let xs = plane.local_intersect(Arc::clone(&plane), ray.clone());
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 3.);
+        assert_eq!(plane.normal_at(ray.position(xs[0].t)), Vector3::new(1., 0., 0.));
+
+        let parallel = Ray::new(Vector3::new(3., 0., 0.), Vector3::new(0., 1., 0.));
+        assert!(plane.local_intersect(Arc::clone(&plane), parallel).is_empty());
+    }
+
+    #[test]
+    fn a_bounded_xy_plane_is_a_wall() {
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new_oriented(
+            None,
+            None,
+            PlaneOrientation::XY,
+            Some((-1., 2.)),
+            Some((0., 3.)),
+        ));
+        assert_eq!(plane.local_normal_at(Vector3::new(0., 0., 0.)), Vector3::new(0., 0., 1.));
+        assert_eq!(plane.bounds(), BoundingBox::new(Vector3::new(-1., 0., 0.), Vector3::new(2., 3., 0.)));
+        assert_eq!(plane.uv_at(&Vector3::new(1.25, 2.5, 0.)), Some((0.25, 0.5)));
+
+        let hit = Ray::new(Vector3::new(1., 2.5, -5.), Vector3::new(0., 0., 1.));
+        assert_eq!(plane.local_intersect(Arc::clone(&plane), hit).len(), 1);
+        let above = Ray::new(Vector3::new(1., 3.5, -5.), Vector3::new(0., 0., 1.));
+        assert!(plane.local_intersect(Arc::clone(&plane), above).is_empty());
+    }
 }
@@ -1,3 +1,4 @@
+use crate::mathf::intersection::Computations;
 use crate::mathf::matrix::Matrix;
 use crate::mathf::vector3::Vector3;
 
@@ -9,7 +10,14 @@ pub struct Ray {
 }
 
 impl Ray {
+    /// The direction doesn't need to be normalized, but it must have a length.
     pub fn new(origin: Vector3, direction: Vector3) -> Ray {
+        // Written so NaN fails too, it would otherwise poison every intersection
+        debug_assert!(
+            direction.magnitude_squared() > 0.,
+            "a ray needs a direction with a length, got {:?}",
+            direction
+        );
         Ray { origin, direction }
     }
 
@@ -18,6 +26,20 @@ impl Ray {
         &self.origin + &(&self.direction * t)
     }
 
+    /// Same as `position`
+    pub fn at(&self, t: f64) -> Vector3 {
+        self.position(t)
+    }
+
+    /// The ray bouncing off the surface at a hit. It starts just above the
+    /// surface so it doesn't hit the same point again.
+    pub fn reflect(computations: &Computations) -> Ray {
+        Ray::new(
+            computations.over_point.clone(),
+            computations.reflect_vector.clone(),
+        )
+    }
+
     pub fn transform(&self, matrix: &Matrix) -> Ray {
         // Note that we only want translation matrices to affect "points" and not "vectors".
         // So we use different multiplication fns for them
@@ -30,8 +52,12 @@ impl Ray {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mathf::intersection::Intersection;
+    use crate::mathf::plane::Plane;
+    use crate::mathf::shapes::Shape;
     use crate::mathf::vector3::Vector3;
     use crate::transformations;
+    use std::sync::Arc;
 
     #[test]
     fn it_creates_a_ray() {
@@ -40,6 +66,13 @@ mod tests {
         assert_eq!(ray.direction, Vector3::new(4.0, 5.0, 6.0));
     }
 
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "a ray needs a direction")]
+    fn a_ray_without_a_direction_is_rejected() {
+        Ray::new(Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.0, 0.0, 0.0));
+    }
+
     #[test]
     fn test_ray_position() {
         let ray = Ray::new(Vector3::new(2.0, 3.0, 4.0), Vector3::new(1.0, 0.0, 0.0));
@@ -80,4 +113,25 @@ mod tests {
         assert_eq!(ray2.direction, Vector3::new(0.0, 3.0, 0.0));
     }
 
+    #[test]
+    fn at_is_the_same_as_position() {
+        let ray = Ray::new(Vector3::new(2.0, 3.0, 4.0), Vector3::new(1.0, 0.0, 0.0));
+        for t in [-1.0, 0.0, 2.5].iter() {
+            assert_eq!(ray.at(*t), ray.position(*t));
+        }
+    }
+
+    #[test]
+    fn the_reflected_ray_off_a_plane_at_45_degrees() {
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new(None, None));
+        let ray = Ray::new(
+            Vector3::new(0., 1., -1.),
+            Vector3::new(0., -(2f64.sqrt()) / 2., 2f64.sqrt() / 2.),
+        );
+        let computations = Intersection::new(2f64.sqrt(), plane).prepare_computations(ray);
+
+        let reflected = Ray::reflect(&computations);
+        assert_eq!(reflected.origin, computations.over_point);
+        assert_eq!(reflected.direction, Vector3::new(0., 2f64.sqrt() / 2., 2f64.sqrt() / 2.));
+    }
 }
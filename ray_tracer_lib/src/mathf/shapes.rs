@@ -1,31 +1,150 @@
-use crate::material::Material;
+use crate::material::{Material, SharedMaterial};
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::group::GroupTransform;
 use crate::mathf::intersection::Intersection;
 use crate::mathf::matrix::Matrix;
 use crate::mathf::ray::Ray;
 use crate::mathf::vector3::Vector3;
 use std::fmt;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLockReadGuard};
+
+static SHAPE_ID: AtomicU32 = AtomicU32::new(0);
+
+/// A new id for a shape, unique even when shapes are created on several threads.
+/// The ids handed out depend on how many shapes were made before, use `with_id`
+/// for an id that is the same on every run.
+pub fn shape_id() -> u32 {
+    SHAPE_ID.fetch_add(1, Ordering::Relaxed) + 1
+}
 
 pub trait Shape: fmt::Debug + Send + Sync {
     fn transform(&self) -> &Matrix;
     fn inverse_transform(&self) -> &Matrix;
-    fn material(&self) -> &Material;
+    /// True if the transform is exactly the identity, so it can be skipped. Shapes
+    /// work this out once when they are made rather than checking every time.
+    fn has_identity_transform(&self) -> bool {
+        self.inverse_transform().is_identity()
+    }
+    fn shared_material(&self) -> &SharedMaterial;
+    /// The transform of the group this shape belongs to, if any
+    fn parent(&self) -> Option<&Arc<GroupTransform>>;
+    fn set_parent(&self, parent: Arc<GroupTransform>);
     fn local_intersect(&self, shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection>;
     fn local_normal_at(&self, object_point: Vector3) -> Vector3;
     fn local_eq(&self, other: &dyn Shape) -> bool;
+    /// A box around the shape in object space
+    fn bounds(&self) -> BoundingBox;
+
+    /// Where a point on the shape (in object space) lands on a flat image, u runs
+    /// left to right and v bottom to top, both 0.0 to 1.0. None for shapes without
+    /// a natural way to unwrap them, see `UvImagePattern`.
+    fn uv_at(&self, _object_point: &Vector3) -> Option<(f64, f64)> {
+        None
+    }
+
+    /// Shapes with an id compare equal when their ids match, see `Sphere::with_id`
+    fn id(&self) -> Option<u32> {
+        None
+    }
+
+    /// A box around the shape in the space of its parent, or world space if it
+    /// doesn't belong to a group. This is checked for every shadow ray, so shapes
+    /// work it out once when they are made and return the cached box.
+    fn parent_space_bounds(&self) -> BoundingBox {
+        self.bounds().transform(self.transform())
+    }
+
+    fn material(&self) -> RwLockReadGuard<'_, Material> {
+        self.shared_material().read().unwrap()
+    }
+
+    /// Returns true if `other` is this exact shape, or for shapes made up of
+    /// other shapes (like CSG) if it is one of the children.
+    fn includes(&self, other: &dyn Shape) -> bool {
+        std::ptr::eq(self as *const Self as *const (), other as *const dyn Shape as *const ())
+    }
+
+    /// Every shape an intersection with this one can refer to, given `shape` (this
+    /// shape). That is just `shape` unless it is made up of other shapes (like
+    /// CSG), then it is the parts of each child.
+    fn parts(&self, shape: &Arc<dyn Shape>) -> Vec<Arc<dyn Shape>> {
+        vec![Arc::clone(shape)]
+    }
 
     fn intersect(&self, shape: Arc<dyn Shape>, world_ray: Ray) -> Vec<Intersection> {
+        // Most shapes are never transformed, don't pay for multiplying by the identity
+        if self.has_identity_transform() {
+            return self.local_intersect(shape, world_ray);
+        }
         self.local_intersect(shape, world_ray.transform(self.inverse_transform()))
     }
 
+    /// True if every hit on the shape is on its own material and that material is
+    /// opaque, so a shadow ray that hits it is fully blocked and `intersects_before`
+    /// is all that is needed. False by default, shapes made of other shapes report
+    /// hits on their children's materials.
+    fn is_opaque(&self) -> bool {
+        false
+    }
+
+    /// True if the ray hits the shape anywhere from t = 0 up to (but not
+    /// including) `max_t`. Shadow rays only need to know if something is in the
+    /// way, not every place it is hit.
+    fn intersects_before(&self, shape: Arc<dyn Shape>, world_ray: Ray, max_t: f64) -> bool {
+        if self.has_identity_transform() {
+            return self.local_intersects_before(shape, world_ray, max_t);
+        }
+        self.local_intersects_before(shape, world_ray.transform(self.inverse_transform()), max_t)
+    }
+
+    /// Works out every intersection, shapes that can answer more cheaply should
+    /// override this. The transform leaves t unchanged, so `max_t` is the same in
+    /// object space.
+    fn local_intersects_before(&self, shape: Arc<dyn Shape>, object_ray: Ray, max_t: f64) -> bool {
+        self.local_intersect(shape, object_ray)
+            .iter()
+            .any(|i| i.t >= 0. && i.t < max_t)
+    }
+
+    // Normals are transformed by the transpose of the inverse transform. This keeps
+    // them outward facing even for mirrored shapes (a transform with a negative
+    // determinant), so there is no need to flip them. Treating the normal as a
+    // vector (w = 0) means any translation is ignored rather than needing the
+    // w component fixed up afterwards.
     fn normal_at(&self, world_point: Vector3) -> Vector3 {
-        let object_normal =
-            self.local_normal_at(self.inverse_transform().multiply_point(&world_point));
-        let world_normal = self
-            .inverse_transform()
-            .transpose()
-            .multiply_vector(&object_normal);
-        world_normal.normalize()
+        let object_normal = self.local_normal_at(self.world_to_object(&world_point));
+        self.normal_to_world(&object_normal)
+    }
+
+    /// Convert a point from world space to object space, applying the
+    /// transforms of any groups this shape is in first.
+    fn world_to_object(&self, point: &Vector3) -> Vector3 {
+        let point = match self.parent() {
+            Some(parent) => parent.world_to_object(point),
+            None => point.clone(),
+        };
+        if self.has_identity_transform() {
+            return point;
+        }
+        self.inverse_transform().multiply_point(&point)
+    }
+
+    /// Convert a normal from object space to world space, applying the
+    /// transforms of any groups this shape is in last.
+    fn normal_to_world(&self, normal: &Vector3) -> Vector3 {
+        let normal = if self.has_identity_transform() {
+            normal.normalize()
+        } else {
+            self.inverse_transform()
+                .transpose()
+                .multiply_vector(normal)
+                .normalize()
+        };
+        match self.parent() {
+            Some(parent) => parent.normal_to_world(&normal),
+            None => normal,
+        }
     }
 }
 
@@ -38,20 +157,28 @@ impl PartialEq for dyn Shape {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mathf::group::set_parent_once;
     use crate::mathf::vector3::Vector3;
     use crate::transformations;
+    use std::cell::RefCell;
     use std::f64::consts::PI;
+    use std::sync::OnceLock;
 
-    static mut SAVED_RAY: Ray = Ray {
-        origin: crate::mathf::vector3::ORIGIN,
-        direction: crate::mathf::vector3::VECTOR_Y_UP,
-    };
+    thread_local! {
+        static SAVED_RAY: RefCell<Ray> = const {
+            RefCell::new(Ray {
+                origin: crate::mathf::vector3::ORIGIN,
+                direction: crate::mathf::vector3::VECTOR_Y_UP,
+            })
+        };
+    }
 
     #[derive(Debug)]
     pub struct TestShape {
-        material: Material,
+        material: SharedMaterial,
         transform: Matrix,
         inverse_transform: Matrix,
+        parent: OnceLock<Arc<GroupTransform>>,
     }
 
     impl Shape for TestShape {
@@ -61,14 +188,18 @@ mod tests {
         fn inverse_transform(&self) -> &Matrix {
             &self.inverse_transform
         }
-        fn material(&self) -> &Material {
+        fn shared_material(&self) -> &SharedMaterial {
             &self.material
         }
+        fn parent(&self) -> Option<&Arc<GroupTransform>> {
+            self.parent.get()
+        }
+        fn set_parent(&self, parent: Arc<GroupTransform>) {
+            set_parent_once(&self.parent, parent);
+        }
 
         fn local_intersect(&self, _shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
-            unsafe {
-                SAVED_RAY = object_ray;
-            }
+            SAVED_RAY.with(|saved| *saved.borrow_mut() = object_ray);
             vec![]
         }
 
@@ -77,7 +208,11 @@ mod tests {
         }
 
         fn local_eq(&self, other: &dyn Shape) -> bool {
-            self.material() == other.material() || self.transform() == other.transform()
+            *self.material() == *other.material() || self.transform() == other.transform()
+        }
+
+        fn bounds(&self) -> BoundingBox {
+            BoundingBox::new(Vector3::new(-1., -1., -1.), Vector3::new(1., 1., 1.))
         }
     }
 
@@ -92,8 +227,9 @@ mod tests {
 
             TestShape {
                 transform: t,
-                material: mat,
+                material: mat.shared(),
                 inverse_transform,
+                parent: OnceLock::new(),
             }
         }
     }
@@ -123,7 +259,7 @@ mod tests {
     #[test]
     fn the_default_material() {
         let s = TestShape::new(None, None);
-        assert_eq!(s.material(), &Material::new());
+        assert_eq!(*s.material(), Material::new());
     }
 
     #[test]
@@ -142,10 +278,10 @@ mod tests {
         let shape2 = Arc::clone(&shape);
 
         let _xs = shape.intersect(shape2, ray);
-        unsafe {
-            assert_eq!(SAVED_RAY.origin, Vector3::new(0., 0., -2.5));
-            assert_eq!(SAVED_RAY.direction, Vector3::new(0., 0., 0.5));
-        }
+        SAVED_RAY.with(|saved| {
+            assert_eq!(saved.borrow().origin, Vector3::new(0., 0., -2.5));
+            assert_eq!(saved.borrow().direction, Vector3::new(0., 0., 0.5));
+        });
     }
 
     #[test]
@@ -156,13 +292,14 @@ mod tests {
         let shape2 = Arc::clone(&shape);
 
         let _xs = shape.intersect(shape2, ray);
-        unsafe {
-            assert_eq!(SAVED_RAY.origin, Vector3::new(-5., 0., -5.));
-            assert_eq!(SAVED_RAY.direction, Vector3::new(0., 0., 1.));
-        }
+        SAVED_RAY.with(|saved| {
+            assert_eq!(saved.borrow().origin, Vector3::new(-5., 0., -5.));
+            assert_eq!(saved.borrow().direction, Vector3::new(0., 0., 1.));
+        });
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn computing_the_normal_on_a_translated_shape() {
         let t = transformations::translation(&Vector3::new(0., 1., 0.));
         let shape = TestShape::new(Some(t), None);
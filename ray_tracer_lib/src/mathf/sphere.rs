@@ -1,41 +1,31 @@
-use crate::material::Material;
+use crate::material::{Material, SharedMaterial};
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::group::{set_parent_once, GroupTransform};
 use crate::mathf::intersection::Intersection;
 use crate::mathf::matrix::Matrix;
 use crate::mathf::ray::Ray;
-use crate::mathf::shapes::Shape;
+use crate::mathf::shapes::{shape_id, Shape};
 use crate::mathf::vector3::Vector3;
-use std::sync::Arc;
+use crate::mathf::uv_mapping::UvMapping;
+use std::sync::{Arc, OnceLock};
 
 #[derive(Debug)]
 pub struct Sphere {
     id: u32,
-    material: Material,
+    material: SharedMaterial,
+    parent_space_bounds: BoundingBox,
     transform: Matrix,
     inverse_transform: Matrix,
+    identity_transform: bool,
+    parent: OnceLock<Arc<GroupTransform>>,
 }
 
 pub fn reflect(vector: &Vector3, normal: &Vector3) -> Vector3 {
-    vector - &(normal * 2.0 * vector.dot(&normal))
-}
-
-// impl PartialEq for Sphere {
-//     fn eq(&self, other: &Self) -> bool {
-//         self.id == other.id
-//     }
-// }
-
-// TODO there is likely a better way to handle this than using an unsafe block
-static mut SPHERE_ID: u32 = 0;
-
-pub fn sphere_id() -> u32 {
-    unsafe {
-        SPHERE_ID += 1;
-        SPHERE_ID
-    }
+    vector - &(normal * 2.0 * vector.dot(normal))
 }
 
 impl Shape for Sphere {
-    fn material(&self) -> &Material {
+    fn shared_material(&self) -> &SharedMaterial {
         &self.material
     }
 
@@ -47,25 +37,38 @@ impl Shape for Sphere {
         &self.inverse_transform
     }
 
+    fn has_identity_transform(&self) -> bool {
+        self.identity_transform
+    }
+
+    fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        self.parent.get()
+    }
+
+    fn set_parent(&self, parent: Arc<GroupTransform>) {
+        set_parent_once(&self.parent, parent);
+    }
+
     fn local_intersect(&self, shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
-        let sphere_to_ray = &object_ray.origin - &Vector3::new(0.0, 0.0, 0.0);
-
-        let a = object_ray.direction.dot(&object_ray.direction);
-        let b = 2. * object_ray.direction.dot(&sphere_to_ray);
-        let c = sphere_to_ray.dot(&sphere_to_ray) - 1.;
-        let discriminant = (b * b) - (4. * a * c);
-
-        if discriminant < 0.0 {
-            // When the discrimint is negative then the ray missed and there were no intersections
-            vec![]
-        } else {
-            let disc_root = discriminant.sqrt();
-            let t1 = (-b - disc_root) / (2. * a);
-            let t2 = (-b + disc_root) / (2. * a);
-
-            let a = Intersection::new(t1, Arc::clone(&shape));
-            let b = Intersection::new(t2, Arc::clone(&shape));
-            vec![a, b]
+        match roots(&object_ray) {
+            // The ray missed and there were no intersections
+            None => vec![],
+            Some((t1, t2)) => {
+                let a = Intersection::new(t1, Arc::clone(&shape));
+                let b = Intersection::new(t2, Arc::clone(&shape));
+                vec![a, b]
+            }
+        }
+    }
+
+    fn is_opaque(&self) -> bool {
+        self.material().transparency == 0.
+    }
+
+    fn local_intersects_before(&self, _shape: Arc<dyn Shape>, object_ray: Ray, max_t: f64) -> bool {
+        match roots(&object_ray) {
+            None => false,
+            Some((t1, t2)) => (t1 >= 0. && t1 < max_t) || (t2 >= 0. && t2 < max_t),
         }
     }
 
@@ -73,37 +76,265 @@ impl Shape for Sphere {
         &object_point - &Vector3::new(0.0, 0.0, 0.0)
     }
 
+    fn uv_at(&self, object_point: &Vector3) -> Option<(f64, f64)> {
+        Some(UvMapping::Spherical.uv(object_point))
+    }
+
     fn local_eq(&self, other: &dyn Shape) -> bool {
-        //        self.id == other.id
-        self.material() == other.material() || self.transform() == other.transform()
+        other.id() == Some(self.id)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(Vector3::new(-1., -1., -1.), Vector3::new(1., 1., 1.))
+    }
+
+    fn parent_space_bounds(&self) -> BoundingBox {
+        self.parent_space_bounds.clone()
+    }
+
+    fn id(&self) -> Option<u32> {
+        Some(self.id)
     }
 }
 
 impl Sphere {
+    /// Use a material that may be shared with other shapes
+    pub fn set_material(&mut self, material: SharedMaterial) {
+        self.material = material;
+    }
+
     pub fn new(transform: Option<Matrix>, material: Option<Material>) -> Sphere {
+        Sphere::with_id(shape_id(), transform, material)
+    }
+
+    /// Intersect a batch of rays with the sphere, giving the same intersections as
+    /// calling `intersect` for each ray. Coherent rays, like the primary rays of a
+    /// tile, are solved together. The rays are split into separate arrays of x, y
+    /// and z values so the quadratic for each is worked out in simple loops the
+    /// compiler can vectorize.
+    pub fn intersect_packet(self: &Arc<Self>, rays: &[Ray]) -> Vec<Vec<Intersection>> {
+        let count = rays.len();
+        let mut origin = [Vec::with_capacity(count), Vec::with_capacity(count), Vec::with_capacity(count)];
+        let mut direction = [Vec::with_capacity(count), Vec::with_capacity(count), Vec::with_capacity(count)];
+        for ray in rays {
+            let object_ray = ray.transform(&self.inverse_transform);
+            origin[0].push(object_ray.origin.x);
+            origin[1].push(object_ray.origin.y);
+            origin[2].push(object_ray.origin.z);
+            direction[0].push(object_ray.direction.x);
+            direction[1].push(object_ray.direction.y);
+            direction[2].push(object_ray.direction.z);
+        }
+
+        // The same quadratic as `local_intersect`, one array entry per ray
+        let mut a = vec![0.; count];
+        let mut b = vec![0.; count];
+        let mut discriminant = vec![0.; count];
+        for i in 0..count {
+            let (ox, oy, oz) = (origin[0][i], origin[1][i], origin[2][i]);
+            let (dx, dy, dz) = (direction[0][i], direction[1][i], direction[2][i]);
+            a[i] = dx * dx + dy * dy + dz * dz;
+            b[i] = 2. * (dx * ox + dy * oy + dz * oz);
+            let c = ox * ox + oy * oy + oz * oz - 1.;
+            discriminant[i] = b[i] * b[i] - 4. * a[i] * c;
+        }
+
+        let shape: Arc<dyn Shape> = Arc::clone(self) as Arc<dyn Shape>;
+        (0..count)
+            .map(|i| {
+                if discriminant[i] < 0.0 {
+                    return vec![];
+                }
+                let disc_root = discriminant[i].sqrt();
+                let t1 = (-b[i] - disc_root) / (2. * a[i]);
+                let t2 = (-b[i] + disc_root) / (2. * a[i]);
+                vec![Intersection::new(t1, Arc::clone(&shape)), Intersection::new(t2, Arc::clone(&shape))]
+            })
+            .collect()
+    }
+
+    /// A sphere with a chosen id rather than the next one from the global counter,
+    /// so tests get the same ids however many shapes were created before.
+    pub fn with_id(id: u32, transform: Option<Matrix>, material: Option<Material>) -> Sphere {
         let t = match transform {
             None => Matrix::identity_4x4(),
             Some(x) => x,
         };
         let inverse_transform = t.inverse().unwrap();
+        Sphere::build(id, t, inverse_transform, material)
+    }
+
+    /// A sphere with an inverse transform that was already worked out. When many
+    /// spheres share a transform, invert it once and pass the inverse to each.
+    /// `inverse_transform` must be the inverse of `transform`.
+    pub fn new_with_inverse(transform: Matrix, inverse_transform: Matrix, material: Option<Material>) -> Sphere {
+        debug_assert!(
+            transform.multiply_4x4(&inverse_transform) == Matrix::identity_4x4(),
+            "inverse_transform is not the inverse of transform"
+        );
+        Sphere::build(shape_id(), transform, inverse_transform, material)
+    }
+
+    fn build(id: u32, transform: Matrix, inverse_transform: Matrix, material: Option<Material>) -> Sphere {
         let mat = material.unwrap_or_default();
-        Sphere {
-            id: sphere_id(),
-            transform: t,
-            material: mat,
+        let mut shape = Sphere {
+            id,
+            transform,
+            material: mat.shared(),
+            identity_transform: inverse_transform.is_identity(),
             inverse_transform,
-        }
+            parent: OnceLock::new(),
+            parent_space_bounds: BoundingBox::empty(),
+        };
+        shape.parent_space_bounds = shape.bounds().transform(shape.transform());
+        shape
     }
 }
 
+// Where the ray enters and leaves the unit sphere, smallest first. None when the
+// discriminant is negative and the ray misses.
+fn roots(object_ray: &Ray) -> Option<(f64, f64)> {
+    let sphere_to_ray = &object_ray.origin - &Vector3::new(0.0, 0.0, 0.0);
+
+    let a = object_ray.direction.dot(&object_ray.direction);
+    let b = 2. * object_ray.direction.dot(&sphere_to_ray);
+    let c = sphere_to_ray.dot(&sphere_to_ray) - 1.;
+    let discriminant = (b * b) - (4. * a * c);
+
+    if discriminant < 0.0 {
+        return None;
+    }
+    let disc_root = discriminant.sqrt();
+    Some(((-b - disc_root) / (2. * a), (-b + disc_root) / (2. * a)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mathf::approximately;
     use crate::mathf::intersection::Intersections;
     use crate::mathf::vector3::Vector3;
     use crate::transformations;
     use std::f64::consts::PI;
 
+    #[test]
+    fn the_uv_of_a_point_on_a_sphere() {
+        let sphere = Sphere::new(None, None);
+        // Where the equator meets the prime meridian (facing the camera down -z)
+        let (u, v) = sphere.uv_at(&Vector3::new(0., 0., -1.)).unwrap();
+        assert!(approximately(u, 0.) && approximately(v, 0.5));
+        let (u, v) = sphere.uv_at(&Vector3::new(1., 0., 0.)).unwrap();
+        assert!(approximately(u, 0.25) && approximately(v, 0.5));
+        // The poles are the top and bottom of the image
+        let (_, v) = sphere.uv_at(&Vector3::new(0., 1., 0.)).unwrap();
+        assert!(approximately(v, 1.));
+        let (_, v) = sphere.uv_at(&Vector3::new(0., -1., 0.)).unwrap();
+        assert!(approximately(v, 0.));
+    }
+
+    #[test]
+    fn a_packet_of_rays_gives_the_same_intersections_as_one_at_a_time() {
+        let sphere = Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(0.5, 0., 1.)).multiply_4x4(&transformations::scaling(&Vector3::new(2., 2., 2.)))),
+            None,
+        ));
+        let shape: Arc<dyn Shape> = Arc::clone(&sphere) as Arc<dyn Shape>;
+        let rays: Vec<Ray> = (0..16)
+            .map(|i| {
+                let x = i as f64 * 0.4 - 3.;
+                Ray::new(Vector3::new(x, 0.5, -5.), Vector3::new(0., 0., 1.))
+            })
+            .collect();
+
+        let packet = sphere.intersect_packet(&rays);
+        assert_eq!(packet.len(), rays.len());
+        for (xs, ray) in packet.iter().zip(rays.iter()) {
+            let expected = shape.intersect(Arc::clone(&shape), ray.clone());
+            let ts: Vec<f64> = xs.iter().map(|i| i.t).collect();
+            let expected_ts: Vec<f64> = expected.iter().map(|i| i.t).collect();
+            assert_eq!(ts, expected_ts);
+            assert!(xs.iter().all(|i| i.object == Arc::clone(&shape)));
+        }
+        assert!(packet.iter().any(|xs| xs.is_empty()));
+        assert!(packet.iter().any(|xs| xs.len() == 2));
+    }
+
+    #[test]
+    fn a_sphere_is_hit_before_a_distance_only_if_it_is_reached_by_then() {
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::scaling(&Vector3::new(2., 2., 2.))),
+            None,
+        ));
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        // The ray enters at t = 3 and leaves at t = 7
+        assert!(sphere.intersects_before(Arc::clone(&sphere), ray.clone(), 3.5));
+        assert!(!sphere.intersects_before(Arc::clone(&sphere), ray.clone(), 3.));
+
+        // From inside only the way out counts
+        let inside = Ray::new(Vector3::new(0., 0., 0.), Vector3::new(0., 0., 1.));
+        assert!(sphere.intersects_before(Arc::clone(&sphere), inside.clone(), 2.5));
+        assert!(!sphere.intersects_before(Arc::clone(&sphere), inside, 1.5));
+
+        let miss = Ray::new(Vector3::new(0., 3., -5.), Vector3::new(0., 0., 1.));
+        assert!(!sphere.intersects_before(Arc::clone(&sphere), miss, f64::INFINITY));
+    }
+
+    #[test]
+    fn a_tiny_transform_is_not_skipped() {
+        assert!(Sphere::new(None, None).has_identity_transform());
+
+        let nudged: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(0., 0., 1e-6))),
+            None,
+        ));
+        assert!(!nudged.has_identity_transform());
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let xs = nudged.intersect(Arc::clone(&nudged), ray);
+        assert!((xs[0].t - 4. - 1e-6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn only_a_sphere_that_lets_no_light_through_is_opaque() {
+        assert!(Sphere::new(None, None).is_opaque());
+        let glass = Material::builder().transparency(0.9).build();
+        assert!(!Sphere::new(None, Some(glass)).is_opaque());
+    }
+
+    #[test]
+    fn a_sphere_can_be_given_its_inverse_transform() {
+        let transform = transformations::scaling(&Vector3::new(2., 2., 2.));
+        let inverse = transform.inverse().unwrap();
+        let spheres: Vec<Sphere> = (0..3)
+            .map(|_| Sphere::new_with_inverse(transform.clone(), inverse.clone(), None))
+            .collect();
+        for sphere in spheres.iter() {
+            assert_eq!(sphere.inverse_transform(), &inverse);
+            assert_eq!(sphere.transform(), &transform);
+        }
+        assert_eq!(spheres[0].parent_space_bounds(), Sphere::new(Some(transform), None).parent_space_bounds());
+    }
+
+    #[test]
+    fn a_sphere_with_an_explicit_id_keeps_it() {
+        let a: Arc<dyn Shape> = Arc::new(Sphere::with_id(42, None, None));
+        let b: Arc<dyn Shape> = Arc::new(Sphere::with_id(42, Some(transformations::scaling(&Vector3::new(2., 2., 2.))), None));
+        let c: Arc<dyn Shape> = Arc::new(Sphere::with_id(43, None, None));
+        assert_eq!(a.id(), Some(42));
+        assert!(a == b);
+        assert!(a != c);
+    }
+
+    #[test]
+    fn spheres_created_on_several_threads_get_different_ids() {
+        let handles: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| (0..100).map(|_| Sphere::new(None, None).id).collect::<Vec<u32>>()))
+            .collect();
+        let mut ids: Vec<u32> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 400);
+    }
+
     #[test]
     fn test_a_sphere_default_transformation() {
         let s = Sphere::new(None, None);
@@ -170,6 +401,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn computing_the_normal_on_a_translated_sphere() {
         let s = Sphere::new(
             Some(transformations::translation(&Vector3::new(0.0, 1.0, 0.0))),
@@ -212,7 +444,7 @@ mod tests {
     fn a_sphere_has_a_default_material() {
         let s = Sphere::new(None, None);
         let m = Material::new();
-        assert_eq!(s.material, m);
+        assert_eq!(*s.material(), m);
     }
 
     #[test]
@@ -224,7 +456,53 @@ mod tests {
         let mut m2 = Material::new();
         m2.ambient = 1.0;
 
-        assert_eq!(sphere.material, m2);
+        assert_eq!(*sphere.material(), m2);
+    }
+
+    #[test]
+    fn the_normals_of_a_mirrored_sphere_point_outward() {
+        let transform = transformations::translation(&Vector3::new(2., 0., 0.))
+            .multiply_4x4(&transformations::scaling(&Vector3::new(-1., 2., 1.)));
+        assert!(transform.determinant() < 0.);
+        let s = Sphere::new(Some(transform), None);
+
+        assert_eq!(s.normal_at(Vector3::new(3., 0., 0.)), Vector3::new(1., 0., 0.));
+        assert_eq!(s.normal_at(Vector3::new(1., 0., 0.)), Vector3::new(-1., 0., 0.));
+        assert_eq!(s.normal_at(Vector3::new(2., 2., 0.)), Vector3::new(0., 1., 0.));
+        assert_eq!(s.normal_at(Vector3::new(2., 0., -1.)), Vector3::new(0., 0., -1.));
+    }
+
+    #[test]
+    fn a_mirrored_sphere_is_lit_like_the_original() {
+        use crate::color::Color;
+        use crate::phong_lighting;
+        use crate::point_light::PointLight;
+
+        let light = PointLight::new(Vector3::new(-10., 10., -10.), Color::new(1., 1., 1.));
+        let eye = Vector3::new(0., 0., -1.);
+        let point = Vector3::new(0., 0., -1.);
+        let original = Sphere::new(None, None);
+        let mirrored = Sphere::new(Some(transformations::scaling(&Vector3::new(-1., 1., 1.))), None);
+
+        let lit = |s: &Sphere| {
+            phong_lighting::lighting(&s.material(), s, &light, &point, &eye, &s.normal_at(point.clone()), false)
+        };
+        assert_eq!(lit(&mirrored), lit(&original));
+    }
+
+    #[test]
+    fn spheres_can_share_a_material() {
+        let shared = Material::new().shared();
+        let mut s1 = Sphere::new(None, None);
+        let mut s2 = Sphere::new(None, None);
+        s1.set_material(Arc::clone(&shared));
+        s2.set_material(Arc::clone(&shared));
+
+        shared.write().unwrap().reflective = 0.8;
+
+        assert_eq!(s1.material().reflective, 0.8);
+        assert_eq!(s2.material().reflective, 0.8);
+        assert_eq!(*s1.material(), *s2.material());
     }
 
     #[test]
@@ -0,0 +1,316 @@
+use crate::material::{Material, SharedMaterial};
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::group::{set_parent_once, GroupTransform};
+use crate::mathf::intersection::Intersection;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::ray::Ray;
+use crate::mathf::shapes::{shape_id, Shape};
+use crate::mathf::vector3::Vector3;
+use std::sync::{Arc, OnceLock};
+
+// Bisection halves the interval each step, this is plenty to reach full precision
+const BISECTION_STEPS: usize = 100;
+
+/// A donut lying in the xz plane around the y axis. The center of the tube is
+/// `major_radius` from the origin and the tube is `minor_radius` thick.
+#[derive(Debug)]
+pub struct Torus {
+    id: u32,
+    material: SharedMaterial,
+    parent_space_bounds: BoundingBox,
+    transform: Matrix,
+    inverse_transform: Matrix,
+    identity_transform: bool,
+    parent: OnceLock<Arc<GroupTransform>>,
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl Shape for Torus {
+    fn shared_material(&self) -> &SharedMaterial {
+        &self.material
+    }
+
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn has_identity_transform(&self) -> bool {
+        self.identity_transform
+    }
+
+    fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        self.parent.get()
+    }
+
+    fn set_parent(&self, parent: Arc<GroupTransform>) {
+        set_parent_once(&self.parent, parent);
+    }
+
+    // A point is on the torus when (|p|² + R² - r²)² = 4R²(x² + z²), putting
+    // in the ray gives a quartic in t.
+    fn local_intersect(&self, shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
+        // The quartic is much better behaved for a unit direction starting close
+        // to the torus, so move the origin up to the bounding sphere first and
+        // convert the roots back afterwards.
+        let scale = object_ray.direction.magnitude();
+        let d = object_ray.direction.normalize();
+        let outer = self.major_radius + self.minor_radius;
+        let skip = (-object_ray.origin.dot(&d) - outer).max(0.);
+        let o = &object_ray.origin + &(&d * skip);
+
+        let r2 = self.major_radius * self.major_radius;
+        let b = 2. * o.dot(&d);
+        let c = o.dot(&o) + r2 - self.minor_radius * self.minor_radius;
+        let coefficients = [
+            1.,
+            2. * b,
+            b * b + 2. * c - 4. * r2 * (d.x * d.x + d.z * d.z),
+            2. * b * c - 8. * r2 * (o.x * d.x + o.z * d.z),
+            c * c - 4. * r2 * (o.x * o.x + o.z * o.z),
+        ];
+
+        real_roots(&coefficients)
+            .into_iter()
+            .map(|t| Intersection::new((t + skip) / scale, Arc::clone(&shape)))
+            .collect()
+    }
+
+    fn local_normal_at(&self, object_point: Vector3) -> Vector3 {
+        // Away from the closest point on the circle through the middle of the tube
+        let around = Vector3::new(object_point.x, 0., object_point.z).normalize();
+        (&object_point - &(&around * self.major_radius)).normalize()
+    }
+
+    fn local_eq(&self, other: &dyn Shape) -> bool {
+        other.id() == Some(self.id)
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let outer = self.major_radius + self.minor_radius;
+        BoundingBox::new(
+            Vector3::new(-outer, -self.minor_radius, -outer),
+            Vector3::new(outer, self.minor_radius, outer),
+        )
+    }
+
+    fn parent_space_bounds(&self) -> BoundingBox {
+        self.parent_space_bounds.clone()
+    }
+
+    fn id(&self) -> Option<u32> {
+        Some(self.id)
+    }
+}
+
+impl Torus {
+    /// Use a material that may be shared with other shapes
+    pub fn set_material(&mut self, material: SharedMaterial) {
+        self.material = material;
+    }
+
+    pub fn new(
+        transform: Option<Matrix>,
+        material: Option<Material>,
+        major_radius: f64,
+        minor_radius: f64,
+    ) -> Torus {
+        let t = transform.unwrap_or_else(Matrix::identity_4x4);
+        let inverse_transform = t.inverse().unwrap();
+        let mut shape = Torus {
+            id: shape_id(),
+            material: material.unwrap_or_default().shared(),
+            parent_space_bounds: BoundingBox::empty(),
+            transform: t,
+            identity_transform: inverse_transform.is_identity(),
+            inverse_transform,
+            parent: OnceLock::new(),
+            major_radius,
+            minor_radius,
+        };
+        shape.parent_space_bounds = shape.bounds().transform(shape.transform());
+        shape
+    }
+
+    /// A torus with a chosen id, see `Sphere::with_id`
+    pub fn with_id(
+        id: u32,
+        transform: Option<Matrix>,
+        material: Option<Material>,
+        major_radius: f64,
+        minor_radius: f64,
+    ) -> Torus {
+        let mut torus = Torus::new(transform, material, major_radius, minor_radius);
+        torus.id = id;
+        torus
+    }
+
+    pub fn major_radius(&self) -> f64 {
+        self.major_radius
+    }
+
+    pub fn minor_radius(&self) -> f64 {
+        self.minor_radius
+    }
+}
+
+// The real roots of a polynomial, coefficients from the highest power down,
+// sorted smallest first. Between neighboring roots of the derivative the
+// polynomial only rises or only falls, so each of those intervals holds at most
+// one root and it can be found by bisection. Roots that only touch zero (where
+// the ray grazes the surface) are skipped.
+fn real_roots(coefficients: &[f64]) -> Vec<f64> {
+    let degree = coefficients.len() - 1;
+    if coefficients[0] == 0. {
+        return real_roots(&coefficients[1..]);
+    }
+    if degree == 1 {
+        return vec![-coefficients[1] / coefficients[0]];
+    }
+
+    let derivative: Vec<f64> = coefficients[..degree]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| c * (degree - i) as f64)
+        .collect();
+
+    // Every root is within this distance of zero (Cauchy's bound)
+    let bound = 1.
+        + coefficients[1..]
+            .iter()
+            .map(|c| (c / coefficients[0]).abs())
+            .fold(0., f64::max);
+
+    let mut edges = vec![-bound];
+    edges.extend(
+        real_roots(&derivative)
+            .into_iter()
+            .filter(|t| t.abs() < bound),
+    );
+    edges.push(bound);
+
+    let value = |t: f64| coefficients.iter().fold(0., |sum, c| sum * t + c);
+    edges
+        .windows(2)
+        .filter_map(|pair| {
+            let (mut low, mut high) = (pair[0], pair[1]);
+            let low_is_negative = value(low) < 0.;
+            if low_is_negative == (value(high) < 0.) {
+                return None;
+            }
+            for _ in 0..BISECTION_STEPS {
+                let middle = (low + high) / 2.;
+                if (value(middle) < 0.) == low_is_negative {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            Some((low + high) / 2.)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathf;
+
+    fn torus() -> Arc<dyn Shape> {
+        Arc::new(Torus::new(None, None, 1., 0.25))
+    }
+
+    #[test]
+    fn finding_the_real_roots_of_a_polynomial() {
+        // (t - 1)(t + 2)(t - 3)(t - 4) = t⁴ - 6t³ + 3t² + 26t - 24
+        let roots = real_roots(&[1., -6., 3., 26., -24.]);
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip([-2., 1., 3., 4.]) {
+            assert!(mathf::approximately(*root, expected));
+        }
+
+        // t⁴ + 1 never reaches zero
+        assert!(real_roots(&[1., 0., 0., 0., 1.]).is_empty());
+    }
+
+    #[test]
+    fn a_ray_through_the_hole_misses() {
+        let torus = torus();
+        let ray = Ray::new(Vector3::new(0., 5., 0.), Vector3::new(0., -1., 0.));
+        assert!(torus.local_intersect(Arc::clone(&torus), ray).is_empty());
+
+        let ray = Ray::new(Vector3::new(0.2, 5., -0.3), Vector3::new(0., -1., 0.));
+        assert!(torus.local_intersect(Arc::clone(&torus), ray).is_empty());
+    }
+
+    #[test]
+    fn a_ray_through_both_sides_of_the_tube_hits_four_times() {
+        let torus = torus();
+        let ray = Ray::new(Vector3::new(-5., 0., 0.), Vector3::new(1., 0., 0.));
+        let xs = torus.local_intersect(Arc::clone(&torus), ray);
+        assert_eq!(xs.len(), 4);
+        for (x, t) in xs.iter().zip([3.75, 4.25, 5.75, 6.25]) {
+            assert!(mathf::approximately(x.t, t), "{} != {}", x.t, t);
+        }
+    }
+
+    #[test]
+    fn a_ray_through_one_part_of_the_tube_hits_twice() {
+        let torus = torus();
+        // Along the line z = 1, which touches the middle of the tube at x = 0
+        let ray = Ray::new(Vector3::new(-5., 0., 1.), Vector3::new(2., 0., 0.));
+        let xs = torus.local_intersect(Arc::clone(&torus), ray);
+        assert_eq!(xs.len(), 2);
+        assert!(mathf::approximately(xs[0].t, 2.125));
+        assert!(mathf::approximately(xs[1].t, 2.875));
+
+        let above = Ray::new(Vector3::new(-5., 0.3, 0.), Vector3::new(1., 0., 0.));
+        assert!(torus.local_intersect(Arc::clone(&torus), above).is_empty());
+    }
+
+    #[test]
+    fn a_distant_ray_hits_in_the_same_place() {
+        let torus = torus();
+        let ray = Ray::new(Vector3::new(-1000., 0., 0.), Vector3::new(1., 0., 0.));
+        let xs = torus.local_intersect(Arc::clone(&torus), ray);
+        assert_eq!(xs.len(), 4);
+        assert!(mathf::approximately(xs[0].t, 998.75));
+        assert!(mathf::approximately(xs[3].t, 1001.25));
+    }
+
+    #[test]
+    fn the_normal_points_out_of_the_tube() {
+        let torus = torus();
+        assert_eq!(
+            torus.local_normal_at(Vector3::new(1.25, 0., 0.)),
+            Vector3::new(1., 0., 0.)
+        );
+        assert_eq!(
+            torus.local_normal_at(Vector3::new(0.75, 0., 0.)),
+            Vector3::new(-1., 0., 0.)
+        );
+        assert_eq!(
+            torus.local_normal_at(Vector3::new(0., 0.25, -1.)),
+            Vector3::new(0., 1., 0.)
+        );
+        assert_eq!(
+            torus.local_normal_at(Vector3::new(0., -0.25, 1.)),
+            Vector3::new(0., -1., 0.)
+        );
+    }
+
+    #[test]
+    fn the_bounds_of_a_torus() {
+        assert_eq!(
+            torus().bounds(),
+            BoundingBox::new(
+                Vector3::new(-1.25, -0.25, -1.25),
+                Vector3::new(1.25, 0.25, 1.25)
+            )
+        );
+    }
+}
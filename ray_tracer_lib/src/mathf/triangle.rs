@@ -0,0 +1,283 @@
+use crate::material::{Material, SharedMaterial};
+use crate::mathf;
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::group::{set_parent_once, GroupTransform};
+use crate::mathf::intersection::Intersection;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::ray::Ray;
+use crate::mathf::shapes::Shape;
+use crate::mathf::vector3::Vector3;
+use std::sync::{Arc, OnceLock};
+
+/// A triangle, mostly used for meshes loaded from OBJ files.
+///
+/// A flat triangle has the same normal everywhere. A smooth triangle has a normal
+/// for each corner and blends between them, so a mesh looks curved rather than faceted.
+#[derive(Debug)]
+pub struct Triangle {
+    pub p1: Vector3,
+    pub p2: Vector3,
+    pub p3: Vector3,
+    pub e1: Vector3,
+    pub e2: Vector3,
+    pub normal: Vector3,
+    /// The normals at p1, p2 and p3 for a smooth triangle
+    pub vertex_normals: Option<[Vector3; 3]>,
+    material: SharedMaterial,
+    parent_space_bounds: BoundingBox,
+    transform: Matrix,
+    inverse_transform: Matrix,
+    identity_transform: bool,
+    parent: OnceLock<Arc<GroupTransform>>,
+}
+
+impl Shape for Triangle {
+    fn shared_material(&self) -> &SharedMaterial {
+        &self.material
+    }
+
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn has_identity_transform(&self) -> bool {
+        self.identity_transform
+    }
+
+    fn parent(&self) -> Option<&Arc<GroupTransform>> {
+        self.parent.get()
+    }
+
+    fn set_parent(&self, parent: Arc<GroupTransform>) {
+        set_parent_once(&self.parent, parent);
+    }
+
+    // Möller–Trumbore
+    fn local_intersect(&self, shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
+        let dir_cross_e2 = object_ray.direction.cross(&self.e2);
+        let det = self.e1.dot(&dir_cross_e2);
+        if det.abs() < mathf::EQUALITY_EPSILON {
+            return vec![];
+        }
+
+        let f = 1.0 / det;
+        let p1_to_origin = &object_ray.origin - &self.p1;
+        let u = f * p1_to_origin.dot(&dir_cross_e2);
+        if !(0.0..=1.0).contains(&u) {
+            return vec![];
+        }
+
+        let origin_cross_e1 = p1_to_origin.cross(&self.e1);
+        let v = f * object_ray.direction.dot(&origin_cross_e1);
+        if v < 0.0 || u + v > 1.0 {
+            return vec![];
+        }
+
+        let t = f * self.e2.dot(&origin_cross_e1);
+        vec![Intersection::new(t, Arc::clone(&shape))]
+    }
+
+    fn local_normal_at(&self, object_point: Vector3) -> Vector3 {
+        match &self.vertex_normals {
+            None => self.normal.clone(),
+            Some([n1, n2, n3]) => {
+                let (u, v) = self.barycentric(&object_point);
+                &(&(n2 * u) + &(n3 * v)) + &(n1 * (1. - u - v))
+            }
+        }
+    }
+
+    fn local_eq(&self, other: &dyn Shape) -> bool {
+        std::ptr::eq(self as *const Triangle as *const (), other as *const dyn Shape as *const ())
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let mut bounds = BoundingBox::empty();
+        bounds.add_point(&self.p1);
+        bounds.add_point(&self.p2);
+        bounds.add_point(&self.p3);
+        bounds
+    }
+
+    fn parent_space_bounds(&self) -> BoundingBox {
+        self.parent_space_bounds.clone()
+    }
+}
+
+impl Triangle {
+    /// Use a material that may be shared with other shapes
+    pub fn set_material(&mut self, material: SharedMaterial) {
+        self.material = material;
+    }
+
+    pub fn new(
+        transform: Option<Matrix>,
+        material: Option<Material>,
+        p1: Vector3,
+        p2: Vector3,
+        p3: Vector3,
+    ) -> Triangle {
+        let t = match transform {
+            None => Matrix::identity_4x4(),
+            Some(x) => x,
+        };
+        let inverse_transform = t.inverse().unwrap();
+        let mat = material.unwrap_or_default();
+
+        let e1 = &p2 - &p1;
+        let e2 = &p3 - &p1;
+        let normal = e2.cross(&e1).normalize();
+        let vertex_normals = None;
+
+        let mut shape = Triangle {
+            p1,
+            p2,
+            p3,
+            e1,
+            e2,
+            normal,
+            vertex_normals,
+            transform: t,
+            material: mat.shared(),
+            identity_transform: inverse_transform.is_identity(),
+            inverse_transform,
+            parent: OnceLock::new(),
+            parent_space_bounds: BoundingBox::empty(),
+        };
+        shape.parent_space_bounds = shape.bounds().transform(shape.transform());
+        shape
+    }
+
+    /// A triangle that interpolates between the normals at each corner
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_smooth(
+        transform: Option<Matrix>,
+        material: Option<Material>,
+        p1: Vector3,
+        p2: Vector3,
+        p3: Vector3,
+        n1: Vector3,
+        n2: Vector3,
+        n3: Vector3,
+    ) -> Triangle {
+        let mut triangle = Triangle::new(transform, material, p1, p2, p3);
+        triangle.vertex_normals = Some([n1, n2, n3]);
+        triangle
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.vertex_normals.is_some()
+    }
+
+    // The weights of p2 (u) and p3 (v) for a point on the triangle, p1 gets 1 - u - v
+    fn barycentric(&self, point: &Vector3) -> (f64, f64) {
+        let p = point - &self.p1;
+        let d00 = self.e1.dot(&self.e1);
+        let d01 = self.e1.dot(&self.e2);
+        let d11 = self.e2.dot(&self.e2);
+        let d20 = p.dot(&self.e1);
+        let d21 = p.dot(&self.e2);
+        let denom = d00 * d11 - d01 * d01;
+        ((d11 * d20 - d01 * d21) / denom, (d00 * d21 - d01 * d20) / denom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_triangle() -> Arc<dyn Shape> {
+        Arc::new(Triangle::new(
+            None,
+            None,
+            Vector3::new(0., 1., 0.),
+            Vector3::new(-1., 0., 0.),
+            Vector3::new(1., 0., 0.),
+        ))
+    }
+
+    #[test]
+    fn constructing_a_triangle() {
+        let t = Triangle::new(
+            None,
+            None,
+            Vector3::new(0., 1., 0.),
+            Vector3::new(-1., 0., 0.),
+            Vector3::new(1., 0., 0.),
+        );
+        assert_eq!(t.e1, Vector3::new(-1., -1., 0.));
+        assert_eq!(t.e2, Vector3::new(1., -1., 0.));
+        assert_eq!(t.normal, Vector3::new(0., 0., -1.));
+    }
+
+    #[test]
+    fn the_normal_of_a_triangle_is_constant_everywhere() {
+        let t = test_triangle();
+        assert_eq!(t.local_normal_at(Vector3::new(0., 0.5, 0.)), Vector3::new(0., 0., -1.));
+        assert_eq!(t.local_normal_at(Vector3::new(-0.5, 0.75, 0.)), Vector3::new(0., 0., -1.));
+        assert_eq!(t.local_normal_at(Vector3::new(0.5, 0.25, 0.)), Vector3::new(0., 0., -1.));
+    }
+
+    #[test]
+    fn intersecting_a_ray_parallel_to_the_triangle() {
+        let t = test_triangle();
+        let ray = Ray::new(Vector3::new(0., -1., -2.), Vector3::new(0., 1., 0.));
+        assert!(t.local_intersect(Arc::clone(&t), ray).is_empty());
+    }
+
+    #[test]
+    fn a_ray_misses_the_edges_of_a_triangle() {
+        let t = test_triangle();
+        for origin in [
+            Vector3::new(1., 1., -2.),
+            Vector3::new(-1., 1., -2.),
+            Vector3::new(0., -1., -2.),
+        ] {
+            let ray = Ray::new(origin, Vector3::new(0., 0., 1.));
+            assert!(t.local_intersect(Arc::clone(&t), ray).is_empty());
+        }
+    }
+
+    #[test]
+    fn a_ray_strikes_a_triangle() {
+        let t = test_triangle();
+        let ray = Ray::new(Vector3::new(0., 0.5, -2.), Vector3::new(0., 0., 1.));
+        let xs = t.local_intersect(Arc::clone(&t), ray);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.);
+    }
+
+    fn test_smooth_triangle() -> Triangle {
+        Triangle::new_smooth(
+            None,
+            None,
+            Vector3::new(0., 1., 0.),
+            Vector3::new(-1., 0., 0.),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(0., 1., 0.),
+            Vector3::new(-1., 0., 0.),
+            Vector3::new(1., 0., 0.),
+        )
+    }
+
+    #[test]
+    fn a_smooth_triangle_uses_the_vertex_normals_at_its_corners() {
+        let t = test_smooth_triangle();
+        assert!(t.is_smooth());
+        assert_eq!(t.local_normal_at(Vector3::new(0., 1., 0.)), Vector3::new(0., 1., 0.));
+        assert_eq!(t.local_normal_at(Vector3::new(-1., 0., 0.)), Vector3::new(-1., 0., 0.));
+        assert_eq!(t.local_normal_at(Vector3::new(1., 0., 0.)), Vector3::new(1., 0., 0.));
+    }
+
+    #[test]
+    fn a_smooth_triangle_interpolates_the_normal() {
+        // The book's u = 0.45, v = 0.25
+        let t = test_smooth_triangle();
+        let normal = t.normal_at(Vector3::new(-0.2, 0.3, 0.));
+        assert_eq!(normal, Vector3::new(-0.5547, 0.83205, 0.));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::mathf;
+use crate::mathf::vector3::Vector3;
+use std::f64::consts::PI;
+
+/// How a point on a shape is turned into a position (u, v) on an image, both
+/// from 0.0 to 1.0 with v running from the bottom of the image to the top.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UvMapping {
+    /// The x and z axes, tiling every unit. Suits planes.
+    Planar,
+    /// Longitude and latitude around the y axis. Suits spheres.
+    Spherical,
+    /// Around the y axis like `Spherical`, with v tiling every unit up the axis.
+    /// Suits columns and other shapes built around the y axis.
+    Cylindrical,
+}
+
+impl UvMapping {
+    pub fn uv(&self, point: &Vector3) -> (f64, f64) {
+        match self {
+            UvMapping::Planar => (point.x.rem_euclid(1.), point.z.rem_euclid(1.)),
+            UvMapping::Spherical => {
+                let theta = point.x.atan2(point.z);
+                let radius = point.magnitude();
+                let phi = (point.y / radius).clamp(-1., 1.).acos();
+                let raw_u = theta / (2. * PI);
+                (1. - (raw_u + 0.5), 1. - phi / PI)
+            }
+            UvMapping::Cylindrical => {
+                // Wrapped so both sides of the seam behind the axis give a u of 0
+                let raw_u = point.x.atan2(point.z) / (2. * PI);
+                ((1. - (raw_u + 0.5)).rem_euclid(1.), point.y.rem_euclid(1.))
+            }
+        }
+    }
+
+    // The directions u and v increase in at the point, not necessarily at right
+    // angles to each other or the surface.
+    pub(crate) fn directions(&self, point: &Vector3) -> (Vector3, Vector3) {
+        match self {
+            UvMapping::Planar => (Vector3::new(1., 0., 0.), Vector3::new(0., 0., 1.)),
+            UvMapping::Spherical | UvMapping::Cylindrical => {
+                // u goes around the y axis, there is no good direction at the poles
+                let u = if point.x.abs() < mathf::EQUALITY_EPSILON
+                    && point.z.abs() < mathf::EQUALITY_EPSILON
+                {
+                    Vector3::new(1., 0., 0.)
+                } else {
+                    Vector3::new(-point.z, 0., point.x)
+                };
+                (u, Vector3::new(0., 1., 0.))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spherical_mapping_on_a_unit_sphere() {
+        let cases = [
+            (Vector3::new(0., 0., -1.), (0., 0.5)),
+            (Vector3::new(1., 0., 0.), (0.25, 0.5)),
+            (Vector3::new(0., 0., 1.), (0.5, 0.5)),
+            (Vector3::new(-1., 0., 0.), (0.75, 0.5)),
+            (Vector3::new(0., 1., 0.), (0.5, 1.)),
+            (Vector3::new(0., -1., 0.), (0.5, 0.)),
+        ];
+        for (point, (u, v)) in cases.iter() {
+            let (mapped_u, mapped_v) = UvMapping::Spherical.uv(point);
+            assert!(mathf::approximately(mapped_u, *u), "u at {:?}", point);
+            assert!(mathf::approximately(mapped_v, *v), "v at {:?}", point);
+        }
+    }
+
+    #[test]
+    fn cylindrical_mapping_around_the_y_axis() {
+        let cases = [
+            (Vector3::new(0., 0., -1.), (0., 0.)),
+            (Vector3::new(0., 0.5, -1.), (0., 0.5)),
+            (Vector3::new(1., 0.25, 0.), (0.25, 0.25)),
+            (Vector3::new(0., 1.75, 1.), (0.5, 0.75)),
+            (Vector3::new(-1., -0.25, 0.), (0.75, 0.75)),
+            (Vector3::new(-0.5f64.sqrt(), 0.5, 0.5f64.sqrt()), (0.625, 0.5)),
+        ];
+        for (point, (u, v)) in cases.iter() {
+            let (mapped_u, mapped_v) = UvMapping::Cylindrical.uv(point);
+            assert!(mathf::approximately(mapped_u, *u), "u at {:?}", point);
+            assert!(mathf::approximately(mapped_v, *v), "v at {:?}", point);
+        }
+    }
+
+    #[test]
+    fn cylindrical_mapping_is_continuous_across_the_seam() {
+        // Either side of the seam at -z is close to 0 or close to 1, which are the
+        // same place on a repeating image. Exactly on the seam is 0 from both sides.
+        let (left, _) = UvMapping::Cylindrical.uv(&Vector3::new(-1e-6, 0., -1.));
+        let (right, _) = UvMapping::Cylindrical.uv(&Vector3::new(1e-6, 0., -1.));
+        assert!(left.min(1. - left) < 1e-6 && right.min(1. - right) < 1e-6);
+        assert_eq!(UvMapping::Cylindrical.uv(&Vector3::new(0., 0., -1.)).0, 0.);
+        assert_eq!(UvMapping::Cylindrical.uv(&Vector3::new(-0., 0., -1.)).0, 0.);
+        assert!((0. ..1.).contains(&left) && (0. ..1.).contains(&right));
+    }
+}
@@ -17,11 +17,22 @@ impl Vector3 {
     }
 
     pub fn magnitude(&self) -> f64 {
-        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+        self.magnitude_squared().sqrt()
     }
 
+    /// Cheaper than `magnitude` as there's no square root, enough when comparing
+    /// lengths with each other or checking for a length of zero.
+    pub fn magnitude_squared(&self) -> f64 {
+        self.x * self.x + self.y * self.y + self.z * self.z
+    }
+
+    /// A zero length vector has no direction, it normalizes to the zero vector
+    /// rather than dividing by zero and filling everything downstream with NaN.
     pub fn normalize(&self) -> Vector3 {
         let mag = self.magnitude();
+        if mag == 0. {
+            return Vector3::new(0., 0., 0.);
+        }
         Vector3::new(self.x / mag, self.y / mag, self.z / mag)
     }
 
@@ -36,6 +47,50 @@ impl Vector3 {
             self.x * rhs.y - self.y * rhs.x,
         )
     }
+
+    /// Linear interpolation, `self` at t = 0 and `other` at t = 1.
+    /// t is not clamped, values outside of 0 to 1 extrapolate along the same line.
+    pub fn lerp(&self, other: &Vector3, t: f64) -> Vector3 {
+        self + &((other - self) * t)
+    }
+
+    /// The distance between two points
+    pub fn distance(&self, other: &Vector3) -> f64 {
+        (other - self).magnitude()
+    }
+
+    /// The part of this vector pointing in the direction of `other`.
+    /// `other` does not need to be normalized, but must not have a length of zero.
+    pub fn project_onto(&self, other: &Vector3) -> Vector3 {
+        other * (self.dot(other) / other.dot(other))
+    }
+
+    /// The direction light continues in after passing through a surface (Snell's law).
+    /// `self` is the eye vector pointing away from the surface, `normal` is on the
+    /// same side as the eye and `n_ratio` is n1 / n2. Returns `None` when there is
+    /// total internal reflection and nothing passes through.
+    pub fn refract(&self, normal: &Vector3, n_ratio: f64) -> Option<Vector3> {
+        let cos_i = self.dot(normal);
+        let sin2_t = n_ratio * n_ratio * (1. - cos_i * cos_i);
+        if sin2_t > 1. {
+            return None;
+        }
+
+        let cos_t = (1. - sin2_t).sqrt();
+        Some(&(normal * (n_ratio * cos_i - cos_t)) - &(self * n_ratio))
+    }
+}
+
+impl From<[f64; 3]> for Vector3 {
+    fn from(xyz: [f64; 3]) -> Self {
+        Vector3::new(xyz[0], xyz[1], xyz[2])
+    }
+}
+
+impl From<(f64, f64, f64)> for Vector3 {
+    fn from((x, y, z): (f64, f64, f64)) -> Self {
+        Vector3::new(x, y, z)
+    }
 }
 
 impl PartialEq for Vector3 {
@@ -59,6 +114,21 @@ impl ops::Add<&Vector3> for &Vector3 {
     }
 }
 
+impl ops::Add<Vector3> for Vector3 {
+    type Output = Vector3;
+    fn add(self, other: Vector3) -> Vector3 {
+        &self + &other
+    }
+}
+
+impl ops::AddAssign<&Vector3> for Vector3 {
+    fn add_assign(&mut self, other: &Vector3) {
+        self.x += other.x;
+        self.y += other.y;
+        self.z += other.z;
+    }
+}
+
 impl ops::Sub<&Vector3> for &Vector3 {
     type Output = Vector3;
     fn sub(self, other: &Vector3) -> Vector3 {
@@ -70,6 +140,21 @@ impl ops::Sub<&Vector3> for &Vector3 {
     }
 }
 
+impl ops::Sub<Vector3> for Vector3 {
+    type Output = Vector3;
+    fn sub(self, other: Vector3) -> Vector3 {
+        &self - &other
+    }
+}
+
+impl ops::SubAssign<&Vector3> for Vector3 {
+    fn sub_assign(&mut self, other: &Vector3) {
+        self.x -= other.x;
+        self.y -= other.y;
+        self.z -= other.z;
+    }
+}
+
 impl ops::Mul<f64> for &Vector3 {
     type Output = Vector3;
     fn mul(self, other: f64) -> Vector3 {
@@ -189,6 +274,18 @@ mod tests {
         assert_eq!(vector.magnitude(), 14.0f64.sqrt());
     }
 
+    #[test]
+    fn the_squared_magnitude_skips_the_square_root() {
+        for vector in [
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 2.0, 3.0),
+            Vector3::new(-0.3, 4.5, -6.7),
+        ] {
+            assert_eq!(vector.magnitude_squared(), vector.dot(&vector));
+            assert!(approximately(vector.magnitude_squared(), vector.magnitude().powi(2)));
+        }
+    }
+
     #[test]
     fn test_vector3_normalize() {
         let vector = Vector3::new(4.0, 0.0, 0.0);
@@ -204,6 +301,13 @@ mod tests {
         assert!(approximately(normalized_vector.z, 0.80178));
     }
 
+    #[test]
+    fn test_normalizing_a_zero_vector3_gives_a_zero_vector3() {
+        let normalized_vector = Vector3::new(0.0, 0.0, 0.0).normalize();
+        assert!(!normalized_vector.x.is_nan() && !normalized_vector.y.is_nan() && !normalized_vector.z.is_nan());
+        assert_eq!(normalized_vector, Vector3::new(0.0, 0.0, 0.0));
+    }
+
     #[test]
     fn test_normalized_vector3_has_magnitude_of_one() {
         let vector = Vector3::new(1.0, 2.0, 3.0);
@@ -233,4 +337,90 @@ mod tests {
         assert_eq!(b_cross_a.y, -2.0);
         assert_eq!(b_cross_a.z, 1.0);
     }
+
+    #[test]
+    fn test_add_and_subtract_owned_values() {
+        let a = Vector3::new(3.0, -2.0, 5.0);
+        let b = Vector3::new(-2.0, 3.0, 1.0);
+        assert_eq!(a.clone() + b.clone(), &a + &b);
+        assert_eq!(a.clone() - b.clone(), &a - &b);
+    }
+
+    #[test]
+    fn test_add_assign_and_sub_assign() {
+        let a = Vector3::new(3.0, -2.0, 5.0);
+        let b = Vector3::new(-2.0, 3.0, 1.0);
+
+        let mut sum = a.clone();
+        sum += &b;
+        assert_eq!(sum, &a + &b);
+
+        let mut difference = a.clone();
+        difference -= &b;
+        assert_eq!(difference, &a - &b);
+    }
+
+    #[test]
+    fn test_vector3_from_array_and_tuple() {
+        let a = Vector3::from([1., 2., 3.]);
+        assert_eq!(a.x, 1.0);
+        assert_eq!(a, Vector3::new(1., 2., 3.));
+
+        let b: Vector3 = (1., 2., 3.).into();
+        assert_eq!(b, Vector3::new(1., 2., 3.));
+    }
+
+    #[test]
+    fn test_vector3_lerp() {
+        let a = Vector3::new(1., 2., 3.);
+        let b = Vector3::new(3., -2., 7.);
+        assert_eq!(a.lerp(&b, 0.), a);
+        assert_eq!(a.lerp(&b, 1.), b);
+        assert_eq!(a.lerp(&b, 0.5), Vector3::new(2., 0., 5.));
+
+        // t is not clamped
+        assert_eq!(a.lerp(&b, 2.), Vector3::new(5., -6., 11.));
+    }
+
+    #[test]
+    fn test_vector3_distance() {
+        let a = Vector3::new(1., 2., 3.);
+        let b = Vector3::new(4., 6., 3.);
+        assert!(approximately(a.distance(&b), 5.));
+        assert!(approximately(b.distance(&a), 5.));
+        assert!(approximately(a.distance(&a), 0.));
+    }
+
+    #[test]
+    fn test_vector3_project_onto() {
+        let v = Vector3::new(3., 4., -5.);
+        assert_eq!(v.project_onto(&Vector3::new(1., 0., 0.)), Vector3::new(3., 0., 0.));
+        assert_eq!(v.project_onto(&Vector3::new(0., 10., 0.)), Vector3::new(0., 4., 0.));
+        assert_eq!(v.project_onto(&Vector3::new(1., 1., 0.)), Vector3::new(3.5, 3.5, 0.));
+    }
+
+    #[test]
+    fn test_vector3_refract_with_equal_indices_goes_straight_through() {
+        let eye = Vector3::new(0., 2f64.sqrt() / 2., -(2f64.sqrt() / 2.));
+        let normal = Vector3::new(0., 1., 0.);
+        assert_eq!(eye.refract(&normal, 1.), Some(-eye.clone()));
+    }
+
+    #[test]
+    fn test_vector3_refract_bends_towards_the_normal() {
+        // From air into glass at 45 degrees
+        let eye = Vector3::new(0., 2f64.sqrt() / 2., -(2f64.sqrt() / 2.));
+        let normal = Vector3::new(0., 1., 0.);
+        let refracted = eye.refract(&normal, 1. / 1.5).unwrap();
+        assert_eq!(refracted, Vector3::new(0., -0.88192, 0.47140));
+        assert!(approximately(refracted.magnitude(), 1.));
+    }
+
+    #[test]
+    fn test_vector3_refract_total_internal_reflection() {
+        // From glass into air at 45 degrees is past the critical angle
+        let eye = Vector3::new(0., 2f64.sqrt() / 2., -(2f64.sqrt() / 2.));
+        let normal = Vector3::new(0., 1., 0.);
+        assert_eq!(eye.refract(&normal, 1.5), None);
+    }
 }
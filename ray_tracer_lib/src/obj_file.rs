@@ -0,0 +1,312 @@
+use crate::mathf::group::Group;
+use crate::mathf::shapes::Shape;
+use crate::mathf::triangle::Triangle;
+use crate::mathf::vector3::Vector3;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    /// A line that looked like a vertex or face but couldn't be read, lines are numbered from 1.
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(e) => write!(f, "unable to read obj file: {}", e),
+            ObjError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<io::Error> for ObjError {
+    fn from(e: io::Error) -> Self {
+        ObjError::Io(e)
+    }
+}
+
+/// The geometry read from a Wavefront OBJ file.
+///
+/// Only vertices (`v`), vertex normals (`vn`), faces (`f`) and groups (`g`) are
+/// understood, any other line is counted in `ignored_lines`. Faces with more than
+/// three vertices are split into a fan of triangles. Faces that give a normal for
+/// every vertex (`f 1//1 2//2 3//3` or `f 1/1/1 2/2/2 3/3/3`) become smooth
+/// triangles, other faces are flat.
+#[derive(Debug, Default)]
+pub struct ObjFile {
+    /// The vertices in file order. Note the file numbers them from 1.
+    pub vertices: Vec<Vector3>,
+    /// The vertex normals in file order, also numbered from 1 in the file.
+    pub normals: Vec<Vector3>,
+    pub ignored_lines: usize,
+    /// Triangles that came before any `g` statement
+    pub default_group: Vec<Arc<Triangle>>,
+    pub groups: Vec<(String, Vec<Arc<Triangle>>)>,
+}
+
+impl ObjFile {
+    /// The triangles in a named group, if there is one
+    pub fn group(&self, name: &str) -> Option<&[Arc<Triangle>]> {
+        self.groups
+            .iter()
+            .find(|(group_name, _)| group_name == name)
+            .map(|(_, triangles)| triangles.as_slice())
+    }
+
+    /// Put every triangle in a single group so the model can be added to a world.
+    /// Each named group becomes a child group.
+    pub fn into_group(self) -> Group {
+        let mut children: Vec<Arc<dyn Shape>> = vec![];
+        for triangle in self.default_group {
+            children.push(triangle);
+        }
+        for (_, triangles) in self.groups {
+            let triangles = triangles.into_iter().map(|t| t as Arc<dyn Shape>).collect();
+            children.push(Arc::new(Group::new(None, triangles)));
+        }
+        Group::new(None, children)
+    }
+}
+
+pub fn load_obj_file(file_path: &str) -> Result<ObjFile, ObjError> {
+    parse_obj(&fs::read_to_string(file_path)?)
+}
+
+pub fn parse_obj(data: &str) -> Result<ObjFile, ObjError> {
+    let mut obj = ObjFile::default();
+
+    for (index, line) in data.lines().enumerate() {
+        let line_number = index + 1;
+        let mut parts = line.split_whitespace();
+
+        match parts.next() {
+            Some("v") => {
+                let xyz = parse_numbers(parts, line_number)?;
+                if xyz.len() < 3 {
+                    return Err(parse_error(line_number, "a vertex needs 3 coordinates"));
+                }
+                obj.vertices.push(Vector3::new(xyz[0], xyz[1], xyz[2]));
+            }
+            Some("vn") => {
+                let xyz = parse_numbers(parts, line_number)?;
+                if xyz.len() < 3 {
+                    return Err(parse_error(line_number, "a normal needs 3 coordinates"));
+                }
+                obj.normals.push(Vector3::new(xyz[0], xyz[1], xyz[2]));
+            }
+            Some("f") => {
+                let corners = parts
+                    .map(|part| face_corner(&obj, part, line_number))
+                    .collect::<Result<Vec<(Vector3, Option<Vector3>)>, ObjError>>()?;
+                if corners.len() < 3 {
+                    return Err(parse_error(line_number, "a face needs at least 3 vertices"));
+                }
+                let smooth = corners.iter().all(|(_, normal)| normal.is_some());
+
+                // Fan triangulation, assumes the polygon is convex
+                for i in 1..corners.len() - 1 {
+                    let (p1, n1) = corners[0].clone();
+                    let (p2, n2) = corners[i].clone();
+                    let (p3, n3) = corners[i + 1].clone();
+                    let triangle = if smooth {
+                        Triangle::new_smooth(None, None, p1, p2, p3, n1.unwrap(), n2.unwrap(), n3.unwrap())
+                    } else {
+                        Triangle::new(None, None, p1, p2, p3)
+                    };
+                    match obj.groups.last_mut() {
+                        Some((_, triangles)) => triangles.push(Arc::new(triangle)),
+                        None => obj.default_group.push(Arc::new(triangle)),
+                    }
+                }
+            }
+            Some("g") => {
+                let name = parts.collect::<Vec<&str>>().join(" ");
+                obj.groups.push((name, vec![]));
+            }
+            _ => obj.ignored_lines += 1,
+        }
+    }
+
+    Ok(obj)
+}
+
+fn parse_numbers<'a>(
+    parts: impl Iterator<Item = &'a str>,
+    line_number: usize,
+) -> Result<Vec<f64>, ObjError> {
+    parts
+        .map(|part| {
+            part.parse::<f64>()
+                .map_err(|_| parse_error(line_number, &format!("'{}' is not a number", part)))
+        })
+        .collect()
+}
+
+// Face entries look like `v`, `v/vt`, `v/vt/vn` or `v//vn`. Texture coordinates are ignored.
+fn face_corner(obj: &ObjFile, part: &str, line_number: usize) -> Result<(Vector3, Option<Vector3>), ObjError> {
+    let mut indices = part.split('/');
+    let vertex = lookup(&obj.vertices, indices.next().unwrap_or(part), "vertex", line_number)?;
+    let normal = match indices.nth(1) {
+        None | Some("") => None,
+        Some(index) => Some(lookup(&obj.normals, index, "normal", line_number)?),
+    };
+    Ok((vertex, normal))
+}
+
+// Indices count from 1, negative ones count back from the last item read so far
+fn lookup(items: &[Vector3], index: &str, kind: &str, line_number: usize) -> Result<Vector3, ObjError> {
+    let parsed = index
+        .parse::<i64>()
+        .map_err(|_| parse_error(line_number, &format!("'{}' is not a {} index", index, kind)))?;
+
+    let position = if parsed < 0 {
+        items.len() as i64 + parsed
+    } else {
+        parsed - 1
+    };
+    if position < 0 || position >= items.len() as i64 {
+        return Err(parse_error(line_number, &format!("there is no {} {}", kind, parsed)));
+    }
+    Ok(items[position as usize].clone())
+}
+
+fn parse_error(line: usize, message: &str) -> ObjError {
+    ObjError::Parse {
+        line,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignoring_unrecognized_lines() {
+        let gibberish = "There was a young lady named Bright\n\
+                         who traveled much faster than light.\n\
+                         She set out one day\n\
+                         in a relative way,\n\
+                         and came back the previous night.";
+        let obj = parse_obj(gibberish).unwrap();
+        assert_eq!(obj.ignored_lines, 5);
+    }
+
+    #[test]
+    fn vertex_records() {
+        let obj = parse_obj("v -1 1 0\nv -1.0000 0.5000 0.0000\nv 1 0 0\nv 1 1 0").unwrap();
+        assert_eq!(obj.vertices[0], Vector3::new(-1., 1., 0.));
+        assert_eq!(obj.vertices[1], Vector3::new(-1., 0.5, 0.));
+        assert_eq!(obj.vertices[2], Vector3::new(1., 0., 0.));
+        assert_eq!(obj.vertices[3], Vector3::new(1., 1., 0.));
+    }
+
+    #[test]
+    fn parsing_triangle_faces() {
+        let obj = parse_obj("v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\n\nf 1 2 3\nf 1 3 4").unwrap();
+        let t1 = &obj.default_group[0];
+        let t2 = &obj.default_group[1];
+        assert_eq!(t1.p1, obj.vertices[0]);
+        assert_eq!(t1.p2, obj.vertices[1]);
+        assert_eq!(t1.p3, obj.vertices[2]);
+        assert_eq!(t2.p1, obj.vertices[0]);
+        assert_eq!(t2.p2, obj.vertices[2]);
+        assert_eq!(t2.p3, obj.vertices[3]);
+    }
+
+    #[test]
+    fn triangulating_polygons() {
+        let obj = parse_obj("v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\nv 0 2 0\n\nf 1 2 3 4 5").unwrap();
+        assert_eq!(obj.default_group.len(), 3);
+        let t3 = &obj.default_group[2];
+        assert_eq!(t3.p1, obj.vertices[0]);
+        assert_eq!(t3.p2, obj.vertices[3]);
+        assert_eq!(t3.p3, obj.vertices[4]);
+    }
+
+    #[test]
+    fn triangles_in_groups() {
+        let obj = parse_obj("v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\ng FirstGroup\nf 1 2 3\ng SecondGroup\nf 1 3 4")
+            .unwrap();
+        assert!(obj.default_group.is_empty());
+        assert_eq!(obj.group("FirstGroup").unwrap()[0].p3, obj.vertices[2]);
+        assert_eq!(obj.group("SecondGroup").unwrap()[0].p3, obj.vertices[3]);
+    }
+
+    #[test]
+    fn converting_an_obj_file_to_a_group() {
+        let obj = parse_obj("v -1 1 0\nv -1 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 3\ng FirstGroup\nf 1 3 4").unwrap();
+        let group = obj.into_group();
+        assert_eq!(group.children().len(), 2);
+    }
+
+    #[test]
+    fn a_face_referencing_a_missing_vertex_is_an_error() {
+        match parse_obj("v -1 1 0\nv -1 0 0\nf 1 2 3") {
+            Err(ObjError::Parse { line, .. }) => assert_eq!(line, 3),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn vertex_normal_records() {
+        let obj = parse_obj("vn 0 0 1\nvn 0.707 0 -0.707\nvn 1 2 3").unwrap();
+        assert_eq!(obj.normals[0], Vector3::new(0., 0., 1.));
+        assert_eq!(obj.normals[1], Vector3::new(0.707, 0., -0.707));
+        assert_eq!(obj.normals[2], Vector3::new(1., 2., 3.));
+    }
+
+    const FACES_WITH_NORMALS: &str = "v 0 1 0\nv -1 0 0\nv 1 0 0\n\nvn -1 0 0\nvn 1 0 0\nvn 0 1 0\n\n";
+
+    #[test]
+    fn faces_with_normals() {
+        let data = format!("{}f 1//3 2//1 3//2\nf 1/0/3 2/102/1 3/14/2", FACES_WITH_NORMALS);
+        let obj = parse_obj(&data).unwrap();
+        let t1 = &obj.default_group[0];
+        let t2 = &obj.default_group[1];
+
+        assert!(t1.is_smooth());
+        assert_eq!(t1.p1, obj.vertices[0]);
+        assert_eq!(t1.p2, obj.vertices[1]);
+        assert_eq!(t1.p3, obj.vertices[2]);
+        let [n1, n2, n3] = t1.vertex_normals.clone().unwrap();
+        assert_eq!(n1, obj.normals[2]);
+        assert_eq!(n2, obj.normals[0]);
+        assert_eq!(n3, obj.normals[1]);
+        assert_eq!(t2.vertex_normals, t1.vertex_normals);
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_record() {
+        let data = format!("{}f -3//-1 -2//-3 -1//-2", FACES_WITH_NORMALS);
+        let obj = parse_obj(&data).unwrap();
+        let t = &obj.default_group[0];
+        assert_eq!(t.p1, obj.vertices[0]);
+        assert_eq!(t.vertex_normals.clone().unwrap()[0], obj.normals[2]);
+        assert_eq!(t.vertex_normals.clone().unwrap()[1], obj.normals[0]);
+    }
+
+    #[test]
+    fn faces_without_normals_are_flat() {
+        let obj = parse_obj(&format!("{}f 1 2 3\nf 1//3 2 3//2", FACES_WITH_NORMALS)).unwrap();
+        assert!(!obj.default_group[0].is_smooth());
+        assert!(!obj.default_group[1].is_smooth());
+    }
+
+    #[test]
+    fn smooth_and_flat_models_shade_differently() {
+        let flat = parse_obj(&format!("{}f 1 2 3", FACES_WITH_NORMALS)).unwrap();
+        let smooth = parse_obj(&format!("{}f 1//3 2//1 3//2", FACES_WITH_NORMALS)).unwrap();
+        let point = Vector3::new(-0.2, 0.3, 0.);
+
+        // The flat triangle faces -z everywhere, the smooth one follows its vertex normals
+        assert_eq!(flat.default_group[0].normal_at(point.clone()), Vector3::new(0., 0., -1.));
+        assert_eq!(smooth.default_group[0].normal_at(point), Vector3::new(-0.5547, 0.83205, 0.));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::color::Color;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::vector3::Vector3;
+use crate::patterns::{Pattern, PatternSpace};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendMode {
+    /// Half of each color
+    Average,
+    /// Multiply the colors together, each one tints the other
+    Multiply,
+}
+
+/// Combines the colors of two patterns at every point.
+/// For example two perpendicular stripe patterns averaged together make a plaid.
+#[derive(Debug)]
+pub struct BlendedPattern {
+    pub a: Arc<dyn Pattern>,
+    pub b: Arc<dyn Pattern>,
+    pub mode: BlendMode,
+    pub space: PatternSpace,
+    transform: Matrix,
+    inverse_transform: Matrix,
+}
+
+impl BlendedPattern {
+    pub fn new(
+        transform: Option<Matrix>,
+        a: Arc<dyn Pattern>,
+        b: Arc<dyn Pattern>,
+        mode: BlendMode,
+    ) -> BlendedPattern {
+        let t = match transform {
+            None => Matrix::identity_4x4(),
+            Some(x) => x,
+        };
+        let inverse_transform = t.inverse().unwrap();
+        BlendedPattern {
+            a,
+            b,
+            mode,
+            space: PatternSpace::default(),
+            transform: t,
+            inverse_transform,
+        }
+    }
+}
+
+impl Pattern for BlendedPattern {
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn space(&self) -> PatternSpace {
+        self.space
+    }
+
+    fn pattern_at(&self, pattern_point: &Vector3) -> Color {
+        let a = self.a.pattern_at_parent(pattern_point);
+        let b = self.b.pattern_at_parent(pattern_point);
+        match self.mode {
+            BlendMode::Average => (a + b) * 0.5,
+            BlendMode::Multiply => a * b,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+    use crate::patterns::solid::SolidPattern;
+    use crate::patterns::stripe::StripePattern;
+    use crate::transformations;
+    use std::f64::consts::PI;
+
+    fn solid(r: f64, g: f64, b: f64) -> Arc<dyn Pattern> {
+        Arc::new(SolidPattern::new(Color::new(r, g, b)))
+    }
+
+    #[test]
+    fn blending_two_solid_patterns_averages_them() {
+        let pattern = BlendedPattern::new(None, solid(1., 0.5, 0.), solid(0., 0.5, 1.), BlendMode::Average);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.3, 2., -5.)), Color::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn multiplying_two_solid_patterns() {
+        let pattern = BlendedPattern::new(None, solid(1., 0.5, 0.2), solid(0.5, 0.5, 1.), BlendMode::Multiply);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 0.)), Color::new(0.5, 0.25, 0.2));
+    }
+
+    #[test]
+    fn blending_perpendicular_stripes_makes_a_plaid() {
+        let stripes_x: Arc<dyn Pattern> = Arc::new(StripePattern::new(None, color::WHITE, color::BLACK));
+        let stripes_z: Arc<dyn Pattern> = Arc::new(StripePattern::new(
+            Some(transformations::rotation_y(-PI / 2.)),
+            color::WHITE,
+            color::BLACK,
+        ));
+        let plaid = BlendedPattern::new(None, stripes_x, stripes_z, BlendMode::Average);
+
+        let gray = Color::new(0.5, 0.5, 0.5);
+        assert_eq!(plaid.pattern_at(&Vector3::new(0.5, 0., 0.5)), color::WHITE);
+        assert_eq!(plaid.pattern_at(&Vector3::new(1.5, 0., 0.5)), gray);
+        assert_eq!(plaid.pattern_at(&Vector3::new(0.5, 0., -0.5)), gray);
+        assert_eq!(plaid.pattern_at(&Vector3::new(1.5, 0., -0.5)), color::BLACK);
+    }
+}
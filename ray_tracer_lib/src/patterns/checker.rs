@@ -0,0 +1,171 @@
+use crate::color::Color;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::vector3::Vector3;
+use crate::patterns::solid::SolidPattern;
+use crate::patterns::{Pattern, PatternSpace};
+use std::sync::Arc;
+
+/// Alternating cubes of two patterns, one unit on each side.
+/// Usually both are solid colors, but they can be any pattern.
+#[derive(Debug)]
+pub struct CheckerPattern {
+    pub a: Arc<dyn Pattern>,
+    pub b: Arc<dyn Pattern>,
+    pub space: PatternSpace,
+    transform: Matrix,
+    inverse_transform: Matrix,
+}
+
+impl CheckerPattern {
+    pub fn new(transform: Option<Matrix>, a: Color, b: Color) -> CheckerPattern {
+        CheckerPattern::new_nested(
+            transform,
+            Arc::new(SolidPattern::new(a)),
+            Arc::new(SolidPattern::new(b)),
+        )
+    }
+
+    /// Checkers made of other patterns, each keeps its own transform
+    /// relative to the checkers.
+    pub fn new_nested(
+        transform: Option<Matrix>,
+        a: Arc<dyn Pattern>,
+        b: Arc<dyn Pattern>,
+    ) -> CheckerPattern {
+        let t = match transform {
+            None => Matrix::identity_4x4(),
+            Some(x) => x,
+        };
+        let inverse_transform = t.inverse().unwrap();
+        CheckerPattern {
+            a,
+            b,
+            space: PatternSpace::default(),
+            transform: t,
+            inverse_transform,
+        }
+    }
+}
+
+impl Pattern for CheckerPattern {
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn space(&self) -> PatternSpace {
+        self.space
+    }
+
+    fn pattern_at(&self, pattern_point: &Vector3) -> Color {
+        let sum = cell(pattern_point.x) + cell(pattern_point.y) + cell(pattern_point.z);
+        if sum.rem_euclid(2) == 0 {
+            self.a.pattern_at_parent(pattern_point)
+        } else {
+            self.b.pattern_at_parent(pattern_point)
+        }
+    }
+}
+
+// How close to an integer a coordinate has to be to count as on it. Far tighter
+// than `mathf::EQUALITY_EPSILON`, rounding error from a transform is around 1e-15
+// while a point like 0.9999999 is really inside the cell below.
+const CELL_EPSILON: f64 = 1e-9;
+
+// Which unit cell a coordinate is in. A transformed point that should be exactly on an
+// integer can come out a hair below it, which would give a seam of the wrong color,
+// so anything that close to an integer is snapped up to it.
+fn cell(value: f64) -> i64 {
+    (value + CELL_EPSILON).floor() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+    use crate::mathf::plane::Plane;
+    use crate::transformations;
+
+    fn checkers() -> CheckerPattern {
+        CheckerPattern::new(None, color::WHITE, color::BLACK)
+    }
+
+    #[test]
+    fn checkers_repeat_in_x() {
+        let pattern = checkers();
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.99, 0., 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(1.01, 0., 0.)), color::BLACK);
+    }
+
+    #[test]
+    fn checkers_repeat_in_y() {
+        let pattern = checkers();
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0.99, 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 1.01, 0.)), color::BLACK);
+    }
+
+    #[test]
+    fn checkers_repeat_in_z() {
+        let pattern = checkers();
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 0.99)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 1.01)), color::BLACK);
+    }
+
+    #[test]
+    fn checkers_alternate_across_negative_coordinates() {
+        let pattern = checkers();
+        assert_eq!(pattern.pattern_at(&Vector3::new(-0.5, 0., 0.)), color::BLACK);
+        assert_eq!(pattern.pattern_at(&Vector3::new(-0.5, 0., -0.5)), color::WHITE);
+    }
+
+    #[test]
+    fn points_near_an_integer_land_in_a_consistent_cell() {
+        let pattern = checkers();
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 0.)), pattern.pattern_at(&Vector3::new(0.9999999, 0., 0.)));
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.9999999, 0., 0.)), color::WHITE);
+        // Rounding error just below an integer is snapped up to it
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., -1e-12, 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(1. - 1e-12, 0., 0.)), color::BLACK);
+    }
+
+    fn points_on_the_floor() -> Vec<Vector3> {
+        vec![
+            Vector3::new(0.5, 0., 0.5),
+            Vector3::new(1.5, 0., 0.5),
+            Vector3::new(-2.5, 0., 3.5),
+            Vector3::new(4.25, 0., -1.75),
+            Vector3::new(7.5, 0., 7.5),
+        ]
+    }
+
+    #[test]
+    fn a_world_space_checker_is_the_same_across_differently_transformed_planes() {
+        let a = Plane::new(None, None);
+        let b = Plane::new(
+            Some(
+                transformations::translation(&Vector3::new(5.3, 0., -2.7))
+                    .multiply_4x4(&transformations::rotation_y(0.7)),
+            ),
+            None,
+        );
+
+        let mut pattern = checkers();
+        pattern.space = PatternSpace::World;
+        for point in points_on_the_floor() {
+            assert_eq!(pattern.pattern_at_shape(&a, &point), pattern.pattern_at_shape(&b, &point));
+            assert_eq!(pattern.pattern_at_shape(&b, &point), pattern.pattern_at(&point));
+        }
+
+        // In object space the checkers move with the plane so they don't line up
+        let pattern = checkers();
+        assert!(points_on_the_floor()
+            .iter()
+            .any(|point| pattern.pattern_at_shape(&a, point) != pattern.pattern_at_shape(&b, point)));
+    }
+}
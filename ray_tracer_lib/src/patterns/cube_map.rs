@@ -0,0 +1,190 @@
+use crate::color::Color;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::vector3::Vector3;
+use crate::patterns::{Pattern, PatternSpace};
+use std::sync::Arc;
+
+/// The face of a cube a point is on (0 to 5 for +x, -x, +y, -y, +z, -z) and
+/// where on it, u runs left to right and v bottom to top, both 0 to 1. The face
+/// is picked by the axis the point is furthest along, so the point doesn't need
+/// to be on the cube, any point in that direction from the center gives the same.
+///
+/// Each face is laid out as seen from outside the cube with +y up, the top and
+/// bottom faces have -z and +z at the top.
+pub fn cube_face_uv(point: &Vector3) -> (usize, f64, f64) {
+    let (ax, ay, az) = (point.x.abs(), point.y.abs(), point.z.abs());
+    let largest = ax.max(ay).max(az);
+    // Project onto the unit cube so each coordinate is from -1 to 1
+    let (x, y, z) = (point.x / largest, point.y / largest, point.z / largest);
+
+    let (face, u, v) = if largest == ax {
+        if x > 0. {
+            (0, 1. - z, y + 1.)
+        } else {
+            (1, z + 1., y + 1.)
+        }
+    } else if largest == ay {
+        if y > 0. {
+            (2, x + 1., 1. - z)
+        } else {
+            (3, x + 1., z + 1.)
+        }
+    } else if z > 0. {
+        (4, x + 1., y + 1.)
+    } else {
+        (5, 1. - x, y + 1.)
+    };
+    (face, u / 2., v / 2.)
+}
+
+/// A different pattern on each face of a cube, a die for example. Each face
+/// pattern is laid flat along its x and z axes, from 0 to 1, so a `UvImagePattern`
+/// fills the face with its image.
+#[derive(Debug)]
+pub struct CubeMapPattern {
+    /// The patterns for the faces in the order +x, -x, +y, -y, +z, -z
+    pub faces: [Arc<dyn Pattern>; 6],
+    pub space: PatternSpace,
+    transform: Matrix,
+    inverse_transform: Matrix,
+}
+
+impl CubeMapPattern {
+    pub fn new(transform: Option<Matrix>, faces: [Arc<dyn Pattern>; 6]) -> CubeMapPattern {
+        let t = transform.unwrap_or_else(Matrix::identity_4x4);
+        let inverse_transform = t.inverse().unwrap();
+        CubeMapPattern {
+            faces,
+            space: PatternSpace::default(),
+            transform: t,
+            inverse_transform,
+        }
+    }
+}
+
+impl Pattern for CubeMapPattern {
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn space(&self) -> PatternSpace {
+        self.space
+    }
+
+    fn pattern_at(&self, pattern_point: &Vector3) -> Color {
+        let (face, u, v) = cube_face_uv(pattern_point);
+        self.faces[face].pattern_at_parent(&Vector3::new(u, 0., v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::Canvas;
+    use crate::patterns::solid::SolidPattern;
+    use crate::patterns::uv_image::UvImagePattern;
+
+    #[test]
+    fn each_face_and_its_corners() {
+        // The face, then the corners at the bottom left and top right of it
+        let cases = [
+            (
+                Vector3::new(1., 0., 0.),
+                0,
+                Vector3::new(1., -1., 1.),
+                Vector3::new(1., 1., -1.),
+            ),
+            (
+                Vector3::new(-1., 0., 0.),
+                1,
+                Vector3::new(-1., -1., -1.),
+                Vector3::new(-1., 1., 1.),
+            ),
+            (
+                Vector3::new(0., 1., 0.),
+                2,
+                Vector3::new(-1., 1., 1.),
+                Vector3::new(1., 1., -1.),
+            ),
+            (
+                Vector3::new(0., -1., 0.),
+                3,
+                Vector3::new(-1., -1., -1.),
+                Vector3::new(1., -1., 1.),
+            ),
+            (
+                Vector3::new(0., 0., 1.),
+                4,
+                Vector3::new(-1., -1., 1.),
+                Vector3::new(1., 1., 1.),
+            ),
+            (
+                Vector3::new(0., 0., -1.),
+                5,
+                Vector3::new(1., -1., -1.),
+                Vector3::new(-1., 1., -1.),
+            ),
+        ];
+        for (center, face, bottom_left, top_right) in cases.iter() {
+            assert_eq!(cube_face_uv(center), (*face, 0.5, 0.5));
+
+            // Nudged towards the center of the face so the corner isn't shared
+            let nudge = |corner: &Vector3| corner * 0.98 + center * 0.02;
+            let (f, u, v) = cube_face_uv(&nudge(bottom_left));
+            assert_eq!(f, *face);
+            assert!(u < 0.02 && v < 0.02, "{:?} {} {}", bottom_left, u, v);
+            let (f, u, v) = cube_face_uv(&nudge(top_right));
+            assert_eq!(f, *face);
+            assert!(u > 0.98 && v > 0.98, "{:?} {} {}", top_right, u, v);
+        }
+    }
+
+    #[test]
+    fn a_pattern_per_face() {
+        let colors = [
+            Color::new(1., 0., 0.),
+            Color::new(0., 1., 0.),
+            Color::new(0., 0., 1.),
+            Color::new(1., 1., 0.),
+            Color::new(0., 1., 1.),
+            Color::new(1., 0., 1.),
+        ];
+        let faces = colors
+            .clone()
+            .map(|color| Arc::new(SolidPattern::new(color)) as Arc<dyn Pattern>);
+        let pattern = CubeMapPattern::new(None, faces);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.9, 0.2, -0.5)), colors[0]);
+        assert_eq!(
+            pattern.pattern_at(&Vector3::new(-0.9, 0.2, -0.5)),
+            colors[1]
+        );
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.1, 3., -0.5)), colors[2]);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.1, -3., -0.5)), colors[3]);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.1, 0.2, 0.5)), colors[4]);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.1, 0.2, -0.5)), colors[5]);
+    }
+
+    #[test]
+    fn an_image_fills_a_face() {
+        // Dark on the left half of the image, light on the right
+        let mut image = Canvas::new(2, 1);
+        image.write_pixel(1, 0, &Color::new(1., 1., 1.));
+        let face: Arc<dyn Pattern> = Arc::new(UvImagePattern::new(None, image));
+        let faces = [(); 6].map(|_| Arc::clone(&face));
+        let pattern = CubeMapPattern::new(None, faces);
+
+        // The front face runs left to right along +x
+        assert_eq!(
+            pattern.pattern_at(&Vector3::new(-0.5, 0., 1.)),
+            Color::new(0., 0., 0.)
+        );
+        assert_eq!(
+            pattern.pattern_at(&Vector3::new(0.5, 0., 1.)),
+            Color::new(1., 1., 1.)
+        );
+    }
+}
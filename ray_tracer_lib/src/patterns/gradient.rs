@@ -0,0 +1,66 @@
+use crate::color::Color;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::vector3::Vector3;
+use crate::patterns::{Pattern, PatternSpace};
+
+/// Blends from `a` at x = 0 to `b` at x = 1, repeating every unit.
+#[derive(Debug)]
+pub struct GradientPattern {
+    pub a: Color,
+    pub b: Color,
+    pub space: PatternSpace,
+    transform: Matrix,
+    inverse_transform: Matrix,
+}
+
+impl GradientPattern {
+    pub fn new(transform: Option<Matrix>, a: Color, b: Color) -> GradientPattern {
+        let t = match transform {
+            None => Matrix::identity_4x4(),
+            Some(x) => x,
+        };
+        let inverse_transform = t.inverse().unwrap();
+        GradientPattern {
+            a,
+            b,
+            space: PatternSpace::default(),
+            transform: t,
+            inverse_transform,
+        }
+    }
+}
+
+impl Pattern for GradientPattern {
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn space(&self) -> PatternSpace {
+        self.space
+    }
+
+    fn pattern_at(&self, pattern_point: &Vector3) -> Color {
+        let distance = self.b.clone() - self.a.clone();
+        let fraction = pattern_point.x - pattern_point.x.floor();
+        self.a.clone() + distance * fraction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+
+    #[test]
+    fn a_gradient_linearly_interpolates_between_colors() {
+        let pattern = GradientPattern::new(None, color::WHITE, color::BLACK);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.25, 0., 0.)), Color::new(0.75, 0.75, 0.75));
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.5, 0., 0.)), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.75, 0., 0.)), Color::new(0.25, 0.25, 0.25));
+    }
+}
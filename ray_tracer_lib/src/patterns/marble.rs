@@ -0,0 +1,93 @@
+use crate::color::Color;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::vector3::Vector3;
+use crate::patterns::perlin;
+use crate::patterns::{Pattern, PatternSpace};
+
+// Enough detail for the veins without spending too long on each point
+const OCTAVES: usize = 6;
+
+/// Marble, bands of `a` and `b` along the x axis bent into veins by Perlin
+/// turbulence (see `perlin::turbulence`).
+#[derive(Debug)]
+pub struct MarblePattern {
+    pub a: Color,
+    pub b: Color,
+    /// How many bands there are per unit, 0 gives an even blend of the two colors
+    pub frequency: f64,
+    /// How much the bands are bent, 0 gives straight stripes
+    pub turbulence: f64,
+    pub space: PatternSpace,
+    transform: Matrix,
+    inverse_transform: Matrix,
+}
+
+impl MarblePattern {
+    pub fn new(transform: Option<Matrix>, a: Color, b: Color) -> MarblePattern {
+        let t = transform.unwrap_or_else(Matrix::identity_4x4);
+        let inverse_transform = t.inverse().unwrap();
+        MarblePattern {
+            a,
+            b,
+            frequency: 1.,
+            turbulence: 5.,
+            space: PatternSpace::default(),
+            transform: t,
+            inverse_transform,
+        }
+    }
+}
+
+impl Pattern for MarblePattern {
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn space(&self) -> PatternSpace {
+        self.space
+    }
+
+    fn pattern_at(&self, pattern_point: &Vector3) -> Color {
+        let point = pattern_point * self.frequency;
+        let phase = point.x + self.turbulence * perlin::turbulence(&point, OCTAVES);
+        let t = (phase.sin() + 1.) / 2.;
+        &self.a * (1. - t) + &self.b * t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+
+    #[test]
+    fn a_zero_frequency_is_the_same_blend_everywhere() {
+        let mut pattern = MarblePattern::new(None, color::WHITE, color::BLACK);
+        pattern.frequency = 0.;
+        for point in [
+            Vector3::new(0., 0., 0.),
+            Vector3::new(1.3, -2.7, 0.4),
+            Vector3::new(40., 5., -9.),
+        ] {
+            assert_eq!(pattern.pattern_at(&point), Color::new(0.5, 0.5, 0.5));
+        }
+    }
+
+    #[test]
+    fn marble_is_veined_rather_than_striped() {
+        let pattern = MarblePattern::new(None, color::WHITE, color::BLACK);
+        let mut straight = MarblePattern::new(None, color::WHITE, color::BLACK);
+        straight.turbulence = 0.;
+
+        // Without turbulence points with the same x match, with it they don't
+        let a = Vector3::new(0.3, 0.45, 0.2);
+        let b = Vector3::new(0.3, 2.7, -1.9);
+        assert_eq!(straight.pattern_at(&a), straight.pattern_at(&b));
+        assert_ne!(pattern.pattern_at(&a), pattern.pattern_at(&b));
+        assert_eq!(pattern.pattern_at(&a), pattern.pattern_at(&a.clone()));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::color::Color;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::shapes::Shape;
+use crate::mathf::vector3::Vector3;
+use std::fmt;
+
+pub mod blended;
+pub mod checker;
+pub mod cube_map;
+pub mod gradient;
+pub mod marble;
+pub mod normal_map;
+pub mod perlin;
+pub mod perturb;
+pub mod solid;
+pub mod stripe;
+pub mod uv_image;
+
+/// Which space a pattern on a shape is evaluated in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PatternSpace {
+    /// The pattern sticks to the shape and moves with it
+    #[default]
+    Object,
+    /// The pattern stays put and shapes move through it. Handy for tiling a
+    /// pattern seamlessly across several shapes.
+    World,
+}
+
+pub trait Pattern: fmt::Debug + Send + Sync {
+    fn transform(&self) -> &Matrix;
+    fn inverse_transform(&self) -> &Matrix;
+
+    /// The color at a point given in the pattern's own space.
+    fn pattern_at(&self, pattern_point: &Vector3) -> Color;
+
+    /// The color at a point given in the space of whatever holds this pattern,
+    /// either a shape or another pattern wrapping this one.
+    fn pattern_at_parent(&self, parent_point: &Vector3) -> Color {
+        self.pattern_at(&self.inverse_transform().multiply_point(parent_point))
+    }
+
+    /// Only used when the pattern is on a shape, patterns inside other patterns
+    /// are always relative to the pattern holding them.
+    fn space(&self) -> PatternSpace {
+        PatternSpace::Object
+    }
+
+    /// The color at a point on a shape, given in world space.
+    fn pattern_at_shape(&self, shape: &dyn Shape, world_point: &Vector3) -> Color {
+        match self.space() {
+            PatternSpace::Object => {
+                let object_point = shape.world_to_object(world_point);
+                self.pattern_at_parent(&object_point)
+            }
+            PatternSpace::World => self.pattern_at_parent(world_point),
+        }
+    }
+}
+
+/// Patterns have no useful notion of equality, two are the same only if they are
+/// the same instance.
+pub fn same_pattern(a: &dyn Pattern, b: &dyn Pattern) -> bool {
+    std::ptr::eq(a as *const dyn Pattern as *const (), b as *const dyn Pattern as *const ())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::mathf::sphere::Sphere;
+    use crate::transformations;
+
+    /// Returns the point it was given as a color, handy to check which space a
+    /// point is in when it reaches the pattern.
+    #[derive(Debug)]
+    pub struct TestPattern {
+        transform: Matrix,
+        inverse_transform: Matrix,
+    }
+
+    impl TestPattern {
+        pub fn new(transform: Option<Matrix>) -> TestPattern {
+            let t = transform.unwrap_or_else(Matrix::identity_4x4);
+            let inverse_transform = t.inverse().unwrap();
+            TestPattern {
+                transform: t,
+                inverse_transform,
+            }
+        }
+    }
+
+    impl Pattern for TestPattern {
+        fn transform(&self) -> &Matrix {
+            &self.transform
+        }
+
+        fn inverse_transform(&self) -> &Matrix {
+            &self.inverse_transform
+        }
+
+        fn pattern_at(&self, pattern_point: &Vector3) -> Color {
+            Color::new(pattern_point.x, pattern_point.y, pattern_point.z)
+        }
+    }
+
+    #[test]
+    fn the_default_pattern_transformation() {
+        let pattern = TestPattern::new(None);
+        assert_eq!(pattern.transform(), &Matrix::identity_4x4());
+    }
+
+    #[test]
+    fn a_pattern_with_an_object_transformation() {
+        let shape = Sphere::new(Some(transformations::scaling(&Vector3::new(2., 2., 2.))), None);
+        let pattern = TestPattern::new(None);
+        let color = pattern.pattern_at_shape(&shape, &Vector3::new(2., 3., 4.));
+        assert_eq!(color, Color::new(1., 1.5, 2.));
+    }
+
+    #[test]
+    fn a_pattern_with_a_pattern_transformation() {
+        let shape = Sphere::new(None, None);
+        let pattern = TestPattern::new(Some(transformations::scaling(&Vector3::new(2., 2., 2.))));
+        let color = pattern.pattern_at_shape(&shape, &Vector3::new(2., 3., 4.));
+        assert_eq!(color, Color::new(1., 1.5, 2.));
+    }
+
+    #[test]
+    fn a_pattern_with_both_an_object_and_a_pattern_transformation() {
+        let shape = Sphere::new(Some(transformations::scaling(&Vector3::new(2., 2., 2.))), None);
+        let pattern = TestPattern::new(Some(transformations::translation(&Vector3::new(0.5, 1., 1.5))));
+        let color = pattern.pattern_at_shape(&shape, &Vector3::new(2.5, 3., 3.5));
+        assert_eq!(color, Color::new(0.75, 0.5, 0.25));
+    }
+}
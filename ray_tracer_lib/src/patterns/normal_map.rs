@@ -0,0 +1,156 @@
+use crate::canvas::Canvas;
+use crate::mathf;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::shapes::Shape;
+use crate::mathf::uv_mapping::UvMapping;
+use crate::mathf::vector3::Vector3;
+use crate::png_encoder;
+use std::fmt;
+
+/// An image of surface normals used to add fine detail (like the grooves between
+/// bricks) without any extra geometry, see `Material::normal_map`.
+///
+/// Each pixel holds a normal in tangent space encoded as a color, red along the
+/// direction u increases, green along v and blue straight out of the surface. Each
+/// channel maps 0.0 to 1.0 onto -1.0 to 1.0, so (0.5, 0.5, 1.0) is a flat surface.
+pub struct NormalMap {
+    pub image: Canvas,
+    pub mapping: UvMapping,
+    transform: Matrix,
+    inverse_transform: Matrix,
+}
+
+impl fmt::Debug for NormalMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NormalMap")
+            .field("width", &self.image.width)
+            .field("height", &self.image.height)
+            .field("mapping", &self.mapping)
+            .field("transform", &self.transform)
+            .finish()
+    }
+}
+
+impl NormalMap {
+    /// The transform places the map on the shape, the same as a pattern transform
+    pub fn new(transform: Option<Matrix>, image: Canvas, mapping: UvMapping) -> NormalMap {
+        let t = transform.unwrap_or_else(Matrix::identity_4x4);
+        let inverse_transform = t.inverse().unwrap();
+        NormalMap {
+            image,
+            mapping,
+            transform: t,
+            inverse_transform,
+        }
+    }
+
+    pub fn from_png(
+        transform: Option<Matrix>,
+        file_path: &str,
+        mapping: UvMapping,
+    ) -> Result<NormalMap, png::DecodingError> {
+        let image = png_encoder::load_canvas_from_png(file_path)?;
+        Ok(NormalMap::new(transform, image, mapping))
+    }
+
+    pub fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    /// The decoded normal at (u, v), see `Canvas::sample_uv`
+    pub fn tangent_space_normal(&self, u: f64, v: f64) -> Vector3 {
+        let color = self.image.sample_uv(u, v);
+        Vector3::new(color.r * 2. - 1., color.g * 2. - 1., color.b * 2. - 1.)
+    }
+
+    /// The outward facing normal at a point on the shape (in world space) with the
+    /// map applied, normalized.
+    pub fn normal_at_shape(&self, shape: &dyn Shape, world_point: &Vector3) -> Vector3 {
+        let object_point = shape.world_to_object(world_point);
+        let normal = shape.local_normal_at(object_point.clone()).normalize();
+
+        let map_point = self.inverse_transform.multiply_point(&object_point);
+        let (u, v) = self.mapping.uv(&map_point);
+        let (du, dv) = self.mapping.directions(&map_point);
+
+        // Square up the directions with the surface to get the tangent frame
+        let du = self.transform.multiply_vector(&du);
+        let dv = self.transform.multiply_vector(&dv);
+        let tangent = (&du - &(&normal * normal.dot(&du))).normalize();
+        let bitangent = &(&dv - &(&normal * normal.dot(&dv))) - &(&tangent * tangent.dot(&dv));
+        let bitangent = if bitangent.magnitude_squared() < mathf::EQUALITY_EPSILON * mathf::EQUALITY_EPSILON {
+            normal.cross(&tangent)
+        } else {
+            bitangent.normalize()
+        };
+
+        let n = self.tangent_space_normal(u, v);
+        let object_normal = &(&(&tangent * n.x) + &(&bitangent * n.y)) + &(&normal * n.z);
+        shape.normal_to_world(&object_normal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::mathf::plane::Plane;
+    use crate::mathf::sphere::Sphere;
+    use crate::transformations;
+
+    fn solid_map(color: &Color, mapping: UvMapping) -> NormalMap {
+        let mut image = Canvas::new(4, 4);
+        for y in 0..4 {
+            for x in 0..4 {
+                image.write_pixel(x, y, color);
+            }
+        }
+        NormalMap::new(None, image, mapping)
+    }
+
+    #[test]
+    fn a_flat_normal_map_leaves_the_normal_unchanged() {
+        let flat = Color::new(0.5, 0.5, 1.);
+        let sphere = Sphere::new(
+            Some(transformations::scaling(&Vector3::new(1., 2., 3.))),
+            None,
+        );
+        let plane = Plane::new(Some(transformations::rotation_z(0.5)), None);
+        let sphere_map = solid_map(&flat, UvMapping::Spherical);
+        let plane_map = solid_map(&flat, UvMapping::Planar);
+
+        for point in [
+            Vector3::new(0.6, 0.8 * 2., 0.),
+            Vector3::new(0., 0., -3.),
+            Vector3::new(0., 2., 0.),
+        ] {
+            assert_eq!(
+                sphere_map.normal_at_shape(&sphere, &point),
+                sphere.normal_at(point.clone())
+            );
+        }
+        let point = transformations::rotation_z(0.5).multiply_point(&Vector3::new(0.3, 0., 7.2));
+        assert_eq!(
+            plane_map.normal_at_shape(&plane, &point),
+            plane.normal_at(point.clone())
+        );
+    }
+
+    #[test]
+    fn red_and_green_tilt_the_normal_along_u_and_v() {
+        let plane = Plane::new(None, None);
+        let point = Vector3::new(0.25, 0., 0.25);
+
+        let along_u = solid_map(&Color::new(1., 0.5, 0.5), UvMapping::Planar);
+        assert_eq!(
+            along_u.normal_at_shape(&plane, &point),
+            Vector3::new(1., 0., 0.)
+        );
+
+        let along_v = solid_map(&Color::new(0.5, 1., 0.5), UvMapping::Planar);
+        assert_eq!(
+            along_v.normal_at_shape(&plane, &point),
+            Vector3::new(0., 0., 1.)
+        );
+    }
+}
@@ -0,0 +1,151 @@
+// Ken Perlin's "improved" gradient noise in 3D. Smooth, repeatable randomness for
+// patterns like marble, wood and clouds.
+
+use crate::mathf::vector3::Vector3;
+use crate::rng::Rng;
+use std::sync::OnceLock;
+
+// The permutation table is shuffled from a fixed seed, so the noise is the same
+// on every run. It is repeated so lookups of up to 511 don't need wrapping.
+fn permutation() -> &'static [usize; 512] {
+    static TABLE: OnceLock<[usize; 512]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut values: Vec<usize> = (0..256).collect();
+        let mut rng = Rng::new(0x5eed);
+        for i in (1..256).rev() {
+            let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+            values.swap(i, j);
+        }
+
+        let mut table = [0; 512];
+        for (i, entry) in table.iter_mut().enumerate() {
+            *entry = values[i % 256];
+        }
+        table
+    })
+}
+
+// Smooths the position inside a cell so the noise has no creases, 6t^5 - 15t^4 + 10t^3
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// The dot product of the offset with one of 12 gradient directions (the middles of
+// the edges of a cube), picked by the low bits of the hash
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+/// Noise at a point, from about -1.0 to 1.0. It varies smoothly, roughly once
+/// per unit, and is 0 at every point with whole number coordinates.
+pub fn noise(point: &Vector3) -> f64 {
+    let p = permutation();
+
+    // The cell the point is in, and where in the cell
+    let (xi, yi, zi) = (
+        (point.x.floor() as i64 & 255) as usize,
+        (point.y.floor() as i64 & 255) as usize,
+        (point.z.floor() as i64 & 255) as usize,
+    );
+    let (x, y, z) = (
+        point.x - point.x.floor(),
+        point.y - point.y.floor(),
+        point.z - point.z.floor(),
+    );
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    // Hash each corner of the cell
+    let a = p[xi] + yi;
+    let aa = p[a] + zi;
+    let ab = p[a + 1] + zi;
+    let b = p[xi + 1] + yi;
+    let ba = p[b] + zi;
+    let bb = p[b + 1] + zi;
+
+    // Blend the gradients of the eight corners
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1., y, z)),
+            lerp(u, grad(p[ab], x, y - 1., z), grad(p[bb], x - 1., y - 1., z)),
+        ),
+        lerp(
+            v,
+            lerp(
+                u,
+                grad(p[aa + 1], x, y, z - 1.),
+                grad(p[ba + 1], x - 1., y, z - 1.),
+            ),
+            lerp(
+                u,
+                grad(p[ab + 1], x, y - 1., z - 1.),
+                grad(p[bb + 1], x - 1., y - 1., z - 1.),
+            ),
+        ),
+    )
+}
+
+/// Noise added up over several `octaves`, each twice the frequency and half the
+/// strength of the last, using the absolute value for a turbulent look. Always 0.0 or more.
+pub fn turbulence(point: &Vector3, octaves: usize) -> f64 {
+    let mut sum = 0.;
+    let mut scale = 1.;
+    for _ in 0..octaves {
+        sum += noise(&(point * scale)).abs() / scale;
+        scale *= 2.;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_points() -> impl Iterator<Item = Vector3> {
+        (0..1000).map(|i| {
+            let i = i as f64;
+            Vector3::new(i * 0.173 - 50., i * 0.071 + 3.3, -i * 0.029)
+        })
+    }
+
+    #[test]
+    fn noise_is_the_same_every_time_at_the_same_point() {
+        for point in sample_points() {
+            assert_eq!(noise(&point), noise(&point.clone()));
+        }
+        assert_eq!(
+            noise(&Vector3::new(1.3, -2.7, 0.4)),
+            noise(&Vector3::new(1.3, -2.7, 0.4))
+        );
+    }
+
+    #[test]
+    fn noise_stays_roughly_between_minus_one_and_one() {
+        let values: Vec<f64> = sample_points().map(|point| noise(&point)).collect();
+        assert!(values.iter().all(|v| (-1.05..=1.05).contains(v)));
+        // And actually uses the range rather than staying near 0
+        assert!(values.iter().any(|v| *v > 0.3));
+        assert!(values.iter().any(|v| *v < -0.3));
+    }
+
+    #[test]
+    fn noise_is_zero_on_whole_numbers() {
+        assert_eq!(noise(&Vector3::new(0., 0., 0.)), 0.);
+        assert_eq!(noise(&Vector3::new(3., -7., 12.)), 0.);
+        assert_eq!(turbulence(&Vector3::new(0., 0., 0.), 4), 0.);
+    }
+}
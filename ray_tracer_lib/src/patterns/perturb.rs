@@ -0,0 +1,144 @@
+use crate::color::Color;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::vector3::Vector3;
+use crate::patterns::{Pattern, PatternSpace};
+use std::sync::Arc;
+
+/// A small, smooth and repeatable offset for a point. Used to jiggle the point a
+/// pattern is sampled at, or a surface normal to fake bumps (see `Material::bump`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Perturbation {
+    /// How far a point is moved, an amplitude of 0 leaves every point alone.
+    pub amplitude: f64,
+    /// How many waves there are per unit.
+    pub frequency: f64,
+}
+
+impl Perturbation {
+    pub fn new(amplitude: f64, frequency: f64) -> Perturbation {
+        Perturbation {
+            amplitude,
+            frequency,
+        }
+    }
+
+    /// The offset for a point. Each axis is moved by a sine wave running along the
+    /// other two, so the same point always gets the same offset.
+    pub fn offset(&self, point: &Vector3) -> Vector3 {
+        let f = self.frequency;
+        Vector3::new(
+            (f * (point.y + point.z)).sin(),
+            (f * (point.z + point.x)).sin(),
+            (f * (point.x + point.y)).sin(),
+        ) * self.amplitude
+    }
+
+    /// Tilt a normal by the offset at the point, the result is normalized.
+    pub fn perturb_normal(&self, point: &Vector3, normal: &Vector3) -> Vector3 {
+        (normal + &self.offset(point)).normalize()
+    }
+}
+
+/// Wraps another pattern and moves the point it is sampled at by a `Perturbation`,
+/// giving the wrapped pattern wavy edges.
+#[derive(Debug)]
+pub struct PerturbPattern {
+    pub pattern: Arc<dyn Pattern>,
+    pub perturbation: Perturbation,
+    pub space: PatternSpace,
+    transform: Matrix,
+    inverse_transform: Matrix,
+}
+
+impl PerturbPattern {
+    pub fn new(
+        transform: Option<Matrix>,
+        pattern: Arc<dyn Pattern>,
+        perturbation: Perturbation,
+    ) -> PerturbPattern {
+        let t = match transform {
+            None => Matrix::identity_4x4(),
+            Some(x) => x,
+        };
+        let inverse_transform = t.inverse().unwrap();
+        PerturbPattern {
+            pattern,
+            perturbation,
+            space: PatternSpace::default(),
+            transform: t,
+            inverse_transform,
+        }
+    }
+}
+
+impl Pattern for PerturbPattern {
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn space(&self) -> PatternSpace {
+        self.space
+    }
+
+    fn pattern_at(&self, pattern_point: &Vector3) -> Color {
+        let perturbed = pattern_point + &self.perturbation.offset(pattern_point);
+        self.pattern.pattern_at_parent(&perturbed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+    use crate::patterns::stripe::StripePattern;
+    use crate::patterns::tests::TestPattern;
+
+    #[test]
+    fn a_perturbation_is_the_same_every_time_for_a_point() {
+        let perturbation = Perturbation::new(0.2, 3.);
+        let point = Vector3::new(0.3, -1.2, 4.5);
+        assert_eq!(perturbation.offset(&point), perturbation.offset(&point));
+        assert!(perturbation.offset(&point).magnitude() > 0.);
+    }
+
+    #[test]
+    fn a_perturbed_pattern_is_deterministic() {
+        let pattern = PerturbPattern::new(
+            None,
+            Arc::new(TestPattern::new(None)),
+            Perturbation::new(0.1, 5.),
+        );
+        let point = Vector3::new(0.25, 0.5, 0.75);
+        let color = pattern.pattern_at(&point);
+        assert_eq!(pattern.pattern_at(&point), color);
+        assert_ne!(color, Color::new(0.25, 0.5, 0.75));
+    }
+
+    #[test]
+    fn a_zero_amplitude_perturbation_is_the_base_pattern() {
+        let base: Arc<dyn Pattern> = Arc::new(StripePattern::new(None, color::WHITE, color::BLACK));
+        let pattern = PerturbPattern::new(None, Arc::clone(&base), Perturbation::new(0., 5.));
+        for x in [-1.5, -0.25, 0., 0.5, 0.99, 1., 2.3].iter() {
+            let point = Vector3::new(*x, 0.4, -0.7);
+            assert_eq!(pattern.pattern_at(&point), base.pattern_at(&point));
+        }
+    }
+
+    #[test]
+    fn the_wrapped_pattern_keeps_its_own_transform() {
+        let inner = TestPattern::new(Some(crate::transformations::scaling(&Vector3::new(2., 2., 2.))));
+        let pattern = PerturbPattern::new(None, Arc::new(inner), Perturbation::new(0., 1.));
+        assert_eq!(pattern.pattern_at(&Vector3::new(2., 4., 6.)), Color::new(1., 2., 3.));
+    }
+
+    #[test]
+    fn a_zero_amplitude_perturbation_leaves_a_normal_alone() {
+        let perturbation = Perturbation::new(0., 10.);
+        let normal = Vector3::new(0., 1., 0.);
+        assert_eq!(perturbation.perturb_normal(&Vector3::new(1., 2., 3.), &normal), normal);
+    }
+}
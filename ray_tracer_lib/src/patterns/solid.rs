@@ -0,0 +1,48 @@
+use crate::color::Color;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::vector3::Vector3;
+use crate::patterns::Pattern;
+
+/// The same color everywhere. Mostly useful inside patterns made of other patterns.
+#[derive(Debug)]
+pub struct SolidPattern {
+    pub color: Color,
+    transform: Matrix,
+    inverse_transform: Matrix,
+}
+
+impl SolidPattern {
+    pub fn new(color: Color) -> SolidPattern {
+        SolidPattern {
+            color,
+            transform: Matrix::identity_4x4(),
+            inverse_transform: Matrix::identity_4x4(),
+        }
+    }
+}
+
+impl Pattern for SolidPattern {
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn pattern_at(&self, _pattern_point: &Vector3) -> Color {
+        self.color.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_solid_pattern_is_the_same_everywhere() {
+        let pattern = SolidPattern::new(Color::new(0.2, 0.4, 0.6));
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 0.)), Color::new(0.2, 0.4, 0.6));
+        assert_eq!(pattern.pattern_at(&Vector3::new(-3.5, 7., 1.2)), Color::new(0.2, 0.4, 0.6));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::color::Color;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::vector3::Vector3;
+use crate::patterns::solid::SolidPattern;
+use crate::patterns::{Pattern, PatternSpace};
+use std::sync::Arc;
+
+/// Alternates between two patterns every unit along the x axis.
+/// Usually both are solid colors, but they can be any pattern.
+#[derive(Debug)]
+pub struct StripePattern {
+    pub a: Arc<dyn Pattern>,
+    pub b: Arc<dyn Pattern>,
+    pub space: PatternSpace,
+    transform: Matrix,
+    inverse_transform: Matrix,
+}
+
+impl StripePattern {
+    pub fn new(transform: Option<Matrix>, a: Color, b: Color) -> StripePattern {
+        StripePattern::new_nested(
+            transform,
+            Arc::new(SolidPattern::new(a)),
+            Arc::new(SolidPattern::new(b)),
+        )
+    }
+
+    /// Stripes made of other patterns, each keeps its own transform
+    /// relative to the stripes.
+    pub fn new_nested(
+        transform: Option<Matrix>,
+        a: Arc<dyn Pattern>,
+        b: Arc<dyn Pattern>,
+    ) -> StripePattern {
+        let t = match transform {
+            None => Matrix::identity_4x4(),
+            Some(x) => x,
+        };
+        let inverse_transform = t.inverse().unwrap();
+        StripePattern {
+            a,
+            b,
+            space: PatternSpace::default(),
+            transform: t,
+            inverse_transform,
+        }
+    }
+}
+
+impl Pattern for StripePattern {
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn space(&self) -> PatternSpace {
+        self.space
+    }
+
+    fn pattern_at(&self, pattern_point: &Vector3) -> Color {
+        if pattern_point.x.floor() as i64 % 2 == 0 {
+            self.a.pattern_at_parent(pattern_point)
+        } else {
+            self.b.pattern_at_parent(pattern_point)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+    use crate::mathf::sphere::Sphere;
+    use crate::patterns::gradient::GradientPattern;
+    use crate::transformations;
+
+    fn stripes() -> StripePattern {
+        StripePattern::new(None, color::WHITE, color::BLACK)
+    }
+
+    #[test]
+    fn creating_a_stripe_pattern() {
+        let pattern = stripes();
+        let origin = Vector3::new(0., 0., 0.);
+        assert_eq!(pattern.a.pattern_at(&origin), color::WHITE);
+        assert_eq!(pattern.b.pattern_at(&origin), color::BLACK);
+    }
+
+    #[test]
+    fn a_stripe_pattern_is_constant_in_y_and_z() {
+        let pattern = stripes();
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 1., 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 2., 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 1.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 2.)), color::WHITE);
+    }
+
+    #[test]
+    fn a_stripe_pattern_alternates_in_x() {
+        let pattern = stripes();
+        assert_eq!(pattern.pattern_at(&Vector3::new(0., 0., 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.9, 0., 0.)), color::WHITE);
+        assert_eq!(pattern.pattern_at(&Vector3::new(1., 0., 0.)), color::BLACK);
+        assert_eq!(pattern.pattern_at(&Vector3::new(-0.1, 0., 0.)), color::BLACK);
+        assert_eq!(pattern.pattern_at(&Vector3::new(-1., 0., 0.)), color::BLACK);
+        assert_eq!(pattern.pattern_at(&Vector3::new(-1.1, 0., 0.)), color::WHITE);
+    }
+
+    #[test]
+    fn stripes_of_gradients() {
+        let red_to_green: Arc<dyn Pattern> = Arc::new(GradientPattern::new(None, color::RED, color::GREEN));
+        // Squash the second gradient so it repeats twice per stripe
+        let blue_to_black: Arc<dyn Pattern> = Arc::new(GradientPattern::new(
+            Some(transformations::scaling(&Vector3::new(0.5, 1., 1.))),
+            color::BLUE,
+            color::BLACK,
+        ));
+        let pattern = StripePattern::new_nested(None, red_to_green, blue_to_black);
+
+        assert_eq!(pattern.pattern_at(&Vector3::new(0.5, 0., 0.)), Color::new(0.5, 0.5, 0.));
+        assert_eq!(pattern.pattern_at(&Vector3::new(1.25, 0., 0.)), Color::new(0., 0., 0.5));
+        assert_eq!(pattern.pattern_at(&Vector3::new(1.75, 0., 0.)), Color::new(0., 0., 0.5));
+    }
+
+    #[test]
+    fn nested_patterns_combine_every_transform_on_a_shape() {
+        let inner: Arc<dyn Pattern> = Arc::new(GradientPattern::new(
+            Some(transformations::scaling(&Vector3::new(0.5, 1., 1.))),
+            color::WHITE,
+            color::BLACK,
+        ));
+        let outer = StripePattern::new_nested(
+            Some(transformations::scaling(&Vector3::new(4., 1., 1.))),
+            Arc::clone(&inner),
+            inner,
+        );
+        let shape = Sphere::new(Some(transformations::scaling(&Vector3::new(2., 1., 1.))), None);
+
+        // world x 1 -> object x 0.5 -> stripe x 0.125 -> gradient x 0.25
+        let color = outer.pattern_at_shape(&shape, &Vector3::new(1., 0., 0.));
+        assert_eq!(color, Color::new(0.75, 0.75, 0.75));
+    }
+}
@@ -0,0 +1,117 @@
+use crate::canvas::Canvas;
+use crate::color::Color;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::shapes::Shape;
+use crate::mathf::vector3::Vector3;
+use crate::mathf::uv_mapping::UvMapping;
+use crate::patterns::Pattern;
+use crate::png_encoder;
+use std::fmt;
+
+/// An image wrapped around a shape using the shape's own unwrapping, see
+/// `Shape::uv_at`. A map of the earth on a sphere for example.
+///
+/// Shapes without a `uv_at`, and this pattern inside another pattern, fall back
+/// to laying the image flat along the x and z axes.
+pub struct UvImagePattern {
+    pub image: Canvas,
+    transform: Matrix,
+    inverse_transform: Matrix,
+}
+
+impl fmt::Debug for UvImagePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UvImagePattern")
+            .field("width", &self.image.width)
+            .field("height", &self.image.height)
+            .field("transform", &self.transform)
+            .finish()
+    }
+}
+
+impl UvImagePattern {
+    pub fn new(transform: Option<Matrix>, image: Canvas) -> UvImagePattern {
+        let t = transform.unwrap_or_else(Matrix::identity_4x4);
+        let inverse_transform = t.inverse().unwrap();
+        UvImagePattern {
+            image,
+            transform: t,
+            inverse_transform,
+        }
+    }
+
+    pub fn from_png(transform: Option<Matrix>, file_path: &str) -> Result<UvImagePattern, png::DecodingError> {
+        Ok(UvImagePattern::new(transform, png_encoder::load_canvas_from_png(file_path)?))
+    }
+}
+
+impl Pattern for UvImagePattern {
+    fn transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn pattern_at(&self, pattern_point: &Vector3) -> Color {
+        let (u, v) = UvMapping::Planar.uv(pattern_point);
+        self.image.sample_uv(u, v)
+    }
+
+    fn pattern_at_shape(&self, shape: &dyn Shape, world_point: &Vector3) -> Color {
+        let object_point = shape.world_to_object(world_point);
+        match shape.uv_at(&self.inverse_transform.multiply_point(&object_point)) {
+            Some((u, v)) => self.image.sample_uv(u, v),
+            None => self.pattern_at_parent(&object_point),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+    use crate::mathf::plane::Plane;
+    use crate::mathf::sphere::Sphere;
+    use crate::transformations;
+    use std::f64::consts::PI;
+
+    // Red on the left, green on the right, a white row along the top
+    fn image() -> Canvas {
+        let mut image = Canvas::new(4, 4);
+        for y in 0..4 {
+            for x in 0..4 {
+                let color = match (x, y) {
+                    (_, 0) => color::WHITE,
+                    (0..=1, _) => color::RED,
+                    _ => color::GREEN,
+                };
+                image.write_pixel(x, y, &color);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn the_image_is_wrapped_using_the_shape_uv() {
+        let pattern = UvImagePattern::new(None, image());
+        let sphere = Sphere::new(Some(transformations::scaling(&Vector3::new(2., 2., 2.))), None);
+
+        // Around the equator at u 0.125 and 0.875, and near the north pole at v 0.875
+        let left = Vector3::new(2f64.sqrt(), 0., -(2f64.sqrt()));
+        let right = Vector3::new(-(2f64.sqrt()), 0., -(2f64.sqrt()));
+        let top = Vector3::new(0., 2. * (PI / 8.).cos(), -2. * (PI / 8.).sin());
+        assert_eq!(pattern.pattern_at_shape(&sphere, &left), color::RED);
+        assert_eq!(pattern.pattern_at_shape(&sphere, &right), color::GREEN);
+        assert_eq!(pattern.pattern_at_shape(&sphere, &top), color::WHITE);
+    }
+
+    #[test]
+    fn a_plane_tiles_the_image() {
+        let pattern = UvImagePattern::new(None, image());
+        let plane = Plane::new(None, None);
+        assert_eq!(pattern.pattern_at_shape(&plane, &Vector3::new(0.125, 0., 0.5)), color::RED);
+        assert_eq!(pattern.pattern_at_shape(&plane, &Vector3::new(3.875, 0., -0.5)), color::GREEN);
+    }
+}
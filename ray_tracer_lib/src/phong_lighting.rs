@@ -1,79 +1,152 @@
 use crate::color;
 use crate::color::Color;
-use crate::material::Material;
+use crate::light::Light;
+use crate::material::{LightingModel, Material};
+use crate::mathf::shapes::Shape;
 use crate::mathf::sphere;
 use crate::mathf::vector3::Vector3;
-use crate::point_light::PointLight;
 
 pub fn lighting(
     material: &Material,
-    light: &PointLight,
+    object: &dyn Shape,
+    light: &dyn Light,
     point: &Vector3,
     eye_vector: &Vector3,
     normal_vector: &Vector3,
     in_shadow: bool,
+) -> Color {
+    let light_intensity = if in_shadow { 0. } else { 1. };
+    lighting_with_intensity(material, object, light, point, eye_vector, normal_vector, light_intensity)
+}
+
+/// Like `lighting`, but the light may be partially blocked. `light_intensity` is the
+/// fraction of the light reaching the point, 0 is fully in shadow and 1 is fully lit.
+pub fn lighting_with_intensity(
+    material: &Material,
+    object: &dyn Shape,
+    light: &dyn Light,
+    point: &Vector3,
+    eye_vector: &Vector3,
+    normal_vector: &Vector3,
+    light_intensity: f64,
 ) -> Color {
     let diffuse;
     let specular;
 
     // Combine the surface color with the light's color/intensity
-    let effective_color = &material.color * &light.intensity;
+    let effective_color = &surface_color(material, object, point) * light.intensity();
 
     // Compute the ambient contribution
     let ambient = &effective_color * material.ambient;
 
-    if in_shadow {
+    if light_intensity <= 0. {
         return ambient;
     }
 
     // Find the direction to the light source
-    let light_vector = (&light.position - point).normalize();
+    let light_vector = light.direction_from(point);
 
     // light_dot_normal represents the cosine of the angle between the light
     // vector and the normal vector. A negative number means the light is
     // on the other side of the surface.
-    let light_dot_normal = light_vector.dot(&normal_vector);
+    let light_dot_normal = light_vector.dot(normal_vector);
 
     if light_dot_normal < 0.0 {
         diffuse = color::BLACK;
         specular = color::BLACK;
     } else {
+        // Fade the light in just past the terminator rather than switching it on
+        let ramp = terminator_ramp(light_dot_normal, material.terminator_ramp);
+
         // Compute the diffuse contribution
-        diffuse = &effective_color * material.diffuse * light_dot_normal;
+        diffuse = &effective_color * material.diffuse * light_dot_normal * ramp;
 
-        // reflect_dot_eye represents the cosine of the angle between the reflection
-        // vector and the eye vector. A negative number means the light reflects
-        // away from the eye.
-        let reflect_vector = sphere::reflect(&(-light_vector), normal_vector);
-        let reflect_dot_eye = reflect_vector.dot(&eye_vector);
+        // highlight represents the cosine of the angle between the reflection
+        // vector and the eye vector (or for Blinn-Phong the halfway vector and the
+        // normal). A negative number means the light reflects away from the eye.
+        let highlight = match material.lighting_model {
+            LightingModel::Phong => {
+                let reflect_vector = sphere::reflect(&(-light_vector), normal_vector);
+                reflect_vector.dot(eye_vector)
+            }
+            LightingModel::BlinnPhong => {
+                let halfway_vector = (light_vector + eye_vector.clone()).normalize();
+                halfway_vector.dot(normal_vector)
+            }
+        };
 
-        if reflect_dot_eye <= 0.0 {
+        if highlight <= 0.0 {
             specular = color::BLACK;
         } else {
             // Compute the specular contribution
-            let factor = reflect_dot_eye.powf(material.shininess);
-            specular = light.intensity.clone() * material.specular * factor;
+            let factor = highlight.powf(material.shininess);
+            specular = light.intensity() * material.specular * factor * ramp;
         }
     }
 
-    ambient + diffuse + specular
+    ambient + (diffuse + specular) * light_intensity
+}
+
+// Rises smoothly from 0 at the terminator to 1 at `width`, always 1 when the width is 0
+fn terminator_ramp(light_dot_normal: f64, width: f64) -> f64 {
+    if width <= 0. {
+        return 1.;
+    }
+    let t = (light_dot_normal / width).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+/// Ambient and diffuse lighting only, no specular highlight and no shadow test.
+/// Used for quick previews of a scene.
+pub fn diffuse_lighting(
+    material: &Material,
+    object: &dyn Shape,
+    light: &dyn Light,
+    point: &Vector3,
+    normal_vector: &Vector3,
+) -> Color {
+    let effective_color = &surface_color(material, object, point) * light.intensity();
+    let ambient = &effective_color * material.ambient;
+
+    let light_vector = light.direction_from(point);
+    let light_dot_normal = light_vector.dot(normal_vector);
+    if light_dot_normal < 0.0 {
+        return ambient;
+    }
+
+    ambient + &effective_color * material.diffuse * light_dot_normal
+}
+
+// The material's color, or its pattern's color at the point if it has one
+fn surface_color(material: &Material, object: &dyn Shape, point: &Vector3) -> Color {
+    match &material.pattern {
+        Some(pattern) => pattern.pattern_at_shape(object, point),
+        None => material.color.clone(),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::color;
+    use crate::directional_light::DirectionalLight;
+    use crate::mathf::sphere::Sphere;
+    use crate::patterns::stripe::StripePattern;
+    use std::sync::Arc;
     use crate::mathf::vector3::Vector3;
+    use crate::point_light::PointLight;
 
 
     #[test]
     fn lighting_with_the_eye_between_the_light_and_surface() {
         let material = Material::new();
+        let object = Sphere::new(None, None);
         let position = Vector3::new(0.0, 0.0, 0.0);
 
         let eye_vector = Vector3::new(0.0, 0.0, -1.0);
         let normal_vector = Vector3::new(0.0, 0.0, -1.0);
         let light = PointLight::new(Vector3::new(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
-        let result = lighting(&material, &light, &position, &eye_vector, &normal_vector, false);
+        let result = lighting(&material, &object, &light, &position, &eye_vector, &normal_vector, false);
 
         assert_eq!(result, Color::new(1.9, 1.9, 1.9));
     }
@@ -81,12 +154,13 @@ mod tests {
     #[test]
     fn lighting_with_the_eye_between_the_light_and_surface_eye_offset_45_degrees() {
         let material = Material::new();
+        let object = Sphere::new(None, None);
         let position = Vector3::new(0.0, 0.0, 0.0);
 
         let eye_vector = Vector3::new(0.0, 2.0f64.sqrt() / 2.0, -2.0f64.sqrt() / 2.0);
         let normal_vector = Vector3::new(0.0, 0.0, -1.0);
         let light = PointLight::new(Vector3::new(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
-        let result = lighting(&material, &light, &position, &eye_vector, &normal_vector, false);
+        let result = lighting(&material, &object, &light, &position, &eye_vector, &normal_vector, false);
 
         assert_eq!(result, Color::new(1.0, 1.0, 1.0));
     }
@@ -94,12 +168,13 @@ mod tests {
     #[test]
     fn lighting_with_eye_opposite_surface() {
         let material = Material::new();
+        let object = Sphere::new(None, None);
         let position = Vector3::new(0.0, 0.0, 0.0);
 
         let eye_vector = Vector3::new(0.0, 0.0, -1.0);
         let normal_vector = Vector3::new(0.0, 0.0, -1.0);
         let light = PointLight::new(Vector3::new(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
-        let result = lighting(&material, &light, &position, &eye_vector, &normal_vector, false);
+        let result = lighting(&material, &object, &light, &position, &eye_vector, &normal_vector, false);
 
         assert_eq!(result, Color::new(0.73640, 0.73640, 0.73640));
     }
@@ -107,12 +182,13 @@ mod tests {
     #[test]
     fn lighting_with_the_eye_in_the_path_of_the_reflection_vector() {
         let material = Material::new();
+        let object = Sphere::new(None, None);
         let position = Vector3::new(0.0, 0.0, 0.0);
 
         let eye_vector = Vector3::new(0.0, -2.0f64.sqrt() / 2.0, -2.0f64.sqrt() / 2.0);
         let normal_vector = Vector3::new(0.0, 0.0, -1.0);
         let light = PointLight::new(Vector3::new(0.0, 10.0, -10.0), Color::new(1.0, 1.0, 1.0));
-        let result = lighting(&material, &light, &position, &eye_vector, &normal_vector, false);
+        let result = lighting(&material, &object, &light, &position, &eye_vector, &normal_vector, false);
 
         assert_eq!(result, Color::new(1.6364, 1.6364, 1.6364));
     }
@@ -120,12 +196,13 @@ mod tests {
     #[test]
     fn lighting_with_the_light_behind_the_surface() {
         let material = Material::new();
+        let object = Sphere::new(None, None);
         let position = Vector3::new(0.0, 0.0, 0.0);
 
         let eye_vector = Vector3::new(0.0, 0.0, -1.0);
         let normal_vector = Vector3::new(0.0, 0.0, -1.0);
         let light = PointLight::new(Vector3::new(0.0, 0.0, 10.0), Color::new(1.0, 1.0, 1.0));
-        let result = lighting(&material, &light, &position, &eye_vector, &normal_vector, false);
+        let result = lighting(&material, &object, &light, &position, &eye_vector, &normal_vector, false);
 
         assert_eq!(result, Color::new(0.1, 0.1, 0.1));
     }
@@ -133,15 +210,139 @@ mod tests {
     #[test]
     fn lighting_with_the_eye_between_the_surface_in_shadow() {
         let material = Material::new();
+        let object = Sphere::new(None, None);
         let position = Vector3::new(0.0, 0.0, 0.0);
 
         let eye_vector = Vector3::new(0.0, 0.0, -1.0);
         let normal_vector = Vector3::new(0.0, 0.0, -1.0);
         let light = PointLight::new(Vector3::new(0.0, 0.0, -10.0), Color::new(1., 1., 1.));
         let in_shadow = true;
-        let result = lighting(&material, &light, &position, &eye_vector, &normal_vector, in_shadow);
+        let result = lighting(&material, &object, &light, &position, &eye_vector, &normal_vector, in_shadow);
 
         assert_eq!(result, Color::new(0.1, 0.1, 0.1));
     }
 
+    #[test]
+    fn diffuse_lighting_has_no_specular_highlight() {
+        let material = Material::new();
+        let object = Sphere::new(None, None);
+        let position = Vector3::new(0.0, 0.0, 0.0);
+
+        let normal_vector = Vector3::new(0.0, 0.0, -1.0);
+        let light = PointLight::new(Vector3::new(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
+        let result = diffuse_lighting(&material, &object, &light, &position, &normal_vector);
+
+        assert_eq!(result, Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn lighting_with_a_directional_light() {
+        let material = Material::new();
+        let object = Sphere::new(None, None);
+        let eye_vector = Vector3::new(0.0, 0.0, -1.0);
+        let normal_vector = Vector3::new(0.0, 0.0, -1.0);
+        let light = DirectionalLight::new(Vector3::new(0.0, 0.0, 1.0), Color::new(1.0, 1.0, 1.0));
+
+        // No matter where the point is the result is the same
+        for position in [Vector3::new(0.0, 0.0, 0.0), Vector3::new(5.0, -3.0, 100.0)].iter() {
+            let result = lighting(&material, &object, &light, position, &eye_vector, &normal_vector, false);
+            assert_eq!(result, Color::new(1.9, 1.9, 1.9));
+        }
+    }
+
+    #[test]
+    fn lighting_with_a_pattern_applied() {
+        let mut material = Material::new();
+        material.pattern = Some(Arc::new(StripePattern::new(None, color::WHITE, color::BLACK)));
+        material.ambient = 1.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        let object = Sphere::new(None, None);
+        let eye_vector = Vector3::new(0., 0., -1.);
+        let normal_vector = Vector3::new(0., 0., -1.);
+        let light = PointLight::new(Vector3::new(0., 0., -10.), Color::new(1., 1., 1.));
+
+        let c1 = lighting(&material, &object, &light, &Vector3::new(0.9, 0., 0.), &eye_vector, &normal_vector, false);
+        let c2 = lighting(&material, &object, &light, &Vector3::new(1.1, 0., 0.), &eye_vector, &normal_vector, false);
+        assert_eq!(c1, color::WHITE);
+        assert_eq!(c2, color::BLACK);
+    }
+
+    #[test]
+    fn phong_and_blinn_phong_agree_head_on() {
+        let phong = Material::new();
+        let blinn = Material::builder().lighting_model(LightingModel::BlinnPhong).build();
+        let object = Sphere::new(None, None);
+        let position = Vector3::new(0.0, 0.0, 0.0);
+        let eye_vector = Vector3::new(0.0, 0.0, -1.0);
+        let normal_vector = Vector3::new(0.0, 0.0, -1.0);
+        let light = PointLight::new(Vector3::new(0.0, 0.0, -10.0), Color::new(1.0, 1.0, 1.0));
+
+        let a = lighting(&phong, &object, &light, &position, &eye_vector, &normal_vector, false);
+        let b = lighting(&blinn, &object, &light, &position, &eye_vector, &normal_vector, false);
+        assert_eq!(a, Color::new(1.9, 1.9, 1.9));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn phong_and_blinn_phong_differ_at_a_grazing_angle() {
+        let phong = Material::builder().shininess(2.).build();
+        let blinn = Material::builder()
+            .shininess(2.)
+            .lighting_model(LightingModel::BlinnPhong)
+            .build();
+        let object = Sphere::new(None, None);
+        let position = Vector3::new(0.0, 0.0, 0.0);
+        let normal_vector = Vector3::new(0.0, 1.0, 0.0);
+        // The light is low on one side and the eye is high on the same side, so the
+        // reflection points away from the eye but the halfway vector is still
+        // above the surface.
+        let light = PointLight::new(Vector3::new(-10.0, 1.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        let eye_vector = Vector3::new(-1.0, 1.0, 0.0).normalize();
+
+        let a = lighting(&phong, &object, &light, &position, &eye_vector, &normal_vector, false);
+        let b = lighting(&blinn, &object, &light, &position, &eye_vector, &normal_vector, false);
+        let diffuse_only = 0.1 + 0.9 * light.direction_from(&position).dot(&normal_vector);
+        assert_eq!(a, Color::new(diffuse_only, diffuse_only, diffuse_only));
+        assert!(b.r > a.r + 0.01);
+    }
+
+    #[test]
+    fn a_terminator_ramp_fades_in_light_that_only_just_reaches_the_surface() {
+        let sharp = Material::new();
+        let soft = Material::builder().terminator_ramp(0.2).build();
+        let object = Sphere::new(None, None);
+        let position = Vector3::new(0.0, 0.0, 0.0);
+        let normal_vector = Vector3::new(0.0, 1.0, 0.0);
+
+        // The light is just above the horizon and the eye looks straight down the
+        // reflection, so without a ramp the full highlight appears at once
+        let light_at = |height: f64| PointLight::new(Vector3::new(-1.0, height, 0.0), Color::new(1.0, 1.0, 1.0));
+        let eye_at = |height: f64| Vector3::new(1.0, height, 0.0).normalize();
+
+        let light = light_at(0.01);
+        let eye_vector = eye_at(0.01);
+        let light_dot_normal = light.direction_from(&position).dot(&normal_vector);
+        let a = lighting(&sharp, &object, &light, &position, &eye_vector, &normal_vector, false);
+        let expected = 0.1 + 0.9 * light_dot_normal + 0.9;
+        assert_eq!(a, Color::new(expected, expected, expected));
+        let b = lighting(&soft, &object, &light, &position, &eye_vector, &normal_vector, false);
+        assert!(b.r < 0.11);
+
+        // Further from the terminator the ramp has no effect
+        let light = light_at(1.0);
+        let eye_vector = eye_at(1.0);
+        let a = lighting(&sharp, &object, &light, &position, &eye_vector, &normal_vector, false);
+        let b = lighting(&soft, &object, &light, &position, &eye_vector, &normal_vector, false);
+        assert_eq!(a, b);
+
+        // And in between it rises steadily
+        let mut previous = 0.;
+        for i in 0..20 {
+            let height = i as f64 * 0.01;
+            let color = lighting(&soft, &object, &light_at(height), &position, &eye_at(height), &normal_vector, false);
+            assert!(color.r >= previous && color.r - previous < 0.2);
+            previous = color.r;
+        }
+    }
 }
@@ -1,36 +1,75 @@
 use crate::canvas;
+use crate::color::Color;
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::Path;
 
-/// Convert a color value in the range 0.0 to 1.0 into 0 to 255
-fn convert_rbg_value_to_byte(value: f64) -> u8 {
-    // Values can be above 1.0, if so clamp them to the correct range for output
-    let clamped = num::clamp(value, 0.0, 1.0);
-    (clamped * 255.0).ceil() as u8
+/// Gamma correct the canvas before saving it, see `Color::apply_gamma`
+pub fn save_canvas_to_png_with_gamma(canvas: &canvas::Canvas, file_path: String, gamma: f64) {
+    save_canvas_to_png(&canvas.apply_gamma(gamma), file_path)
 }
 
 pub fn save_canvas_to_png(canvas: &canvas::Canvas, file_path: String) {
-    let path = Path::new(&file_path);
-    let file = File::create(path).unwrap();
+    write_canvas_to_png(canvas, &file_path).unwrap();
+}
+
+/// Like `save_canvas_to_png` but returns an error rather than panicking,
+/// for example if the directory doesn't exist.
+pub fn write_canvas_to_png(canvas: &canvas::Canvas, file_path: &str) -> Result<(), png::EncodingError> {
+    let file = File::create(Path::new(file_path))?;
     let w = BufWriter::new(file);
 
     let mut encoder = png::Encoder::new(w, canvas.width as u32, canvas.height as u32);
     encoder.set_color(png::ColorType::RGB);
     encoder.set_depth(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().unwrap();
+    let mut writer = encoder.write_header()?;
 
     const BYTES_PER_PIXEL: usize = 3;
-    let mut image_data: Vec<u8> = vec![0u8; canvas.width * canvas.height * BYTES_PER_PIXEL];
-    let mut i: usize = 0;
+    let mut image_data: Vec<u8> = Vec::with_capacity(canvas.width * canvas.height * BYTES_PER_PIXEL);
     for color in canvas.pixels.iter().flat_map(|r| r.iter()) {
-        image_data[i] = convert_rbg_value_to_byte(color.r);
-        image_data[i + 1] = convert_rbg_value_to_byte(color.g);
-        image_data[i + 2] = convert_rbg_value_to_byte(color.b);
-        i += 3;
+        image_data.extend_from_slice(&color.to_rgb8());
+    }
+
+    writer.write_image_data(&image_data) // Save
+}
+
+/// Read a PNG into a canvas, 8 bit channels become colors in the range 0.0 to 1.0.
+/// Any alpha channel is ignored.
+pub fn load_canvas_from_png(file_path: &str) -> Result<canvas::Canvas, png::DecodingError> {
+    let file = File::open(Path::new(file_path))?;
+    let mut decoder = png::Decoder::new(file);
+    // Turn palettes and low bit depths into 8 bit samples, and 16 bit into 8
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buffer = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buffer)?;
+
+    let samples = info.color_type.samples();
+    let width = info.width as usize;
+    let height = info.height as usize;
+    let mut canvas = canvas::Canvas::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = &buffer[y * info.line_size + x * samples..];
+            let color = match info.color_type {
+                png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => {
+                    let value = convert_byte_to_rgb_value(pixel[0]);
+                    Color::new(value, value, value)
+                }
+                _ => Color::new(
+                    convert_byte_to_rgb_value(pixel[0]),
+                    convert_byte_to_rgb_value(pixel[1]),
+                    convert_byte_to_rgb_value(pixel[2]),
+                ),
+            };
+            canvas.write_pixel(x, y, &color);
+        }
     }
+    Ok(canvas)
+}
 
-    writer.write_image_data(&image_data).unwrap(); // Save
+fn convert_byte_to_rgb_value(value: u8) -> f64 {
+    value as f64 / 255.0
 }
 
 #[cfg(test)]
@@ -38,10 +77,30 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_convert_rbg_value_to_byte() {
-        assert_eq!(convert_rbg_value_to_byte(1.0), 255);
-        assert_eq!(convert_rbg_value_to_byte(1.5), 255);
-        assert_eq!(convert_rbg_value_to_byte(0.), 0);
-        assert_eq!(convert_rbg_value_to_byte(0.5), 128);
+    fn test_colors_are_clamped_to_bytes() {
+        assert_eq!(Color::new(1.0, 1.5, 0.).to_rgb8(), [255, 255, 0]);
+        assert_eq!(Color::new(0.5, 0.5, 0.5).to_rgb8(), [128, 128, 128]);
+    }
+
+    #[test]
+    fn saving_and_loading_a_png() {
+        let mut canvas = canvas::Canvas::new(2, 1);
+        canvas.write_pixel(0, 0, &Color::new(1.0, 0.0, 0.0));
+        canvas.write_pixel(1, 0, &Color::new(0.0, 0.0, 1.0));
+
+        let path = std::env::temp_dir().join("ray_tracer_saving_and_loading_a_png.png");
+        let path = path.to_str().unwrap().to_string();
+        save_canvas_to_png(&canvas, path.clone());
+        let loaded = load_canvas_from_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.width, 2);
+        assert_eq!(loaded.height, 1);
+        assert_eq!(loaded.pixels, canvas.pixels);
+    }
+
+    #[test]
+    fn loading_a_missing_png_is_an_error() {
+        assert!(load_canvas_from_png("this/file/does/not/exist.png").is_err());
     }
 }
@@ -1,10 +1,13 @@
 use crate::color::Color;
+use crate::light::Light;
 use crate::mathf::vector3::Vector3;
 
 #[derive(Debug)]
 pub struct PointLight {
     pub position: Vector3,
     pub intensity: Color,
+    /// When false nothing blocks this light, see `Light::casts_shadows`
+    pub casts_shadows: bool,
 }
 
 impl PointLight {
@@ -12,13 +15,38 @@ impl PointLight {
         PointLight {
             position,
             intensity,
+            casts_shadows: true,
         }
     }
 }
 
+impl Light for PointLight {
+    fn intensity(&self) -> &Color {
+        &self.intensity
+    }
+
+    fn direction_from(&self, point: &Vector3) -> Vector3 {
+        (&self.position - point).normalize()
+    }
+
+    fn distance_from(&self, point: &Vector3) -> f64 {
+        (&self.position - point).magnitude()
+    }
+
+    fn positions_to_sample(&self) -> Vec<Vector3> {
+        vec![self.position.clone()]
+    }
+
+    fn casts_shadows(&self) -> bool {
+        self.casts_shadows
+    }
+}
+
 impl PartialEq for PointLight {
     fn eq(&self, other: &Self) -> bool {
-        self.position == other.position && self.intensity == other.intensity
+        self.position == other.position
+            && self.intensity == other.intensity
+            && self.casts_shadows == other.casts_shadows
     }
 }
 
@@ -26,6 +54,7 @@ impl PartialEq for PointLight {
 mod tests {
     use super::*;
     use crate::mathf::vector3::Vector3;
+    use crate::world;
 
     #[test]
     fn a_point_light_has_a_position_and_intensity() {
@@ -36,4 +65,36 @@ mod tests {
         assert_eq!(light.position, Vector3::new(0.0, 0.0, 0.0));
         assert_eq!(light.intensity, Color::new(1.0, 1.0, 1.0));
     }
+
+    #[test]
+    fn the_light_vector_points_at_the_light() {
+        let light = PointLight::new(Vector3::new(0., 10., 0.), Color::new(1., 1., 1.));
+        let point = Vector3::new(0., 0., 0.);
+        assert_eq!(light.direction_from(&point), Vector3::new(0., 1., 0.));
+        assert_eq!(light.distance_from(&point), 10.);
+    }
+
+    #[test]
+    fn a_point_light_has_a_single_sample() {
+        let light = PointLight::new(Vector3::new(-10., 10., -10.), Color::new(1., 1., 1.));
+        assert_eq!(light.positions_to_sample(), vec![Vector3::new(-10., 10., -10.)]);
+    }
+
+    #[test]
+    fn a_point_light_is_at_full_intensity_unless_shadowed() {
+        let world = world::default_world();
+        let light = PointLight::new(Vector3::new(-10., 10., -10.), Color::new(1., 1., 1.));
+        assert_eq!(light.intensity_at(&Vector3::new(0., 1.0001, 0.), &world, None), 1.);
+        assert_eq!(light.intensity_at(&Vector3::new(-10., 10., 10.), &world, None), 1.);
+        assert_eq!(light.intensity_at(&Vector3::new(10., -10., 10.), &world, None), 0.);
+    }
+
+    #[test]
+    fn a_light_that_casts_no_shadows_is_never_blocked() {
+        let world = world::default_world();
+        let mut light = PointLight::new(Vector3::new(-10., 10., -10.), Color::new(1., 1., 1.));
+        assert!(light.casts_shadows());
+        light.casts_shadows = false;
+        assert_eq!(light.intensity_at(&Vector3::new(10., -10., 10.), &world, None), 1.);
+    }
 }
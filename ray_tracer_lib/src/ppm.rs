@@ -3,37 +3,120 @@
 
 use crate::canvas;
 use crate::color::Color;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum PpmError {
+    /// The magic number, size or max color value was missing or not understood
+    Header(String),
+    /// There were too few pixel values, or one of them wasn't a number
+    PixelData(String),
+}
+
+impl fmt::Display for PpmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PpmError::Header(message) => write!(f, "invalid ppm header: {}", message),
+            PpmError::PixelData(message) => write!(f, "invalid ppm pixel data: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for PpmError {}
 
 pub fn canvas_to_ppm(canvas: &canvas::Canvas) -> String {
-    ppm_header(&canvas) + &ppm_pixel_data(canvas)
+    ppm_header(canvas, 255) + &ppm_pixel_data(canvas, 255)
+}
+
+/// Scale each channel from 0.0 to 1.0 up to 0 to `max_value` rather than 255.
+/// 65535 gives a 16-bit file that keeps much finer gradations. A `max_value` of 0
+/// isn't a valid PPM and is rejected.
+pub fn canvas_to_ppm_with_max_value(canvas: &canvas::Canvas, max_value: u16) -> Result<String, PpmError> {
+    if max_value == 0 {
+        return Err(PpmError::Header(String::from("max color value must be from 1 to 65535: 0")));
+    }
+    Ok(ppm_header(canvas, max_value) + &ppm_pixel_data(canvas, max_value))
+}
+
+/// Gamma correct the canvas before converting it, see `Color::apply_gamma`
+pub fn canvas_to_ppm_with_gamma(canvas: &canvas::Canvas, gamma: f64) -> String {
+    canvas_to_ppm(&canvas.apply_gamma(gamma))
+}
+
+/// Tone map and then gamma correct the canvas before converting it, so colors
+/// brighter than 1.0 roll off smoothly instead of being clamped.
+/// See `Color::tone_map_reinhard` and `Color::apply_gamma`
+pub fn canvas_to_ppm_tone_mapped(canvas: &canvas::Canvas, gamma: f64) -> String {
+    canvas_to_ppm(&canvas.tone_map_reinhard().apply_gamma(gamma))
 }
 
-fn f64_to_ppm_pixel(value: f64) -> u8 {
-    let clamped = num::clamp(value, 0.0, 1.0);
-    (clamped * 255.0).ceil() as u8
+/// Read a plain (P3) PPM back into a canvas, scaling each value by the file's max
+/// color value so 255 becomes 1.0. Comments starting with `#` are skipped, and
+/// values may be split across lines in any way.
+pub fn ppm_to_canvas(text: &str) -> Result<canvas::Canvas, PpmError> {
+    let mut values = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(|line| line.split_whitespace());
+
+    if values.next() != Some("P3") {
+        return Err(PpmError::Header(String::from("expected the magic number P3")));
+    }
+
+    let mut header_value = |name: &str| -> Result<usize, PpmError> {
+        let value = values
+            .next()
+            .ok_or_else(|| PpmError::Header(format!("missing {}", name)))?;
+        value
+            .parse::<usize>()
+            .map_err(|_| PpmError::Header(format!("{} is not a number: {}", name, value)))
+    };
+    let width = header_value("width")?;
+    let height = header_value("height")?;
+    let max_value = header_value("max color value")?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(PpmError::Header(format!("max color value must be from 1 to 65535: {}", max_value)));
+    }
+
+    let scale = 1. / max_value as f64;
+    let mut canvas = canvas::Canvas::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let mut channel = || -> Result<f64, PpmError> {
+                let value = values
+                    .next()
+                    .ok_or_else(|| PpmError::PixelData(format!("missing values for pixel ({}, {})", x, y)))?;
+                match value.parse::<usize>() {
+                    Ok(v) if v <= max_value => Ok(v as f64 * scale),
+                    _ => Err(PpmError::PixelData(format!("not a color value: {}", value))),
+                }
+            };
+            let color = Color::new(channel()?, channel()?, channel()?);
+            canvas.write_pixel(x, y, &color);
+        }
+    }
+    Ok(canvas)
 }
 
-fn color_to_ppm_pixel(color: &Color) -> String {
-    let red = f64_to_ppm_pixel(color.r);
-    let green = f64_to_ppm_pixel(color.g);
-    let blue = f64_to_ppm_pixel(color.b);
-    format!("{} {} {}", red, green, blue)
+fn color_to_ppm_pixel(color: &Color, max_value: u16) -> String {
+    let [r, g, b] = color.to_scaled(max_value);
+    format!("{} {} {}", r, g, b)
 }
 
-fn ppm_header(canvas: &canvas::Canvas) -> String {
+fn ppm_header(canvas: &canvas::Canvas, max_value: u16) -> String {
     // Identifier of the flavor of PPM we are using
     let line1 = String::from("P3");
     let line2 = format!("{} {}", canvas.width, canvas.height);
 
-    // Max color value. Here we indicate we will scale to be from 0 to 255
-    let line3 = String::from("255");
+    // Max color value, usually 255 for 8 bits per channel
+    let line3 = max_value.to_string();
     format!("{}\n{}\n{}\n", line1, line2, line3)
 }
 
-fn ppm_pixel_data(canvas: &canvas::Canvas) -> String {
+fn ppm_pixel_data(canvas: &canvas::Canvas, max_value: u16) -> String {
     let mut pixel_data = String::from("");
     for color in canvas.pixels.iter().flat_map(|r| r.iter()) {
-        let out = format!("{}\n", color_to_ppm_pixel(&color));
+        let out = format!("{}\n", color_to_ppm_pixel(color, max_value));
         pixel_data.push_str(&out);
     }
     pixel_data
@@ -73,13 +156,13 @@ mod tests {
     #[test]
     fn test_color_to_ppm_pixel() {
         let c1 = Color::new(1.5, 0.0, 0.0);
-        assert_eq!(color_to_ppm_pixel(&c1), "255 0 0");
+        assert_eq!(color_to_ppm_pixel(&c1, 255), "255 0 0");
 
         let c2 = Color::new(0.0, 0.5, 0.0);
-        assert_eq!(color_to_ppm_pixel(&c2), "0 128 0");
+        assert_eq!(color_to_ppm_pixel(&c2, 255), "0 128 0");
 
         let c3 = Color::new(-0.5, 0.0, 1.0);
-        assert_eq!(color_to_ppm_pixel(&c3), "0 0 255");
+        assert_eq!(color_to_ppm_pixel(&c3, 255), "0 0 255");
     }
 
     #[test]
@@ -93,4 +176,92 @@ mod tests {
         assert_eq!(split[3], "0 0 0");
         assert_eq!(split[4], "");
     }
+
+    #[test]
+    fn gamma_correction_brightens_mid_gray() {
+        let mut canvas = Canvas::new(1, 1);
+        canvas.write_pixel(0, 0, &Color::new(0.5, 0.5, 0.5));
+
+        let linear = canvas_to_ppm(&canvas);
+        assert_eq!(linear.split("\n").collect::<Vec<_>>()[3], "128 128 128");
+
+        let corrected = canvas_to_ppm_with_gamma(&canvas, 1.0 / 2.2);
+        assert_eq!(corrected.split("\n").collect::<Vec<_>>()[3], "187 187 187");
+    }
+
+    #[test]
+    fn tone_mapping_keeps_bright_colors_from_clamping() {
+        let mut canvas = Canvas::new(1, 1);
+        canvas.write_pixel(0, 0, &Color::new(3.0, 1.0, 0.0));
+
+        let clamped = canvas_to_ppm(&canvas);
+        assert_eq!(clamped.split("\n").collect::<Vec<_>>()[3], "255 255 0");
+
+        let mapped = canvas_to_ppm_tone_mapped(&canvas, 1.0);
+        assert_eq!(mapped.split("\n").collect::<Vec<_>>()[3], "192 128 0");
+    }
+
+    #[test]
+    fn a_canvas_survives_a_round_trip_through_ppm() {
+        let mut canvas = Canvas::new(4, 2);
+        canvas.write_pixel(0, 0, &Color::new(1., 0.5, 0.));
+        canvas.write_pixel(3, 1, &Color::new(0.2, 0.4, 0.6));
+
+        let result = ppm_to_canvas(&canvas_to_ppm(&canvas)).unwrap();
+        assert_eq!(result.width, 4);
+        assert_eq!(result.height, 2);
+        for (row, expected_row) in result.pixels.iter().zip(canvas.pixels.iter()) {
+            for (pixel, expected) in row.iter().zip(expected_row.iter()) {
+                assert!((pixel.r - expected.r).abs() < 1. / 255.);
+                assert!((pixel.g - expected.g).abs() < 1. / 255.);
+                assert!((pixel.b - expected.b).abs() < 1. / 255.);
+            }
+        }
+    }
+
+    #[test]
+    fn a_16_bit_ppm_keeps_more_precision_than_8_bit() {
+        let mut canvas = Canvas::new(1, 1);
+        let color = Color::new(0.1234, 0.5678, 0.9012);
+        canvas.write_pixel(0, 0, &color);
+
+        let ppm = canvas_to_ppm_with_max_value(&canvas, 65535).unwrap();
+        assert_eq!(ppm.split("\n").collect::<Vec<_>>()[2], "65535");
+
+        let error = |ppm: String| {
+            let pixel = &ppm_to_canvas(&ppm).unwrap().pixels[0][0];
+            (pixel.r - color.r).abs().max((pixel.g - color.g).abs()).max((pixel.b - color.b).abs())
+        };
+        let error_16 = error(ppm);
+        let error_8 = error(canvas_to_ppm(&canvas));
+        assert!(error_16 < 1. / 65535.);
+        assert!(error_16 < error_8);
+    }
+
+    #[test]
+    fn a_max_color_value_of_0_is_rejected() {
+        let canvas = Canvas::new(1, 1);
+        assert!(matches!(canvas_to_ppm_with_max_value(&canvas, 0), Err(PpmError::Header(_))));
+    }
+
+    #[test]
+    fn reading_a_value_above_the_max_is_an_error() {
+        assert!(matches!(ppm_to_canvas("P3\n1 1\n15\n0 16 0\n"), Err(PpmError::PixelData(_))));
+        assert!(matches!(ppm_to_canvas("P3\n1 1\n70000\n0 0 0\n"), Err(PpmError::Header(_))));
+    }
+
+    #[test]
+    fn reading_a_ppm_skips_comments_and_wrapped_lines() {
+        let ppm = "P3\n# made by hand\n2 1\n255\n255 0 0 0\n# the second pixel\n51 255\n";
+        let canvas = ppm_to_canvas(ppm).unwrap();
+        assert_eq!(canvas.pixels[0][0], Color::new(1., 0., 0.));
+        assert_eq!(canvas.pixels[0][1], Color::new(0., 0.2, 1.));
+    }
+
+    #[test]
+    fn reading_a_ppm_with_a_bad_header_is_an_error() {
+        assert!(matches!(ppm_to_canvas("P6\n1 1\n255\n0 0 0\n"), Err(PpmError::Header(_))));
+        assert!(matches!(ppm_to_canvas("P3\n1 x\n255\n0 0 0\n"), Err(PpmError::Header(_))));
+        assert!(matches!(ppm_to_canvas("P3\n2 1\n255\n0 0 0\n"), Err(PpmError::PixelData(_))));
+    }
 }
@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts of the work done during a render, useful when tuning the scene or
+/// comparing acceleration structures. See `Camera::render_with_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RenderStats {
+    /// Rays cast from the camera
+    pub primary_rays: usize,
+    /// Reflected and refracted rays
+    pub secondary_rays: usize,
+    /// Rays cast towards a light to see if a point is in shadow
+    pub shadow_rays: usize,
+    /// How many times a ray was intersected with one of the world's objects
+    pub intersection_tests: usize,
+    /// The most bounces any ray made, 0 if nothing was reflective or transparent
+    pub max_depth: usize,
+    /// Reflected and refracted rays that weren't cast because `World::max_depth`
+    /// was reached, leaving their light black
+    pub depth_limit_reached: usize,
+}
+
+impl RenderStats {
+    pub fn total_rays(&self) -> usize {
+        self.primary_rays + self.secondary_rays + self.shadow_rays
+    }
+}
+
+/// The work done so far in a render, shared between its threads so every counter
+/// is atomic. Passed to `Light::intensity_at` so shadow rays are counted, a light
+/// that overrides it should hand it on to `World::light_visibility`.
+#[derive(Debug, Default)]
+pub struct RenderCounters {
+    primary_rays: AtomicUsize,
+    secondary_rays: AtomicUsize,
+    shadow_rays: AtomicUsize,
+    intersection_tests: AtomicUsize,
+    max_depth: AtomicUsize,
+    depth_limit_reached: AtomicUsize,
+}
+
+impl RenderCounters {
+    pub(crate) fn primary_ray(&self) {
+        self.primary_rays.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn secondary_ray(&self, depth: usize) {
+        self.secondary_rays.fetch_add(1, Ordering::Relaxed);
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    pub(crate) fn depth_limit_reached(&self) {
+        self.depth_limit_reached.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn shadow_ray(&self) {
+        self.shadow_rays.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn intersection_test(&self) {
+        self.intersection_tests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> RenderStats {
+        RenderStats {
+            primary_rays: self.primary_rays.load(Ordering::Relaxed),
+            secondary_rays: self.secondary_rays.load(Ordering::Relaxed),
+            shadow_rays: self.shadow_rays.load(Ordering::Relaxed),
+            intersection_tests: self.intersection_tests.load(Ordering::Relaxed),
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+            depth_limit_reached: self.depth_limit_reached.load(Ordering::Relaxed),
+        }
+    }
+}
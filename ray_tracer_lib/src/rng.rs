@@ -0,0 +1,68 @@
+// The random numbers used by anything stochastic (jittered antialiasing, depth
+// of field). Renders need to be reproducible, so everything is seeded.
+
+/// A small seeded pseudo random number generator (splitmix64). Quick and good
+/// enough for sampling, the same seed always gives the same numbers.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// A generator for one pixel of one frame of a render. Giving each pixel its
+    /// own generator means the numbers it gets don't depend on the order pixels
+    /// are rendered in, so a render is the same on any number of threads.
+    pub fn for_pixel(seed: u64, x: usize, y: usize, frame: u64) -> Rng {
+        Rng::new(
+            seed ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+                ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+                ^ frame.wrapping_mul(0x1656_67b1_9e37_79f9),
+        )
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0.0 up to but not including 1.0
+    pub fn next_f64(&mut self) -> f64 {
+        // The top 53 bits fill the mantissa of a float
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_seed_gives_the_same_numbers() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(7);
+        let xs: Vec<f64> = (0..100).map(|_| a.next_f64()).collect();
+        let ys: Vec<f64> = (0..100).map(|_| b.next_f64()).collect();
+        let zs: Vec<f64> = (0..100).map(|_| c.next_f64()).collect();
+        assert_eq!(xs, ys);
+        assert_ne!(xs, zs);
+        assert!(xs.iter().all(|x| (0. ..1.).contains(x)));
+    }
+
+    #[test]
+    fn each_pixel_and_frame_gets_its_own_numbers() {
+        let first = |mut rng: Rng| rng.next_u64();
+        let pixel = first(Rng::for_pixel(1, 3, 4, 0));
+        assert_eq!(pixel, first(Rng::for_pixel(1, 3, 4, 0)));
+        assert_ne!(pixel, first(Rng::for_pixel(1, 4, 3, 0)));
+        assert_ne!(pixel, first(Rng::for_pixel(1, 3, 4, 1)));
+        assert_ne!(pixel, first(Rng::for_pixel(2, 3, 4, 0)));
+    }
+}
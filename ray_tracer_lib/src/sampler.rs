@@ -0,0 +1,139 @@
+//! Where to put the samples when a pixel (or anything else covering an area) is
+//! sampled more than once. Spreading them evenly converges much faster than
+//! placing them purely at random, random samples clump together and leave gaps.
+//!
+//! Camera antialiasing (`SampleConfig::strategy`) and area lights
+//! (`AreaLight::new`) both choose a strategy.
+
+use crate::rng::Rng;
+
+/// How sample points are spread over the unit square.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SampleStrategy {
+    /// The centers of a square grid, the same for every pixel
+    #[default]
+    Grid,
+    /// Anywhere at random, noisy but free of patterns
+    Random,
+    /// One random point in each cell of a square grid, see `stratified_2d`
+    Stratified,
+    /// The Halton sequence in bases 2 and 3, evenly spread for any number of samples
+    Halton,
+}
+
+/// One point at random within each cell of an `n` by `n` grid, so `n * n` points
+/// in all, a row at a time from the top left.
+pub fn stratified_2d(n: usize, rng: &mut Rng) -> Vec<(f64, f64)> {
+    let step = 1. / n as f64;
+    (0..n * n)
+        .map(|cell| {
+            let (i, j) = (cell % n, cell / n);
+            (
+                (i as f64 + rng.next_f64()) * step,
+                (j as f64 + rng.next_f64()) * step,
+            )
+        })
+        .collect()
+}
+
+/// The `index`th number of the Halton sequence in the given base, the digits of
+/// `index` mirrored around the decimal point. Starts at 0 for an index of 0.
+/// The base must be at least 2.
+pub fn halton(index: usize, base: usize) -> f64 {
+    assert!(base >= 2, "a Halton sequence needs a base of at least 2, got {}", base);
+    let mut result = 0.;
+    let mut fraction = 1.;
+    let mut index = index;
+    while index > 0 {
+        fraction /= base as f64;
+        result += fraction * (index % base) as f64;
+        index /= base;
+    }
+    result
+}
+
+/// `count` points in the unit square spread out with the given strategy. The grid
+/// strategies round `count` to the nearest square, so 10 gives a 3 by 3 grid.
+/// Halton points are shifted by a random amount (wrapping around), so samplers
+/// with different generators don't all use the same points.
+pub fn samples_2d(strategy: SampleStrategy, count: usize, rng: &mut Rng) -> Vec<(f64, f64)> {
+    let grid_size = ((count as f64).sqrt().round() as usize).max(1);
+    match strategy {
+        SampleStrategy::Grid => {
+            let step = 1. / grid_size as f64;
+            (0..grid_size * grid_size)
+                .map(|cell| {
+                    let (i, j) = (cell % grid_size, cell / grid_size);
+                    ((i as f64 + 0.5) * step, (j as f64 + 0.5) * step)
+                })
+                .collect()
+        }
+        SampleStrategy::Random => (0..count)
+            .map(|_| (rng.next_f64(), rng.next_f64()))
+            .collect(),
+        SampleStrategy::Stratified => stratified_2d(grid_size, rng),
+        SampleStrategy::Halton => {
+            let (shift_x, shift_y) = (rng.next_f64(), rng.next_f64());
+            (1..=count)
+                .map(|i| {
+                    (
+                        (halton(i, 2) + shift_x).fract(),
+                        (halton(i, 3) + shift_y).fract(),
+                    )
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mathf;
+
+    #[test]
+    fn stratified_samples_are_one_per_cell() {
+        let samples = stratified_2d(4, &mut Rng::new(1));
+        assert_eq!(samples.len(), 16);
+
+        let mut cells = [0; 16];
+        for (x, y) in samples {
+            cells[(y * 4.) as usize * 4 + (x * 4.) as usize] += 1;
+        }
+        assert!(cells.iter().all(|count| *count == 1));
+    }
+
+    #[test]
+    fn the_first_few_halton_numbers() {
+        let base_2: Vec<f64> = (1..=4).map(|i| halton(i, 2)).collect();
+        assert_eq!(base_2, vec![0.5, 0.25, 0.75, 0.125]);
+
+        let base_3: Vec<f64> = (1..=4).map(|i| halton(i, 3)).collect();
+        for (value, expected) in base_3.iter().zip([1. / 3., 2. / 3., 1. / 9., 4. / 9.]) {
+            assert!(mathf::approximately(*value, expected));
+        }
+        assert_eq!(halton(0, 2), 0.);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 2")]
+    fn a_halton_base_below_2_is_rejected() {
+        halton(3, 1);
+    }
+
+    #[test]
+    fn every_strategy_stays_inside_the_unit_square() {
+        for strategy in [
+            SampleStrategy::Grid,
+            SampleStrategy::Random,
+            SampleStrategy::Stratified,
+            SampleStrategy::Halton,
+        ] {
+            let samples = samples_2d(strategy, 9, &mut Rng::new(5));
+            assert_eq!(samples.len(), 9);
+            assert!(samples
+                .iter()
+                .all(|(x, y)| (0. ..1.).contains(x) && (0. ..1.).contains(y)));
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Load a world and camera from a YAML scene description, using the format from
+//! the book's bonus chapters:
+//!
+//! ```yaml
+//! - add: camera
+//!   width: 100
+//!   height: 50
+//!   field-of-view: 1.0472
+//!   from: [0, 1.5, -5]
+//!   to: [0, 1, 0]
+//!   up: [0, 1, 0]
+//!
+//! - add: light
+//!   at: [-10, 10, -10]
+//!   intensity: [1, 1, 1]
+//!
+//! - define: shiny
+//!   value:
+//!     specular: 0.9
+//!     shininess: 300
+//!
+//! - add: sphere
+//!   material: shiny
+//!   transform:
+//!     - [scale, 0.5, 0.5, 0.5]
+//!     - [translate, 1, 0.5, 0]
+//! ```
+//!
+//! Shapes may be a `sphere`, `plane` or `disk`. Transforms are applied in the
+//! order they are listed. A `define` may name a material or a list of transforms,
+//! and can `extend` an earlier definition.
+use crate::camera::Camera;
+use crate::color::Color;
+use crate::material::Material;
+use crate::mathf::disk::Disk;
+use crate::mathf::matrix::Matrix;
+use crate::mathf::plane::Plane;
+use crate::mathf::shapes::Shape;
+use crate::mathf::sphere::Sphere;
+use crate::mathf::vector3::Vector3;
+use crate::point_light::PointLight;
+use crate::transformations;
+use crate::world;
+use crate::world::World;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use yaml_rust2::{ScanError, Yaml, YamlLoader};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Yaml(ScanError),
+    /// The file is valid YAML but doesn't describe a scene we can render.
+    Invalid(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "unable to read scene: {}", e),
+            SceneError::Yaml(e) => write!(f, "the scene is not valid yaml: {}", e),
+            SceneError::Invalid(message) => write!(f, "invalid scene: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(e: io::Error) -> Self {
+        SceneError::Io(e)
+    }
+}
+
+impl From<ScanError> for SceneError {
+    fn from(e: ScanError) -> Self {
+        SceneError::Yaml(e)
+    }
+}
+
+pub struct Scene {
+    pub camera: Camera,
+    pub world: World,
+}
+
+pub fn load_scene(file_path: &str) -> Result<Scene, SceneError> {
+    parse_scene(&fs::read_to_string(file_path)?)
+}
+
+pub fn parse_scene(data: &str) -> Result<Scene, SceneError> {
+    let docs = YamlLoader::load_from_str(data)?;
+    let items = match docs.first() {
+        Some(Yaml::Array(items)) => items,
+        _ => return Err(invalid("a scene must be a list of items")),
+    };
+
+    let mut defines: HashMap<String, Yaml> = HashMap::new();
+    let mut camera = None;
+    let mut world = world::new();
+
+    for item in items {
+        if let Some(name) = item["define"].as_str() {
+            let value = match item["extend"].as_str() {
+                Some(parent) => extend(lookup(&defines, parent)?, &item["value"]),
+                None => item["value"].clone(),
+            };
+            defines.insert(name.to_string(), value);
+            continue;
+        }
+
+        match item["add"].as_str() {
+            Some("camera") => camera = Some(parse_camera(item)?),
+            Some("light") => {
+                world.add_light(PointLight::new(vector(&item["at"])?, color(&item["intensity"])?));
+            }
+            Some(kind) => world.add(parse_shape(kind, item, &defines)?),
+            None => return Err(invalid("each item must either `add` or `define` something")),
+        }
+    }
+
+    match camera {
+        Some(camera) => Ok(Scene { camera, world }),
+        None => Err(invalid("the scene has no camera")),
+    }
+}
+
+fn parse_camera(item: &Yaml) -> Result<Camera, SceneError> {
+    let width = integer(&item["width"], "width")?;
+    let height = integer(&item["height"], "height")?;
+    let field_of_view = number(&item["field-of-view"], "field-of-view")?;
+
+    let mut camera = Camera::new(width, height, field_of_view);
+    let view = transformations::view_transform(vector(&item["from"])?, vector(&item["to"])?, vector(&item["up"])?);
+    camera
+        .set_transform(view)
+        .map_err(|e| invalid(&e.to_string()))?;
+    Ok(camera)
+}
+
+fn parse_shape(kind: &str, item: &Yaml, defines: &HashMap<String, Yaml>) -> Result<Arc<dyn Shape>, SceneError> {
+    let transform = transform(&item["transform"], defines)?;
+    if transform.inverse().is_none() {
+        return Err(invalid(&format!("the transform of a {} is not invertible", kind)));
+    }
+    let material = material(&item["material"], defines)?;
+
+    let shape: Arc<dyn Shape> = match kind {
+        "sphere" => Arc::new(Sphere::new(Some(transform), Some(material))),
+        "plane" => Arc::new(Plane::new(Some(transform), Some(material))),
+        "disk" => Arc::new(Disk::new(Some(transform), Some(material))),
+        _ => return Err(invalid(&format!("unknown shape '{}'", kind))),
+    };
+    Ok(shape)
+}
+
+fn material(value: &Yaml, defines: &HashMap<String, Yaml>) -> Result<Material, SceneError> {
+    let value = match value {
+        Yaml::BadValue => return Ok(Material::new()),
+        Yaml::String(name) => lookup(defines, name)?,
+        _ => value,
+    };
+    let hash = match value {
+        Yaml::Hash(hash) => hash,
+        _ => return Err(invalid("a material must be a map or the name of one")),
+    };
+
+    let mut material = Material::new();
+    for (key, value) in hash {
+        match key.as_str() {
+            Some("color") => material.color = color(value)?,
+            Some("ambient") => material.ambient = number(value, "ambient")?,
+            Some("diffuse") => material.diffuse = number(value, "diffuse")?,
+            Some("specular") => material.specular = number(value, "specular")?,
+            Some("shininess") => material.shininess = number(value, "shininess")?,
+            Some("reflective") => material.reflective = number(value, "reflective")?,
+            Some("transparency") => material.transparency = number(value, "transparency")?,
+            Some("refractive-index") => material.refractive_index = number(value, "refractive-index")?,
+            _ => return Err(invalid(&format!("unknown material property {:?}", key))),
+        }
+    }
+    Ok(material)
+}
+
+fn transform(value: &Yaml, defines: &HashMap<String, Yaml>) -> Result<Matrix, SceneError> {
+    let mut matrix = Matrix::identity_4x4();
+    let steps = match value {
+        Yaml::BadValue => return Ok(matrix),
+        Yaml::Array(steps) => steps,
+        _ => return Err(invalid("a transform must be a list")),
+    };
+
+    for step in steps {
+        if let Yaml::String(name) = step {
+            let defined = transform(lookup(defines, name)?, defines)?;
+            matrix = defined.multiply_4x4(&matrix);
+            continue;
+        }
+
+        let (op, args) = match step {
+            Yaml::Array(parts) if !parts.is_empty() => (parts[0].as_str(), &parts[1..]),
+            _ => return Err(invalid("each transform must be a list like [translate, 1, 2, 3]")),
+        };
+        let args = args
+            .iter()
+            .map(|arg| number(arg, "transform"))
+            .collect::<Result<Vec<f64>, SceneError>>()?;
+        let expect = |count: usize| {
+            if args.len() == count {
+                Ok(())
+            } else {
+                Err(invalid(&format!("{:?} needs {} numbers", op.unwrap_or(""), count)))
+            }
+        };
+
+        matrix = match op {
+            Some("translate") => expect(3).map(|_| matrix.translate(args[0], args[1], args[2]))?,
+            Some("scale") => expect(3).map(|_| matrix.scale(args[0], args[1], args[2]))?,
+            Some("rotate-x") => expect(1).map(|_| matrix.rotate_x(args[0]))?,
+            Some("rotate-y") => expect(1).map(|_| matrix.rotate_y(args[0]))?,
+            Some("rotate-z") => expect(1).map(|_| matrix.rotate_z(args[0]))?,
+            Some("shear") => expect(6)
+                .map(|_| matrix.shear(args[0], args[1], args[2], args[3], args[4], args[5]))?,
+            _ => return Err(invalid(&format!("unknown transform {:?}", step[0]))),
+        };
+    }
+    Ok(matrix)
+}
+
+// A define that extends another inherits its keys (for materials) or steps (for transforms)
+fn extend(parent: &Yaml, value: &Yaml) -> Yaml {
+    match (parent, value) {
+        (Yaml::Hash(parent), Yaml::Hash(value)) => {
+            let mut merged = parent.clone();
+            for (k, v) in value {
+                merged.insert(k.clone(), v.clone());
+            }
+            Yaml::Hash(merged)
+        }
+        (Yaml::Array(parent), Yaml::Array(value)) => {
+            Yaml::Array(parent.iter().chain(value.iter()).cloned().collect())
+        }
+        _ => value.clone(),
+    }
+}
+
+fn lookup<'a>(defines: &'a HashMap<String, Yaml>, name: &str) -> Result<&'a Yaml, SceneError> {
+    defines
+        .get(name)
+        .ok_or_else(|| invalid(&format!("'{}' has not been defined", name)))
+}
+
+fn number(value: &Yaml, name: &str) -> Result<f64, SceneError> {
+    match value {
+        Yaml::Real(_) => value.as_f64(),
+        Yaml::Integer(i) => Some(*i as f64),
+        _ => None,
+    }
+    .ok_or_else(|| invalid(&format!("{} must be a number", name)))
+}
+
+fn integer(value: &Yaml, name: &str) -> Result<usize, SceneError> {
+    match value.as_i64() {
+        Some(i) if i > 0 => Ok(i as usize),
+        _ => Err(invalid(&format!("{} must be a positive whole number", name))),
+    }
+}
+
+fn triple(value: &Yaml) -> Result<(f64, f64, f64), SceneError> {
+    match value {
+        Yaml::Array(xs) if xs.len() == 3 => Ok((
+            number(&xs[0], "x")?,
+            number(&xs[1], "y")?,
+            number(&xs[2], "z")?,
+        )),
+        _ => Err(invalid(&format!("expected a list of 3 numbers, found {:?}", value))),
+    }
+}
+
+fn vector(value: &Yaml) -> Result<Vector3, SceneError> {
+    triple(value).map(Vector3::from)
+}
+
+fn color(value: &Yaml) -> Result<Color, SceneError> {
+    triple(value).map(|(r, g, b)| Color::new(r, g, b))
+}
+
+fn invalid(message: &str) -> SceneError {
+    SceneError::Invalid(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    const SCENE: &str = "
+- add: camera
+  width: 100
+  height: 50
+  field-of-view: 1.0472
+  from: [0, 1.5, -5]
+  to: [0, 1, 0]
+  up: [0, 1, 0]
+
+- add: light
+  at: [-10, 10, -10]
+  intensity: [1, 1, 1]
+
+- define: shiny
+  value:
+    color: [1, 0, 0]
+    specular: 0.9
+
+- define: shinier
+  extend: shiny
+  value:
+    shininess: 300
+
+- define: standard-transform
+  value:
+    - [translate, 1, -1, 1]
+    - [scale, 0.5, 0.5, 0.5]
+
+- add: plane
+
+- add: sphere
+  material: shinier
+  transform:
+    - standard-transform
+    - [rotate-y, 1.5707963]
+";
+
+    #[test]
+    fn parsing_a_scene() {
+        let scene = parse_scene(SCENE).unwrap();
+        assert_eq!(scene.camera.hsize, 100);
+        assert_eq!(scene.camera.vsize, 50);
+        assert_eq!(scene.world.lights.len(), 1);
+        assert_eq!(scene.world.objects.len(), 2);
+    }
+
+    #[test]
+    fn a_define_can_extend_another_material() {
+        let scene = parse_scene(SCENE).unwrap();
+        let sphere = &scene.world.objects[1];
+        assert_eq!(sphere.material().color, Color::new(1., 0., 0.));
+        assert_eq!(sphere.material().specular, 0.9);
+        assert_eq!(sphere.material().shininess, 300.);
+    }
+
+    #[test]
+    fn transforms_are_applied_in_order() {
+        let scene = parse_scene(SCENE).unwrap();
+        let expected = transformations::rotation_y(PI / 2.)
+            .multiply_4x4(&transformations::scaling(&Vector3::new(0.5, 0.5, 0.5)))
+            .multiply_4x4(&transformations::translation(&Vector3::new(1., -1., 1.)));
+        assert!(scene.world.objects[1].transform().approx_eq(&expected, 0.0001));
+    }
+
+    #[test]
+    fn a_scene_needs_a_camera() {
+        assert!(matches!(parse_scene("- add: sphere"), Err(SceneError::Invalid(_))));
+    }
+
+    #[test]
+    fn unknown_shapes_are_an_error() {
+        let scene = SCENE.replace("add: plane", "add: teapot");
+        assert!(matches!(parse_scene(&scene), Err(SceneError::Invalid(_))));
+    }
+
+    #[test]
+    fn a_missing_file_is_an_io_error() {
+        assert!(matches!(load_scene("no/such/scene.yml"), Err(SceneError::Io(_))));
+    }
+}
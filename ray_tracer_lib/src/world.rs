@@ -1,37 +1,168 @@
 use crate::color;
 use crate::color::Color;
+use crate::environment::Environment;
+use crate::light::Light;
 use crate::material::Material;
+use crate::mathf;
+use crate::mathf::bounding_box::BoundingBox;
+use crate::mathf::bvh::Bvh;
 use crate::mathf::intersection::{Computations, Intersection, Intersections};
 use crate::mathf::ray::Ray;
 use crate::mathf::sphere::Sphere;
 use crate::mathf::vector3::Vector3;
 use crate::phong_lighting;
 use crate::point_light::PointLight;
+use crate::render_stats::RenderCounters;
+use crate::rng::Rng;
 use crate::transformations;
 use std::sync::Arc;
 use crate::mathf::shapes::Shape;
 
+/// How many times a ray may bounce between reflective or transparent surfaces
+/// before we give up and return black. The default for `World::max_depth`.
+pub const MAX_RECURSION_DEPTH: usize = 5;
+
+// How many times a fuzzed reflection is redrawn when it points below the surface
+const FUZZ_ATTEMPTS: usize = 16;
+
+// The reflection direction moved to a random point within `fuzz` of it. The
+// generator is seeded from the sample's `seed` mixed with the hit point, so a
+// render is still reproducible while each sample of a pixel, and each bounce,
+// scatters differently.
+fn fuzzed_reflection(computations: &Computations, fuzz: f64, seed: u64) -> Vector3 {
+    let point = &computations.over_point;
+    let mut rng = Rng::new(
+        seed ^ point.x.to_bits() ^ point.y.to_bits().rotate_left(21) ^ point.z.to_bits().rotate_left(42),
+    );
+
+    // Scattering below the surface would reflect off the inside, so draw again.
+    // Only a mirror direction skimming the surface can miss every time.
+    for _ in 0..FUZZ_ATTEMPTS {
+        let offset = loop {
+            let candidate = Vector3::new(rng.next_f64() * 2. - 1., rng.next_f64() * 2. - 1., rng.next_f64() * 2. - 1.);
+            if candidate.magnitude_squared() <= 1. {
+                break candidate;
+            }
+        };
+        let direction = (&computations.reflect_vector + &(&offset * fuzz)).normalize();
+        if direction.dot(&computations.normal_vector) > 0. {
+            return direction;
+        }
+    }
+    computations.reflect_vector.clone()
+}
+
+/// Controls how much work is done when shading a hit. `Preview` skips shadow
+/// rays and specular highlights, which is useful while setting up a scene.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RenderQuality {
+    Preview,
+    #[default]
+    Full,
+}
+
+/// Ways of coloring a hit that show the geometry rather than the lit surface,
+/// useful for tracking down a shape that looks wrong.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugMode {
+    /// The world space surface normal, x, y and z mapped from [-1, 1] to red, green and blue
+    Normals,
+    /// Grayscale by distance along the ray, white at the camera fading to black at `max_distance`
+    Depth { max_distance: f64 },
+}
+
+/// Darkens surfaces that nearby geometry hides from the sky, like corners and
+/// the ground just under an object, by casting short rays out from each hit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmbientOcclusion {
+    /// How many rays are cast over the hemisphere around the normal
+    pub samples: usize,
+    /// Anything further away than this doesn't darken the surface
+    pub max_distance: f64,
+}
+
+/// Fades surfaces toward a color the further they are from the camera, for haze
+/// or mist. Only the first hit of each camera ray is fogged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fog {
+    pub color: Color,
+    /// How quickly the fog thickens with distance. At a distance of 1 / density
+    /// about 63% of what is seen is fog.
+    pub density: f64,
+}
+
+impl Fog {
+    /// The surface color seen through `distance` of fog
+    pub fn apply(&self, surface: &Color, distance: f64) -> Color {
+        let amount = 1. - (-self.density * distance).exp();
+        surface + &((&self.color - surface) * amount)
+    }
+}
 
 #[derive(Debug)]
 pub struct World {
-    pub light: Option<PointLight>,
+    /// The lighting from each light is added together
+    pub lights: Vec<Box<dyn Light>>,
     pub objects: Vec<Arc<dyn Shape>>,
+    /// When set, each channel of a shaded hit (surface plus reflection and
+    /// refraction) is clamped to this value to avoid overly bright "fireflies".
+    pub energy_cap: Option<f64>,
+    /// The color returned when a ray doesn't hit anything
+    pub background: Color,
+    /// When set, rays that don't hit anything sample this instead of using the background
+    pub environment: Option<Box<dyn Environment>>,
+    /// When set, the lit surface (but not reflections or refraction) is scaled
+    /// by the fraction of occlusion rays that escape
+    pub ambient_occlusion: Option<AmbientOcclusion>,
+    /// When set, hits seen directly from the camera fade toward the fog color with
+    /// distance. Rays that miss everything (the background) and reflections and
+    /// refractions are not fogged.
+    pub fog: Option<Fog>,
+    /// How far hit points are pushed off of a surface before casting shadow,
+    /// reflection and refraction rays, defaults to `mathf::SHADOW_EPSILON`.
+    /// Floating point error grows with the size of the scene, raise this if very
+    /// large objects shadow themselves (acne). Lower it if shadows come away from
+    /// the objects casting them in very small scenes.
+    pub surface_offset: f64,
+    /// How far shadow rays start along the direction to the light, on top of
+    /// `surface_offset`. 0 by default. The surface offset is along the normal,
+    /// which for smooth triangles is only an estimate and can even point slightly
+    /// into the surface, leaving speckled shadows. A small bias here (like 0.001)
+    /// steps the shadow ray clear of the surface whichever way the normal points.
+    pub shadow_bias: f64,
+    /// How many times a ray may bounce between reflective or transparent surfaces,
+    /// defaults to `MAX_RECURSION_DEPTH`. Past the limit the reflected or refracted
+    /// light is black, so raise it if nested glass or facing mirrors have dark
+    /// cores. `RenderStats::depth_limit_reached` shows how often that happens.
+    pub max_depth: usize,
+    // Set by `build_bvh`, used to pick which of `objects` a ray might hit
+    bvh: Option<Bvh>,
 }
 
 pub fn new() -> World {
     World {
-        light: None,
+        lights: vec![],
         objects: vec![],
+        energy_cap: None,
+        background: color::BLACK,
+        environment: None,
+        ambient_occlusion: None,
+        fog: None,
+        surface_offset: mathf::SHADOW_EPSILON,
+        shadow_bias: 0.,
+        max_depth: MAX_RECURSION_DEPTH,
+        bvh: None,
     }
 }
 
 pub fn default_world() -> World {
     let light = PointLight::new(Vector3::new(-10., 10., -10.), Color::new(1., 1., 1.));
 
-    let mut material = Material::new();
-    material.color = Color::new(0.8, 1.0, 0.6);
-    material.diffuse = 0.7;
-    material.specular = 0.2;
+    let material = Material::builder()
+        .color(Color::new(0.8, 1.0, 0.6))
+        .diffuse(0.7)
+        .specular(0.2)
+        .build();
 
     let s1 = Sphere::new(None, Some(material));
     let s1 = Arc::new(s1);
@@ -40,88 +171,772 @@ pub fn default_world() -> World {
     let s2 = Arc::new(s2);
 
     World {
-        light: Some(light),
+        lights: vec![Box::new(light)],
         objects: vec![s1, s2],
+        ..new()
     }
 }
 
 impl World {
+    /// Add an object to the world. Any BVH is dropped as it doesn't hold the new
+    /// object, call `build_bvh` again once everything has been added.
+    pub fn add(&mut self, shape: Arc<dyn Shape>) {
+        self.objects.push(shape);
+        self.bvh = None;
+    }
+
+    pub fn add_light<L: Light + 'static>(&mut self, light: L) {
+        self.lights.push(Box::new(light));
+    }
+
+    /// Sort the objects into a bounding volume hierarchy so each ray only tests the
+    /// objects near it, a big speed up for scenes with many objects. The tree is
+    /// built from the objects as they are now, call this again after changing
+    /// `objects` directly (`add` drops the tree so nothing goes missing).
+    pub fn build_bvh(&mut self) {
+        self.bvh = Some(Bvh::new(self.objects.clone()));
+    }
+
+    /// The box around every object in world space, empty when there are no
+    /// objects. Infinite if the world has a shape like a plane that goes on forever.
+    pub fn bounds(&self) -> BoundingBox {
+        let mut bounds = BoundingBox::empty();
+        for object in self.objects.iter() {
+            bounds.merge(&object.parent_space_bounds());
+        }
+        bounds
+    }
+
+    // The objects a ray might hit before `max_t`, only those in the BVH leaves it
+    // passes through if there is a BVH
+    fn candidates(&self, ray: &Ray, max_t: f64) -> Vec<&Arc<dyn Shape>> {
+        match &self.bvh {
+            Some(bvh) => {
+                let mut found = vec![];
+                bvh.candidates(ray, max_t, &mut found);
+                found
+            }
+            None => self.objects.iter().collect(),
+        }
+    }
+
     pub fn color_at(&self, ray: Ray) -> Color {
+        self.color_at_with_quality(ray, RenderQuality::default())
+    }
+
+    pub fn color_at_with_quality(&self, ray: Ray, quality: RenderQuality) -> Color {
+        self.color_at_depth(ray, quality, self.max_depth, 0, None)
+    }
+
+    /// Same as `color_at_with_quality`, for one of several samples of a pixel.
+    /// Anything random along the way, like fuzzy reflections, is seeded from
+    /// `seed`, so give each sample its own.
+    pub fn color_at_sample(&self, ray: Ray, quality: RenderQuality, seed: u64) -> Color {
+        self.color_at_depth(ray, quality, self.max_depth, seed, None)
+    }
+
+    /// Color the first hit using `mode` instead of lighting it, misses are black.
+    /// The normal is not flipped to face the eye, so an inside out surface shows up.
+    pub fn color_at_debug(&self, ray: Ray, mode: DebugMode) -> Color {
         let xs = self.intersect(ray.clone());
+        let hit = match xs.hit() {
+            None => return color::BLACK,
+            Some(hit) => hit,
+        };
+
+        match mode {
+            DebugMode::Normals => {
+                let normal = hit.object.normal_at(ray.position(hit.t));
+                Color::new((normal.x + 1.) / 2., (normal.y + 1.) / 2., (normal.z + 1.) / 2.)
+            }
+            DebugMode::Depth { max_distance } => {
+                let distance = hit.t * ray.direction.magnitude();
+                let gray = (1. - distance / max_distance).clamp(0., 1.);
+                Color::new(gray, gray, gray)
+            }
+        }
+    }
+
+    // Same as `color_at_sample`, but records the work done in `counters`
+    pub(crate) fn color_at_counted(&self, ray: Ray, quality: RenderQuality, seed: u64, counters: &RenderCounters) -> Color {
+        counters.primary_ray();
+        self.color_at_depth(ray, quality, self.max_depth, seed, Some(counters))
+    }
+
+    // `remaining` is how many more bounces reflection and refraction may make,
+    // `seed` seeds anything random in shading, see `color_at_sample`
+    fn color_at_depth(
+        &self,
+        ray: Ray,
+        quality: RenderQuality,
+        remaining: usize,
+        seed: u64,
+        counters: Option<&RenderCounters>,
+    ) -> Color {
+        let xs = self.intersect_counted(ray.clone(), counters);
         match xs.hit() {
-            None => color::BLACK,
+            None => match &self.environment {
+                None => self.background.clone(),
+                Some(environment) => environment.sample(&ray.direction),
+            },
             Some(i) => {
-                let comps = i.prepare_computations(ray);
-                self.shade_hit(comps)
+                let distance = i.t * ray.direction.magnitude();
+                let comps = i.prepare_computations_with_offset(ray, &xs, self.surface_offset);
+                let color = self.shade_hit(comps, quality, remaining, seed, counters);
+                match &self.fog {
+                    // Only camera rays start with every bounce remaining
+                    Some(fog) if remaining == self.max_depth => fog.apply(&color, distance),
+                    _ => color,
+                }
             }
         }
     }
 
 
-    fn intersect(&self, ray: Ray) -> Intersections {
+    /// Intersect a ray with every object in the world, sorted by t.
+    ///
+    /// ```
+    /// use ray_tracer_lib::mathf::ray::Ray;
+    /// use ray_tracer_lib::mathf::vector3::Vector3;
+    /// use ray_tracer_lib::world;
+    ///
+    /// let world = world::default_world();
+    /// let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+    /// let xs = world.intersect(ray);
+    ///
+    /// let ts: Vec<f64> = xs.intersections.iter().map(|i| i.t).collect();
+    /// assert_eq!(ts, vec![4., 4.5, 5.5, 6.]);
+    /// assert_eq!(xs.hit().unwrap().t, 4.);
+    /// ```
+    pub fn intersect(&self, ray: Ray) -> Intersections {
+        self.intersect_counted(ray, None)
+    }
+
+    fn intersect_counted(&self, ray: Ray, counters: Option<&RenderCounters>) -> Intersections {
         let mut result: Vec<Intersection> = vec![];
-        for object in self.objects.iter() {
-            let i = object.intersect(Arc::clone(&object), ray.clone());
+        for object in self.candidates(&ray, f64::INFINITY) {
+            if let Some(counters) = counters {
+                counters.intersection_test();
+            }
+            let i = object.intersect(Arc::clone(object), ray.clone());
             result.extend(i);
         }
 
-        result.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
-
-        Intersections::new(result)
+        let mut intersections = Intersections::new(result);
+        intersections.sort();
+        intersections
     }
 
-    fn shade_hit(&self, computations: Computations) -> Color {
+    fn shade_hit(
+        &self,
+        computations: Computations,
+        quality: RenderQuality,
+        remaining: usize,
+        seed: u64,
+        counters: Option<&RenderCounters>,
+    ) -> Color {
         // For now it's probably ok to just panic, but probably should handle this better?
-        if self.light == None {
+        if self.lights.is_empty() {
             panic!("You must add a light to a world before attempting to render it");
         }
 
-        let shadowed = self.is_shadowed(&computations.over_point);
+        let emission = computations.object.material().emission.clone();
+        if quality == RenderQuality::Preview {
+            return self.lights.iter().fold(emission, |sum, light| {
+                sum + phong_lighting::diffuse_lighting(
+                    &computations.object.material(),
+                    &*computations.object,
+                    light.as_ref(),
+                    &computations.point,
+                    &computations.normal_vector,
+                )
+            });
+        }
 
-        // The world only supports one light at this time. To add additional ones we
-        // would need to call the phong_lighting::lighting() function for each one,
-        // and add the resulting colors together.
+        let surface = self.lights.iter().fold(color::BLACK, |sum, light| {
+            let visibility = light.intensity_at(&computations.over_point, self, counters);
+            sum + phong_lighting::lighting_with_intensity(
+                &computations.object.material(),
+                &*computations.object,
+                light.as_ref(),
+                &computations.point,
+                &computations.eye_vector,
+                &computations.normal_vector,
+                visibility,
+            )
+        });
+
+        let surface = match &self.ambient_occlusion {
+            None => surface,
+            Some(occlusion) => {
+                surface * self.unoccluded_fraction(occlusion, &computations.over_point, &computations.normal_vector, counters)
+            }
+        };
+        // Glowing doesn't depend on the lights, so it is added once rather than per light
+        let surface = surface + emission;
 
-        phong_lighting::lighting(
-            &computations.object.material(),
-            &self.light.as_ref().unwrap(),
-            &computations.point,
-            &computations.eye_vector,
-            &computations.normal_vector,
-            shadowed,
-        )
+        let reflected = self.reflected_color(&computations, remaining, seed, counters);
+        let refracted = self.refracted_color(&computations, remaining, seed, counters);
+
+        // When a surface is both reflective and transparent, use the Fresnel effect
+        // to decide how much of the light is reflected and how much is refracted.
+        let material = computations.object.material();
+        let color = if material.reflective > 0. && material.transparency > 0. {
+            let reflectance = computations.schlick();
+            surface + reflected * reflectance + refracted * (1. - reflectance)
+        } else {
+            surface + reflected + refracted
+        };
+
+        match self.energy_cap {
+            None => color,
+            Some(cap) => Color::new(color.r.min(cap), color.g.min(cap), color.b.min(cap)),
+        }
     }
 
-    fn is_shadowed(&self, point: &Vector3) -> bool {
-        let vector = &(self.light.as_ref().unwrap().position) - point;
-        let distance = vector.magnitude();
-        let direction = vector.normalize();
+    fn reflected_color(&self, computations: &Computations, remaining: usize, seed: u64, counters: Option<&RenderCounters>) -> Color {
+        let (reflective, fuzz) = {
+            let material = computations.object.material();
+            (material.reflective, material.reflective_fuzz)
+        };
+        if reflective == 0. {
+            return color::BLACK;
+        }
+        if remaining == 0 {
+            if let Some(counters) = counters {
+                counters.depth_limit_reached();
+            }
+            return color::BLACK;
+        }
+
+        let mut reflect_ray = Ray::reflect(computations);
+        if fuzz > 0. {
+            reflect_ray.direction = fuzzed_reflection(computations, fuzz, seed);
+        }
+        if let Some(counters) = counters {
+            counters.secondary_ray(self.max_depth.saturating_sub(remaining) + 1);
+        }
+        let color = self.color_at_depth(reflect_ray, RenderQuality::Full, remaining - 1, seed, counters);
+        color * reflective
+    }
+
+    fn refracted_color(&self, computations: &Computations, remaining: usize, seed: u64, counters: Option<&RenderCounters>) -> Color {
+        let transparency = computations.object.material().transparency;
+        if transparency == 0. {
+            return color::BLACK;
+        }
+        if remaining == 0 {
+            if let Some(counters) = counters {
+                counters.depth_limit_reached();
+            }
+            return color::BLACK;
+        }
+
+        let n_ratio = computations.n1 / computations.n2;
+        let direction = match computations.eye_vector.refract(&computations.normal_vector, n_ratio) {
+            Some(direction) => direction,
+            // Total internal reflection
+            None => return color::BLACK,
+        };
+        let refract_ray = Ray::new(computations.under_point.clone(), direction);
+        if let Some(counters) = counters {
+            counters.secondary_ray(self.max_depth.saturating_sub(remaining) + 1);
+        }
+
+        let color = self.color_at_depth(refract_ray, RenderQuality::Full, remaining - 1, seed, counters);
+        color * transparency
+    }
+
+    // The fraction of rays cast over the hemisphere around the normal that travel
+    // `max_distance` without hitting anything. The rays follow a spiral that is
+    // denser near the normal (cosine weighted), as light from overhead counts for more.
+    fn unoccluded_fraction(
+        &self,
+        occlusion: &AmbientOcclusion,
+        point: &Vector3,
+        normal: &Vector3,
+        counters: Option<&RenderCounters>,
+    ) -> f64 {
+        let samples = occlusion.samples.max(1);
+
+        // Two unit vectors at right angles to the normal and each other
+        let helper = if normal.x.abs() > 0.9 {
+            Vector3::new(0., 1., 0.)
+        } else {
+            Vector3::new(1., 0., 0.)
+        };
+        let tangent = normal.cross(&helper).normalize();
+        let bitangent = normal.cross(&tangent);
+
+        let mut escaped = 0;
+        for sample in 0..samples {
+            let radius = ((sample as f64 + 0.5) / samples as f64).sqrt();
+            let theta = sample as f64 * mathf::GOLDEN_ANGLE;
+            let height = (1. - radius * radius).sqrt();
+            let direction = &(&(&tangent * (radius * theta.cos())) + &(&bitangent * (radius * theta.sin())))
+                + &(normal * height);
+
+            let ray = Ray::new(point.clone(), direction);
+            let blocked = self
+                .intersect_counted(ray, counters)
+                .hit()
+                .is_some_and(|hit| hit.t < occlusion.max_distance);
+            if !blocked {
+                escaped += 1;
+            }
+        }
+        escaped as f64 / samples as f64
+    }
+
+    /// True if anything between the point and the light blocks some of its light.
+    /// The light doesn't need to be one of the world's, handy for working out why
+    /// part of a render is dark.
+    pub fn is_shadowed(&self, point: &Vector3, light: &dyn Light) -> bool {
+        light.intensity_at(point, self, None) < 1.
+    }
+
+    /// The fraction of the light that reaches the point, 0 when fully in shadow.
+    /// Lights with several sample positions average a shadow ray to each of them.
+    /// The default for `Light::intensity_at`.
+    pub fn light_visibility<L: Light + ?Sized>(
+        &self,
+        light: &L,
+        point: &Vector3,
+        counters: Option<&RenderCounters>,
+    ) -> f64 {
+        if !light.casts_shadows() {
+            return 1.;
+        }
+
+        let samples = light.positions_to_sample();
+        if samples.is_empty() {
+            // For a directional light the distance is infinite, so any hit casts a shadow
+            let direction = light.direction_from(point);
+            return self.visibility_along(point, direction, f64::INFINITY, counters);
+        }
+
+        // The direction is normalized so t is a distance. Checks for rays running
+        // alongside a surface use a fixed epsilon on the direction, they would
+        // depend on how far away the light is otherwise.
+        let total: f64 = samples
+            .iter()
+            .map(|sample| {
+                let to_sample = sample - point;
+                let distance = to_sample.magnitude();
+                if distance == 0. {
+                    return 1.;
+                }
+                self.visibility_along(point, &to_sample / distance, distance, counters)
+            })
+            .sum();
+        total / samples.len() as f64
+    }
+
+    // Transparent objects between the point and the light only dim it. The light
+    // is `max_t` along the unit `direction`.
+    fn visibility_along(
+        &self,
+        point: &Vector3,
+        direction: Vector3,
+        max_t: f64,
+        counters: Option<&RenderCounters>,
+    ) -> f64 {
+        // Start the ray `shadow_bias` closer to the light, which is then that
+        // much closer in t too
+        let (origin, max_t) = if self.shadow_bias > 0. {
+            let step = self.shadow_bias;
+            if step >= max_t {
+                return 1.;
+            }
+            (point + &(&direction * step), max_t - step)
+        } else {
+            (point.clone(), max_t)
+        };
+
+        let ray = Ray::new(origin, direction);
+        if let Some(counters) = counters {
+            counters.shadow_ray();
+        }
 
-        let ray = Ray::new(point.clone(), direction);
-        let intersections = self.intersect(ray);
-        let hit = intersections.hit();
+        let mut visibility = 1.;
+        for object in self.candidates(&ray, max_t) {
+            // Skip anything the shadow ray can't reach before it gets to the light
+            if !object.parent_space_bounds().intersects(&ray, max_t) {
+                continue;
+            }
 
-        hit.is_some() && hit.unwrap().t < distance
+            if let Some(counters) = counters {
+                counters.intersection_test();
+            }
+            // An opaque object only needs to be found in the way, anything else
+            // needs every hit to see how much light gets through
+            if object.is_opaque() {
+                if object.intersects_before(Arc::clone(object), ray.clone(), max_t) {
+                    return 0.;
+                }
+                continue;
+            }
+            let xs = Intersections::new(object.intersect(Arc::clone(object), ray.clone()));
+            visibility *= xs.transmittance(max_t);
+            if visibility <= 0. {
+                // Fully in shadow, there is no need to look at the rest of the objects
+                return 0.;
+            }
+        }
+        visibility
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::directional_light::DirectionalLight;
+    use crate::environment::GradientSky;
+    use crate::mathf::bounding_box::BoundingBox;
+    use crate::mathf::group::GroupTransform;
+    use crate::mathf::matrix::Matrix;
+    use crate::mathf::plane::Plane;
+    use crate::mathf::triangle::Triangle;
+    use std::f64::consts::PI;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     #[test]
     fn test_creating_a_world() {
         let world = new();
-        assert!(world.light.is_none());
+        assert!(world.lights.is_empty());
         assert_eq!(world.objects.len(), 0);
     }
 
+    #[test]
+    fn adding_shapes_and_lights_to_a_world() {
+        let mut world = new();
+        for x in 0..3 {
+            let transform = transformations::translation(&Vector3::new(x as f64 * 2., 0., 0.));
+            world.add(Arc::new(Sphere::new(Some(transform), None)));
+        }
+        world.add_light(PointLight::new(Vector3::new(-10., 10., -10.), color::WHITE));
+
+        assert_eq!(world.objects.len(), 3);
+        assert!(!world.lights.is_empty());
+    }
+
+    #[test]
+    fn the_lighting_from_each_light_is_added_together() {
+        let mut world = default_world();
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let one_light = world.color_at(ray.clone());
+
+        world.add_light(PointLight::new(Vector3::new(-10., 10., -10.), Color::new(1., 1., 1.)));
+        assert_eq!(world.color_at(ray), one_light * 2.);
+    }
+
+    #[test]
+    fn a_sphere_hit_head_on_shows_its_normal_in_normals_mode() {
+        let world = default_world();
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        assert_eq!(world.color_at_debug(ray, DebugMode::Normals), Color::new(0.5, 0.5, 0.));
+    }
+
+    #[test]
+    fn depth_mode_fades_with_distance_and_misses_are_black() {
+        let world = default_world();
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let mode = DebugMode::Depth { max_distance: 8. };
+        assert_eq!(world.color_at_debug(ray, mode), Color::new(0.5, 0.5, 0.5));
+
+        let miss = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 1., 0.));
+        assert_eq!(world.color_at_debug(miss, mode), color::BLACK);
+    }
+
+    // Points lit from directly above a sphere with a radius of 1e11, how many of
+    // them wrongly shadow themselves
+    fn count_self_shadowed_points(surface_offset: f64) -> usize {
+        let scale = 1e11;
+        let mut world = new();
+        world.surface_offset = surface_offset;
+        world
+            .lights
+            .push(Box::new(PointLight::new(Vector3::new(0., 10. * scale, 0.), color::WHITE)));
+        world.objects.push(Arc::new(Sphere::new(
+            Some(transformations::scaling(&Vector3::new(scale, scale, scale))),
+            None,
+        )));
+
+        (0..100)
+            .filter(|i| {
+                let x = (*i as f64 / 100. - 0.5) * scale;
+                let ray = Ray::new(Vector3::new(x, 3. * scale, -0.3 * scale), Vector3::new(0., -1., 0.));
+                let xs = world.intersect(ray.clone());
+                let hit = xs.hit().unwrap();
+                let computations = hit.prepare_computations_with_offset(ray, &xs, world.surface_offset);
+                world.is_shadowed(&computations.over_point, world.lights[0].as_ref())
+            })
+            .count()
+    }
+
+    #[test]
+    fn a_larger_surface_offset_removes_acne_on_a_very_large_sphere() {
+        assert!(count_self_shadowed_points(mathf::SHADOW_EPSILON) > 0);
+        assert_eq!(count_self_shadowed_points(1.), 0);
+    }
+
+    #[test]
+    fn the_bounds_of_a_world_cover_every_object() {
+        let mut world = new();
+        assert!(world.bounds().is_empty());
+        for x in [-2., 2.] {
+            world.add(Arc::new(Sphere::new(
+                Some(transformations::translation(&Vector3::new(x, 0., 0.))),
+                None,
+            )));
+        }
+        assert_eq!(
+            world.bounds(),
+            BoundingBox::new(Vector3::new(-3., -1., -1.), Vector3::new(3., 1., 1.))
+        );
+    }
+
+    #[test]
+    fn a_shadow_bias_clears_a_smooth_triangle_whose_normal_dips_below_it() {
+        // The triangle lies flat, but its vertex normals lean slightly down so
+        // the surface offset moves points just under it
+        let normal = Vector3::new(1., -0.1, 0.).normalize();
+        let mut world = new();
+        world.add(Arc::new(Triangle::new_smooth(
+            None,
+            None,
+            Vector3::new(-10., 0., -10.),
+            Vector3::new(10., 0., -10.),
+            Vector3::new(0., 0., 10.),
+            normal.clone(),
+            normal.clone(),
+            normal,
+        )));
+        world.add_light(PointLight::new(Vector3::new(20., 20., 0.), color::WHITE));
+
+        let self_shadowed = |world: &World| {
+            (0..25)
+                .filter(|i| {
+                    let x = (i % 5) as f64 - 2.;
+                    let z = (i / 5) as f64 - 2.;
+                    let ray = Ray::new(Vector3::new(x + 5., 5., z), Vector3::new(-1., -1., 0.));
+                    let xs = world.intersect(ray.clone());
+                    let computations = xs.hit().unwrap().prepare_computations_with_offset(ray, &xs, world.surface_offset);
+                    world.is_shadowed(&computations.over_point, world.lights[0].as_ref())
+                })
+                .count()
+        };
+
+        assert_eq!(world.shadow_bias, 0.);
+        assert_eq!(self_shadowed(&world), 25);
+        world.shadow_bias = 0.001;
+        assert_eq!(self_shadowed(&world), 0);
+    }
+
+    #[test]
+    fn a_dielectric_shows_both_reflection_and_refraction() {
+        // Water over a red floor, under a green sky
+        let mut world = new();
+        world.background = Color::new(0., 1., 0.);
+        world.add(Arc::new(Plane::new(None, Some(Material::dielectric(1.333)))));
+        world.add(Arc::new(Plane::new(
+            Some(transformations::translation(&Vector3::new(0., -1., 0.))),
+            Some(Material::builder().color(Color::new(1., 0., 0.)).build()),
+        )));
+        world.add_light(PointLight::new(Vector3::new(0., 10., 0.), color::WHITE));
+
+        let ray = Ray::new(Vector3::new(0., 1., -1.), Vector3::new(0., -1., 1.).normalize());
+        let color = world.color_at(ray);
+        // The floor is seen through the water, and the sky reflected in it
+        assert!(color.r > 0.5, "{:?}", color);
+        assert!(color.g > 0.01, "{:?}", color);
+        assert!(color.r + color.g < 1.5);
+    }
+
+    #[test]
+    fn the_default_surface_offset_is_the_shadow_epsilon() {
+        let world = default_world();
+        assert_eq!(world.surface_offset, mathf::SHADOW_EPSILON);
+
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let xs = world.intersect(ray.clone());
+        let computations = xs[0].prepare_computations_with_offset(ray, &xs, world.surface_offset);
+        assert!(computations.over_point.z < -1. + mathf::SHADOW_EPSILON);
+        assert!(computations.over_point.z > -1. - mathf::SHADOW_EPSILON * 1.5);
+    }
+
+    #[test]
+    fn a_bvh_finds_the_same_hits_as_testing_every_object() {
+        let mut rng = Rng::new(3);
+        let mut world = new();
+        world.add_light(PointLight::new(Vector3::new(-10., 10., -10.), Color::new(1., 1., 1.)));
+        for _ in 0..50 {
+            let size = 0.2 + rng.next_f64();
+            let transform = Matrix::identity_4x4().scale(size, size, size).translate(
+                rng.next_f64() * 20. - 10.,
+                rng.next_f64() * 20. - 10.,
+                rng.next_f64() * 20.,
+            );
+            world.add(Arc::new(Sphere::new(Some(transform), None)));
+        }
+        world.add(Arc::new(Plane::new(Some(transformations::translation(&Vector3::new(0., -12., 0.))), None)));
+
+        let mut with_bvh = World {
+            objects: world.objects.clone(),
+            ..new()
+        };
+        with_bvh.build_bvh();
+
+        let eye = Vector3::new(0., 0., -15.);
+        let mut hits = 0;
+        for y in -20..20 {
+            for x in -20..20 {
+                let target = Vector3::new(x as f64 * 0.5, y as f64 * 0.5, 10.);
+                let ray = Ray::new(eye.clone(), (&target - &eye).normalize());
+                let expected = world.intersect(ray.clone()).hit();
+                let actual = with_bvh.intersect(ray.clone()).hit();
+                match (expected, actual) {
+                    (None, None) => {}
+                    (Some(expected), Some(actual)) => {
+                        hits += 1;
+                        assert_eq!(expected.t, actual.t);
+                        assert!(Arc::ptr_eq(&expected.object, &actual.object));
+                        // Shadow rays go through the BVH too
+                        let point = ray.position(expected.t - 0.001);
+                        let light = world.lights[0].as_ref();
+                        assert_eq!(world.is_shadowed(&point, light), with_bvh.is_shadowed(&point, light));
+                    }
+                    (expected, actual) => panic!("expected {:?} but the BVH found {:?}", expected.map(|i| i.t), actual.map(|i| i.t)),
+                }
+            }
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn an_object_added_after_building_the_bvh_is_still_seen() {
+        let mut world = default_world();
+        world.build_bvh();
+        let red = Material::builder().color(color::RED).build();
+        world.add(Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(0., 0., -5.))),
+            Some(red),
+        )));
+
+        let ray = Ray::new(Vector3::new(0., 0., -10.), Vector3::new(0., 0., 1.));
+        assert_eq!(world.intersect(ray.clone()).hit().unwrap().t, 4.);
+        let color = world.color_at(ray);
+        assert!(color.r > color.g);
+
+        // Rebuilding it afterwards finds the same hit
+        world.build_bvh();
+        let ray = Ray::new(Vector3::new(0., 0., -10.), Vector3::new(0., 0., 1.));
+        assert_eq!(world.intersect(ray).hit().unwrap().t, 4.);
+    }
+
+    #[test]
+    fn a_bvh_counts_an_intersection_test_for_each_object_it_reaches() {
+        let mut world = new();
+        for x in 0..8 {
+            world.add(Arc::new(Sphere::new(
+                Some(transformations::translation(&Vector3::new(x as f64 * 3., 0., 0.))),
+                None,
+            )));
+        }
+        world.build_bvh();
+
+        // Only the leaf holding the first two spheres is reached
+        let counters = RenderCounters::default();
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        world.intersect_counted(ray, Some(&counters));
+        assert_eq!(counters.stats().intersection_tests, 2);
+
+        // A shadow ray stops at the light (x = 1 here), so leaves past it aren't
+        // reached, and the second sphere in the first leaf is skipped by its own box
+        let counters = RenderCounters::default();
+        let along = Vector3::new(1., 0., 0.);
+        world.visibility_along(&Vector3::new(-5., 0., 0.), along, 6., Some(&counters));
+        assert_eq!(counters.stats().intersection_tests, 1);
+    }
+
+    // A floor with walls along x = 0 and z = 0
+    fn corner_world() -> World {
+        let floor = Plane::new(None, None);
+        let wall_x = Plane::new(Some(transformations::rotation_z(PI / 2.)), None);
+        let wall_z = Plane::new(Some(transformations::rotation_x(PI / 2.)), None);
+        World {
+            lights: vec![Box::new(PointLight::new(Vector3::new(5., 10., 5.), Color::new(1., 1., 1.)))],
+            objects: vec![Arc::new(floor), Arc::new(wall_x), Arc::new(wall_z)],
+            ambient_occlusion: Some(AmbientOcclusion {
+                samples: 32,
+                max_distance: 1.,
+            }),
+            ..new()
+        }
+    }
+
+    #[test]
+    fn a_point_deep_in_a_corner_is_more_occluded_than_an_exposed_point() {
+        let world = corner_world();
+        let occlusion = world.ambient_occlusion.unwrap();
+        let up = Vector3::new(0., 1., 0.);
+        let corner = world.unoccluded_fraction(&occlusion, &Vector3::new(0.05, 0.0001, 0.05), &up, None);
+        let exposed = world.unoccluded_fraction(&occlusion, &Vector3::new(5., 0.0001, 5.), &up, None);
+        assert!(corner < 0.5);
+        assert_eq!(exposed, 1.);
+    }
+
+    #[test]
+    fn ambient_occlusion_darkens_the_floor_in_a_corner() {
+        let mut world = corner_world();
+        let down = Vector3::new(0., -1., 0.);
+        let corner = world.color_at(Ray::new(Vector3::new(0.05, 5., 0.05), down.clone()));
+        let exposed = world.color_at(Ray::new(Vector3::new(5., 5., 5.), down.clone()));
+        assert!(corner.r < exposed.r);
+
+        // Without it only the lighting differs, and the light is right above the exposed point
+        world.ambient_occlusion = None;
+        assert_eq!(world.color_at(Ray::new(Vector3::new(5., 5., 5.), down)), exposed);
+    }
+
+    #[test]
+    fn fog_fades_distant_hits_toward_the_fog_color() {
+        let mut world = default_world();
+        let near = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let far = Ray::new(Vector3::new(0., 0., -500.), Vector3::new(0., 0., 1.));
+        let clear = world.color_at(near.clone());
+
+        let blue = Color::new(0.5, 0.6, 0.9);
+        world.fog = Some(Fog {
+            color: blue.clone(),
+            density: 0.,
+        });
+        assert_eq!(world.color_at(near.clone()), clear);
+
+        world.fog = Some(Fog {
+            color: blue.clone(),
+            density: 0.1,
+        });
+        let near_color = world.color_at(near);
+        let far_color = world.color_at(far);
+        assert_ne!(near_color, clear);
+        assert_eq!(far_color, blue);
+
+        // A miss shows the background as it is
+        let miss = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 1., 0.));
+        assert_eq!(world.color_at(miss), world.background);
+    }
+
     #[test]
     fn test_creating_a_default_world() {
         let light = PointLight::new(Vector3::new(-10., 10., -10.), Color::new(1., 1., 1.));
 
         let world = default_world();
-        assert_eq!(world.light.unwrap(), light);
+        let world_light = &world.lights[0];
+        let origin = Vector3::new(0., 0., 0.);
+        assert_eq!(world_light.intensity(), light.intensity());
+        assert_eq!(world_light.direction_from(&origin), light.direction_from(&origin));
+        assert_eq!(world_light.distance_from(&origin), light.distance_from(&origin));
 
         assert_eq!(world.objects.len(), 2);
 
@@ -160,26 +975,69 @@ mod tests {
         let world = default_world();
         let ray = Ray::new(Vector3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
         let shape = &world.objects[0];
-        let intersection = Intersection::new(4., Arc::clone(&shape));
+        let intersection = Intersection::new(4., Arc::clone(shape));
         let computations = intersection.prepare_computations(ray);
-        let color = world.shade_hit(computations);
+        let color = world.shade_hit(computations, RenderQuality::Full, MAX_RECURSION_DEPTH, 0, None);
 
         assert_eq!(color, Color::new(0.38066, 0.47583, 0.2855));
     }
 
+    // A point light that never reaches anything, to check shading asks the light
+    #[derive(Debug)]
+    struct EclipsedLight(PointLight);
+
+    impl Light for EclipsedLight {
+        fn intensity(&self) -> &Color {
+            self.0.intensity()
+        }
+
+        fn direction_from(&self, point: &Vector3) -> Vector3 {
+            self.0.direction_from(point)
+        }
+
+        fn distance_from(&self, point: &Vector3) -> f64 {
+            self.0.distance_from(point)
+        }
+
+        fn positions_to_sample(&self) -> Vec<Vector3> {
+            self.0.positions_to_sample()
+        }
+
+        fn intensity_at(&self, _point: &Vector3, _world: &World, _counters: Option<&RenderCounters>) -> f64 {
+            0.
+        }
+    }
+
+    #[test]
+    fn shading_uses_the_intensity_the_light_reports() {
+        let mut world = default_world();
+        world.lights = vec![Box::new(EclipsedLight(PointLight::new(
+            Vector3::new(-10., 10., -10.),
+            Color::new(1., 1., 1.),
+        )))];
+        let ray = Ray::new(Vector3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+        let intersection = Intersection::new(4., Arc::clone(&world.objects[0]));
+        let computations = intersection.prepare_computations(ray);
+        let color = world.shade_hit(computations, RenderQuality::Full, MAX_RECURSION_DEPTH, 0, None);
+
+        // Only the ambient light is left
+        assert_eq!(color, Color::new(0.08, 0.1, 0.06));
+        assert!(world.is_shadowed(&Vector3::new(0., 10., 0.), world.lights[0].as_ref()));
+    }
+
     #[test]
     fn test_shading_an_intersection_from_the_inside() {
         let mut world = default_world();
-        world.light = Some(PointLight::new(
+        world.lights = vec![Box::new(PointLight::new(
             Vector3::new(0., 0.25, 0.),
             Color::new(1., 1., 1.),
-        ));
+        ))];
 
         let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
         let shape = &world.objects[1];
-        let intersection = Intersection::new(0.5, Arc::clone(&shape));
+        let intersection = Intersection::new(0.5, Arc::clone(shape));
         let computations = intersection.prepare_computations(ray);
-        let color = world.shade_hit(computations);
+        let color = world.shade_hit(computations, RenderQuality::Full, MAX_RECURSION_DEPTH, 0, None);
 
         assert_eq!(color, Color::new(0.90498, 0.90498, 0.90498));
     }
@@ -220,8 +1078,9 @@ mod tests {
             let s2 = Arc::new(s2);
 
             World {
-                light: Some(light),
+                lights: vec![Box::new(light)],
                 objects: vec![s1, s2],
+                ..new()
             }
         };
 
@@ -236,28 +1095,200 @@ mod tests {
     fn there_is_no_shadow_when_nothing_is_colinear_with_point_and_light() {
         let world = default_world();
         let point = Vector3::new(0., 10., 0.);
-        assert!(!world.is_shadowed(&point));
+        assert!(!world.is_shadowed(&point, world.lights[0].as_ref()));
     }
 
     #[test]
     fn the_shadow_when_an_object_is_between_the_point_and_the_light() {
         let world = default_world();
         let point = Vector3::new(10., -10., 10.);
-        assert!(world.is_shadowed(&point));
+        assert!(world.is_shadowed(&point, world.lights[0].as_ref()));
     }
 
     #[test]
     fn there_is_no_shadow_when_an_object_is_behind_the_light() {
         let world = default_world();
         let point = Vector3::new(-20., 20., -20.);
-        assert!(!world.is_shadowed(&point));
+        assert!(!world.is_shadowed(&point, world.lights[0].as_ref()));
     }
 
     #[test]
     fn there_is_no_shadow_when_an_object_is_behind_the_point() {
         let world = default_world();
         let point = Vector3::new(-2., 2., -2.);
-        assert!(!world.is_shadowed(&point));
+        assert!(!world.is_shadowed(&point, world.lights[0].as_ref()));
+    }
+
+    #[test]
+    fn a_grazing_occluder_shadows_the_same_however_far_away_the_light_is() {
+        // A ceiling the shadow ray crosses at a shallow angle, 0.05 along it
+        let mut world = new();
+        world.add(Arc::new(Plane::new(
+            Some(transformations::translation(&Vector3::new(0., 1. + 5e-6, 0.))),
+            None,
+        )));
+        let point = Vector3::new(0., 1., 0.);
+        let direction = Vector3::new(1., 1e-4, 0.).normalize();
+        for distance in [0.08, 1., 100.] {
+            let light = PointLight::new(&point + &(&direction * distance), Color::new(1., 1., 1.));
+            assert!(world.is_shadowed(&point, &light), "light {} away", distance);
+        }
+    }
+
+    #[test]
+    fn shadows_agree_with_checking_every_intersection() {
+        let world = default_world();
+        let light = world.lights[0].as_ref();
+        let light_position = light.positions_to_sample()[0].clone();
+        let mut shadowed = 0;
+        for x in -6..=6 {
+            for y in -6..=6 {
+                let point = Vector3::new(x as f64 * 0.5, y as f64 * 0.5, 2.);
+                let to_light = &light_position - &point;
+                let ray = Ray::new(point.clone(), to_light.normalize());
+                let blocked = world
+                    .intersect(ray)
+                    .hit()
+                    .is_some_and(|hit| hit.t < to_light.magnitude());
+                assert_eq!(world.is_shadowed(&point, light), blocked, "at {:?}", point);
+                shadowed += blocked as usize;
+            }
+        }
+        // Behind the spheres from the light, so some points are in shadow
+        assert!(shadowed > 0);
+    }
+
+    #[test]
+    fn each_light_is_checked_for_shadows_on_its_own() {
+        let world = default_world();
+        // Opposite the default light, so the spheres block it for different points
+        let behind = PointLight::new(Vector3::new(10., -10., 10.), Color::new(1., 1., 1.));
+
+        let cases = [
+            (Vector3::new(0., 10., 0.), false, false),
+            (Vector3::new(10., -10., 10.), true, false),
+            (Vector3::new(-20., 20., -20.), false, true),
+            (Vector3::new(-2., 2., -2.), false, true),
+        ];
+        for (point, from_default, from_behind) in cases.iter() {
+            assert_eq!(world.is_shadowed(point, world.lights[0].as_ref()), *from_default);
+            assert_eq!(world.is_shadowed(point, &behind), *from_behind);
+        }
+    }
+
+    #[test]
+    fn a_shadowless_fill_light_lights_a_point_the_key_light_cant_reach() {
+        let scene = |with_blocker: bool, key: bool, fill: bool| {
+            let mut world = new();
+            world.add(Arc::new(Plane::new(None, None)));
+            if with_blocker {
+                world.add(Arc::new(Sphere::new(
+                    Some(transformations::translation(&Vector3::new(0., 3., 0.))),
+                    None,
+                )));
+            }
+            if key {
+                world.add_light(PointLight::new(Vector3::new(0., 10., 0.), color::WHITE));
+            }
+            if fill {
+                let mut light = PointLight::new(Vector3::new(0., 10., 0.), color::WHITE);
+                light.casts_shadows = false;
+                world.add_light(light);
+            }
+            let ray = Ray::new(Vector3::new(0., 1., -1.), Vector3::new(0., -1., 1.).normalize());
+            world.color_at(ray)
+        };
+
+        let key_only = scene(true, true, false);
+        assert_eq!(key_only, Color::new(0.1, 0.1, 0.1));
+        let fill_unblocked = scene(false, false, true);
+        assert_eq!(scene(true, true, true), key_only + fill_unblocked);
+    }
+
+    // A sphere that counts how many times it has been intersected
+    #[derive(Debug)]
+    struct CountingSphere {
+        sphere: Sphere,
+        count: Arc<AtomicUsize>,
+    }
+
+    impl Shape for CountingSphere {
+        fn transform(&self) -> &crate::mathf::matrix::Matrix {
+            self.sphere.transform()
+        }
+        fn inverse_transform(&self) -> &crate::mathf::matrix::Matrix {
+            self.sphere.inverse_transform()
+        }
+        fn shared_material(&self) -> &crate::material::SharedMaterial {
+            self.sphere.shared_material()
+        }
+        fn parent(&self) -> Option<&Arc<GroupTransform>> {
+            self.sphere.parent()
+        }
+        fn set_parent(&self, parent: Arc<GroupTransform>) {
+            self.sphere.set_parent(parent)
+        }
+        fn local_intersect(&self, shape: Arc<dyn Shape>, object_ray: Ray) -> Vec<Intersection> {
+            self.count.fetch_add(1, Ordering::SeqCst);
+            self.sphere.local_intersect(shape, object_ray)
+        }
+        fn local_normal_at(&self, object_point: Vector3) -> Vector3 {
+            self.sphere.local_normal_at(object_point)
+        }
+        fn local_eq(&self, other: &dyn Shape) -> bool {
+            self.sphere.local_eq(other)
+        }
+        fn bounds(&self) -> BoundingBox {
+            self.sphere.bounds()
+        }
+    }
+
+    #[test]
+    fn shadow_rays_skip_objects_they_cannot_reach() {
+        let mut world = default_world();
+        let count = Arc::new(AtomicUsize::new(0));
+        for i in 0..10 {
+            // Off to the side, away from the light
+            let transform = transformations::translation(&Vector3::new(20. + 3. * i as f64, 0., 0.));
+            world.add(Arc::new(CountingSphere {
+                sphere: Sphere::new(Some(transform), None),
+                count: Arc::clone(&count),
+            }));
+        }
+
+        let points = [
+            Vector3::new(0., 10., 0.),
+            Vector3::new(10., -10., 10.),
+            Vector3::new(-20., 20., -20.),
+            Vector3::new(-2., 2., -2.),
+            Vector3::new(0., 0., 0.),
+        ];
+        for point in points.iter() {
+            // Same answer as intersecting the shadow ray with everything
+            let light = world.lights[0].as_ref();
+            let ray = Ray::new(point.clone(), light.direction_from(point));
+            let expected = world.intersect(ray).transmittance(light.distance_from(point)) < 1.;
+            count.store(0, Ordering::SeqCst);
+
+            assert_eq!(world.is_shadowed(point, light), expected);
+            assert_eq!(count.load(Ordering::SeqCst), 0);
+        }
+    }
+
+    #[test]
+    fn shadows_match_a_normalized_shadow_ray_across_the_scene() {
+        let world = default_world();
+        let light = world.lights[0].as_ref();
+        for i in 0..400 {
+            let point = Vector3::new(
+                (i % 20) as f64 * 0.4 - 4.,
+                ((i / 20) % 20) as f64 * 0.4 - 4.,
+                (i % 7) as f64 * 0.5,
+            );
+            let ray = Ray::new(point.clone(), light.direction_from(&point));
+            let expected = world.intersect(ray).transmittance(light.distance_from(&point)) < 1.;
+            assert_eq!(world.is_shadowed(&point, light), expected, "at {:?}", point);
+        }
     }
 
     #[test]
@@ -273,15 +1304,442 @@ mod tests {
             let light = PointLight::new(Vector3::new(0., 0., -10.), Color::new(1., 1., 1.));
 
             World {
-                light: Some(light),
+                lights: vec![Box::new(light)],
                 objects: vec![s1, s2],
+                ..new()
             }
         };
 
         let ray = Ray::new(Vector3::new(0., 0., 5.), Vector3::new(0., 0., 1.));
         let intersection = Intersection::new(4., Arc::clone(&s2_clone));
         let comps = intersection.prepare_computations(ray);
-        let color = world.shade_hit(comps);
+        let color = world.shade_hit(comps, RenderQuality::Full, MAX_RECURSION_DEPTH, 0, None);
         assert_eq!(color, Color::new(0.1, 0.1, 0.1));
     }
+
+    #[test]
+    fn an_emissive_surface_in_full_shadow_still_glows() {
+        let s1: Arc<dyn Shape> = Arc::new(Sphere::new(None, None));
+        let glowing = Material::builder().emission(Color::new(0.5, 0.25, 0.)).build();
+        let s2: Arc<dyn Shape> = Arc::new(Sphere::new(
+            Some(transformations::translation(&Vector3::new(0., 0., 10.))),
+            Some(glowing),
+        ));
+
+        // Two lights, both blocked, and the glow is still only added once
+        let mut world = World {
+            objects: vec![s1, Arc::clone(&s2)],
+            ..new()
+        };
+        world.add_light(PointLight::new(Vector3::new(0., 0., -10.), Color::new(1., 1., 1.)));
+        world.add_light(PointLight::new(Vector3::new(0., 0., -10.), Color::new(1., 1., 1.)));
+
+        let ray = Ray::new(Vector3::new(0., 0., 5.), Vector3::new(0., 0., 1.));
+        let comps = Intersection::new(4., s2).prepare_computations(ray);
+        let color = world.shade_hit(comps, RenderQuality::Full, MAX_RECURSION_DEPTH, 0, None);
+        assert_eq!(color, Color::new(0.2 + 0.5, 0.2 + 0.25, 0.2));
+    }
+
+    #[test]
+    fn a_mirror_reflects_the_glow_of_an_emissive_surface() {
+        let mut world = default_world();
+        world.objects[0] = Arc::new(Sphere::new(
+            None,
+            Some(Material::builder().color(color::BLACK).emission(Color::new(0., 1., 0.)).build()),
+        ));
+        // A wall facing the sphere across z = 3
+        world.add(Arc::new(Plane::new(
+            Some(Matrix::identity_4x4().rotate_x(PI / 2.).translate(0., 0., 3.)),
+            Some(Material::builder().color(color::BLACK).reflective(1.).build()),
+        )));
+
+        // Looking straight back at the sphere in the mirror from behind it
+        let ray = Ray::new(Vector3::new(0., 0., 2.), Vector3::new(0., 0., 1.));
+        let xs = world.intersect(ray.clone());
+        let comps = xs.hit().unwrap().prepare_computations_with(ray, &xs);
+        let color = world.shade_hit(comps, RenderQuality::Full, MAX_RECURSION_DEPTH, 0, None);
+        assert!(color.g >= 1.);
+        assert!(color.g > color.r && color.g > color.b);
+    }
+
+    #[test]
+    fn the_default_render_quality_is_full() {
+        assert_eq!(RenderQuality::default(), RenderQuality::Full);
+    }
+
+    #[test]
+    fn a_preview_render_skips_shadows() {
+        let s1 = Sphere::new(None, None);
+        let s1: Arc<dyn Shape> = Arc::new(s1);
+
+        let s2 = Sphere::new(Some(transformations::translation(&Vector3::new(0., 0., 10.))), None);
+        let s2: Arc<dyn Shape> = Arc::new(s2);
+
+        let world = World {
+            lights: vec![Box::new(PointLight::new(Vector3::new(0., 0., -10.), Color::new(1., 1., 1.)))],
+            objects: vec![s1, Arc::clone(&s2)],
+            ..new()
+        };
+
+        let ray = Ray::new(Vector3::new(0., 0., 5.), Vector3::new(0., 0., 1.));
+        let intersection = Intersection::new(4., Arc::clone(&s2));
+        let full = world.shade_hit(intersection.prepare_computations(ray.clone()), RenderQuality::Full, MAX_RECURSION_DEPTH, 0, None);
+        let preview = world.shade_hit(intersection.prepare_computations(ray), RenderQuality::Preview, MAX_RECURSION_DEPTH, 0, None);
+
+        assert_eq!(full, Color::new(0.1, 0.1, 0.1));
+        assert_eq!(preview, Color::new(1.0, 1.0, 1.0));
+        assert!(preview.r > full.r);
+    }
+
+    #[test]
+    fn a_flattened_sphere_does_not_shadow_itself() {
+        let floor = Sphere::new(Some(transformations::scaling(&Vector3::new(100., 0.001, 100.))), None);
+        let floor: Arc<dyn Shape> = Arc::new(floor);
+        let world = World {
+            lights: vec![Box::new(PointLight::new(Vector3::new(-10., 10., -10.), Color::new(1., 1., 1.)))],
+            objects: vec![floor],
+            ..new()
+        };
+
+        let eye = Vector3::new(0., 1.5, -5.);
+        for x in -50..50 {
+            for z in -50..50 {
+                let target = Vector3::new(x as f64 * 0.37, 0., z as f64 * 0.37);
+                let ray = Ray::new(eye.clone(), (&target - &eye).normalize());
+                let hit = world.intersect(ray.clone()).hit().unwrap();
+                let comps = hit.prepare_computations(ray);
+                assert!(!world.is_shadowed(&comps.over_point, world.lights[0].as_ref()));
+            }
+        }
+    }
+
+    #[test]
+    fn the_reflected_color_for_a_nonreflective_material() {
+        let world = default_world();
+        let ray = Ray::new(Vector3::new(0., 0., 0.), Vector3::new(0., 0., 1.));
+        let intersection = Intersection::new(1., Arc::clone(&world.objects[1]));
+        let computations = intersection.prepare_computations(ray);
+        let color = world.reflected_color(&computations, MAX_RECURSION_DEPTH, 0, None);
+        assert_eq!(color, Color::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn the_reflected_color_for_a_reflective_material() {
+        let mut world = default_world();
+        let mut material = Material::new();
+        material.reflective = 0.5;
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new(
+            Some(transformations::translation(&Vector3::new(0., -1., 0.))),
+            Some(material),
+        ));
+        world.objects.push(Arc::clone(&plane));
+
+        let ray = Ray::new(
+            Vector3::new(0., 0., -3.),
+            Vector3::new(0., -(2f64.sqrt()) / 2., 2f64.sqrt() / 2.),
+        );
+        let intersection = Intersection::new(2f64.sqrt(), plane);
+        let computations = intersection.prepare_computations(ray);
+        let color = world.reflected_color(&computations, MAX_RECURSION_DEPTH, 0, None);
+        assert_eq!(color, Color::new(0.19035, 0.23793, 0.14276));
+    }
+
+    #[test]
+    fn fuzzy_reflections_scatter_the_reflected_ray() {
+        let reflected_color = |fuzz: f64| {
+            let mut world = default_world();
+            let material = Material::builder().reflective(0.5).reflective_fuzz(fuzz).build();
+            let plane: Arc<dyn Shape> = Arc::new(Plane::new(
+                Some(transformations::translation(&Vector3::new(0., -1., 0.))),
+                Some(material),
+            ));
+            world.objects.push(Arc::clone(&plane));
+
+            let ray = Ray::new(
+                Vector3::new(0., 0., -3.),
+                Vector3::new(0., -(2f64.sqrt()) / 2., 2f64.sqrt() / 2.),
+            );
+            let computations = Intersection::new(2f64.sqrt(), plane).prepare_computations(ray);
+            world.reflected_color(&computations, MAX_RECURSION_DEPTH, 0, None)
+        };
+
+        // No fuzz is the same mirror reflection as the test above
+        assert_eq!(reflected_color(0.), Color::new(0.19035, 0.23793, 0.14276));
+        // Scattering sees something a little different, but the same every time
+        assert_ne!(reflected_color(0.5), reflected_color(0.));
+        assert_eq!(reflected_color(0.5), reflected_color(0.5));
+    }
+
+    #[test]
+    fn fuzzed_reflections_are_redrawn_until_they_leave_the_surface() {
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new(None, None));
+        // Nearly parallel to the plane, so the mirror direction barely leaves it
+        let ray = Ray::new(Vector3::new(0., 0.1, -10.), Vector3::new(0., -0.01, 1.));
+        let computations = Intersection::new(10., plane).prepare_computations(ray);
+
+        for seed in 0..50 {
+            let direction = fuzzed_reflection(&computations, 1., seed);
+            assert!(direction.dot(&computations.normal_vector) > 0.);
+            assert_ne!(direction, computations.reflect_vector);
+        }
+        // Each sample scatters its own way
+        assert_ne!(fuzzed_reflection(&computations, 1., 1), fuzzed_reflection(&computations, 1., 2));
+    }
+
+    #[test]
+    fn the_reflected_color_at_the_maximum_recursive_depth() {
+        let mut world = default_world();
+        let mut material = Material::new();
+        material.reflective = 0.5;
+        let plane: Arc<dyn Shape> = Arc::new(Plane::new(
+            Some(transformations::translation(&Vector3::new(0., -1., 0.))),
+            Some(material),
+        ));
+        world.objects.push(Arc::clone(&plane));
+
+        let ray = Ray::new(
+            Vector3::new(0., 0., -3.),
+            Vector3::new(0., -(2f64.sqrt()) / 2., 2f64.sqrt() / 2.),
+        );
+        let intersection = Intersection::new(2f64.sqrt(), plane);
+        let computations = intersection.prepare_computations(ray);
+        let color = world.reflected_color(&computations, 0, 0, None);
+        assert_eq!(color, Color::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn color_at_with_mutually_reflective_surfaces_terminates() {
+        let mut material = Material::new();
+        material.reflective = 1.;
+        let lower = Plane::new(
+            Some(transformations::translation(&Vector3::new(0., -1., 0.))),
+            Some(material.clone()),
+        );
+        let upper = Plane::new(
+            Some(transformations::translation(&Vector3::new(0., 1., 0.))
+                .multiply_4x4(&transformations::rotation_x(std::f64::consts::PI))),
+            Some(material),
+        );
+        let world = World {
+            lights: vec![Box::new(PointLight::new(Vector3::new(0., 0., 0.), Color::new(1., 1., 1.)))],
+            objects: vec![Arc::new(lower), Arc::new(upper)],
+            ..new()
+        };
+
+        let ray = Ray::new(Vector3::new(0., 0., 0.), Vector3::new(0., 1., 0.));
+        let _color = world.color_at(ray);
+    }
+
+    #[test]
+    fn the_refracted_color_with_an_opaque_surface() {
+        let world = default_world();
+        let ray = Ray::new(Vector3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        let shape = &world.objects[0];
+        let xs = Intersections::new(vec![
+            Intersection::new(4., Arc::clone(shape)),
+            Intersection::new(6., Arc::clone(shape)),
+        ]);
+        let computations = xs[0].prepare_computations_with(ray, &xs);
+        let color = world.refracted_color(&computations, MAX_RECURSION_DEPTH, 0, None);
+        assert_eq!(color, Color::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn the_refracted_color_under_total_internal_reflection() {
+        let mut material = Material::new();
+        material.color = Color::new(0.8, 1.0, 0.6);
+        material.diffuse = 0.7;
+        material.specular = 0.2;
+        material.transparency = 1.0;
+        material.refractive_index = 1.5;
+        let shape: Arc<dyn Shape> = Arc::new(Sphere::new(None, Some(material)));
+        let world = World {
+            lights: vec![Box::new(PointLight::new(Vector3::new(-10., 10., -10.), Color::new(1., 1., 1.)))],
+            objects: vec![Arc::clone(&shape)],
+            ..new()
+        };
+
+        let ray = Ray::new(Vector3::new(0., 0., 2f64.sqrt() / 2.), Vector3::new(0., 1., 0.));
+        let xs = Intersections::new(vec![
+            Intersection::new(-(2f64.sqrt()) / 2., Arc::clone(&shape)),
+            Intersection::new(2f64.sqrt() / 2., Arc::clone(&shape)),
+        ]);
+
+        // We're inside the sphere, so look at the second intersection
+        let computations = xs[1].prepare_computations_with(ray, &xs);
+        let color = world.refracted_color(&computations, MAX_RECURSION_DEPTH, 0, None);
+        assert_eq!(color, Color::new(0., 0., 0.));
+    }
+
+    fn world_with_a_glass_floor(reflective: f64) -> (World, Arc<dyn Shape>) {
+        let mut world = default_world();
+
+        let mut floor_material = Material::new();
+        floor_material.reflective = reflective;
+        floor_material.transparency = 0.5;
+        floor_material.refractive_index = 1.5;
+        let floor: Arc<dyn Shape> = Arc::new(Plane::new(
+            Some(transformations::translation(&Vector3::new(0., -1., 0.))),
+            Some(floor_material),
+        ));
+
+        let mut ball_material = Material::new();
+        ball_material.color = Color::new(1., 0., 0.);
+        ball_material.ambient = 0.5;
+        let ball = Sphere::new(
+            Some(transformations::translation(&Vector3::new(0., -3.5, -0.5))),
+            Some(ball_material),
+        );
+
+        world.objects.push(Arc::clone(&floor));
+        world.objects.push(Arc::new(ball));
+        (world, floor)
+    }
+
+    #[test]
+    fn shade_hit_with_a_transparent_material() {
+        let (world, floor) = world_with_a_glass_floor(0.);
+        let ray = Ray::new(
+            Vector3::new(0., 0., -3.),
+            Vector3::new(0., -(2f64.sqrt()) / 2., 2f64.sqrt() / 2.),
+        );
+        let xs = Intersections::new(vec![Intersection::new(2f64.sqrt(), floor)]);
+        let computations = xs[0].prepare_computations_with(ray, &xs);
+        let color = world.shade_hit(computations, RenderQuality::Full, MAX_RECURSION_DEPTH, 0, None);
+        // The book has 0.93642 for red, there the half transparent floor casts a
+        // solid shadow on the ball. Here half of the light reaches the ball.
+        assert_eq!(color, Color::new(1.12547, 0.68642, 0.68642));
+    }
+
+    #[test]
+    fn shade_hit_with_a_reflective_transparent_material() {
+        let (world, floor) = world_with_a_glass_floor(0.5);
+        let ray = Ray::new(
+            Vector3::new(0., 0., -3.),
+            Vector3::new(0., -(2f64.sqrt()) / 2., 2f64.sqrt() / 2.),
+        );
+        let xs = Intersections::new(vec![Intersection::new(2f64.sqrt(), floor)]);
+        let computations = xs[0].prepare_computations_with(ray, &xs);
+        let color = world.shade_hit(computations, RenderQuality::Full, MAX_RECURSION_DEPTH, 0, None);
+        // The book has 0.93391 for red, see shade_hit_with_a_transparent_material
+        assert_eq!(color, Color::new(1.11500, 0.69643, 0.69243));
+    }
+
+    #[test]
+    fn the_energy_cap_clamps_a_shaded_hit() {
+        let (mut world, floor) = world_with_a_glass_floor(0.5);
+        world.energy_cap = Some(0.8);
+        let ray = Ray::new(
+            Vector3::new(0., 0., -3.),
+            Vector3::new(0., -(2f64.sqrt()) / 2., 2f64.sqrt() / 2.),
+        );
+        let xs = Intersections::new(vec![Intersection::new(2f64.sqrt(), floor)]);
+        let computations = xs[0].prepare_computations_with(ray, &xs);
+        let color = world.shade_hit(computations, RenderQuality::Full, MAX_RECURSION_DEPTH, 0, None);
+        assert_eq!(color, Color::new(0.8, 0.69643, 0.69243));
+    }
+
+    #[test]
+    fn a_directional_light_casts_shadows_from_infinitely_far_away() {
+        let mut world = default_world();
+        world.lights = vec![Box::new(DirectionalLight::new(
+            Vector3::new(0., -1., 0.),
+            Color::new(1., 1., 1.),
+        ))];
+
+        // Directly below the spheres, no matter how far away
+        assert!(world.is_shadowed(&Vector3::new(0., -10., 0.), world.lights[0].as_ref()));
+        assert!(world.is_shadowed(&Vector3::new(0., -1000., 0.), world.lights[0].as_ref()));
+
+        // Off to the side the sun shines straight down past the spheres
+        assert!(!world.is_shadowed(&Vector3::new(5., -10., 0.), world.lights[0].as_ref()));
+        assert!(!world.is_shadowed(&Vector3::new(0., 10., 0.), world.lights[0].as_ref()));
+    }
+
+    fn world_with_a_sphere_above_the_floor(transparency: f64) -> (World, Arc<dyn Shape>) {
+        let mut world = new();
+        world.lights = vec![Box::new(PointLight::new(Vector3::new(0., 10., 0.), Color::new(1., 1., 1.)))];
+
+        let floor: Arc<dyn Shape> = Arc::new(Plane::new(None, None));
+        let mut material = Material::new();
+        material.transparency = transparency;
+        material.refractive_index = 1.5;
+        let sphere = Sphere::new(
+            Some(transformations::translation(&Vector3::new(0., 2., 0.))),
+            Some(material),
+        );
+
+        world.objects.push(Arc::clone(&floor));
+        world.objects.push(Arc::new(sphere));
+        (world, floor)
+    }
+
+    fn floor_color_below_the_sphere(world: &World, floor: Arc<dyn Shape>) -> Color {
+        let ray = Ray::new(Vector3::new(0., 0.5, 0.), Vector3::new(0., -1., 0.));
+        let xs = Intersections::new(vec![Intersection::new(0.5, floor)]);
+        let computations = xs[0].prepare_computations_with(ray, &xs);
+        world.shade_hit(computations, RenderQuality::Full, MAX_RECURSION_DEPTH, 0, None)
+    }
+
+    #[test]
+    fn a_transparent_sphere_casts_a_lighter_shadow_than_an_opaque_one() {
+        let (opaque, floor) = world_with_a_sphere_above_the_floor(0.);
+        let opaque_color = floor_color_below_the_sphere(&opaque, floor);
+
+        let (glass, floor) = world_with_a_sphere_above_the_floor(1.);
+        let glass_color = floor_color_below_the_sphere(&glass, floor);
+
+        let (tinted, floor) = world_with_a_sphere_above_the_floor(0.5);
+        let tinted_color = floor_color_below_the_sphere(&tinted, floor);
+
+        // Opaque only leaves the ambient light, clear glass doesn't cast a shadow and
+        // partly transparent glass lets a quarter through (the ray crosses two surfaces)
+        assert_eq!(opaque_color, Color::new(0.1, 0.1, 0.1));
+        assert_eq!(glass_color, Color::new(1.9, 1.9, 1.9));
+        assert_eq!(tinted_color, Color::new(0.55, 0.55, 0.55));
+        assert!(opaque.is_shadowed(&Vector3::new(0., 0.0001, 0.), opaque.lights[0].as_ref()));
+        assert!(!glass.is_shadowed(&Vector3::new(0., 0.0001, 0.), glass.lights[0].as_ref()));
+    }
+
+    #[test]
+    fn the_background_color_when_a_ray_misses() {
+        let mut world = default_world();
+        assert_eq!(world.background, color::BLACK);
+
+        world.background = Color::new(0.5, 0.7, 1.0);
+        let ray = Ray::new(Vector3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(world.color_at(ray), Color::new(0.5, 0.7, 1.0));
+    }
+
+    #[test]
+    fn a_reflective_surface_reflects_the_background() {
+        let mut material = Material::new();
+        material.color = Color::new(0., 0., 0.);
+        material.ambient = 0.;
+        material.diffuse = 0.;
+        material.specular = 0.;
+        material.reflective = 1.;
+        let mirror = Plane::new(None, Some(material));
+
+        let mut world = new();
+        world.lights = vec![Box::new(PointLight::new(Vector3::new(0., 10., 0.), Color::new(1., 1., 1.)))];
+        world.objects = vec![Arc::new(mirror)];
+        world.background = Color::new(0.5, 0.7, 1.0);
+
+        let ray = Ray::new(Vector3::new(0., 1., -1.), Vector3::new(0., -1., 1.).normalize());
+        assert_eq!(world.color_at(ray), Color::new(0.5, 0.7, 1.0));
+    }
+
+    #[test]
+    fn a_ray_that_misses_samples_the_environment() {
+        let mut world = default_world();
+        world.background = Color::new(1., 0., 0.);
+        world.environment = Some(Box::new(GradientSky::new(
+            Color::new(0.2, 0.4, 1.0),
+            Color::new(1.0, 1.0, 1.0),
+        )));
+
+        let ray = Ray::new(Vector3::new(0.0, 0.0, -5.0), Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(world.color_at(ray), Color::new(0.2, 0.4, 1.0));
+    }
 }
@@ -9,25 +9,90 @@ use ray_tracer_lib::mathf::plane::Plane;
 use ray_tracer_lib::mathf::ray::Ray;
 use ray_tracer_lib::mathf::sphere::Sphere;
 use ray_tracer_lib::mathf::vector3::Vector3;
+use ray_tracer_lib::obj_file;
 use ray_tracer_lib::phong_lighting;
 use ray_tracer_lib::point_light::PointLight;
 use ray_tracer_lib::ppm;
+use ray_tracer_lib::scene;
 use ray_tracer_lib::transformations;
 use ray_tracer_lib::world;
 use std::f64::consts::PI;
+use std::env;
 use std::fs;
+use std::process;
 use std::sync::Arc;
 use crate::mathf::shapes::Shape;
 use ray_tracer_lib::png_encoder;
 
 
+const USAGE: &str = "usage:
+    ray_tracer_main                                draw the built in scene
+    ray_tracer_main render <scene.yml> <out.png>   render a yaml scene
+    ray_tracer_main obj <model.obj> <out.png>      render an obj model";
+
 fn main() {
-    // draw_simple();
-    // draw_clock();
-    // draw_circle();
-    // draw_circle_lit();
-    // draw_three_spheres_scene();
-    draw_three_spheres_and_plane_scene();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
+        // draw_simple();
+        // draw_clock();
+        // draw_circle();
+        // draw_circle_lit();
+        // draw_three_spheres_scene();
+        // draw_depth_of_field_scene();
+        draw_three_spheres_and_plane_scene();
+        return;
+    }
+
+    if let Err(message) = run(&args) {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    match args {
+        [command, scene_path, out_path] if command == "render" => render_scene_file(scene_path, out_path),
+        [command, obj_path, out_path] if command == "obj" => render_obj_file(obj_path, out_path),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn render_scene_file(scene_path: &str, out_path: &str) -> Result<(), String> {
+    let scene = scene::load_scene(scene_path).map_err(|e| format!("{}: {}", scene_path, e))?;
+    let canvas = scene.camera.render_multithreaded(&scene.world);
+    png_encoder::write_canvas_to_png(&canvas, out_path).map_err(|e| format!("{}: {}", out_path, e))
+}
+
+/// Render a model with a light over the camera's shoulder, framed so the whole model is visible.
+fn render_obj_file(obj_path: &str, out_path: &str) -> Result<(), String> {
+    let obj = obj_file::load_obj_file(obj_path).map_err(|e| format!("{}: {}", obj_path, e))?;
+    if obj.vertices.is_empty() {
+        return Err(format!("{}: the model has no vertices", obj_path));
+    }
+
+    let sum = obj.vertices.iter().fold(Vector3::new(0., 0., 0.), |sum, v| &sum + v);
+    let center = &sum / obj.vertices.len() as f64;
+    let radius = obj
+        .vertices
+        .iter()
+        .map(|v| v.distance(&center))
+        .fold(0.0001, f64::max);
+    let eye = &center + &Vector3::new(0., radius, -3. * radius);
+
+    let mut world = world::new();
+    world.add_light(PointLight::new(
+        &eye + &Vector3::new(-radius * 2., radius * 2., 0.),
+        color::WHITE,
+    ));
+    world.add(Arc::new(obj.into_group()));
+
+    let mut camera = Camera::new(400, 300, PI / 3.);
+    camera
+        .look_at(eye, center, Vector3::new(0., 1., 0.))
+        .map_err(|e| e.to_string())?;
+
+    let canvas = camera.render_multithreaded(&world);
+    png_encoder::write_canvas_to_png(&canvas, out_path).map_err(|e| format!("{}: {}", out_path, e))
 }
 
 #[allow(dead_code)]
@@ -61,18 +126,16 @@ fn draw_three_spheres_and_plane_scene() {
 
     let mut world = world::new();
     let light = PointLight::new(Vector3::new(-10., 10., -10.), color::WHITE);
-    world.light = Some(light);
+    world.add_light(light);
     world.objects = vec![Arc::new(floor_plane), Arc::new(middle), Arc::new(right), Arc::new(left)];
 
     // let mut camera = Camera::new(200, 100, PI / 3.);
     //let mut camera = Camera::new(100, 50, PI / 3.);
     let mut camera = Camera::new(700, 500, PI / 3.);
     //    let mut camera = Camera::new(1200, 600, PI / 3.);
-    camera.transform = transformations::view_transform(
-        Vector3::new(0., 1.5, -5.),
-        Vector3::new(0., 1., 0.),
-        Vector3::new(0., 1., 0.),
-    );
+    camera
+        .look_at(Vector3::new(0., 1.5, -5.), Vector3::new(0., 1., 0.), Vector3::new(0., 1., 0.))
+        .unwrap();
 
     //let canvas = camera.render(&world);
     let canvas = camera.render_multithreaded(&world);
@@ -82,6 +145,46 @@ fn draw_three_spheres_and_plane_scene() {
 }
 
 
+#[allow(dead_code)]
+fn draw_depth_of_field_scene() {
+    let floor_plane = Plane::new(None, None);
+
+    let near_transform = transformations::translation(&Vector3::new(-1., 0.5, -1.5))
+        .multiply_4x4(&transformations::scaling(&Vector3::new(0.5, 0.5, 0.5)));
+    let mut near_material = Material::new();
+    near_material.color = Color::new(1.0, 0.8, 0.1);
+    let near = Sphere::new(Some(near_transform), Some(near_material));
+
+    let middle_transform = transformations::translation(&Vector3::new(0.5, 1., 2.));
+    let mut middle_material = Material::new();
+    middle_material.color = Color::new(0.1, 1., 0.5);
+    let middle = Sphere::new(Some(middle_transform), Some(middle_material));
+
+    let far_transform = transformations::translation(&Vector3::new(2.5, 1., 6.));
+    let mut far_material = Material::new();
+    far_material.color = Color::new(0.5, 0.5, 1.);
+    let far = Sphere::new(Some(far_transform), Some(far_material));
+
+    let mut world = world::new();
+    let light = PointLight::new(Vector3::new(-10., 10., -10.), color::WHITE);
+    world.add_light(light);
+    world.objects = vec![Arc::new(floor_plane), Arc::new(near), Arc::new(middle), Arc::new(far)];
+
+    let from = Vector3::new(0., 1.5, -5.);
+    let mut camera = Camera::new(700, 500, PI / 3.);
+    camera
+        .look_at(from.clone(), Vector3::new(0., 1., 0.), Vector3::new(0., 1., 0.))
+        .unwrap();
+
+    // Focus on the nearest sphere so the ones behind it are blurred
+    camera.aperture = 0.1;
+    camera.focal_distance = (&Vector3::new(-1., 0.5, -1.5) - &from).magnitude();
+    camera.aperture_samples = 32;
+
+    let canvas = camera.render_multithreaded(&world);
+    png_encoder::save_canvas_to_png(&canvas, String::from("renders/depth_of_field.png"));
+}
+
 #[allow(dead_code)]
 fn draw_three_spheres_scene() {
     let floor_transform = transformations::scaling(&Vector3::new(10., 0.01, 10.));
@@ -138,16 +241,14 @@ fn draw_three_spheres_scene() {
 
     let mut world = world::new();
     let light = PointLight::new(Vector3::new(-10., 10., -10.), color::WHITE);
-    world.light = Some(light);
+    world.add_light(light);
     world.objects = vec![Arc::new(floor), Arc::new(wall_left), Arc::new(wall_right), Arc::new(middle), Arc::new(right), Arc::new(left)];
 
     // let mut camera = Camera::new(100, 50, PI / 3.);
     let mut camera = Camera::new(700, 500, PI / 3.);
-    camera.transform = transformations::view_transform(
-        Vector3::new(0., 1.5, -5.),
-        Vector3::new(0., 1., 0.),
-        Vector3::new(0., 1., 0.),
-    );
+    camera
+        .look_at(Vector3::new(0., 1.5, -5.), Vector3::new(0., 1., 0.), Vector3::new(0., 1., 0.))
+        .unwrap();
 
     // let canvas = camera.render(&world);
     let canvas = camera.render_multithreaded(&world);
@@ -196,6 +297,7 @@ fn draw_circle_lit() {
                 let eye = -ray.direction;
                 let color = phong_lighting::lighting(
                     &hit_info.object.material(),
+                    &*hit_info.object,
                     &light,
                     &point,
                     &eye,
@@ -203,7 +305,7 @@ fn draw_circle_lit() {
                     false,
                 );
 
-                canvas.write_pixel(x as usize, y as usize, &color);
+                canvas.write_pixel(x, y, &color);
             }
         }
     }
@@ -241,7 +343,7 @@ fn draw_circle() {
             let xs = Intersections::new(xs);
             let hit = xs.hit();
             if hit.is_some() {
-                canvas.write_pixel(x as usize, y as usize, &color);
+                canvas.write_pixel(x, y, &color);
             }
         }
     }
@@ -291,3 +393,43 @@ fn draw_clock() {
     let ppm_data = ppm::canvas_to_ppm(&canvas);
     fs::write("renders/clock.ppm", ppm_data).expect("Unable to write file");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn unknown_commands_print_the_usage() {
+        assert_eq!(run(&args(&["draw", "a", "b"])), Err(USAGE.to_string()));
+        assert_eq!(run(&args(&["render", "scene.yml"])), Err(USAGE.to_string()));
+    }
+
+    #[test]
+    fn missing_input_files_are_an_error() {
+        let result = run(&args(&["render", "no/such/scene.yml", "out.png"]));
+        assert!(result.unwrap_err().starts_with("no/such/scene.yml"));
+        let result = run(&args(&["obj", "no/such/model.obj", "out.png"]));
+        assert!(result.unwrap_err().starts_with("no/such/model.obj"));
+    }
+
+    #[test]
+    fn rendering_an_obj_file() {
+        let dir = env::temp_dir();
+        let obj_path = dir.join("ray_tracer_main_test_triangle.obj");
+        let out_path = dir.join("ray_tracer_main_test_triangle.png");
+        fs::write(&obj_path, "v -1 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+
+        let obj_path = obj_path.to_str().unwrap();
+        let out_path = out_path.to_str().unwrap();
+        assert_eq!(run(&args(&["obj", obj_path, out_path])), Ok(()));
+        assert!(png_encoder::load_canvas_from_png(out_path).is_ok());
+
+        // The output directory must exist
+        let result = run(&args(&["obj", obj_path, "no/such/dir/out.png"]));
+        assert!(result.is_err());
+    }
+}
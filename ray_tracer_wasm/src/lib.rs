@@ -7,6 +7,7 @@ use wasm_bindgen::Clamped;
 use web_sys::{CanvasRenderingContext2d, ImageData};
 
 use ray_tracer_lib::camera::Camera;
+use ray_tracer_lib::canvas::Canvas;
 use ray_tracer_lib::color;
 use ray_tracer_lib::color::Color;
 use ray_tracer_lib::material::Material;
@@ -41,13 +42,6 @@ pub fn main_js() -> Result<(), JsValue> {
     Ok(())
 }
 
-// Convert a color value in the range 0.0 to 1.0 into 0 to 255
-fn convert_rbg_value_to_byte(value: f64) -> u8 {
-    // Values can be above 1.0, if so clamp them to the correct range for output
-    let clamped = num::clamp(value, 0.0, 1.0);
-    (clamped * 255.0).ceil() as u8
-}
-
 #[wasm_bindgen]
 pub struct WasmColor {
     pub r: u8,
@@ -64,11 +58,8 @@ pub fn color_at_pixel(
 ) -> Result<WasmColor, JsValue> {
     let world = generate_world();
     let color = color_at_pixel_three_spheres_and_plane_scene(&world, width, height, x, y);
-    Ok(WasmColor {
-        r: convert_rbg_value_to_byte(color.r),
-        g: convert_rbg_value_to_byte(color.g),
-        b: convert_rbg_value_to_byte(color.b),
-    })
+    let [r, g, b] = color.to_rgb8();
+    Ok(WasmColor { r, g, b })
 }
 
 fn color_at_pixel_three_spheres_and_plane_scene(
@@ -79,11 +70,9 @@ fn color_at_pixel_three_spheres_and_plane_scene(
     y: usize,
 ) -> Color {
     let mut camera = Camera::new(width, height, PI / 3.);
-    camera.transform = transformations::view_transform(
-        Vector3::new(0., 1.5, -5.),
-        Vector3::new(0., 1., 0.),
-        Vector3::new(0., 1., 0.),
-    );
+    camera
+        .look_at(Vector3::new(0., 1.5, -5.), Vector3::new(0., 1., 0.), Vector3::new(0., 1., 0.))
+        .unwrap();
 
     camera.color_at_pixel(&world, x, y)
 }
@@ -96,36 +85,24 @@ pub fn draw_single_threaded(
 ) -> Result<(), JsValue> {
     console::log_1(&JsValue::from_str("Running!"));
 
-    let colors = draw_three_spheres_and_plane_scene(width as usize, height as usize);
-
-    let mut data = Vec::new();
-    for row in colors.iter() {
-        for column in row.iter() {
-            data.push(convert_rbg_value_to_byte(column.r));
-            data.push(convert_rbg_value_to_byte(column.g));
-            data.push(convert_rbg_value_to_byte(column.b));
-            data.push(255);
-        }
-    }
+    let canvas = draw_three_spheres_and_plane_scene(width as usize, height as usize);
+
+    let mut data = canvas.to_rgba_bytes();
     console::log_1(&JsValue::from_str("Finished ray tracing!"));
 
     let data = ImageData::new_with_u8_clamped_array_and_sh(Clamped(&mut data), width, height)?;
     ctx.put_image_data(&data, 0.0, 0.0)
 }
 
-fn draw_three_spheres_and_plane_scene(width: usize, height: usize) -> Vec<std::vec::Vec<Color>> {
+fn draw_three_spheres_and_plane_scene(width: usize, height: usize) -> Canvas {
     let world = generate_world();
 
     let mut camera = Camera::new(width, height, PI / 3.);
-    camera.transform = transformations::view_transform(
-        Vector3::new(0., 1.5, -5.),
-        Vector3::new(0., 1., 0.),
-        Vector3::new(0., 1., 0.),
-    );
-
-    let canvas = camera.render(&world);
+    camera
+        .look_at(Vector3::new(0., 1.5, -5.), Vector3::new(0., 1., 0.), Vector3::new(0., 1., 0.))
+        .unwrap();
 
-    canvas.pixels
+    camera.render(&world)
 }
 
 fn generate_world() -> World {
@@ -156,7 +133,7 @@ fn generate_world() -> World {
 
     let mut world = world::new();
     let light = PointLight::new(Vector3::new(-10., 10., -10.), color::WHITE);
-    world.light = Some(light);
+    world.add_light(light);
     world.objects = vec![
         Arc::new(floor_plane),
         Arc::new(middle),